@@ -0,0 +1,655 @@
+//! Batch rendering of every profile into an output directory.
+//!
+//! Each profile is rendered through the normal pipeline and written to its own
+//! file, with dotted namespaces mapped to subdirectories (`python.api` becomes
+//! `python/api.txt`). Files are staged in a temporary sibling directory and only
+//! moved into place, one rename per file, once every profile has rendered, so a
+//! failure leaves the previous output untouched. Files in the output directory
+//! that are not profile outputs are never touched. Profiles are independent, so they can be rendered
+//! on several threads. By default every failure is collected and reported
+//! together; `--fail-fast` stops at the first.
+//! Unchanged profiles can be copied from the render cache (see [`crate::cache`]),
+//! or, with `--only-changed`, from the previous output when git reports no
+//! change to any file they include.
+
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fs;
+use std::io::{self, Write};
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Mutex, PoisonError};
+use std::thread;
+
+use crate::cache::{self, CacheMode, RenderCache};
+use crate::progress::progress_bar;
+use crate::{
+    Config, ConfigSource, FailurePolicy, RenderFormat, RenderOptions, render_to_writer, to_json,
+};
+
+/// Summary of a batch render
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub struct BatchOutput {
+    /// Directory the rendered files were written to
+    pub output_dir: PathBuf,
+    /// Number of profiles written
+    pub written: usize,
+    /// Number of those copied from the render cache
+    pub cached: usize,
+    /// Number of those kept from the previous output (`--only-changed`)
+    pub unchanged: usize,
+}
+
+/// How `run --all` renders: threads, cache use, which profiles to re-render,
+/// and whether to stop at the first failure.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BatchOptions {
+    /// Worker threads (1 renders sequentially)
+    pub jobs: usize,
+    /// Whether to reuse, bypass, or first clear the render cache
+    pub cache: CacheMode,
+    /// Only re-render profiles whose includes changed in git since this revision
+    pub only_changed: Option<String>,
+    /// Stop at the first failure, or render everything and report every one
+    pub failures: FailurePolicy,
+    /// Show a progress bar on stderr (`--progress`)
+    pub progress: bool,
+}
+
+/// Output path of `profile` relative to the output directory.
+fn profile_output_path(profile: &str, format: RenderFormat) -> PathBuf {
+    let extension = match format {
+        RenderFormat::Text => "txt",
+        RenderFormat::Json | RenderFormat::Chat => "json",
+    };
+    let mut path: PathBuf = profile.split('.').collect();
+    path.set_extension(extension);
+    path
+}
+
+/// Check that no two of `names` are written to the same output path, as
+/// `a.b` and `a..b` would be.
+fn check_output_paths(names: &[&String], format: RenderFormat) -> Result<(), String> {
+    let mut seen: HashMap<PathBuf, &String> = HashMap::new();
+    for name in names {
+        let path = profile_output_path(name, format);
+        if let Some(other) = seen.insert(path.clone(), name) {
+            return Err(format!(
+                "Profiles '{other}' and '{name}' would both be written to {}",
+                path.display()
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Temporary sibling of `out_dir` used to stage output before the final move.
+fn staging_dir(out_dir: &Path, suffix: &str) -> Result<PathBuf, String> {
+    let name = out_dir
+        .file_name()
+        .ok_or_else(|| format!("Invalid output directory: {}", out_dir.display()))?;
+    let mut staged = name.to_os_string();
+    staged.push(format!(".{suffix}-{}", std::process::id()));
+    Ok(out_dir.with_file_name(staged))
+}
+
+/// Worker threads for `--parallel[=N]`; `0` means one per available CPU.
+#[must_use]
+pub fn worker_count(requested: usize) -> usize {
+    if requested == 0 {
+        thread::available_parallelism().map_or(1, NonZeroUsize::get)
+    } else {
+        requested
+    }
+}
+
+fn render_one(
+    cfg: &Config,
+    lib: &Path,
+    dir: &Path,
+    name: &str,
+    opts: &RenderOptions,
+    cache: Option<&RenderCache>,
+) -> Result<(), String> {
+    let path = dir.join(profile_output_path(name, opts.format));
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+    }
+    let key = cache.map(|_| cache::cache_key(cfg, lib, name, opts));
+    let cached = cache.zip(key.as_deref()).and_then(|(c, k)| c.get(k));
+    let out = if let Some(out) = cached {
+        out
+    } else {
+        let mut out = Vec::new();
+        render_to_writer(cfg, lib, &mut out, &[name.to_string()], opts)
+            .map_err(|e| e.to_string())?;
+        if let Some((cache, key)) = cache.zip(key.as_deref()) {
+            cache.put(key, &out);
+        }
+        out
+    };
+    fs::write(&path, out).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+fn render_into(
+    cfg: &Config,
+    lib: &Path,
+    dir: &Path,
+    names: &[&String],
+    opts: &RenderOptions,
+    batch: &BatchOptions,
+    cache: Option<&RenderCache>,
+) -> Result<(), String> {
+    let fail_fast = batch.failures == FailurePolicy::FailFast;
+    let progress = progress_bar("rendering", names.len(), batch.progress);
+    let mut failures = Vec::new();
+    if batch.jobs <= 1 {
+        for name in names {
+            let rendered = render_one(cfg, lib, dir, name, opts, cache);
+            progress.inc(1);
+            if let Err(e) = rendered {
+                failures.push(((*name).clone(), e));
+                if fail_fast {
+                    break;
+                }
+            }
+        }
+    } else {
+        let next = AtomicUsize::new(0);
+        let stop = AtomicBool::new(false);
+        let found = Mutex::new(Vec::new());
+        thread::scope(|scope| {
+            for _ in 0..batch.jobs.min(names.len()) {
+                scope.spawn(|| {
+                    while !stop.load(Ordering::Relaxed) {
+                        let Some(name) = names.get(next.fetch_add(1, Ordering::Relaxed)) else {
+                            break;
+                        };
+                        if let Err(e) = render_one(cfg, lib, dir, name, opts, cache) {
+                            stop.store(fail_fast, Ordering::Relaxed);
+                            found
+                                .lock()
+                                .unwrap_or_else(PoisonError::into_inner)
+                                .push(((*name).clone(), e));
+                        }
+                        progress.inc(1);
+                    }
+                });
+            }
+        });
+        failures = found.into_inner().unwrap_or_else(PoisonError::into_inner);
+    }
+    progress.finish_and_clear();
+
+    if failures.is_empty() {
+        return Ok(());
+    }
+    failures.sort();
+    let messages: Vec<String> = failures.into_iter().map(|(_, e)| e).collect();
+    Err(messages.join("\n"))
+}
+
+/// Move the staged output at `rel` into `out_dir`, replacing the previous file.
+fn move_into_place(staged: &Path, out_dir: &Path, rel: &Path) -> Result<(), String> {
+    let target = out_dir.join(rel);
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+    }
+    fs::rename(staged.join(rel), &target)
+        .map_err(|e| format!("Failed to write {}: {}", target.display(), e))
+}
+
+/// Render every profile in `cfg` into `out_dir` with the threads and failure
+/// policy of `batch`, replacing each profile's previous output file. Other
+/// files in `out_dir` are left alone.
+///
+/// With a `cache`, profiles whose inputs are unchanged are copied from it and
+/// new renders are stored in it. With `only`, other profiles keep their
+/// previous output when there is one.
+///
+/// # Errors
+/// Returns an error if any profile fails to render, in which case `out_dir` is
+/// left unchanged, or if the output cannot be written.
+pub fn render_all_to_dir(
+    cfg: &Config,
+    lib: &Path,
+    out_dir: &Path,
+    opts: &RenderOptions,
+    batch: &BatchOptions,
+    cache: Option<&RenderCache>,
+    only: Option<&BTreeSet<String>>,
+) -> Result<BatchOutput, String> {
+    let mut names: Vec<&String> = cfg.profiles.keys().collect();
+    names.sort();
+    check_output_paths(&names, opts.format)?;
+    let staged = staging_dir(out_dir, "tmp")?;
+    if staged.exists() {
+        fs::remove_dir_all(&staged)
+            .map_err(|e| format!("Failed to remove {}: {}", staged.display(), e))?;
+    }
+    fs::create_dir_all(&staged)
+        .map_err(|e| format!("Failed to create {}: {}", staged.display(), e))?;
+
+    let (kept, render): (Vec<&String>, Vec<&String>) = names.iter().partition(|name| {
+        only.is_some_and(|only| !only.contains(**name))
+            && out_dir
+                .join(profile_output_path(name, opts.format))
+                .is_file()
+    });
+    let moved = render_into(cfg, lib, &staged, &render, opts, batch, cache).and_then(|()| {
+        render.iter().try_for_each(|name| {
+            move_into_place(&staged, out_dir, &profile_output_path(name, opts.format))
+        })
+    });
+    let _ = fs::remove_dir_all(&staged);
+    moved?;
+    Ok(BatchOutput {
+        output_dir: out_dir.to_path_buf(),
+        written: names.len(),
+        cached: cache.map_or(0, RenderCache::hits),
+        unchanged: kept.len(),
+    })
+}
+
+/// `path` with symlinks resolved, or as given when it does not exist.
+fn canonical(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// Files changed since `rev` in the git work tree containing `dir`, tracked
+/// or untracked, as absolute paths; `None` when `dir` is not in a git
+/// repository.
+fn git_changed_files(dir: &Path, rev: &str) -> Result<Option<HashSet<PathBuf>>, String> {
+    let git = |args: &[&str]| Command::new("git").arg("-C").arg(dir).args(args).output();
+    let toplevel = match git(&["rev-parse", "--show-toplevel"]) {
+        Ok(out) if out.status.success() => {
+            PathBuf::from(String::from_utf8_lossy(&out.stdout).trim())
+        }
+        _ => return Ok(None),
+    };
+    let mut changed = HashSet::new();
+    let listings: [&[&str]; 2] = [
+        &["diff", "--name-only", rev, "--"],
+        &["ls-files", "--others", "--exclude-standard", "--full-name"],
+    ];
+    for args in listings {
+        let out = git(args).map_err(|e| format!("Failed to run git: {e}"))?;
+        if !out.status.success() {
+            return Err(format!(
+                "git {} failed: {}",
+                args.join(" "),
+                String::from_utf8_lossy(&out.stderr).trim()
+            ));
+        }
+        let stdout = String::from_utf8_lossy(&out.stdout);
+        changed.extend(stdout.lines().map(|line| canonical(&toplevel.join(line))));
+    }
+    Ok(Some(changed))
+}
+
+/// Profiles with a changed file among their transitive includes.
+fn affected_profiles(cfg: &Config, lib: &Path, changed: &HashSet<PathBuf>) -> BTreeSet<String> {
+    cfg.profiles
+        .keys()
+        .filter(|name| {
+            let (_, files) = cache::reachable(cfg, lib, name);
+            files.iter().any(|file| changed.contains(&canonical(file)))
+        })
+        .cloned()
+        .collect()
+}
+
+/// The profiles to re-render for `--only-changed=rev`, or `None` to render
+/// every profile: outside git (with a warning), or when the config file
+/// itself changed.
+fn changed_profiles(
+    cfg: &Config,
+    lib: &Path,
+    config: &ConfigSource,
+    rev: &str,
+) -> Result<Option<BTreeSet<String>>, String> {
+    let Some(changed) = git_changed_files(lib, rev)? else {
+        eprintln!(
+            "Warning: {} is not in a git repository; rendering every profile",
+            lib.display()
+        );
+        return Ok(None);
+    };
+    let config_changed = config
+        .config_file()?
+        .is_some_and(|file| changed.contains(&canonical(&file)));
+    if config_changed {
+        return Ok(None);
+    }
+    Ok(Some(affected_profiles(cfg, lib, &changed)))
+}
+
+/// Render every profile into `out_dir` as `batch` says (threads, render
+/// cache, `--only-changed`), and report how many were written (unless
+/// `quiet` is set; JSON output is always written).
+///
+/// # Errors
+/// Returns an error if the configuration cannot be read or parsed, the cache
+/// cannot be located or cleared, git fails, any profile fails to render, or
+/// the output cannot be written.
+pub fn run_render_all_stdout(
+    out_dir: &Path,
+    opts: &RenderOptions,
+    config: &ConfigSource,
+    json: bool,
+    quiet: bool,
+    batch: &BatchOptions,
+) -> Result<(), String> {
+    let (cfg, lib) = config.load()?;
+    let cache = match batch.cache {
+        CacheMode::Bypass => None,
+        CacheMode::Use | CacheMode::Clear => Some(RenderCache::new(cache::cache_dir()?)),
+    };
+    if let Some(cache) = cache.as_ref().filter(|_| batch.cache == CacheMode::Clear) {
+        cache.clear()?;
+    }
+    let only = match &batch.only_changed {
+        Some(rev) => changed_profiles(&cfg, &lib, config, rev)?,
+        None => None,
+    };
+    let output = render_all_to_dir(
+        &cfg,
+        &lib,
+        out_dir,
+        opts,
+        batch,
+        cache.as_ref(),
+        only.as_ref(),
+    )?;
+
+    let mut stdout = io::stdout();
+    if json {
+        let json_output = to_json(&output, opts.json_layout)?;
+        writeln!(stdout, "{json_output}").map_err(|e| format!("Write error: {e}"))
+    } else if quiet {
+        Ok(())
+    } else {
+        let mut notes = Vec::new();
+        if output.cached > 0 {
+            notes.push(format!("{} from cache", output.cached));
+        }
+        if output.unchanged > 0 {
+            notes.push(format!("{} unchanged", output.unchanged));
+        }
+        let notes = if notes.is_empty() {
+            String::new()
+        } else {
+            format!(" ({})", notes.join(", "))
+        };
+        writeln!(
+            stdout,
+            "Wrote {} profile(s) to {}{notes}",
+            output.written,
+            out_dir.display()
+        )
+        .map_err(|e| format!("Write error: {e}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn jobs(jobs: usize) -> BatchOptions {
+        BatchOptions {
+            jobs,
+            failures: FailurePolicy::KeepGoing,
+            ..BatchOptions::default()
+        }
+    }
+
+    #[test]
+    fn renders_every_profile_with_namespace_dirs() {
+        let root = crate::mk_tmp("prompter_batch");
+        fs::create_dir_all(&root).unwrap();
+        let lib = root.join("library");
+        fs::create_dir_all(&lib).unwrap();
+        fs::write(lib.join("a.md"), b"A\n").unwrap();
+        fs::write(lib.join("b.md"), b"B\n").unwrap();
+        let cfg = Config {
+            profiles: HashMap::from([
+                ("base".into(), vec!["a.md".into()]),
+                ("python.api".into(), vec!["base".into(), "b.md".into()]),
+            ]),
+            ..Config::default()
+        };
+        let out = root.join("dist");
+        fs::create_dir_all(&out).unwrap();
+        fs::write(out.join("thesis.txt"), b"mine").unwrap();
+        fs::write(out.join("base.txt"), b"old").unwrap();
+
+        let written = render_all_to_dir(
+            &cfg,
+            &lib,
+            &out,
+            &RenderOptions::default(),
+            &jobs(1),
+            None,
+            None,
+        )
+        .unwrap();
+        assert_eq!(written.written, 2);
+        assert!(
+            fs::read_to_string(out.join("base.txt"))
+                .unwrap()
+                .contains('A')
+        );
+        let api = fs::read_to_string(out.join("python").join("api.txt")).unwrap();
+        assert!(api.contains('A') && api.contains('B'));
+        assert_eq!(fs::read_to_string(out.join("thesis.txt")).unwrap(), "mine");
+    }
+
+    #[test]
+    fn dotted_names_get_distinct_output_paths() {
+        let names = ["python", "python.api", "python_api", "python.api.v2", "api"];
+        let paths: HashSet<PathBuf> = names
+            .iter()
+            .map(|name| profile_output_path(name, RenderFormat::Text))
+            .collect();
+        assert_eq!(paths.len(), names.len());
+        assert_eq!(
+            profile_output_path("python.api", RenderFormat::Json),
+            Path::new("python").join("api.json")
+        );
+
+        let root = crate::mk_tmp("prompter_batch_collide");
+
+        fs::create_dir_all(&root).unwrap();
+        let cfg = Config {
+            profiles: HashMap::from([("x.y".into(), Vec::new()), ("x..y".into(), Vec::new())]),
+            ..Config::default()
+        };
+        let out = root.join("dist");
+        let err = render_all_to_dir(
+            &cfg,
+            &root,
+            &out,
+            &RenderOptions::default(),
+            &jobs(1),
+            None,
+            None,
+        )
+        .unwrap_err();
+        assert_eq!(
+            err,
+            format!(
+                "Profiles 'x..y' and 'x.y' would both be written to {}",
+                Path::new("x").join("y.txt").display()
+            )
+        );
+        assert!(!out.exists());
+    }
+
+    #[test]
+    fn failure_leaves_output_untouched() {
+        let root = crate::mk_tmp("prompter_batch_fail");
+        fs::create_dir_all(&root).unwrap();
+        let lib = root.join("library");
+        fs::create_dir_all(&lib).unwrap();
+        fs::write(lib.join("a.md"), b"A\n").unwrap();
+        let cfg = Config {
+            profiles: HashMap::from([
+                ("good".into(), vec!["a.md".into()]),
+                ("bad".into(), vec!["missing.md".into()]),
+            ]),
+            ..Config::default()
+        };
+        let out = root.join("dist");
+        fs::create_dir_all(&out).unwrap();
+        fs::write(out.join("keep.txt"), b"keep").unwrap();
+
+        let err = render_all_to_dir(
+            &cfg,
+            &lib,
+            &out,
+            &RenderOptions::default(),
+            &jobs(1),
+            None,
+            None,
+        )
+        .unwrap_err();
+        assert!(err.contains("bad"));
+        assert!(out.join("keep.txt").exists());
+        assert!(!out.join("good.txt").exists());
+        assert!(!staging_dir(&out, "tmp").unwrap().exists());
+    }
+
+    #[test]
+    fn parallel_render_matches_sequential_and_collects_every_failure() {
+        let root = crate::mk_tmp("prompter_batch_parallel");
+        fs::create_dir_all(&root).unwrap();
+        let lib = root.join("library");
+        fs::create_dir_all(&lib).unwrap();
+        let mut profiles = HashMap::new();
+        for i in 0..20 {
+            fs::write(lib.join(format!("f{i}.md")), format!("F{i}\n")).unwrap();
+            profiles.insert(format!("group.p{i:02}"), vec![format!("f{i}.md")]);
+        }
+        let cfg = Config {
+            profiles,
+            ..Config::default()
+        };
+        let opts = RenderOptions::default();
+        let (seq, par) = (root.join("seq"), root.join("par"));
+        assert_eq!(
+            render_all_to_dir(&cfg, &lib, &seq, &opts, &jobs(1), None, None)
+                .unwrap()
+                .written,
+            20
+        );
+        assert_eq!(
+            render_all_to_dir(&cfg, &lib, &par, &opts, &jobs(4), None, None)
+                .unwrap()
+                .written,
+            20
+        );
+        for i in 0..20 {
+            let file = PathBuf::from("group").join(format!("p{i:02}.txt"));
+            assert_eq!(
+                fs::read(seq.join(&file)).unwrap(),
+                fs::read(par.join(&file)).unwrap()
+            );
+        }
+
+        let mut cfg = cfg;
+        cfg.profiles
+            .insert("bad.one".into(), vec!["gone1.md".into()]);
+        cfg.profiles
+            .insert("bad.two".into(), vec!["gone2.md".into()]);
+        let err = render_all_to_dir(&cfg, &lib, &par, &opts, &jobs(4), None, None).unwrap_err();
+        let lines: Vec<&str> = err.lines().collect();
+        assert_eq!(lines.len(), 2, "{err}");
+        assert!(lines[0].contains("'bad.one'") && lines[1].contains("'bad.two'"));
+        let fail_fast = BatchOptions {
+            failures: FailurePolicy::FailFast,
+            ..jobs(1)
+        };
+        let err = render_all_to_dir(&cfg, &lib, &seq, &opts, &fail_fast, None, None).unwrap_err();
+        assert_eq!(err.lines().count(), 1, "{err}");
+        let err = render_all_to_dir(&cfg, &lib, &seq, &opts, &jobs(1), None, None).unwrap_err();
+        assert_eq!(err.lines().count(), 2, "{err}");
+        assert_eq!(worker_count(3), 3);
+        assert!(worker_count(0) >= 1);
+    }
+
+    #[test]
+    fn only_changed_keeps_previous_output_of_unaffected_profiles() {
+        let root = crate::mk_tmp("prompter_batch_only");
+        fs::create_dir_all(&root).unwrap();
+        let lib = root.join("library");
+        fs::create_dir_all(&lib).unwrap();
+        fs::write(lib.join("a.md"), b"A\n").unwrap();
+        fs::write(lib.join("b.md"), b"B\n").unwrap();
+        let cfg = Config {
+            profiles: HashMap::from([
+                ("one".into(), vec!["a.md".into()]),
+                ("two".into(), vec!["b.md".into()]),
+                ("both".into(), vec!["one".into(), "two".into()]),
+            ]),
+            ..Config::default()
+        };
+        let changed = HashSet::from([canonical(&lib.join("b.md"))]);
+        let affected = affected_profiles(&cfg, &lib, &changed);
+        assert_eq!(
+            affected.iter().map(String::as_str).collect::<Vec<_>>(),
+            ["both", "two"]
+        );
+
+        let out = root.join("dist");
+        let opts = RenderOptions::default();
+        render_all_to_dir(&cfg, &lib, &out, &opts, &jobs(1), None, None).unwrap();
+        fs::write(out.join("one.txt"), b"previous").unwrap();
+        fs::write(lib.join("b.md"), b"B2\n").unwrap();
+        let output =
+            render_all_to_dir(&cfg, &lib, &out, &opts, &jobs(1), None, Some(&affected)).unwrap();
+        assert_eq!((output.written, output.unchanged), (3, 1));
+        assert_eq!(fs::read_to_string(out.join("one.txt")).unwrap(), "previous");
+        assert!(
+            fs::read_to_string(out.join("two.txt"))
+                .unwrap()
+                .contains("B2")
+        );
+
+        fs::remove_file(out.join("one.txt")).unwrap();
+        let output =
+            render_all_to_dir(&cfg, &lib, &out, &opts, &jobs(1), None, Some(&affected)).unwrap();
+        assert_eq!(output.unchanged, 0);
+        assert!(
+            fs::read_to_string(out.join("one.txt"))
+                .unwrap()
+                .contains('A')
+        );
+    }
+
+    #[test]
+    fn cached_render_still_honours_deny_remote() {
+        let root = crate::mk_tmp("prompter_batch_deny_cached");
+        let lib = root.join("library");
+        fs::create_dir_all(&lib).unwrap();
+        let mut cfg = Config {
+            profiles: HashMap::from([("r".into(), vec!["https://example.test/rules.md".into()])]),
+            ..Config::default()
+        };
+        let opts = RenderOptions::default();
+        let cache = RenderCache::new(root.join("cache"));
+        cache.put(&cache::cache_key(&cfg, &lib, "r", &opts), b"cached rules\n");
+        let out = root.join("dist");
+        let render =
+            |cfg: &Config| render_all_to_dir(cfg, &lib, &out, &opts, &jobs(1), Some(&cache), None);
+        assert_eq!(render(&cfg).unwrap().cached, 1);
+
+        cfg.remote_access.deny = true;
+        let err = render(&cfg).unwrap_err();
+        assert!(err.contains("--deny-remote"), "{err}");
+    }
+}
@@ -0,0 +1,389 @@
+//! Portable bundles of a configuration and its library (`export`/`import`).
+//!
+//! `export` writes the loaded configuration and every file it can include into
+//! one TOML (or JSON) document; `import` scaffolds `config.toml` and
+//! `library/` from it, so the imported copy renders every profile the same way.
+//! Inheritance is already flattened in the loaded model, so bundled profiles
+//! list their full `depends_on` instead of `extends`.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use crate::{
+    Config, ConfigSource, INLINE_PROFILES_SECTION, REMOTE_SECTION, SCENARIO_PREFIX, Scaffold,
+    fragment_path, library_fragments, split_line_range, split_optional, success_message,
+};
+
+/// Version written to and accepted from bundles.
+const BUNDLE_VERSION: u32 = 1;
+
+/// A configuration and the files its profiles include, in one document.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Bundle {
+    /// Bundle format version
+    pub version: u32,
+    /// Configuration text, written to `config.toml` on import
+    pub config: String,
+    /// Library fragments by path relative to the library
+    #[serde(default)]
+    pub library: BTreeMap<String, String>,
+    /// `@path` include files by path relative to the configuration directory
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub includes: BTreeMap<String, String>,
+}
+
+/// `s` as a quoted config string, escaped the way the config parser unescapes.
+fn quote(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '"' => out.push_str("\\\""),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// `items` as a one-line config array.
+fn quote_array(items: &[String]) -> String {
+    let items: Vec<String> = items.iter().map(|item| quote(item)).collect();
+    format!("[{}]", items.join(", "))
+}
+
+/// Configuration text that loads back into the same profiles and settings.
+///
+/// Locations (`relative_to`, `include_base`) are left out: an imported bundle
+/// always keeps its library next to the config file.
+#[must_use]
+pub fn config_text(cfg: &Config) -> String {
+    let mut out = String::new();
+    let settings = [
+        ("post_prompt", cfg.post_prompt.as_deref().map(quote)),
+        ("comment_prefix", cfg.comment_prefix.as_deref().map(quote)),
+        (
+            "case_insensitive_profiles",
+            cfg.case_insensitive_profiles.map(|b| b.to_string()),
+        ),
+    ];
+    for (key, value) in settings {
+        if let Some(value) = value {
+            let _ = writeln!(out, "{key} = {value}");
+        }
+    }
+
+    let mut inline: Vec<(&String, &String)> = cfg.inline_profiles.iter().collect();
+    inline.sort();
+    if !inline.is_empty() {
+        let _ = writeln!(out, "\n[{INLINE_PROFILES_SECTION}]");
+        for (name, body) in inline {
+            let _ = writeln!(out, "{} = {}", quote(name), quote(body));
+        }
+    }
+
+    let mut names: Vec<&String> = cfg
+        .profiles
+        .keys()
+        .filter(|name| !cfg.inline_profiles.contains_key(*name))
+        .collect();
+    names.sort();
+    for name in names {
+        let _ = writeln!(out, "\n[{name}]");
+        if let Some(meta) = cfg.metadata.get(name) {
+            if let Some(description) = &meta.description {
+                let _ = writeln!(out, "description = {}", quote(description));
+            }
+            if !meta.tags.is_empty() {
+                let _ = writeln!(out, "tags = {}", quote_array(&meta.tags));
+            }
+            if let Some(date) = meta.review_after {
+                let _ = writeln!(out, "review_after = \"{date}\"");
+            }
+        }
+        let _ = writeln!(out, "depends_on = {}", quote_array(&cfg.profiles[name]));
+    }
+
+    let mut scenarios: Vec<_> = cfg.scenarios.iter().collect();
+    scenarios.sort_by_key(|(name, _)| *name);
+    for (name, scenario) in scenarios {
+        let _ = writeln!(out, "\n[{SCENARIO_PREFIX}{name}]");
+        let _ = writeln!(out, "profiles = {}", quote_array(&scenario.profiles));
+        let settings = [
+            ("separator", &scenario.separator),
+            ("pre_prompt", &scenario.pre_prompt),
+            ("post_prompt", &scenario.post_prompt),
+        ];
+        for (key, value) in settings {
+            if let Some(value) = value {
+                let _ = writeln!(out, "{key} = {}", quote(value));
+            }
+        }
+    }
+    if let Some(hosts) = &cfg.allowed_hosts {
+        let _ = writeln!(out, "\n[{REMOTE_SECTION}]");
+        let _ = writeln!(out, "allowed_hosts = {}", quote_array(hosts));
+    }
+    out.trim_start().to_string()
+}
+
+/// `rel` as a path that stays inside the directory it is joined to.
+fn portable_path(rel: &str) -> Result<PathBuf, String> {
+    let path = PathBuf::from(rel);
+    let inside = !rel.is_empty()
+        && path
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)));
+    if inside {
+        Ok(path)
+    } else {
+        Err(format!(
+            "'{rel}' is not a relative path inside its directory"
+        ))
+    }
+}
+
+fn read_file(path: &Path) -> Result<String, String> {
+    fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))
+}
+
+/// Bundle `cfg` with every library fragment and every existing `@path`
+/// include its profiles reference.
+///
+/// # Errors
+/// Returns an error if a file cannot be read, or an `@path` include points
+/// outside the configuration directory.
+pub fn export_bundle(cfg: &Config, lib: &Path) -> Result<Bundle, String> {
+    let mut library = BTreeMap::new();
+    for fragment in library_fragments(lib)? {
+        let content = read_file(&lib.join(&fragment))?;
+        library.insert(fragment, content);
+    }
+    let mut includes = BTreeMap::new();
+    for dep in cfg.profiles.values().flatten() {
+        let file = split_line_range(split_optional(dep).0).0;
+        let Some(rel) = file.strip_prefix('@') else {
+            continue;
+        };
+        portable_path(rel).map_err(|e| format!("Cannot bundle include {file}: {e}"))?;
+        let path = fragment_path(file, cfg, lib);
+        if path.is_file() && !includes.contains_key(rel) {
+            includes.insert(rel.to_string(), read_file(&path)?);
+        }
+    }
+    Ok(Bundle {
+        version: BUNDLE_VERSION,
+        config: config_text(cfg),
+        library,
+        includes,
+    })
+}
+
+/// Whether `path` names a JSON bundle (by its `.json` extension).
+fn is_json(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("json"))
+}
+
+/// Write a bundle of the configuration to `output` (JSON for a `.json` path,
+/// TOML otherwise) and report what it holds unless `quiet` is set.
+///
+/// # Errors
+/// Returns an error if the configuration cannot be loaded, a file cannot be
+/// read, or the bundle cannot be serialized or written.
+pub fn run_export(config: &ConfigSource, output: &Path, quiet: bool) -> Result<(), String> {
+    let (cfg, lib) = config.load()?;
+    let bundle = export_bundle(&cfg, &lib)?;
+    let text = if is_json(output) {
+        serde_json::to_string_pretty(&bundle).map_err(|e| format!("JSON error: {e}"))?
+    } else {
+        toml::to_string_pretty(&bundle).map_err(|e| format!("TOML serialization error: {e}"))?
+    };
+    fs::write(output, text).map_err(|e| format!("Failed to write {}: {}", output.display(), e))?;
+    if !quiet {
+        println!(
+            "{}",
+            success_message(&format!(
+                "Exported {} profile(s) and {} file(s) to {}",
+                cfg.profiles.len(),
+                bundle.library.len() + bundle.includes.len(),
+                output.display()
+            ))
+        );
+    }
+    Ok(())
+}
+
+/// Read a bundle written by `export`, in either format.
+///
+/// # Errors
+/// Returns an error if the file cannot be read or parsed, or was written by
+/// an unsupported bundle version.
+pub fn read_bundle(path: &Path) -> Result<Bundle, String> {
+    let text = read_file(path)?;
+    let bundle: Bundle = if is_json(path) {
+        serde_json::from_str(&text)
+            .map_err(|e| format!("Invalid bundle {}: {e}", path.display()))?
+    } else {
+        toml::from_str(&text).map_err(|e| format!("Invalid bundle {}: {e}", path.display()))?
+    };
+    if bundle.version != BUNDLE_VERSION {
+        return Err(format!(
+            "Unsupported bundle version {} in {} (expected {BUNDLE_VERSION})",
+            bundle.version,
+            path.display()
+        ));
+    }
+    Ok(bundle)
+}
+
+/// Create `dir/config.toml`, `dir/library/`, and the bundled `@path` includes
+/// from `bundle`, returning the config path. Nothing is overwritten, and a
+/// failed import removes what it created.
+///
+/// # Errors
+/// Returns an error if the bundled config does not parse, a bundled path
+/// would land outside `dir`, a target file already exists, or writing fails.
+pub fn import_bundle(bundle: &Bundle, dir: &Path) -> Result<PathBuf, String> {
+    crate::parse_config_toml(&bundle.config).map_err(|e| format!("Bundled config: {e}"))?;
+    let cfg_path = dir.join("config.toml");
+    let lib = dir.join("library");
+    let mut files = vec![(cfg_path.clone(), bundle.config.as_str())];
+    for (root, entries) in [(lib.as_path(), &bundle.library), (dir, &bundle.includes)] {
+        for (rel, content) in entries {
+            let rel = portable_path(rel).map_err(|e| format!("Bundle path {e}"))?;
+            files.push((root.join(rel), content.as_str()));
+        }
+    }
+    if let Some((existing, _)) = files.iter().find(|(path, _)| path.exists()) {
+        return Err(format!("{} already exists", existing.display()));
+    }
+
+    let mut scaffold = Scaffold::default();
+    let written = scaffold.create_dir_all(&lib).and_then(|()| {
+        files.iter().try_for_each(|(path, content)| {
+            if let Some(parent) = path.parent() {
+                scaffold.create_dir_all(parent)?;
+            }
+            scaffold.write(path, content)
+        })
+    });
+    if let Err(e) = written {
+        scaffold.roll_back();
+        return Err(e);
+    }
+    Ok(cfg_path)
+}
+
+/// Import the bundle at `path` into `dir` and report where the config went
+/// unless `quiet` is set.
+///
+/// # Errors
+/// Returns an error if the bundle cannot be read or imported.
+pub fn run_import(path: &Path, dir: &Path, quiet: bool) -> Result<(), String> {
+    let bundle = read_bundle(path)?;
+    let cfg_path = import_bundle(&bundle, dir)?;
+    if !quiet {
+        println!(
+            "{}",
+            success_message(&format!(
+                "Imported {} into {} (use --config {})",
+                path.display(),
+                dir.display(),
+                cfg_path.display()
+            ))
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{RenderOptions, render_to_writer};
+
+    fn render(cfg: &Config, lib: &Path, profile: &str) -> String {
+        let mut out = Vec::new();
+        render_to_writer(
+            cfg,
+            lib,
+            &mut out,
+            &[profile.to_string()],
+            &RenderOptions::default(),
+        )
+        .unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn round_trip_renders_the_same() {
+        let root = crate::mk_tmp("prompter_bundle");
+        fs::create_dir_all(&root).unwrap();
+        let lib = root.join("library");
+        fs::create_dir_all(lib.join("python")).unwrap();
+        fs::write(lib.join("base.md"), "BASE \"quoted\" # not a comment\n").unwrap();
+        fs::write(lib.join("python/api.md"), "API\n").unwrap();
+        fs::write(root.join("notes.txt"), "NOTES\n").unwrap();
+        let cfg_path = root.join("config.toml");
+        fs::write(
+            &cfg_path,
+            "post_prompt = \"Done.\\n\"\n\
+             [profiles]\nhello = \"Hi {{name}}\"\n\
+             [base]\ndescription = \"Base rules\"\ntags = [\"core\"]\n\
+             depends_on = [\"base.md\", \"hello\"]\n\
+             [python.api]\nextends = \"base\"\ndepends_on = [\"python/api.md\", \"@notes.txt\"]\n\
+             [scenarios.review]\nprofiles = [\"python.api\"]\nseparator = \"--\"\n",
+        )
+        .unwrap();
+        let (cfg, lib) = ConfigSource::File(cfg_path).load().unwrap();
+
+        let bundle = export_bundle(&cfg, &lib).unwrap();
+        assert_eq!(
+            bundle.library.keys().collect::<Vec<_>>(),
+            ["base.md", "python/api.md"]
+        );
+        assert_eq!(bundle.includes["notes.txt"], "NOTES\n");
+        let text = toml::to_string_pretty(&bundle).unwrap();
+        assert_eq!(toml::from_str::<Bundle>(&text).unwrap(), bundle);
+
+        let target = root.join("imported");
+        let imported = import_bundle(&bundle, &target).unwrap();
+        let (copy, copy_lib) = ConfigSource::File(imported).load().unwrap();
+        assert_eq!(copy.profiles, cfg.profiles);
+        assert_eq!(copy.inline_profiles, cfg.inline_profiles);
+        assert_eq!(copy.scenarios, cfg.scenarios);
+        assert_eq!(copy.post_prompt, cfg.post_prompt);
+        assert_eq!(copy.metadata["base"], cfg.metadata["base"]);
+        assert_eq!(
+            render(&copy, &copy_lib, "python.api"),
+            render(&cfg, &lib, "python.api")
+        );
+
+        let err = import_bundle(&bundle, &target).unwrap_err();
+        assert!(err.contains("already exists"), "{err}");
+    }
+
+    #[test]
+    fn rejects_paths_outside_the_target() {
+        let bundle = Bundle {
+            version: BUNDLE_VERSION,
+            config: String::new(),
+            library: BTreeMap::from([("../escape.md".to_string(), String::new())]),
+            includes: BTreeMap::new(),
+        };
+        let target = crate::mk_tmp("prompter_bundle_escape");
+        fs::create_dir_all(&target).unwrap();
+        let err = import_bundle(&bundle, &target).unwrap_err();
+        assert!(err.contains("'../escape.md'"), "{err}");
+        assert!(!target.join("config.toml").exists());
+        assert!(portable_path("/abs.md").is_err());
+        assert!(portable_path("a/b.md").is_ok());
+    }
+}
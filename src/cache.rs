@@ -0,0 +1,384 @@
+//! On-disk render cache for `run --all`.
+//!
+//! A profile's rendered output is stored under a key hashing everything that
+//! can change it: the render options, the built-in date variables the reached
+//! fragments use, the contents of the pre-prompt, prepend, and append files,
+//! the configuration settings shared by every profile, and the definition and
+//! fragment contents of every profile the render reaches.
+//! Editing any transitive include therefore changes the key, so stale output
+//! is never reused. Entries not rewritten for [`MAX_AGE`] are removed on the
+//! next write, and `--clear-cache` removes them all.
+
+use std::collections::BTreeSet;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::{Duration, SystemTime};
+
+use crate::{
+    Config, RenderOptions, fragment_path, home_dir, is_fragment, plain_system_info,
+    split_line_range, split_optional,
+};
+
+/// Age after which an entry that has not been rewritten is removed.
+pub const MAX_AGE: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// How `run --all` uses the render cache.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CacheMode {
+    /// Reuse cached output and store new renders
+    #[default]
+    Use,
+    /// Render everything without reading or writing the cache (`--no-cache`)
+    Bypass,
+    /// Wipe the cache, then render and store everything (`--clear-cache`)
+    Clear,
+}
+
+/// Location of the render cache.
+///
+/// # Errors
+/// Returns an error if neither `$XDG_CACHE_HOME` nor `$HOME` is set.
+pub fn cache_dir() -> Result<PathBuf, String> {
+    Ok(cache_root()?.join("render"))
+}
+
+/// Directory holding every prompter cache.
+pub(crate) fn cache_root() -> Result<PathBuf, String> {
+    let cache = match env::var_os("XDG_CACHE_HOME").map(PathBuf::from) {
+        Some(dir) if dir.is_absolute() => dir,
+        _ => home_dir()?.join(".cache"),
+    };
+    Ok(cache.join("prompter"))
+}
+
+/// 64-bit FNV-1a, stable across builds so keys survive toolchain upgrades.
+struct Fingerprint(u64);
+
+impl Fingerprint {
+    const fn new() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+
+    /// Feed `bytes`, length-prefixed so adjacent fields cannot run together.
+    fn field(&mut self, bytes: &[u8]) {
+        for byte in (bytes.len() as u64).to_le_bytes().iter().chain(bytes) {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    /// Feed `path` and its `contents`, or a marker when it could not be read.
+    fn file(&mut self, path: &Path, contents: Option<&[u8]>) {
+        self.field(path.to_string_lossy().as_bytes());
+        self.field(contents.unwrap_or(b"\0missing"));
+    }
+}
+
+/// Whether `text` uses the variable `name` inside a `{{ }}` or `{% %}` tag.
+fn uses_variable(text: &str, name: &str) -> bool {
+    let mut rest = text;
+    while let Some(start) = rest.find("{{").into_iter().chain(rest.find("{%")).min() {
+        let tag = &rest[start + 2..];
+        let end = tag.find("}}").into_iter().chain(tag.find("%}")).min();
+        let inside = &tag[..end.unwrap_or(tag.len())];
+        let mut words = inside.split(|c: char| !(c.is_alphanumeric() || c == '_'));
+        if words.any(|word| word == name) {
+            return true;
+        }
+        rest = tag;
+    }
+    false
+}
+
+/// Profiles reachable from `profile` (itself included) and the fragment files
+/// they include, whether or not they exist.
+pub(crate) fn reachable(
+    cfg: &Config,
+    lib: &Path,
+    profile: &str,
+) -> (BTreeSet<String>, BTreeSet<PathBuf>) {
+    let mut reached = BTreeSet::new();
+    let mut pending = vec![profile.to_string()];
+    while let Some(name) = pending.pop() {
+        if reached.insert(name.clone()) {
+            let deps = cfg.profiles.get(&name).into_iter().flatten();
+            let deps = deps.map(|dep| split_optional(dep).0);
+            pending.extend(deps.filter(|dep| !is_fragment(dep)).map(str::to_string));
+        }
+    }
+    let mut files = BTreeSet::new();
+    for name in &reached {
+        for dep in cfg.profiles.get(name).into_iter().flatten() {
+            let dep = split_optional(dep).0;
+            if is_fragment(dep) {
+                files.insert(fragment_path(split_line_range(dep).0, cfg, lib));
+            }
+        }
+    }
+    (reached, files)
+}
+
+/// Cache key for rendering `profile` with `opts`.
+#[must_use]
+pub fn cache_key(cfg: &Config, lib: &Path, profile: &str, opts: &RenderOptions) -> String {
+    let mut hash = Fingerprint::new();
+    hash.field(env!("CARGO_PKG_VERSION").as_bytes());
+    hash.field(profile.as_bytes());
+    let (reached, files) = reachable(cfg, lib, profile);
+    let contents: Vec<Option<Vec<u8>>> = files.iter().map(|path| fs::read(path).ok()).collect();
+    let inline = reached
+        .iter()
+        .filter_map(|name| cfg.inline_profiles.get(name));
+    let texts: Vec<String> = contents
+        .iter()
+        .flatten()
+        .map(|bytes| String::from_utf8_lossy(bytes).into_owned())
+        .chain(inline.cloned())
+        .collect();
+
+    // The built-in date variables change by the day or the second, so only
+    // those a reached fragment uses are part of the key.
+    let mut keyed = opts.clone();
+    keyed
+        .vars
+        .builtin
+        .retain(|name, _| texts.iter().any(|text| uses_variable(text, name)));
+    hash.field(format!("{keyed:?}").as_bytes());
+    hash.field(plain_system_info().as_bytes());
+    let extra_files = opts.prepend_files.iter().chain(&opts.append_files);
+    for path in extra_files.chain(&opts.pre_prompt_files) {
+        hash.file(path, fs::read(path).ok().as_deref());
+    }
+    hash.field(lib.to_string_lossy().as_bytes());
+    // The remote policy decides whether a render may run at all, so a render
+    // cached under a laxer policy is never reused under a stricter one.
+    hash.field(
+        format!(
+            "{:?}",
+            (
+                &cfg.post_prompt,
+                &cfg.comment_prefix,
+                &cfg.base_dir,
+                &cfg.allowed_hosts,
+                cfg.remote_access,
+            )
+        )
+        .as_bytes(),
+    );
+
+    for name in &reached {
+        hash.field(name.as_bytes());
+        let definition = (
+            cfg.profiles.get(name),
+            cfg.inline_profiles.get(name),
+            cfg.metadata.get(name),
+        );
+        hash.field(format!("{definition:?}").as_bytes());
+    }
+    for (path, contents) in files.iter().zip(&contents) {
+        hash.file(path, contents.as_deref());
+    }
+
+    let name: String = profile
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '.' || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    format!("{name}-{:016x}", hash.0)
+}
+
+/// Rendered output stored by key, counting the hits served.
+#[derive(Debug)]
+pub struct RenderCache {
+    dir: PathBuf,
+    hits: AtomicUsize,
+    pruned: AtomicBool,
+}
+
+impl RenderCache {
+    /// A cache stored in `dir` (created on the first write).
+    #[must_use]
+    pub const fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            hits: AtomicUsize::new(0),
+            pruned: AtomicBool::new(false),
+        }
+    }
+
+    /// Cached output for `key`, if any.
+    #[must_use]
+    pub fn get(&self, key: &str) -> Option<Vec<u8>> {
+        let bytes = fs::read(self.dir.join(key)).ok()?;
+        self.hits.fetch_add(1, Ordering::Relaxed);
+        Some(bytes)
+    }
+
+    /// Store `output` under `key`, removing entries older than [`MAX_AGE`] on
+    /// the first write. Failures are ignored: a cache that cannot be written
+    /// only costs a re-render next time.
+    pub fn put(&self, key: &str, output: &[u8]) {
+        if !self.pruned.swap(true, Ordering::Relaxed) {
+            self.prune(MAX_AGE);
+        }
+        let staged = self.dir.join(format!(".{key}.tmp-{}", std::process::id()));
+        let stored = fs::create_dir_all(&self.dir)
+            .and_then(|()| fs::write(&staged, output))
+            .and_then(|()| fs::rename(&staged, self.dir.join(key)));
+        if stored.is_err() {
+            let _ = fs::remove_file(&staged);
+        }
+    }
+
+    /// Remove entries last written more than `max_age` ago.
+    fn prune(&self, max_age: Duration) {
+        let Some(cutoff) = SystemTime::now().checked_sub(max_age) else {
+            return;
+        };
+        let Ok(entries) = fs::read_dir(&self.dir) else {
+            return;
+        };
+        for entry in entries.flatten() {
+            let modified = entry.metadata().and_then(|meta| meta.modified());
+            if modified.is_ok_and(|time| time < cutoff) {
+                let _ = fs::remove_file(entry.path());
+            }
+        }
+    }
+
+    /// Remove every cached entry.
+    ///
+    /// # Errors
+    /// Returns an error if the cache directory exists but cannot be removed.
+    pub fn clear(&self) -> Result<(), String> {
+        if !self.dir.exists() {
+            return Ok(());
+        }
+        fs::remove_dir_all(&self.dir)
+            .map_err(|e| format!("Failed to clear cache {}: {}", self.dir.display(), e))
+    }
+
+    /// Number of renders served from the cache so far.
+    #[must_use]
+    pub fn hits(&self) -> usize {
+        self.hits.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn key_changes_with_transitive_includes_only() {
+        let lib = crate::mk_tmp("prompter_cache_key");
+        fs::create_dir_all(&lib).unwrap();
+        fs::write(lib.join("deep.md"), b"one").unwrap();
+        fs::write(lib.join("other.md"), b"x").unwrap();
+        let cfg = Config {
+            profiles: HashMap::from([
+                ("base".into(), vec!["deep.md".into()]),
+                ("top".into(), vec!["base".into()]),
+                ("other".into(), vec!["other.md".into()]),
+            ]),
+            ..Config::default()
+        };
+        let opts = RenderOptions::default();
+        let key = cache_key(&cfg, &lib, "top", &opts);
+        assert!(key.starts_with("top-"));
+        assert_eq!(key, cache_key(&cfg, &lib, "top", &opts));
+
+        fs::write(lib.join("other.md"), b"y").unwrap();
+        assert_eq!(key, cache_key(&cfg, &lib, "top", &opts));
+
+        fs::write(lib.join("deep.md"), b"two").unwrap();
+        let changed = cache_key(&cfg, &lib, "top", &opts);
+        assert_ne!(key, changed);
+
+        let opts = RenderOptions {
+            separator: Some("--".into()),
+            ..RenderOptions::default()
+        };
+        assert_ne!(changed, cache_key(&cfg, &lib, "top", &opts));
+
+        let cache = RenderCache::new(lib.join("cache"));
+        assert_eq!(cache.get(&changed), None);
+        cache.put(&changed, b"rendered");
+        assert_eq!(cache.get(&changed).as_deref(), Some(&b"rendered"[..]));
+        assert_eq!(cache.hits(), 1);
+        cache.clear().unwrap();
+        assert_eq!(cache.get(&changed), None);
+    }
+
+    #[test]
+    fn key_ignores_date_variables_fragments_do_not_use() {
+        let lib = crate::mk_tmp("prompter_cache_dates");
+        fs::create_dir_all(&lib).unwrap();
+        fs::write(lib.join("plain.md"), b"Now, read this.").unwrap();
+        fs::write(lib.join("stamped.md"), b"Generated {{ now }}").unwrap();
+        let cfg = Config {
+            profiles: HashMap::from([
+                ("plain".into(), vec!["plain.md".into()]),
+                ("stamped".into(), vec!["stamped.md".into()]),
+            ]),
+            ..Config::default()
+        };
+        let at = |second| {
+            let now = chrono::TimeZone::with_ymd_and_hms(&chrono::Local, 2024, 3, 9, 14, 5, second);
+            let mut opts = RenderOptions::default();
+            opts.vars.builtin = crate::vars::Variables::date_builtins(now.unwrap(), "%Y-%m-%d");
+            opts
+        };
+        let key = |profile, second| cache_key(&cfg, &lib, profile, &at(second));
+        assert_eq!(key("plain", 0), key("plain", 30));
+        assert_ne!(key("stamped", 0), key("stamped", 30));
+        assert!(uses_variable("{% if year %}x{% endif %}", "year"));
+        assert!(!uses_variable("{{nowhere}} now", "now"));
+    }
+
+    #[test]
+    fn key_changes_with_pre_prompt_files() {
+        let lib = crate::mk_tmp("prompter_cache_pre_prompt");
+        fs::create_dir_all(&lib).unwrap();
+        fs::write(lib.join("a.md"), b"A").unwrap();
+        fs::write(lib.join("role.md"), b"You review code.").unwrap();
+        let cfg = Config {
+            profiles: HashMap::from([("p".into(), vec!["a.md".into()])]),
+            ..Config::default()
+        };
+        let opts = RenderOptions {
+            pre_prompt_files: vec![lib.join("role.md")],
+            ..RenderOptions::default()
+        };
+        let key = cache_key(&cfg, &lib, "p", &opts);
+        let cache = RenderCache::new(lib.join("cache"));
+        cache.put(&key, b"rendered");
+
+        fs::write(lib.join("role.md"), b"You write docs.").unwrap();
+        let edited = cache_key(&cfg, &lib, "p", &opts);
+        assert_ne!(key, edited);
+        assert_eq!(cache.get(&edited), None);
+    }
+
+    #[test]
+    fn first_write_prunes_old_entries() {
+        let dir = crate::mk_tmp("prompter_cache_prune");
+        fs::create_dir_all(&dir).unwrap();
+        let old = fs::File::create(dir.join("old")).unwrap();
+        old.set_modified(SystemTime::now() - MAX_AGE - Duration::from_secs(60))
+            .unwrap();
+        fs::write(dir.join("recent"), b"kept").unwrap();
+        let cache = RenderCache::new(dir.clone());
+        cache.put("new", b"rendered");
+        assert!(!dir.join("old").exists());
+        assert!(dir.join("recent").exists() && dir.join("new").exists());
+    }
+}
@@ -2,31 +2,100 @@
 //!
 //! This module wraps `clap_complete` output and augments it so that the
 //! `prompter run` subcommand (and the top-level shorthand) offer dynamic
-//! profile completions sourced from the active configuration.
+//! profile completions sourced from the active configuration, and scenario
+//! names after `run --scenario`. With `--eager`, the profile and scenario
+//! names are instead baked into the script when it is generated.
 
 use clap::CommandFactory;
 use clap_complete::Shell;
+use std::env;
+use std::fmt::Write as _;
 use std::io::{self, Write};
+use std::path::Path;
 
-use crate::Cli;
+use crate::{Cli, ConfigSource};
+
+/// Placeholder in the helper templates replaced by the command used to list profiles.
+const LIST_COMMAND_PLACEHOLDER: &str = "__PROMPTER_COMMAND__";
+
+/// Placeholder in the static helper templates replaced by quoted profile names.
+const PROFILE_NAMES_PLACEHOLDER: &str = "__PROMPTER_PROFILES__";
+
+/// Placeholder in the static helper templates replaced by quoted scenario names.
+const SCENARIO_NAMES_PLACEHOLDER: &str = "__PROMPTER_SCENARIOS__";
+
+/// Placeholder in the bash case blocks replaced by the options `clap_complete`
+/// generated for the same block, so they track the CLI definition.
+const OPTIONS_PLACEHOLDER: &str = "__PROMPTER_OPTS__";
+
+/// Where a generated script gets profile and scenario names from.
+enum ProfileList<'a> {
+    /// Run this command's `list` subcommand at completion time
+    Command(&'a str),
+    /// Fixed lists captured when the script was generated
+    Names {
+        profiles: &'a [String],
+        scenarios: &'a [String],
+    },
+}
 
 /// Generate shell completion script for the requested shell and write it to stdout.
 ///
+/// The dynamic helpers invoke `dynamic_command list` to fetch profile names. When
+/// no override is given, the name the binary was invoked as is used so renamed
+/// installs keep working.
+///
+/// # Panics
+/// Panics if the generated completion script is not valid UTF-8 or if writing to `stdout` fails.
+pub fn generate(shell: Shell, dynamic_command: Option<&str>) {
+    let bin_name = Cli::command().get_name().to_string();
+    let list_command = dynamic_command.map_or_else(|| invoked_name(&bin_name), ToString::to_string);
+    let instructions = render_instructions(shell, &bin_name);
+    write_script(shell, &instructions, &ProfileList::Command(&list_command));
+}
+
+/// Generate a completion script with the profile and scenario names of
+/// `config` baked in as static lists and write it to stdout.
+///
+/// The script never runs `prompter`, so it keeps working where spawning the
+/// binary is slow or not allowed, but it must be regenerated whenever
+/// profiles are added, renamed, or removed.
+///
+/// # Errors
+/// Returns an error if the configuration cannot be loaded.
+///
 /// # Panics
 /// Panics if the generated completion script is not valid UTF-8 or if writing to `stdout` fails.
-pub fn generate(shell: Shell) {
+pub fn generate_eager(shell: Shell, config: &ConfigSource) -> Result<(), String> {
+    let (cfg, _) = config.load()?;
+    let mut names: Vec<String> = cfg.profiles.into_keys().collect();
+    names.sort();
+    let mut scenarios: Vec<String> = cfg.scenarios.into_keys().collect();
+    scenarios.sort();
+    let bin_name = Cli::command().get_name().to_string();
+    let instructions = format!(
+        "{}# Profile names are baked in; regenerate this script after changing profiles.\n\n",
+        render_instructions(shell, &bin_name)
+    );
+    let profiles = ProfileList::Names {
+        profiles: &names,
+        scenarios: &scenarios,
+    };
+    write_script(shell, &instructions, &profiles);
+    Ok(())
+}
+
+fn write_script(shell: Shell, instructions: &str, profiles: &ProfileList) {
     let mut cmd = Cli::command();
     let bin_name = cmd.get_name().to_string();
-
-    let instructions = render_instructions(shell, &bin_name);
     let mut buffer = Vec::new();
-    clap_complete::generate(shell, &mut cmd, bin_name, &mut buffer);
+    clap_complete::generate(shell, &mut cmd, bin_name.as_str(), &mut buffer);
     let mut script = String::from_utf8(buffer).expect("clap_complete output must be valid UTF-8");
 
     match shell {
-        Shell::Bash => augment_bash(&mut script),
-        Shell::Zsh => augment_zsh(&mut script),
-        Shell::Fish => augment_fish(&mut script),
+        Shell::Bash => augment_bash(&mut script, profiles),
+        Shell::Zsh => augment_zsh(&mut script, profiles),
+        Shell::Fish => augment_fish(&mut script, profiles, &bin_name),
         _ => {}
     }
 
@@ -39,6 +108,19 @@ pub fn generate(shell: Shell) {
         .expect("failed to write completion script");
 }
 
+/// Name the binary was invoked as, falling back to `default` when unavailable.
+fn invoked_name(default: &str) -> String {
+    env::args_os()
+        .next()
+        .and_then(|arg0| {
+            Path::new(&arg0)
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+        })
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| default.to_string())
+}
+
 fn render_instructions(shell: Shell, bin_name: &str) -> String {
     match shell {
         Shell::Bash => format!(
@@ -62,9 +144,32 @@ fn render_instructions(shell: Shell, bin_name: &str) -> String {
     }
 }
 
-fn augment_bash(script: &mut String) {
+/// Fill a helper template: the list command for dynamic helpers, or the
+/// single-quoted profile and scenario names for static ones.
+fn helpers(dynamic: &str, fixed: &str, profiles: &ProfileList) -> String {
+    match profiles {
+        ProfileList::Command(command) => dynamic.replace(LIST_COMMAND_PLACEHOLDER, command),
+        ProfileList::Names {
+            profiles,
+            scenarios,
+        } => fixed
+            .replace(PROFILE_NAMES_PLACEHOLDER, &quoted(profiles))
+            .replace(SCENARIO_NAMES_PLACEHOLDER, &quoted(scenarios)),
+    }
+}
+
+/// `names` single-quoted for the shell and joined with spaces.
+fn quoted(names: &[String]) -> String {
+    let quoted: Vec<String> = names
+        .iter()
+        .map(|name| format!("'{}'", name.replace('\'', r"'\''")))
+        .collect();
+    quoted.join(" ")
+}
+
+fn augment_bash(script: &mut String, profiles: &ProfileList) {
     const ROOT_REPLACEMENT: &str = r#"        prompter)
-            opts="-s -p -P -c -h -V --separator --pre-prompt --post-prompt --config --help --version version license init list validate run completions doctor update help"
+            opts="__PROMPTER_OPTS__"
             if [[ ${cur} == -* ]]; then
                 COMPREPLY=( $(compgen -W "${opts}" -- "${cur}") )
                 return 0
@@ -74,6 +179,10 @@ fn augment_bash(script: &mut String) {
                     COMPREPLY=( $(compgen -f -- "${cur}") )
                     return 0
                     ;;
+                --color)
+                    COMPREPLY=( $(compgen -W "auto always never" -- "${cur}") )
+                    return 0
+                    ;;
                 --separator|-s|--pre-prompt|-p|--post-prompt|-P)
                     return 0
                     ;;
@@ -88,7 +197,7 @@ fn augment_bash(script: &mut String) {
             ;;"#;
 
     const RUN_REPLACEMENT: &str = r#"        prompter__run)
-            opts="-s -p -P -c -h --separator --pre-prompt --post-prompt --config --help"
+            opts="__PROMPTER_OPTS__"
             if [[ ${cur} == -* ]]; then
                 COMPREPLY=( $(compgen -W "${opts}" -- "${cur}") )
                 return 0
@@ -98,6 +207,10 @@ fn augment_bash(script: &mut String) {
                     COMPREPLY=( $(compgen -f -- "${cur}") )
                     return 0
                     ;;
+                --scenario)
+                    COMPREPLY=( $(compgen -W "$(__prompter_bash_list_scenarios)" -- "${cur}") )
+                    return 0
+                    ;;
                 --separator|-s|--pre-prompt|-p|--post-prompt|-P)
                     return 0
                     ;;
@@ -109,17 +222,39 @@ fn augment_bash(script: &mut String) {
             return 0
             ;;"#;
 
+    const META_REPLACEMENT: &str = r#"        prompter__meta)
+            opts="__PROMPTER_OPTS__"
+            if [[ ${cur} == -* ]]; then
+                COMPREPLY=( $(compgen -W "${opts}" -- "${cur}") )
+                return 0
+            fi
+            case "${prev}" in
+                --config|-c)
+                    COMPREPLY=( $(compgen -f -- "${cur}") )
+                    return 0
+                    ;;
+            esac
+            local profiles="$(__prompter_bash_list_profiles)"
+            if [[ -n ${profiles} ]]; then
+                COMPREPLY=( $(compgen -W "${profiles}" -- "${cur}") )
+            fi
+            return 0
+            ;;"#;
+
     replace_case_block(script, "prompter", ROOT_REPLACEMENT);
     replace_case_block(script, "prompter__run", RUN_REPLACEMENT);
+    replace_case_block(script, "prompter__meta", META_REPLACEMENT);
 
-    script.push_str(BASH_HELPERS);
+    script.push_str(&helpers(BASH_HELPERS, BASH_STATIC_HELPERS, profiles));
 }
 
-fn augment_zsh(script: &mut String) {
+fn augment_zsh(script: &mut String, profiles: &ProfileList) {
     // With Vec<String>, clap generates '*::profiles' variadic patterns
     const ROOT_MARKER: &str =
         "::profile -- Profile to render (shorthand for 'run `<profile>`'):_default";
     const RUN_MARKER_VARIADIC: &str = "*::profiles -- Profile name(s) to render:_default";
+    const SCENARIO_OPTION: &str = "--scenario=[";
+    const SCENARIO_ACTION: &str = ":NAME:_default";
 
     // Update root shorthand profile completion
     if let Some(start) = script.find(ROOT_MARKER) {
@@ -137,11 +272,33 @@ fn augment_zsh(script: &mut String) {
         );
     }
 
-    script.push_str(ZSH_HELPERS);
+    // Update run --scenario value completion
+    if let Some(option) = script.find(SCENARIO_OPTION) {
+        if let Some(offset) = script[option..].find(SCENARIO_ACTION) {
+            let start = option + offset;
+            script.replace_range(
+                start..start + SCENARIO_ACTION.len(),
+                ":NAME:_prompter_dynamic_scenarios",
+            );
+        }
+    }
+
+    script.push_str(&helpers(ZSH_HELPERS, ZSH_STATIC_HELPERS, profiles));
 }
 
-fn augment_fish(script: &mut String) {
-    script.push_str(FISH_HELPERS);
+fn augment_fish(script: &mut String, profiles: &ProfileList, bin_name: &str) {
+    script.push_str(&helpers(FISH_HELPERS, FISH_STATIC_HELPERS, profiles));
+    script.push_str(FISH_PROFILE_HELPERS);
+    // Named the way clap_complete names its condition helpers for `bin_name`
+    let fish_name = bin_name.replace('-', "_");
+    let needs_command = format!("__fish_{fish_name}_needs_command");
+    let using_run = format!("__fish_{fish_name}_using_subcommand run");
+    let _ = write!(
+        script,
+        "\ncomplete -c {bin_name} -n \"{needs_command}\" -f -a \"(__fish_prompter__profiles)\" -d 'Profile'\n\
+         complete -c {bin_name} -n \"{using_run}\" -f -a \"(__fish_prompter__profiles)\" -d 'Profile'\n\
+         complete -c {bin_name} -n \"{using_run}\" -l scenario -x -a \"(__fish_prompter__scenarios)\" -d 'Scenario'\n"
+    );
 }
 
 fn replace_case_block(script: &mut String, label: &str, replacement: &str) {
@@ -154,7 +311,24 @@ fn replace_case_block(script: &mut String, label: &str, replacement: &str) {
         .find("\n            ;;\n")
         .unwrap_or_else(|| panic!("expected terminator for {label} block"));
     let end = start + end_offset + "\n            ;;\n".len();
-    script.replace_range(start..end, replacement);
+    let opts = generated_opts(&script[start..end]);
+    let replacement = replacement.replace(OPTIONS_PLACEHOLDER, &opts);
+    script.replace_range(start..end, &format!("{replacement}\n"));
+}
+
+/// Flags and subcommand names of the `opts="..."` line in a generated bash
+/// case block, without positional placeholders such as `[PROFILE]`.
+fn generated_opts(block: &str) -> String {
+    let line = block
+        .lines()
+        .find_map(|line| line.trim_start().strip_prefix("opts=\""))
+        .and_then(|rest| rest.strip_suffix('"'))
+        .unwrap_or_default();
+    let opts: Vec<&str> = line
+        .split_whitespace()
+        .filter(|word| !word.starts_with(['[', '<']))
+        .collect();
+    opts.join(" ")
 }
 
 const BASH_HELPERS: &str = r#"
@@ -178,13 +352,33 @@ __prompter_bash_config_value() {
 __prompter_bash_list_profiles() {
     local cfg="$(__prompter_bash_config_value)"
     if [[ -n "${cfg}" ]]; then
-        prompter list --config "${cfg}" 2>/dev/null
+        __PROMPTER_COMMAND__ list --config "${cfg}" 2>/dev/null
+    else
+        __PROMPTER_COMMAND__ list 2>/dev/null
+    fi
+}
+
+__prompter_bash_list_scenarios() {
+    local cfg="$(__prompter_bash_config_value)"
+    if [[ -n "${cfg}" ]]; then
+        __PROMPTER_COMMAND__ list --scenarios --config "${cfg}" 2>/dev/null
     else
-        prompter list 2>/dev/null
+        __PROMPTER_COMMAND__ list --scenarios 2>/dev/null
     fi
 }
 "#;
 
+const BASH_STATIC_HELPERS: &str = r"
+# Static profile list baked in by prompter completions --eager.
+__prompter_bash_list_profiles() {
+    printf '%s\n' __PROMPTER_PROFILES__
+}
+
+__prompter_bash_list_scenarios() {
+    printf '%s\n' __PROMPTER_SCENARIOS__
+}
+";
+
 const ZSH_HELPERS: &str = r#"
 _prompter_config_value() {
     local idx=1
@@ -207,18 +401,50 @@ _prompter_dynamic_profiles() {
     local cfg=$(_prompter_config_value)
     local -a profiles
     if [[ -n ${cfg} ]]; then
-        profiles=(${(f)"$(prompter list --config ${cfg:q} 2>/dev/null)"})
+        profiles=(${(f)"$(__PROMPTER_COMMAND__ list --config ${cfg:q} 2>/dev/null)"})
     else
-        profiles=(${(f)"$(prompter list 2>/dev/null)"})
+        profiles=(${(f)"$(__PROMPTER_COMMAND__ list 2>/dev/null)"})
     fi
     if (( ${#profiles} )); then
-        compadd -a profiles
+        # Complete slash-delimited namespaces one segment at a time
+        _multi_parts / profiles
         return 0
     fi
     return 1
 }
+
+_prompter_dynamic_scenarios() {
+    local cfg=$(_prompter_config_value)
+    local -a scenarios
+    if [[ -n ${cfg} ]]; then
+        scenarios=(${(f)"$(__PROMPTER_COMMAND__ list --scenarios --config ${cfg:q} 2>/dev/null)"})
+    else
+        scenarios=(${(f)"$(__PROMPTER_COMMAND__ list --scenarios 2>/dev/null)"})
+    fi
+    _describe -t scenarios 'scenario' scenarios
+}
 "#;
 
+const ZSH_STATIC_HELPERS: &str = r"
+# Static profile list baked in by prompter completions --eager.
+_prompter_dynamic_profiles() {
+    local -a profiles
+    profiles=(__PROMPTER_PROFILES__)
+    if (( ${#profiles} )); then
+        # Complete slash-delimited namespaces one segment at a time
+        _multi_parts / profiles
+        return 0
+    fi
+    return 1
+}
+
+_prompter_dynamic_scenarios() {
+    local -a scenarios
+    scenarios=(__PROMPTER_SCENARIOS__)
+    _describe -t scenarios 'scenario' scenarios
+}
+";
+
 const FISH_HELPERS: &str = r#"
 function __fish_prompter__config_arg
 	set -l tokens (commandline -opc)
@@ -241,17 +467,52 @@ function __fish_prompter__config_arg
 	end
 end
 
-function __fish_prompter__profiles
+function __fish_prompter__all_profiles
+	set -l cfg (__fish_prompter__config_arg)
+	if test -n "$cfg"
+		__PROMPTER_COMMAND__ list --config "$cfg" 2>/dev/null
+	else
+		__PROMPTER_COMMAND__ list 2>/dev/null
+	end
+end
+
+function __fish_prompter__scenarios
 	set -l cfg (__fish_prompter__config_arg)
 	if test -n "$cfg"
-		prompter list --config "$cfg" 2>/dev/null
+		__PROMPTER_COMMAND__ list --scenarios --config "$cfg" 2>/dev/null
 	else
-		prompter list 2>/dev/null
+		__PROMPTER_COMMAND__ list --scenarios 2>/dev/null
 	end
 end
+"#;
+
+const FISH_STATIC_HELPERS: &str = r"
+# Static profile list baked in by prompter completions --eager.
+function __fish_prompter__all_profiles
+	printf '%s\n' __PROMPTER_PROFILES__
+end
 
-complete -c prompter -n "__fish_prompter_needs_command" -f -a "(__fish_prompter__profiles)" -d 'Profile'
-complete -c prompter -n "__fish_prompter_using_subcommand run" -f -a "(__fish_prompter__profiles)" -d 'Profile'
+function __fish_prompter__scenarios
+	printf '%s\n' __PROMPTER_SCENARIOS__
+end
+";
+
+const FISH_PROFILE_HELPERS: &str = r#"
+# Offer the next slash-delimited namespace segment rather than the flat list
+function __fish_prompter__profiles
+	set -l token (commandline -ct)
+	set -l prefix (string replace -r '[^/]*$' '' -- $token)
+	set -l skip (math (string length -- "$prefix") + 1)
+	for profile in (__fish_prompter__all_profiles)
+		string match -q -- "$prefix*" $profile; or continue
+		set -l rest (string sub -s $skip -- $profile)
+		if string match -q -- '*/*' $rest
+			echo $prefix(string split -m1 / -- $rest)[1]/
+		else
+			echo $profile
+		end
+	end | sort -u
+end
 "#;
 
 #[cfg(test)]
@@ -269,7 +530,7 @@ mod tests {
     #[test]
     fn bash_augmentation_injects_dynamic_helpers() {
         let mut script = raw_script(Shell::Bash);
-        augment_bash(&mut script);
+        augment_bash(&mut script, &ProfileList::Command("prompter"));
         assert!(script.contains("__prompter_bash_list_profiles"));
         assert!(script.contains("prompter list --config"));
         assert!(
@@ -278,10 +539,37 @@ mod tests {
         );
     }
 
+    #[test]
+    fn bash_options_follow_the_cli_definition() {
+        let mut script = raw_script(Shell::Bash);
+        augment_bash(&mut script, &ProfileList::Command("prompter"));
+        assert!(!script.contains(OPTIONS_PLACEHOLDER));
+        let cmd = Cli::command();
+        for (label, sub) in [("prompter__run", "run"), ("prompter__meta", "meta")] {
+            let start = script.find(&format!("        {label})")).unwrap();
+            let opts = script[start..].lines().nth(1).unwrap();
+            let sub = cmd.find_subcommand(sub).unwrap();
+            for long in sub.get_arguments().filter_map(clap::Arg::get_long) {
+                assert!(
+                    opts.split(['"', ' ']).any(|w| w == format!("--{long}")),
+                    "{label} completions miss --{long}"
+                );
+            }
+        }
+        for flag in [
+            "--fail-fast",
+            "--keep-going",
+            "--pre-prompt-file",
+            "--encoding",
+        ] {
+            assert!(script.contains(flag), "{flag}");
+        }
+    }
+
     #[test]
     fn zsh_augmentation_redirects_profile_completion() {
         let mut script = raw_script(Shell::Zsh);
-        augment_zsh(&mut script);
+        augment_zsh(&mut script, &ProfileList::Command("prompter"));
 
         // Verify the dynamic profile completion function is present
         assert!(script.contains("_prompter_dynamic_profiles"));
@@ -296,8 +584,102 @@ mod tests {
     #[test]
     fn fish_augmentation_appends_profile_commands() {
         let mut script = raw_script(Shell::Fish);
-        augment_fish(&mut script);
+        augment_fish(&mut script, &ProfileList::Command("prompter"), "prompter");
         assert!(script.contains("__fish_prompter__profiles"));
         assert!(script.contains("prompter list --config"));
     }
+
+    #[test]
+    fn fish_profile_completions_use_the_binary_name() {
+        let mut script = String::new();
+        augment_fish(
+            &mut script,
+            &ProfileList::Command("my-prompter"),
+            "my-prompter",
+        );
+        assert!(script.contains(
+            "complete -c my-prompter -n \"__fish_my_prompter_needs_command\" -f -a \"(__fish_prompter__profiles)\""
+        ));
+        assert!(script.contains(
+            "complete -c my-prompter -n \"__fish_my_prompter_using_subcommand run\" -l scenario"
+        ));
+        assert!(!script.contains("complete -c prompter"));
+    }
+
+    #[test]
+    fn namespace_completion_groups_by_slash() {
+        let mut script = raw_script(Shell::Zsh);
+        augment_zsh(&mut script, &ProfileList::Command("prompter"));
+        assert!(script.contains("_multi_parts / profiles"));
+
+        let mut script = raw_script(Shell::Fish);
+        augment_fish(&mut script, &ProfileList::Command("prompter"), "prompter");
+        assert!(script.contains("function __fish_prompter__all_profiles"));
+        assert!(script.contains("string split -m1 / -- $rest"));
+    }
+
+    #[test]
+    fn helpers_invoke_the_requested_command() {
+        let mut script = raw_script(Shell::Bash);
+        augment_bash(&mut script, &ProfileList::Command("my-prompter"));
+        assert!(script.contains("my-prompter list --config"));
+        assert!(!script.contains(LIST_COMMAND_PLACEHOLDER));
+
+        let mut script = raw_script(Shell::Zsh);
+        augment_zsh(&mut script, &ProfileList::Command("my-prompter"));
+        assert!(script.contains("$(my-prompter list 2>/dev/null)"));
+
+        let mut script = raw_script(Shell::Fish);
+        augment_fish(
+            &mut script,
+            &ProfileList::Command("my-prompter"),
+            "prompter",
+        );
+        assert!(script.contains("\t\tmy-prompter list 2>/dev/null"));
+        assert!(!script.contains(LIST_COMMAND_PLACEHOLDER));
+    }
+
+    #[test]
+    fn scenario_completion_lists_scenarios() {
+        let mut script = raw_script(Shell::Bash);
+        augment_bash(&mut script, &ProfileList::Command("prompter"));
+        assert!(script.contains("--scenario)\n"));
+        assert!(script.contains("prompter list --scenarios --config"));
+
+        let mut script = raw_script(Shell::Zsh);
+        augment_zsh(&mut script, &ProfileList::Command("prompter"));
+        assert!(script.contains(":NAME:_prompter_dynamic_scenarios'"));
+        assert!(script.contains("$(prompter list --scenarios 2>/dev/null)"));
+
+        let mut script = raw_script(Shell::Fish);
+        augment_fish(&mut script, &ProfileList::Command("prompter"), "prompter");
+        assert!(script.contains("-l scenario -x -a \"(__fish_prompter__scenarios)\""));
+        assert!(script.contains("prompter list --scenarios --config"));
+    }
+
+    #[test]
+    fn eager_scripts_embed_profile_names() {
+        let names = ["python.api".to_string(), "it's".to_string()];
+        let scenarios = ["review".to_string()];
+        let profiles = ProfileList::Names {
+            profiles: &names,
+            scenarios: &scenarios,
+        };
+        for (shell, augment) in [
+            (Shell::Bash, augment_bash as fn(&mut String, &ProfileList)),
+            (Shell::Zsh, augment_zsh),
+            (Shell::Fish, |script, profiles| {
+                augment_fish(script, profiles, "prompter");
+            }),
+        ] {
+            let mut script = raw_script(shell);
+            augment(&mut script, &profiles);
+            assert!(script.contains(r"'python.api' 'it'\''s'"), "{shell}");
+            assert!(script.contains("'review'"), "{shell}");
+            assert!(!script.contains(" list 2>/dev/null"), "{shell}");
+            assert!(!script.contains(" list --scenarios"), "{shell}");
+            assert!(!script.contains(PROFILE_NAMES_PLACEHOLDER), "{shell}");
+            assert!(!script.contains(SCENARIO_NAMES_PLACEHOLDER), "{shell}");
+        }
+    }
 }
@@ -0,0 +1,87 @@
+//! Comparing a render with an expected copy (`run --diff-against PATH`).
+//!
+//! Text is compared line by line. A missing final newline is not a
+//! difference: `"text"` and `"text\n"` match, so golden files saved by editors
+//! that add (or strip) the last newline still compare equal. Any other
+//! difference, extra trailing blank lines included, is reported as a unified
+//! diff.
+
+use std::borrow::Cow;
+use std::fs;
+use std::path::Path;
+
+/// Lines of context around each change.
+const CONTEXT_LINES: usize = 3;
+
+/// `text` ending in a newline, unless it is empty.
+fn with_final_newline(text: &str) -> Cow<'_, str> {
+    if text.is_empty() || text.ends_with('\n') {
+        Cow::Borrowed(text)
+    } else {
+        Cow::Owned(format!("{text}\n"))
+    }
+}
+
+/// Unified diff from `expected` to `actual`, labelled with the given names, or
+/// `None` if they match.
+#[must_use]
+pub fn unified_diff(
+    expected_label: &str,
+    expected: &str,
+    actual_label: &str,
+    actual: &str,
+) -> Option<String> {
+    let expected = with_final_newline(expected);
+    let actual = with_final_newline(actual);
+    if expected == actual {
+        return None;
+    }
+    let diff = similar::TextDiff::from_lines(expected.as_ref(), actual.as_ref());
+    Some(
+        diff.unified_diff()
+            .context_radius(CONTEXT_LINES)
+            .header(expected_label, actual_label)
+            .to_string(),
+    )
+}
+
+/// Compare `rendered` with the contents of `path`, returning the unified diff
+/// when they differ.
+///
+/// # Errors
+/// Returns an error if `path` cannot be read.
+pub fn diff_against_file(path: &Path, rendered: &[u8]) -> Result<Option<String>, String> {
+    let expected =
+        fs::read(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    Ok(unified_diff(
+        &path.display().to_string(),
+        &String::from_utf8_lossy(&expected),
+        "rendered",
+        &String::from_utf8_lossy(rendered),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn final_newline_is_not_a_difference() {
+        assert_eq!(unified_diff("a", "one\ntwo", "b", "one\ntwo\n"), None);
+        assert_eq!(unified_diff("a", "", "b", ""), None);
+        assert!(unified_diff("a", "one\n", "b", "one\n\n").is_some());
+        assert!(unified_diff("a", "", "b", "\n").is_some());
+
+        let diff = unified_diff(
+            "golden.md",
+            "one\ntwo\nthree\n",
+            "rendered",
+            "one\n2\nthree",
+        )
+        .unwrap();
+        assert_eq!(
+            diff,
+            "--- golden.md\n+++ rendered\n@@ -1,3 +1,3 @@\n one\n-two\n+2\n three\n"
+        );
+    }
+}
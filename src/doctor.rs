@@ -1,50 +1,238 @@
 //! Health check and diagnostics module.
 
+use colored::Colorize;
+use prompter::templates::DEFAULT_TEMPLATE;
+use prompter::{
+    ConfigSource, JsonLayout, ValidateOptions, duplicate_dependencies, init_scaffold, parse_config,
+    validate,
+};
 use serde::Serialize;
-use std::path::Path;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
 
 /// JSON output structure for doctor command
 #[derive(Debug, Serialize)]
 struct DoctorOutput {
     config_file_exists: bool,
-    config_valid_toml: bool,
+    config_valid: bool,
+    library_directory: PathBuf,
     library_directory_exists: bool,
+    editor: Option<String>,
     version: String,
     errors: Vec<String>,
     warnings: Vec<String>,
 }
 
+const EDITOR_WARNING: &str = "Neither $VISUAL nor $EDITOR is set; editing profiles needs one";
+
+/// Editor from `$VISUAL` or `$EDITOR`, preferring `$VISUAL`; empty values count as unset.
+fn configured_editor() -> Option<String> {
+    editor_from(std::env::var("VISUAL").ok(), std::env::var("EDITOR").ok())
+}
+
+fn editor_from(visual: Option<String>, editor: Option<String>) -> Option<String> {
+    visual
+        .filter(|v| !v.trim().is_empty())
+        .or_else(|| editor.filter(|e| !e.trim().is_empty()))
+}
+
+/// Where a shell's completion script can be installed, or sourced from.
+struct CompletionSetup {
+    /// Script files the shell loads completions from
+    scripts: Vec<PathBuf>,
+    /// Startup files that may source `prompter completions <shell>` directly
+    startup_files: Vec<PathBuf>,
+}
+
+impl CompletionSetup {
+    /// Known locations for `shell` (bash, zsh, or fish) under `home`, plus the
+    /// usual system-wide directories; `None` for shells that are not checked.
+    fn for_shell(shell: &str, home: &Path) -> Option<Self> {
+        let (user, system, startup): (&[&str], &[&str], &[&str]) = match shell {
+            "bash" => (
+                &[
+                    ".local/share/bash-completion/completions/prompter",
+                    ".bash_completion.d/prompter",
+                ],
+                &[
+                    "/etc/bash_completion.d/prompter",
+                    "/usr/share/bash-completion/completions/prompter",
+                    "/usr/local/etc/bash_completion.d/prompter",
+                    "/opt/homebrew/etc/bash_completion.d/prompter",
+                ],
+                &[".bashrc", ".bash_profile", ".profile"],
+            ),
+            "zsh" => (
+                &[".zsh/completions/_prompter", ".zfunc/_prompter"],
+                &[
+                    "/usr/local/share/zsh/site-functions/_prompter",
+                    "/usr/share/zsh/site-functions/_prompter",
+                    "/opt/homebrew/share/zsh/site-functions/_prompter",
+                ],
+                &[".zshrc"],
+            ),
+            "fish" => (
+                &[".config/fish/completions/prompter.fish"],
+                &[
+                    "/usr/share/fish/vendor_completions.d/prompter.fish",
+                    "/usr/local/share/fish/vendor_completions.d/prompter.fish",
+                ],
+                &[".config/fish/config.fish"],
+            ),
+            _ => return None,
+        };
+        Some(Self {
+            scripts: user
+                .iter()
+                .map(|p| home.join(p))
+                .chain(system.iter().map(PathBuf::from))
+                .collect(),
+            startup_files: startup.iter().map(|p| home.join(p)).collect(),
+        })
+    }
+
+    /// Whether a script exists or a startup file runs `prompter completions`.
+    fn installed(&self) -> bool {
+        self.scripts.iter().any(|p| p.is_file())
+            || self.startup_files.iter().any(|p| {
+                std::fs::read_to_string(p).is_ok_and(|text| text.contains("prompter completions"))
+            })
+    }
+}
+
+/// The user's shell, from the file name of `$SHELL`.
+fn current_shell() -> Option<String> {
+    let shell = std::env::var("SHELL").ok()?;
+    Path::new(&shell)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+}
+
+/// Warning for `shell` without completions, with the command that installs them.
+fn completion_warning(shell: &str) -> String {
+    let install = match shell {
+        "bash" => "add `source <(prompter completions bash)` to ~/.bashrc".to_string(),
+        "zsh" => "run `prompter completions zsh > ~/.zsh/completions/_prompter`".to_string(),
+        "fish" => {
+            "run `prompter completions fish > ~/.config/fish/completions/prompter.fish`".to_string()
+        }
+        other => format!("see `prompter completions {other}`"),
+    };
+    format!("Shell completions for {shell} do not appear to be installed; {install}")
+}
+
+/// Best-effort check of completions for the current shell: `None` when the
+/// shell is unknown, else the shell and whether completions look installed.
+fn completion_status(home: &Path) -> Option<(String, bool)> {
+    let shell = current_shell()?;
+    let installed = CompletionSetup::for_shell(&shell, home)?.installed();
+    Some((shell, installed))
+}
+
+/// Create the default config and library with `init_scaffold` if either is
+/// missing, asking on stderr first unless `yes` is set. Declining leaves
+/// everything as it is.
+///
+/// # Errors
+/// Returns an error if reading the answer or initialization fails.
+pub fn fix_missing(yes: bool, quiet: bool) -> Result<(), String> {
+    let home = std::env::var("HOME").unwrap_or_else(|_| "~".to_string());
+    let config_path = Path::new(&home).join(".config/prompter/config.toml");
+    let library_path = Path::new(&home).join(".local/prompter/library");
+    if config_path.exists() && library_path.exists() {
+        return Ok(());
+    }
+    if !yes {
+        eprint!("Config or library is missing. Create them as 'prompter init' would? [y/N] ");
+        io::stderr()
+            .flush()
+            .map_err(|e| format!("Write error: {e}"))?;
+        let mut answer = String::new();
+        io::stdin()
+            .lock()
+            .read_line(&mut answer)
+            .map_err(|e| format!("Failed to read answer: {e}"))?;
+        if !is_yes(&answer) {
+            return Ok(());
+        }
+    }
+    init_scaffold(DEFAULT_TEMPLATE, quiet)
+}
+
+fn is_yes(answer: &str) -> bool {
+    matches!(answer.trim().to_ascii_lowercase().as_str(), "y" | "yes")
+}
+
+/// Library directory the default configuration resolves to, honoring
+/// `--include-base` and the `include_base` and `relative_to` keys; falls back
+/// to the override or the default location when the config cannot be loaded.
+fn effective_library(config: &ConfigSource, home: &str) -> PathBuf {
+    config.load().map_or_else(
+        |_| match config {
+            ConfigSource::IncludeBase(_, dir) => dir.clone(),
+            _ => Path::new(home).join(".local/prompter/library"),
+        },
+        |(_, lib)| lib,
+    )
+}
+
+/// Run the full validator against the default configuration, returning
+/// `(errors, warnings)`.
+fn deep_check(config: &ConfigSource) -> (Vec<String>, Vec<String>) {
+    match config.load() {
+        Ok((cfg, lib)) => {
+            let errors = validate(&cfg, &lib, &ValidateOptions::default())
+                .err()
+                .map(|e| e.lines().map(String::from).collect())
+                .unwrap_or_default();
+            (errors, duplicate_dependencies(&cfg))
+        }
+        Err(e) => (vec![e], Vec::new()),
+    }
+}
+
 /// Run doctor command to check health and configuration with JSON support.
 ///
+/// With `quiet`, the text report is replaced by error lines on stderr, so a
+/// healthy setup prints nothing. With `deep`, the full validator also runs.
+/// `config` is the default configuration, possibly with `--include-base`.
+///
 /// Returns exit code: 0 if healthy, 1 if issues found.
-pub fn run_doctor_with_json(json: bool) -> i32 {
+pub fn run_doctor_with_json(
+    json: bool,
+    json_layout: JsonLayout,
+    quiet: bool,
+    deep: bool,
+    config: &ConfigSource,
+) -> i32 {
     if json {
-        run_doctor_json()
+        run_doctor_json(deep, config, json_layout)
+    } else if quiet {
+        run_doctor_quiet(deep, config)
     } else {
-        run_doctor()
+        run_doctor(deep, config)
     }
 }
 
-/// Run doctor command with JSON output.
-fn run_doctor_json() -> i32 {
+/// Gather diagnostics without printing anything.
+fn diagnose(deep: bool, config: &ConfigSource) -> DoctorOutput {
     let home = std::env::var("HOME").unwrap_or_else(|_| "~".to_string());
     let config_path = Path::new(&home).join(".config/prompter/config.toml");
-    let library_path = Path::new(&home).join(".local/prompter/library");
+    let library_path = effective_library(config, &home);
 
     let config_file_exists = config_path.exists();
-    let mut config_valid_toml = false;
+    let mut config_valid = false;
     let mut errors = Vec::new();
-    let warnings = Vec::new();
+    let mut warnings = Vec::new();
 
     if config_file_exists {
         match std::fs::read_to_string(&config_path) {
-            Ok(content) => {
-                if toml::from_str::<toml::Value>(&content).is_ok() {
-                    config_valid_toml = true;
-                } else {
-                    errors.push(format!("Config is invalid TOML: {}", config_path.display()));
+            Ok(content) => match parse_config(&content, config.format_of(Some(&config_path))) {
+                Ok(_) => config_valid = true,
+                Err(e) => {
+                    errors.push(format!("Config is invalid: {}: {e}", config_path.display()));
                 }
-            }
+            },
             Err(e) => {
                 errors.push(format!("Failed to read config: {e}"));
             }
@@ -61,75 +249,117 @@ fn run_doctor_json() -> i32 {
         ));
     }
 
-    let output = DoctorOutput {
+    // Validation needs a readable config; its absence is already reported.
+    if deep && config_valid {
+        let (deep_errors, deep_warnings) = deep_check(config);
+        errors.extend(deep_errors);
+        warnings.extend(deep_warnings);
+    }
+
+    let editor = configured_editor();
+    if editor.is_none() {
+        warnings.push(EDITOR_WARNING.to_string());
+    }
+    if let Some((shell, false)) = completion_status(Path::new(&home)) {
+        warnings.push(completion_warning(&shell));
+    }
+
+    DoctorOutput {
         config_file_exists,
-        config_valid_toml,
+        config_valid,
+        library_directory: library_path,
         library_directory_exists,
+        editor,
         version: env!("CARGO_PKG_VERSION").to_string(),
         errors,
         warnings,
-    };
+    }
+}
 
-    match serde_json::to_string_pretty(&output) {
+/// Run doctor command with JSON output.
+fn run_doctor_json(deep: bool, config: &ConfigSource, json_layout: JsonLayout) -> i32 {
+    let output = diagnose(deep, config);
+    match prompter::to_json(&output, json_layout) {
         Ok(json_output) => {
             println!("{json_output}");
-            if output.errors.is_empty() { 0 } else { 1 }
+            i32::from(!output.errors.is_empty())
         }
         Err(e) => {
-            eprintln!(r#"{{"error":"JSON serialization error: {}"}}"#, e);
+            eprintln!(r#"{{"error":"{e}"}}"#);
             1
         }
     }
 }
 
+/// Run doctor command printing only errors, to stderr.
+fn run_doctor_quiet(deep: bool, config: &ConfigSource) -> i32 {
+    let output = diagnose(deep, config);
+    for error in &output.errors {
+        eprintln!("{error}");
+    }
+    i32::from(!output.errors.is_empty())
+}
+
 /// Run doctor command to check health and configuration.
 ///
 /// Returns exit code: 0 if healthy, 1 if issues found.
-fn run_doctor() -> i32 {
-    println!("🏥 prompter health check");
+fn run_doctor(deep: bool, config: &ConfigSource) -> i32 {
+    println!("🏥 {}", "prompter health check".bold());
     println!("========================");
     println!();
 
     let mut has_errors = false;
+    let mut config_readable = false;
 
     // Check configuration
-    println!("Configuration:");
+    println!("{}", "Configuration:".bold());
     let home = std::env::var("HOME").unwrap_or_else(|_| "~".to_string());
     let config_path = Path::new(&home).join(".config/prompter/config.toml");
 
     if config_path.exists() {
-        println!("  ✅ Config file: {}", config_path.display());
+        println!(
+            "  ✅ Config file: {}",
+            config_path.display().to_string().green()
+        );
 
         // Try to parse it
         match std::fs::read_to_string(&config_path) {
-            Ok(content) => {
-                if toml::from_str::<toml::Value>(&content).is_ok() {
-                    println!("  ✅ Config is valid TOML");
-                } else {
-                    println!("  ❌ Config is invalid TOML");
+            Ok(content) => match parse_config(&content, config.format_of(Some(&config_path))) {
+                Ok(_) => {
+                    println!("  ✅ {}", "Config is valid".green());
+                    config_readable = true;
+                }
+                Err(e) => {
+                    println!("  ❌ {}", format!("Config is invalid: {e}").red());
                     has_errors = true;
                 }
-            }
+            },
             Err(e) => {
-                println!("  ❌ Failed to read config: {e}");
+                println!("  ❌ {}", format!("Failed to read config: {e}").red());
                 has_errors = true;
             }
         }
     } else {
-        println!("  ❌ Config file not found: {}", config_path.display());
+        println!(
+            "  ❌ {}",
+            format!("Config file not found: {}", config_path.display()).red()
+        );
         println!("  ℹ️  Run 'prompter init' to create default configuration");
         has_errors = true;
     }
 
     // Check library directory
-    let library_path = Path::new(&home).join(".local/prompter/library");
+    let library_path = effective_library(config, &home);
 
     if library_path.exists() {
-        println!("  ✅ Library directory: {}", library_path.display());
+        println!(
+            "  ✅ Library directory: {}",
+            library_path.display().to_string().green()
+        );
     } else {
         println!(
-            "  ❌ Library directory not found: {}",
-            library_path.display()
+            "  ❌ {}",
+            format!("Library directory not found: {}", library_path.display()).red()
         );
         println!("  ℹ️  Run 'prompter init' to create default library");
         has_errors = true;
@@ -137,8 +367,38 @@ fn run_doctor() -> i32 {
 
     println!();
 
+    if deep && config_readable {
+        println!("{}", "Validation:".bold());
+        let (errors, warnings) = deep_check(config);
+        for error in &errors {
+            println!("  ❌ {}", error.red());
+        }
+        for warning in &warnings {
+            println!("  ⚠️  {}", warning.yellow());
+        }
+        if errors.is_empty() && warnings.is_empty() {
+            println!("  ✅ {}", "All profiles valid".green());
+        }
+        has_errors |= !errors.is_empty();
+        println!();
+    }
+
+    // Environment
+    println!("{}", "Environment:".bold());
+    match configured_editor() {
+        Some(editor) => println!("  ✅ Editor: {}", editor.green()),
+        None => println!("  ⚠️  {}", EDITOR_WARNING.yellow()),
+    }
+    match completion_status(Path::new(&home)) {
+        Some((shell, true)) => println!("  ✅ Completions: installed for {}", shell.green()),
+        Some((shell, false)) => println!("  ⚠️  {}", completion_warning(&shell).yellow()),
+        None => {}
+    }
+
+    println!();
+
     // Version info
-    println!("Version:");
+    println!("{}", "Version:".bold());
     println!("  ℹ️  Current version: v{}", env!("CARGO_PKG_VERSION"));
     println!("  💡 Check https://github.com/tftio/prompter/releases for updates");
 
@@ -146,10 +406,10 @@ fn run_doctor() -> i32 {
 
     // Summary
     if has_errors {
-        println!("❌ Errors found");
+        println!("❌ {}", "Errors found".red().bold());
         1
     } else {
-        println!("✨ Everything looks healthy!");
+        println!("✨ {}", "Everything looks healthy!".green().bold());
         0
     }
 }
@@ -160,14 +420,67 @@ mod tests {
 
     #[test]
     fn test_run_doctor_returns_valid_exit_code() {
-        let exit_code = run_doctor();
+        let exit_code = run_doctor(false, &ConfigSource::Default);
         // Should return 0 or 1
         assert!(exit_code == 0 || exit_code == 1);
     }
 
+    #[test]
+    fn test_editor_from_prefers_visual_and_ignores_empty() {
+        assert_eq!(
+            editor_from(Some("code -w".into()), Some("vim".into())),
+            Some("code -w".into())
+        );
+        assert_eq!(
+            editor_from(Some("  ".into()), Some("vim".into())),
+            Some("vim".into())
+        );
+        assert_eq!(editor_from(None, Some(String::new())), None);
+        assert_eq!(editor_from(None, None), None);
+    }
+
+    #[test]
+    fn test_completion_setup_detection() {
+        // The binary's tests cannot reach the library's test-only mk_tmp.
+        let home = std::env::temp_dir().join(format!(
+            "prompter_doctor_completions_{}_{}",
+            std::process::id(),
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_nanos()
+        ));
+        std::fs::create_dir_all(&home).unwrap();
+        assert!(CompletionSetup::for_shell("tcsh", &home).is_none());
+        let zsh = CompletionSetup::for_shell("zsh", &home).unwrap();
+        assert!(
+            zsh.scripts
+                .contains(&home.join(".zsh/completions/_prompter"))
+        );
+        let user_only = CompletionSetup {
+            scripts: vec![home.join(".zsh/completions/_prompter")],
+            startup_files: zsh.startup_files,
+        };
+        assert!(!user_only.installed());
+        std::fs::write(
+            home.join(".zshrc"),
+            "eval \"$(prompter completions zsh)\"\n",
+        )
+        .unwrap();
+        assert!(user_only.installed());
+        assert!(completion_warning("fish").contains("prompter completions fish >"));
+        std::fs::remove_dir_all(&home).unwrap();
+    }
+
+    #[test]
+    fn test_is_yes() {
+        assert!(is_yes("y\n") && is_yes(" YES "));
+        assert!(!is_yes("\n") && !is_yes("no") && !is_yes(""));
+    }
+
     #[test]
     fn test_run_doctor_json_returns_valid_exit_code() {
-        let exit_code = run_doctor_json();
+        let exit_code = run_doctor_json(true, &ConfigSource::Default, JsonLayout::Pretty);
         // Should return 0 or 1
         assert!(exit_code == 0 || exit_code == 1);
     }
@@ -0,0 +1,151 @@
+//! Template engines for fragment bodies (`run --template-engine`).
+//!
+//! The default engine is the `{{name}}` substitution in [`crate::vars`]. Built
+//! with the `template-engine` feature (on by default), `minijinja` runs bodies
+//! through a Jinja-style engine instead, with the same variables as string
+//! context values, so fragments can use loops, conditionals, and filters.
+
+use clap::ValueEnum;
+use std::collections::HashMap;
+
+use crate::vars;
+
+/// Engine that fragment bodies are rendered with.
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TemplateEngine {
+    /// `{{name}}` substitution only
+    #[default]
+    Simple,
+    /// Jinja-style templates: `{% for %}`, `{% if %}`, filters
+    Minijinja,
+}
+
+/// Render `content`, the text of fragment `name`, with the variables in `ctx`.
+///
+/// `line_of` maps a line of `content` to the line of `name` it came from, for
+/// error messages. With `strict`, minijinja fails on undefined values.
+///
+/// # Errors
+/// Returns an error naming the fragment and line when the template is invalid
+/// or fails to render, or when minijinja is requested but not built in.
+#[allow(clippy::implicit_hasher)]
+pub fn render(
+    engine: TemplateEngine,
+    content: &str,
+    ctx: &HashMap<&str, &str>,
+    name: &str,
+    line_of: &dyn Fn(usize) -> usize,
+    strict: bool,
+) -> Result<String, String> {
+    match engine {
+        TemplateEngine::Simple => Ok(vars::substitute(content, ctx)),
+        TemplateEngine::Minijinja => render_minijinja(content, ctx, name, line_of, strict),
+    }
+}
+
+#[cfg(feature = "template-engine")]
+fn render_minijinja(
+    content: &str,
+    ctx: &HashMap<&str, &str>,
+    name: &str,
+    line_of: &dyn Fn(usize) -> usize,
+    strict: bool,
+) -> Result<String, String> {
+    use minijinja::{Environment, UndefinedBehavior};
+
+    let mut env = Environment::new();
+    env.set_keep_trailing_newline(true);
+    if strict {
+        env.set_undefined_behavior(UndefinedBehavior::Strict);
+    }
+    env.template_from_named_str(name, content)
+        .and_then(|template| template.render(ctx))
+        .map_err(|e| {
+            let line = e
+                .line()
+                .map_or(String::new(), |line| format!(":{}", line_of(line)));
+            let detail = e
+                .detail()
+                .map_or_else(|| e.kind().to_string(), String::from);
+            format!("Template error at {name}{line}: {detail}")
+        })
+}
+
+#[cfg(not(feature = "template-engine"))]
+fn render_minijinja(
+    _content: &str,
+    _ctx: &HashMap<&str, &str>,
+    _name: &str,
+    _line_of: &dyn Fn(usize) -> usize,
+    _strict: bool,
+) -> Result<String, String> {
+    Err("--template-engine minijinja needs prompter built with the template-engine feature".into())
+}
+
+#[cfg(all(test, feature = "template-engine"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn minijinja_renders_loops_and_reports_lines() {
+        let ctx = HashMap::from([("langs", "rust,go"), ("team", "core")]);
+        let body = "Team {{ team }}\n{% for l in langs|split(\",\") %}- {{ l }}\n{% endfor %}";
+        assert_eq!(
+            render(
+                TemplateEngine::Minijinja,
+                body,
+                &ctx,
+                "a.md",
+                &|line| line,
+                false
+            )
+            .unwrap(),
+            "Team core\n- rust\n- go\n"
+        );
+        assert_eq!(
+            render(
+                TemplateEngine::Simple,
+                "{{team}} {% x %}",
+                &ctx,
+                "a.md",
+                &|line| line,
+                false
+            )
+            .unwrap(),
+            "core {% x %}"
+        );
+
+        let err = render(
+            TemplateEngine::Minijinja,
+            "ok\n{% if %}\n",
+            &ctx,
+            "a.md",
+            &|line| line + 9,
+            false,
+        )
+        .unwrap_err();
+        assert!(err.starts_with("Template error at a.md:11: "), "{err}");
+        let err = render(
+            TemplateEngine::Minijinja,
+            "{{ nope }}",
+            &ctx,
+            "a.md",
+            &|line| line,
+            true,
+        )
+        .unwrap_err();
+        assert!(err.starts_with("Template error at a.md:1: "), "{err}");
+        assert_eq!(
+            render(
+                TemplateEngine::Minijinja,
+                "[{{ nope }}]",
+                &ctx,
+                "a.md",
+                &|line| line,
+                false
+            )
+            .unwrap(),
+            "[]"
+        );
+    }
+}
@@ -0,0 +1,226 @@
+//! JSON and YAML configuration files.
+//!
+//! A JSON or YAML config has the same shape as the TOML one: top-level
+//! settings, a `profiles` map of inline bodies, a `scenarios` map, and one
+//! object per profile section. It is read into the same [`Config`], so the
+//! rest of the loader (merging, inheritance, validation) does not change.
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::{Config, INLINE_PROFILES_SECTION, SCENARIO_PREFIX, Scenario, parse_review_after};
+
+/// Syntax of a configuration file.
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConfigFormat {
+    /// TOML (the default)
+    #[default]
+    Toml,
+    /// JSON
+    Json,
+    /// YAML
+    Yaml,
+}
+
+impl ConfigFormat {
+    /// Format implied by a file's extension: `.json`, `.yaml`/`.yml`, else TOML.
+    #[must_use]
+    pub fn for_path(path: &Path) -> Self {
+        let ext = path
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_ascii_lowercase());
+        match ext.as_deref() {
+            Some("json") => Self::Json,
+            Some("yaml" | "yml") => Self::Yaml,
+            _ => Self::Toml,
+        }
+    }
+}
+
+/// Format to read the configuration at `path` (`None` for `--config-inline`
+/// text) in: `forced` (from `--input-format`) if given, else detected from
+/// the extension.
+#[must_use]
+pub fn input_format(forced: Option<ConfigFormat>, path: Option<&Path>) -> ConfigFormat {
+    forced.unwrap_or_else(|| path.map(ConfigFormat::for_path).unwrap_or_default())
+}
+
+/// A whole JSON or YAML configuration document.
+#[derive(Debug, Deserialize)]
+struct Document {
+    post_prompt: Option<String>,
+    comment_prefix: Option<String>,
+    relative_to: Option<PathBuf>,
+    include_base: Option<PathBuf>,
+    case_insensitive_profiles: Option<bool>,
+    #[serde(default)]
+    profiles: BTreeMap<String, String>,
+    #[serde(default)]
+    scenarios: BTreeMap<String, ScenarioDocument>,
+    remote: Option<RemoteDocument>,
+    /// Every other key is a profile section
+    #[serde(flatten)]
+    sections: BTreeMap<String, Section>,
+}
+
+/// One profile section.
+#[derive(Debug, Deserialize)]
+struct Section {
+    depends_on: Option<Vec<String>>,
+    description: Option<String>,
+    tags: Option<Vec<String>>,
+    extends: Option<String>,
+    review_after: Option<String>,
+}
+
+/// One scenario.
+#[derive(Debug, Default, Deserialize)]
+struct ScenarioDocument {
+    #[serde(default)]
+    profiles: Vec<String>,
+    separator: Option<String>,
+    pre_prompt: Option<String>,
+    post_prompt: Option<String>,
+}
+
+/// The remote include policy.
+#[derive(Debug, Deserialize)]
+struct RemoteDocument {
+    allowed_hosts: Option<Vec<String>>,
+}
+
+/// Parse one JSON or YAML configuration document, like `parse_config_layer`
+/// does for TOML.
+pub(crate) fn parse_document(input: &str, format: ConfigFormat) -> Result<Config, String> {
+    let document: Document = match format {
+        ConfigFormat::Json => {
+            serde_json::from_str(input).map_err(|e| format!("Invalid JSON: {e}"))?
+        }
+        ConfigFormat::Yaml => {
+            serde_yaml_ng::from_str(input).map_err(|e| format!("Invalid YAML: {e}"))?
+        }
+        ConfigFormat::Toml => unreachable!("TOML is read by parse_config_layer"),
+    };
+    document.into_config()
+}
+
+impl Document {
+    fn into_config(self) -> Result<Config, String> {
+        let mut cfg = Config {
+            post_prompt: self.post_prompt,
+            comment_prefix: self.comment_prefix,
+            relative_to: self.relative_to,
+            include_base: self.include_base,
+            case_insensitive_profiles: self.case_insensitive_profiles,
+            allowed_hosts: self.remote.and_then(|remote| remote.allowed_hosts),
+            ..Config::default()
+        };
+        if self.profiles.contains_key("") {
+            return Err(format!("Empty profile name in [{INLINE_PROFILES_SECTION}]"));
+        }
+        cfg.inline_profiles.extend(self.profiles);
+        for (name, scenario) in self.scenarios {
+            if name.is_empty() {
+                return Err(format!("Empty scenario name [{SCENARIO_PREFIX}]"));
+            }
+            let scenario = Scenario {
+                profiles: scenario.profiles,
+                separator: scenario.separator,
+                pre_prompt: scenario.pre_prompt,
+                post_prompt: scenario.post_prompt,
+            };
+            cfg.scenarios.insert(name, scenario);
+        }
+        for (name, section) in self.sections {
+            if name.is_empty() {
+                return Err("Empty section name []".into());
+            }
+            if let Some(deps) = section.depends_on {
+                cfg.profiles.insert(name.clone(), deps);
+            }
+            let described = section.description.is_some()
+                || section.tags.is_some()
+                || section.extends.is_some()
+                || section.review_after.is_some();
+            if described {
+                let meta = cfg.metadata.entry(name.clone()).or_default();
+                meta.description = section.description;
+                meta.tags = section.tags.unwrap_or_default();
+                meta.extends = section.extends;
+                meta.review_after = section
+                    .review_after
+                    .map(|date| parse_review_after(&name, &date))
+                    .transpose()?;
+            }
+        }
+        Ok(cfg)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bundle::config_text;
+    use crate::parse_config;
+
+    #[test]
+    fn json_and_yaml_match_toml() {
+        let toml = "post_prompt = \"Done.\"\n\
+                    [profiles]\nhello = \"Hi\"\n\
+                    [base]\ndescription = \"Base\"\ntags = [\"core\"]\ndepends_on = [\"a.md\", \"hello\"]\n\
+                    [child]\nextends = \"base\"\ndepends_on = [\"b.md\"]\n\
+                    [scenarios.review]\nprofiles = [\"child\"]\nseparator = \"--\"\n\
+                    [remote]\nallowed_hosts = [\"example.com\"]\n";
+        let json = r#"{
+            "post_prompt": "Done.",
+            "profiles": {"hello": "Hi"},
+            "base": {"description": "Base", "tags": ["core"], "depends_on": ["a.md", "hello"]},
+            "child": {"extends": "base", "depends_on": ["b.md"]},
+            "scenarios": {"review": {"profiles": ["child"], "separator": "--"}},
+            "remote": {"allowed_hosts": ["example.com"]}
+        }"#;
+        let yaml = "post_prompt: Done.\n\
+                    profiles:\n  hello: Hi\n\
+                    base:\n  description: Base\n  tags: [core]\n  depends_on: [a.md, hello]\n\
+                    child:\n  extends: base\n  depends_on:\n    - b.md\n\
+                    scenarios:\n  review:\n    profiles: [child]\n    separator: \"--\"\n\
+                    remote:\n  allowed_hosts: [example.com]\n";
+        let text = |input, format| config_text(&parse_config(input, format).unwrap());
+        let expected = text(toml, ConfigFormat::Toml);
+        assert!(expected.ends_with("[remote]\nallowed_hosts = [\"example.com\"]\n"));
+        assert_eq!(text(json, ConfigFormat::Json), expected);
+        assert_eq!(text(yaml, ConfigFormat::Yaml), expected);
+
+        let err = parse_config("a: {review_after: soon}\n", ConfigFormat::Yaml).unwrap_err();
+        assert!(err.contains("review_after for [a]"), "{err}");
+        assert!(
+            parse_config("{\"a\": 1}", ConfigFormat::Json)
+                .unwrap_err()
+                .starts_with("Invalid JSON")
+        );
+    }
+
+    #[test]
+    fn format_follows_extension() {
+        assert_eq!(
+            ConfigFormat::for_path(Path::new("a/config.JSON")),
+            ConfigFormat::Json
+        );
+        assert_eq!(
+            ConfigFormat::for_path(Path::new("c.yml")),
+            ConfigFormat::Yaml
+        );
+        assert_eq!(
+            ConfigFormat::for_path(Path::new("c.conf")),
+            ConfigFormat::Toml
+        );
+        assert_eq!(input_format(None, None), ConfigFormat::Toml);
+        assert_eq!(
+            input_format(Some(ConfigFormat::Yaml), Some(Path::new("c.json"))),
+            ConfigFormat::Yaml
+        );
+    }
+}
@@ -0,0 +1,362 @@
+//! History of rendered profiles for `prompter history` and `run --repeat`.
+//!
+//! Each successful `run` appends a JSON line to
+//! `$XDG_STATE_HOME/prompter/history.jsonl` (default
+//! `~/.local/state/prompter/history.jsonl`). The file keeps the newest
+//! [`MAX_ENTRIES`] entries; older ones are evicted on write. Setting
+//! [`DISABLE_VAR`] to a non-empty value turns recording off.
+//!
+//! Text passed with `--config-inline` is never recorded, since it may hold
+//! secrets; an entry only notes that an inline config was used, and
+//! `run --repeat` refuses to replay it.
+
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::formats::ConfigFormat;
+use crate::remote::RemoteAccess;
+use crate::{
+    ConfigSource, JsonLayout, RenderError, RenderOptions, home_dir, run_render_stdout, to_json,
+};
+
+/// Maximum number of entries kept in the history file.
+pub const MAX_ENTRIES: usize = 100;
+
+/// Environment variable that turns history recording off when non-empty.
+pub const DISABLE_VAR: &str = "PROMPTER_NO_HISTORY";
+
+/// One recorded render.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct HistoryEntry {
+    /// When the render happened (RFC 3339)
+    pub timestamp: String,
+    /// Profiles rendered, in order
+    pub profiles: Vec<String>,
+    /// Absolute path of the `--config` file, if one was given
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub config_file: Option<PathBuf>,
+    /// Absolute paths of further `--config` files merged over the first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub config_overlays: Vec<PathBuf>,
+    /// Whether the config was passed with `--config-inline` (its text is not
+    /// recorded)
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub config_inline: bool,
+    /// Absolute `--relative-to` directory, if one was given
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub relative_to: Option<PathBuf>,
+    /// Absolute `--include-base` directory, if one was given
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub include_base: Option<PathBuf>,
+    /// `--input-format`, if one was given
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input_format: Option<ConfigFormat>,
+}
+
+impl HistoryEntry {
+    fn new(profiles: &[String], config: &ConfigSource) -> Self {
+        // Remote limits belong to the invocation, not the recorded source
+        let config = match config {
+            ConfigSource::Remote(source, _) => source.as_ref(),
+            source => source,
+        };
+        let (config, input_format) = match config {
+            ConfigSource::InputFormat(source, format) => (source.as_ref(), Some(*format)),
+            source => (source, None),
+        };
+        let (config, include_base) = match config {
+            ConfigSource::IncludeBase(source, dir) => (source.as_ref(), Some(absolute(dir))),
+            source => (source, None),
+        };
+        let (config, relative_to) = match config {
+            ConfigSource::RelativeTo(source, dir) => (source.as_ref(), Some(absolute(dir))),
+            source => (source, None),
+        };
+        let (files, config_inline) = match config {
+            ConfigSource::Default
+            | ConfigSource::RelativeTo(..)
+            | ConfigSource::IncludeBase(..)
+            | ConfigSource::InputFormat(..)
+            | ConfigSource::Remote(..) => (Vec::new(), false),
+            ConfigSource::File(path) => (vec![absolute(path)], false),
+            ConfigSource::Layered(paths) => (paths.iter().map(|p| absolute(p)).collect(), false),
+            ConfigSource::Inline(_) => (Vec::new(), true),
+        };
+        let mut files = files.into_iter();
+        Self {
+            timestamp: chrono::Local::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, false),
+            profiles: profiles.to_vec(),
+            config_file: files.next(),
+            config_overlays: files.collect(),
+            config_inline,
+            relative_to,
+            include_base,
+            input_format,
+        }
+    }
+
+    /// The recorded configuration, or `None` for an inline one.
+    fn config_source(&self) -> Option<ConfigSource> {
+        if self.config_inline {
+            return None;
+        }
+        let files = self
+            .config_file
+            .iter()
+            .chain(&self.config_overlays)
+            .cloned();
+        Some(
+            ConfigSource::from_args(files.collect(), None)
+                .relative_to(self.relative_to.clone())
+                .include_base(self.include_base.clone())
+                .input_format(self.input_format),
+        )
+    }
+}
+
+/// `path` joined to the current directory, so it survives a later `cd`.
+fn absolute(path: &Path) -> PathBuf {
+    env::current_dir().map_or_else(|_| path.to_path_buf(), |cwd| cwd.join(path))
+}
+
+/// Location of the history file.
+///
+/// # Errors
+/// Returns an error if neither `$XDG_STATE_HOME` nor `$HOME` is set.
+pub fn history_path() -> Result<PathBuf, String> {
+    let state = match env::var_os("XDG_STATE_HOME").map(PathBuf::from) {
+        Some(dir) if dir.is_absolute() => dir,
+        _ => home_dir()?.join(".local/state"),
+    };
+    Ok(state.join("prompter").join("history.jsonl"))
+}
+
+/// Read history entries from `path`, oldest first; unreadable lines are skipped.
+///
+/// # Errors
+/// Returns an error if the file exists but cannot be read.
+pub fn read_history(path: &Path) -> Result<Vec<HistoryEntry>, String> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let text = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    Ok(text
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect())
+}
+
+/// Append `entry` to the history at `path`, evicting the oldest entries
+/// beyond `max_entries`.
+///
+/// # Errors
+/// Returns an error if the history cannot be read or written.
+pub fn append_history(path: &Path, entry: HistoryEntry, max_entries: usize) -> Result<(), String> {
+    let mut entries = read_history(path)?;
+    entries.push(entry);
+    let excess = entries.len().saturating_sub(max_entries);
+    entries.drain(..excess);
+
+    let mut text = String::new();
+    for entry in &entries {
+        let line =
+            serde_json::to_string(entry).map_err(|e| format!("JSON serialization error: {e}"))?;
+        text.push_str(&line);
+        text.push('\n');
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+    }
+    let tmp = path.with_extension("jsonl.tmp");
+    fs::write(&tmp, text).map_err(|e| format!("Failed to write {}: {}", tmp.display(), e))?;
+    fs::rename(&tmp, path).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+/// Record a render in the history file, unless [`DISABLE_VAR`] is set.
+///
+/// # Errors
+/// Returns an error if the history location is unknown or cannot be written.
+pub fn record(profiles: &[String], config: &ConfigSource) -> Result<(), String> {
+    if env::var_os(DISABLE_VAR).is_some_and(|value| !value.is_empty()) {
+        return Ok(());
+    }
+    append_history(
+        &history_path()?,
+        HistoryEntry::new(profiles, config),
+        MAX_ENTRIES,
+    )
+}
+
+/// JSON form of a listed history entry
+#[derive(Debug, Serialize)]
+struct IndexedEntry<'a> {
+    index: usize,
+    #[serde(flatten)]
+    entry: &'a HistoryEntry,
+}
+
+/// Write history entries newest first, numbered from 1.
+///
+/// # Errors
+/// Returns an error if JSON serialization or writing to the output fails.
+pub fn show_history(
+    entries: &[HistoryEntry],
+    json: bool,
+    json_layout: JsonLayout,
+    mut w: impl Write,
+) -> Result<(), String> {
+    let newest_first = entries.iter().rev().enumerate().map(|(i, e)| (i + 1, e));
+    if json {
+        let indexed: Vec<IndexedEntry> = newest_first
+            .map(|(index, entry)| IndexedEntry { index, entry })
+            .collect();
+        let json_output = to_json(&indexed, json_layout)?;
+        return writeln!(w, "{json_output}").map_err(|e| format!("Write error: {e}"));
+    }
+    for (index, entry) in newest_first {
+        let source = match (&entry.config_file, entry.config_inline) {
+            (Some(file), _) => {
+                let files: Vec<String> = std::iter::once(file)
+                    .chain(&entry.config_overlays)
+                    .map(|f| f.display().to_string())
+                    .collect();
+                format!("  (config: {})", files.join(" + "))
+            }
+            (None, true) => "  (inline config)".to_string(),
+            (None, false) => String::new(),
+        };
+        writeln!(
+            w,
+            "{index:>3}  {}  {}{source}",
+            entry.timestamp,
+            entry.profiles.join(" ")
+        )
+        .map_err(|e| format!("Write error: {e}"))?;
+    }
+    Ok(())
+}
+
+/// List recent renders to stdout.
+///
+/// # Errors
+/// Returns an error if the history cannot be read or writing to stdout fails.
+pub fn run_history_stdout(json: bool, json_layout: JsonLayout) -> Result<(), String> {
+    let entries = read_history(&history_path()?)?;
+    show_history(&entries, json, json_layout, io::stdout())
+}
+
+/// Re-render history entry `index` (1 = most recent) with `opts`, limiting
+/// its remote includes by `access`.
+///
+/// # Errors
+/// Returns an error if the entry does not exist, used an inline config, or
+/// rendering fails.
+pub fn run_repeat_stdout(
+    index: usize,
+    opts: &RenderOptions,
+    access: RemoteAccess,
+) -> Result<(), RenderError> {
+    let entries = read_history(&history_path()?)?;
+    let entry = index
+        .checked_sub(1)
+        .and_then(|i| entries.iter().rev().nth(i))
+        .ok_or_else(|| format!("No history entry {index} ({} recorded)", entries.len()))?;
+    let config = entry.config_source().ok_or_else(|| {
+        format!(
+            "History entry {index} used --config-inline, whose text is not recorded; \
+             render it again with the same --config-inline"
+        )
+    })?;
+    run_render_stdout(
+        &entry.profiles,
+        &[],
+        &[],
+        None,
+        opts,
+        &config.remote_access(access),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(profile: &str) -> HistoryEntry {
+        HistoryEntry {
+            timestamp: "2024-01-01T00:00:00+00:00".into(),
+            profiles: vec![profile.into()],
+            config_file: None,
+            config_overlays: Vec::new(),
+            config_inline: false,
+            relative_to: None,
+            include_base: None,
+            input_format: None,
+        }
+    }
+
+    #[test]
+    fn append_evicts_oldest_and_lists_newest_first() {
+        let dir = crate::mk_tmp("prompter_history");
+        let path = dir.join("history.jsonl");
+        for name in ["a", "b", "c"] {
+            append_history(&path, entry(name), 2).unwrap();
+        }
+        let entries = read_history(&path).unwrap();
+        assert_eq!(entries, vec![entry("b"), entry("c")]);
+
+        let mut out = Vec::new();
+        show_history(&entries, false, JsonLayout::Pretty, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert!(lines[0].starts_with("  1  ") && lines[0].ends_with(" c"));
+        assert!(lines[1].starts_with("  2  ") && lines[1].ends_with(" b"));
+    }
+
+    #[test]
+    fn entry_round_trips_config_source() {
+        let file = ConfigSource::File("/ci/prompter.toml".into());
+        assert_eq!(
+            HistoryEntry::new(&[], &file).config_source(),
+            Some(file.clone())
+        );
+        let rooted = file.relative_to(Some("/ci/checkout".into()));
+        assert_eq!(
+            HistoryEntry::new(&[], &rooted).config_source(),
+            Some(rooted.clone())
+        );
+        let based = rooted.include_base(Some("/srv/prompts".into()));
+        assert_eq!(
+            HistoryEntry::new(&[], &based).config_source(),
+            Some(based.clone())
+        );
+        let yaml = based.input_format(Some(ConfigFormat::Yaml));
+        assert_eq!(
+            HistoryEntry::new(&[], &yaml).config_source(),
+            Some(yaml.clone())
+        );
+        let offline = yaml.clone().remote_access(RemoteAccess {
+            offline: true,
+            deny: true,
+            allow_http: true,
+        });
+        assert_eq!(HistoryEntry::new(&[], &offline).config_source(), Some(yaml));
+        assert_eq!(
+            HistoryEntry::new(&[], &ConfigSource::Default).config_source(),
+            Some(ConfigSource::Default)
+        );
+    }
+
+    #[test]
+    fn inline_config_text_is_not_recorded() {
+        let inline = ConfigSource::Inline("[p]\napi_key = \"secret\"\n".into());
+        let entry = HistoryEntry::new(&["p".into()], &inline.relative_to(Some("/ci".into())));
+        assert!(entry.config_inline);
+        assert!(!serde_json::to_string(&entry).unwrap().contains("secret"));
+        assert_eq!(entry.config_source(), None);
+    }
+}
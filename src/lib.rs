@@ -4,30 +4,120 @@
 //! from a structured library using TOML configuration files. It supports recursive
 //! profile dependencies, file deduplication, and customizable output formatting.
 
+pub mod batch;
+pub mod bundle;
+pub mod cache;
 pub mod completions;
-
-use chrono::Local;
-use clap::{Parser, Subcommand};
+pub mod diff;
+pub mod engine;
+pub mod formats;
+pub mod history;
+pub mod lint;
+pub mod meta;
+pub mod metrics;
+pub mod picker;
+pub mod progress;
+pub mod remote;
+pub mod replace;
+pub mod schema;
+pub mod serve;
+pub mod stats;
+pub mod templates;
+pub mod vars;
+pub mod verbose;
+
+use chrono::{Local, NaiveDate};
+use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
 use colored::Colorize;
 use indicatif::{ProgressBar, ProgressStyle};
 use is_terminal::IsTerminal;
 use serde::{Deserialize, Serialize};
-use std::collections::{HashMap, HashSet};
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::env;
 use std::fs;
 use std::io::{self, Write};
 use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::{Duration, Instant, SystemTime};
+
+use formats::ConfigFormat;
 
 /// Configuration structure holding profile definitions and their dependencies.
 ///
-/// Profiles map names to lists of dependencies, where dependencies can be either
-/// markdown files (ending in .md) or references to other profiles.
-#[derive(Debug)]
+/// Profiles map names to lists of dependencies, where dependencies can be
+/// markdown files (ending in .md), arbitrary files (`@path`), or references to
+/// other profiles.
+#[derive(Debug, Default)]
 pub struct Config {
     /// Map of profile names to their dependency lists
     pub(crate) profiles: HashMap<String, Vec<String>>,
     /// Optional post-prompt text to append at the end of output
     pub(crate) post_prompt: Option<String>,
+    /// Optional prefix marking comment lines stripped from fragments at render time
+    pub(crate) comment_prefix: Option<String>,
+    /// Descriptive keys of profile sections, by profile name
+    pub(crate) metadata: HashMap<String, ProfileMetadata>,
+    /// Directory `@path` includes resolve against (the library when unset)
+    pub(crate) base_dir: Option<PathBuf>,
+    /// Root the library and `@path` includes resolve against (`relative_to = "..."`)
+    pub(crate) relative_to: Option<PathBuf>,
+    /// Library directory overriding every derived location (`include_base = "..."`)
+    pub(crate) include_base: Option<PathBuf>,
+    /// Bodies of profiles defined inline in the `[profiles]` table, by profile name
+    pub(crate) inline_profiles: HashMap<String, String>,
+    /// Named profile sets from `[scenarios.NAME]` sections, by scenario name
+    pub(crate) scenarios: HashMap<String, Scenario>,
+    /// Match profile names regardless of case (`case_insensitive_profiles = true`)
+    pub(crate) case_insensitive_profiles: Option<bool>,
+    /// Hosts remote includes may be fetched from (`[remote] allowed_hosts`);
+    /// any host when unset
+    pub(crate) allowed_hosts: Option<Vec<String>>,
+    /// Command-line limits on remote includes, set by [`ConfigSource::load`]
+    pub(crate) remote_access: remote::RemoteAccess,
+}
+
+impl Config {
+    /// The defined profile that `name` refers to: `name` itself, or with
+    /// `case_insensitive_profiles = true`, the profile spelled like it
+    /// ignoring case. Unknown names are returned unchanged.
+    #[must_use]
+    pub fn profile_name<'a>(&'a self, name: &'a str) -> &'a str {
+        if self.case_insensitive_profiles != Some(true) || self.profiles.contains_key(name) {
+            return name;
+        }
+        let folded = name.to_lowercase();
+        self.profiles
+            .keys()
+            .find(|defined| defined.to_lowercase() == folded)
+            .map_or(name, String::as_str)
+    }
+}
+
+/// Optional descriptive keys of a profile section.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProfileMetadata {
+    /// One-line summary (`description = "..."`)
+    pub description: Option<String>,
+    /// Free-form labels (`tags = ["..."]`)
+    pub tags: Vec<String>,
+    /// Parent profile whose dependencies this one inherits (`extends = "..."`)
+    pub extends: Option<String>,
+    /// Date after which the profile is due for review (`review_after = "YYYY-MM-DD"`)
+    pub review_after: Option<NaiveDate>,
+}
+
+/// A named set of profiles and render settings, run with `run --scenario`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Scenario {
+    /// Profiles rendered, in order (`profiles = ["..."]`)
+    pub profiles: Vec<String>,
+    /// Separator used unless `--separator` is given
+    pub separator: Option<String>,
+    /// Pre-prompt used unless `--pre-prompt` is given
+    pub pre_prompt: Option<String>,
+    /// Post-prompt used unless `--post-prompt` is given
+    pub post_prompt: Option<String>,
 }
 
 /// Command-line interface structure for the prompter tool.
@@ -37,60 +127,522 @@ pub struct Config {
 #[command(name = "prompter")]
 #[command(about = "A CLI tool for composing reusable prompt snippets")]
 #[command(version)]
+#[allow(clippy::struct_excessive_bools)]
 pub struct Cli {
-    /// Subcommand to execute
+    /// Subcommand to execute (required unless `--dump-config` is given)
     #[command(subcommand)]
-    pub command: Commands,
+    pub command: Option<Commands>,
 
-    /// Override configuration file path
+    /// Override configuration file path (repeatable; later files override earlier ones)
     #[arg(short = 'c', long, value_name = "FILE", global = true)]
-    pub config: Option<PathBuf>,
+    pub config: Vec<PathBuf>,
+
+    /// Use this TOML text as the configuration (fragments resolve relative to the cwd)
+    #[arg(long, value_name = "TOML", global = true, conflicts_with = "config")]
+    pub config_inline: Option<String>,
+
+    /// Read configuration files as FORMAT instead of detecting it from the extension
+    #[arg(long, value_enum, value_name = "FORMAT", global = true)]
+    pub input_format: Option<ConfigFormat>,
+
+    /// Resolve the library (DIR/library) and `@path` includes against DIR
+    #[arg(long, value_name = "DIR", global = true)]
+    pub relative_to: Option<PathBuf>,
+
+    /// Resolve profile fragments against DIR instead of the derived library
+    #[arg(long, value_name = "DIR", global = true)]
+    pub include_base: Option<PathBuf>,
 
     /// Output in JSON format
     #[arg(short = 'j', long, global = true)]
     pub json: bool,
+
+    /// Suppress informational output; errors are still printed to stderr
+    #[arg(short = 'q', long, global = true)]
+    pub quiet: bool,
+
+    /// Validate the config and all includes before running the subcommand
+    #[arg(long, global = true)]
+    pub config_check: bool,
+
+    /// Print the fully resolved configuration (TOML, or JSON with --json) and exit
+    #[arg(long, global = true)]
+    pub dump_config: bool,
+
+    /// Indent JSON output for reading (the default)
+    #[arg(long, global = true, conflicts_with = "compact")]
+    pub pretty: bool,
+
+    /// Write JSON output on a single line
+    #[arg(long, global = true)]
+    pub compact: bool,
+
+    /// Colorize human-readable output
+    #[arg(long, value_enum, value_name = "WHEN", default_value_t = ColorChoice::Auto, global = true)]
+    pub color: ColorChoice,
+
+    /// Report every error on stderr as a JSON object with `code`, `message`, and `context`
+    #[arg(long, global = true)]
+    pub json_errors: bool,
+
+    /// Show a progress bar on stderr during `run --all` and `sed` (only on a terminal)
+    #[arg(long, global = true)]
+    pub progress: bool,
+
+    /// Log phase timings to stderr; repeat (-vv, -vvv) for per-file detail
+    #[arg(short = 'v', long, action = clap::ArgAction::Count, global = true, conflicts_with = "quiet")]
+    pub verbose: u8,
+
+    /// Never fetch `https://` includes; use cached copies and fail on the rest
+    #[arg(long, global = true)]
+    pub offline: bool,
+
+    /// Refuse every `https://` include, cached or not
+    #[arg(long, global = true)]
+    pub deny_remote: bool,
+
+    /// Also accept plain `http://` includes (still subject to the allowlist)
+    #[arg(long, global = true)]
+    pub allow_http: bool,
+}
+
+impl Cli {
+    /// Layout of JSON output selected by `--pretty` / `--compact`.
+    #[must_use]
+    pub const fn json_layout(&self) -> JsonLayout {
+        if self.compact {
+            JsonLayout::Compact
+        } else {
+            JsonLayout::Pretty
+        }
+    }
+}
+
+/// Layout of JSON output.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum JsonLayout {
+    /// Indented over several lines (`--pretty`, the default)
+    #[default]
+    Pretty,
+    /// On a single line (`--compact`)
+    Compact,
+}
+
+/// Settings of the global options that concern the whole invocation rather
+/// than one subcommand, returned by [`parse_command_line`] beside the
+/// [`AppMode`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GlobalOptions {
+    /// Layout of top-level JSON output
+    pub json_layout: JsonLayout,
+    /// Report errors as `{code, message, context}` objects (`--json-errors`)
+    pub json_errors: bool,
+    /// Show progress bars during batch operations (`--progress` without `--quiet`)
+    pub progress: bool,
+    /// Repetitions of `-v`, for [`verbose::init`]
+    pub verbose: u8,
+    /// When to colorize human-readable output (`--color`)
+    pub color: ColorChoice,
+}
+
+/// Serialize `value` for JSON output in `layout`.
+///
+/// # Errors
+/// Returns an error if serialization fails.
+pub fn to_json<T: Serialize + ?Sized>(value: &T, layout: JsonLayout) -> Result<String, String> {
+    let json = match layout {
+        JsonLayout::Pretty => serde_json::to_string_pretty(value),
+        JsonLayout::Compact => serde_json::to_string(value),
+    };
+    json.map_err(|e| format!("JSON serialization error: {e}"))
+}
+
+/// When to emit ANSI colors in human-readable output.
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ColorChoice {
+    /// Colorize only when the output stream is a terminal and `NO_COLOR` is unset
+    #[default]
+    Auto,
+    /// Always colorize, even when piped
+    Always,
+    /// Never colorize
+    Never,
+}
+
+impl ColorChoice {
+    /// Resolve whether this choice enables colors on stdout.
+    #[must_use]
+    pub fn enabled(self) -> bool {
+        self.enabled_for(is_terminal())
+    }
+
+    /// Resolve whether this choice enables colors on a stream, given whether
+    /// that stream is a terminal.
+    #[must_use]
+    pub fn enabled_for(self, terminal: bool) -> bool {
+        match self {
+            Self::Always => true,
+            Self::Never => false,
+            Self::Auto => env::var_os("NO_COLOR").is_none_or(|v| v.is_empty()) && terminal,
+        }
+    }
+
+    /// Apply this choice process-wide for stdout so every colorized helper
+    /// honors it.
+    pub fn apply(self) {
+        colored::control::set_override(self.enabled());
+    }
 }
 
 /// Available subcommands for the prompter CLI.
 ///
 /// Each variant represents a different operation mode of the tool.
 #[derive(Subcommand, Debug)]
+#[allow(clippy::large_enum_variant)]
 pub enum Commands {
     /// Show version information
     Version,
     /// Show license information
     License,
     /// Initialize default config and library
-    Init,
+    Init {
+        /// Starter library to create (minimal, coding, writing)
+        #[arg(long, value_name = "NAME", default_value = templates::DEFAULT_TEMPLATE)]
+        template: String,
+    },
     /// List available profiles
-    List,
+    List {
+        /// Only show profiles whose files changed within this duration (e.g. 7d, 12h, 30m)
+        #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+        since: Option<Duration>,
+        /// Order in which profiles are listed
+        #[arg(long, value_enum, default_value_t = ListSort::Name)]
+        sort: ListSort,
+        /// Output format (defaults to text, or json with --json)
+        #[arg(long, value_enum)]
+        format: Option<ListFormat>,
+        /// Group profiles under their top-level `namespace/` (text and json formats)
+        #[arg(long)]
+        grouped: bool,
+        /// List scenario names (and, in json formats, their profiles) instead of profiles
+        #[arg(long, conflicts_with_all = ["since", "grouped"])]
+        scenarios: bool,
+        /// List at most N profiles, after filtering and sorting
+        #[arg(long, value_name = "N", conflicts_with_all = ["grouped", "scenarios"])]
+        limit: Option<usize>,
+        /// Skip the first M profiles, after filtering and sorting
+        #[arg(long, value_name = "M", default_value_t = 0, conflicts_with_all = ["grouped", "scenarios"])]
+        offset: usize,
+    },
     /// Show dependency tree for profiles
     Tree,
     /// Validate configuration and library references
-    Validate,
+    Validate {
+        /// Report profiles whose include chain nests more than N profiles deep
+        #[arg(long, value_name = "N")]
+        max_depth: Option<usize>,
+        /// Also warn about profiles past their `review_after` date or without metadata
+        #[arg(long)]
+        review: bool,
+        /// Report missing `?` includes as errors rather than notes
+        #[arg(long)]
+        strict_includes: bool,
+        /// Output format (defaults to json with --json, otherwise text)
+        #[arg(long, value_enum)]
+        format: Option<ValidateFormat>,
+    },
+    /// Summarize the library: sizes, include depth, and reuse
+    Stats,
+    /// Check fragments for style problems (whitespace, indentation, long lines)
+    Lint {
+        /// Report lines longer than N characters
+        #[arg(long, value_name = "N", default_value_t = lint::DEFAULT_MAX_LINE_LENGTH)]
+        max_line_length: usize,
+        /// Exit non-zero when any warning is reported
+        #[arg(long)]
+        fail_on_warning: bool,
+    },
+    /// Find and replace text across every library fragment referenced by a profile
+    Sed {
+        /// Regular expression to search for
+        pattern: String,
+        /// Replacement text (`$1` and `${name}` refer to capture groups)
+        replacement: String,
+        /// Treat the pattern and replacement as plain text
+        #[arg(long)]
+        literal: bool,
+        /// Write the changes (without it, only the affected files and counts are shown)
+        #[arg(long, visible_alias = "yes")]
+        apply: bool,
+        /// Also rewrite a leading `---` frontmatter block
+        #[arg(long)]
+        include_frontmatter: bool,
+        /// Stop at the first fragment that cannot be rewritten (the default)
+        #[arg(long, conflicts_with = "keep_going")]
+        fail_fast: bool,
+        /// Rewrite every fragment it can, then report all failures and exit non-zero
+        #[arg(long)]
+        keep_going: bool,
+    },
     /// Render one or more profiles (concatenated file contents with deduplication)
     Run {
         /// Profile name(s) to render
-        #[arg(required = true)]
         profiles: Vec<String>,
+        /// Profile to render (repeatable; merged with positional profiles in order)
+        #[arg(long, value_name = "NAME")]
+        profile: Vec<String>,
+        /// Without profiles, fail instead of picking them interactively (the default on a terminal)
+        #[arg(long)]
+        no_interactive: bool,
+        /// Render the profiles and settings of a `[scenarios.NAME]` section; flags override it
+        #[arg(long, value_name = "NAME", conflicts_with_all = ["check", "count_includes", "all", "repeat"])]
+        scenario: Option<String>,
+        /// Also render every profile whose name matches PATTERN (`*`, `?`), sorted (repeatable)
+        #[arg(long, value_name = "PATTERN", conflicts_with_all = ["check", "count_includes"])]
+        profiles_glob: Vec<String>,
+        /// Insert PROFILE right after ANCHOR in the requested profile order (repeatable)
+        #[arg(long, num_args = 2, value_names = ["ANCHOR", "PROFILE"], conflicts_with_all = ["check", "count_includes"])]
+        after: Vec<String>,
+        /// Insert PROFILE right before ANCHOR in the requested profile order (repeatable)
+        #[arg(long, num_args = 2, value_names = ["ANCHOR", "PROFILE"], conflicts_with_all = ["check", "count_includes"])]
+        before: Vec<String>,
         /// Separator between files
         #[arg(short, long)]
         separator: Option<String>,
+        /// Where the separator goes: where profiles end, between a profile's includes, or both
+        #[arg(long, value_enum, value_name = "WHERE", default_value_t = SeparatorPlacement::Both)]
+        separator_between: SeparatorPlacement,
+        /// Separator between the pre-prompt and system info and the first file (default: --separator)
+        #[arg(long, value_name = "TEXT")]
+        pre_separator: Option<String>,
+        /// Separator between the last file and the post-prompt (default: --separator)
+        #[arg(long, value_name = "TEXT")]
+        post_separator: Option<String>,
         /// Pre-prompt text to inject at the beginning
         #[arg(short = 'p', long)]
         pre_prompt: Option<String>,
+        /// File whose contents form the pre-prompt (repeatable; joined by --pre-separator)
+        #[arg(long, value_name = "PATH", conflicts_with = "pre_prompt")]
+        pre_prompt_file: Vec<PathBuf>,
         /// Post-prompt text to inject at the end
         #[arg(short = 'P', long)]
         post_prompt: Option<String>,
+        /// File whose contents go before the composed body (repeatable)
+        #[arg(long, value_name = "PATH")]
+        prepend_file: Vec<PathBuf>,
+        /// File whose contents go after the composed body (repeatable)
+        #[arg(long, value_name = "PATH")]
+        append_file: Vec<PathBuf>,
+        /// Output format (defaults to text, or json with --json)
+        #[arg(long, value_enum)]
+        format: Option<RenderFormat>,
+        /// Chat role for the pre-prompt message in chat format
+        #[arg(long, value_name = "ROLE", default_value = "system")]
+        pre_prompt_role: String,
+        /// Chat role for the composed body message in chat format
+        #[arg(long, value_name = "ROLE", default_value = "user")]
+        body_role: String,
+        /// Skip this profile wherever it appears in the include tree (repeatable)
+        #[arg(long, value_name = "NAME")]
+        exclude: Vec<String>,
+        /// Render `<!-- omitted: NAME -->` in place of this profile's content (repeatable)
+        #[arg(long, value_name = "NAME")]
+        comment_out: Vec<String>,
+        /// What to do with a requested profile that does not exist
+        #[arg(long, value_enum, value_name = "POLICY", default_value_t = MissingProfilePolicy::Error)]
+        on_missing: MissingProfilePolicy,
+        /// Fail on missing `?` includes instead of skipping them
+        #[arg(long)]
+        strict_includes: bool,
+        /// Render only content reached through this profile (repeatable)
+        #[arg(long, value_name = "NAME")]
+        include_only: Vec<String>,
+        /// Omit fragment lines starting with PREFIX (e.g. "//") from output; empty disables
+        #[arg(long, value_name = "PREFIX")]
+        comment_prefix: Option<String>,
+        /// Substitute `{{KEY}}` with VALUE in every fragment (repeatable)
+        #[arg(long = "var", value_name = "KEY=VALUE", value_parser = vars::parse_var)]
+        vars: Vec<(String, String)>,
+        /// Load `KEY=VALUE` lines from a .env-style file as variables; --var wins (repeatable)
+        #[arg(long, value_name = "PATH", value_parser = vars::read_env_file)]
+        env_file: Vec<vars::EnvFile>,
+        /// Substitute `{{KEY}}` with environment variable KEY, which must be set (repeatable)
+        #[arg(long, value_name = "KEY", value_parser = vars::capture_env)]
+        capture_env: Vec<(String, String)>,
+        /// Like --capture-env, but an unset KEY substitutes as empty (repeatable)
+        #[arg(long, value_name = "KEY", value_parser = vars::capture_env_optional)]
+        capture_env_optional: Vec<(String, String)>,
+        /// Substitute `{{KEY}}` only in content reached through PROFILE (repeatable)
+        #[arg(long, value_name = "PROFILE:KEY=VALUE", value_parser = vars::parse_profile_arg)]
+        profile_arg: Vec<(String, String, String)>,
+        /// strftime format of the built-in `{{today}}` variable
+        #[arg(long, value_name = "FORMAT", default_value = vars::DEFAULT_DATE_FORMAT, value_parser = vars::parse_date_format)]
+        date_format: String,
+        /// Collapse runs of 3+ blank lines to one and trim leading/trailing blank lines
+        #[arg(long)]
+        strip_blank_runs: bool,
+        /// Trim whitespace in each included file: trailing per line, or that plus leading/trailing blank lines
+        #[arg(long, value_enum, value_name = "POLICY", default_value_t = TrimPolicy::None)]
+        trim: TrimPolicy,
+        /// Render each profile's includes in alphabetical order instead of as listed
+        #[arg(long)]
+        sort_includes: bool,
+        /// Render each profile's includes in a shuffled order (random seed printed to stderr)
+        #[arg(long, conflicts_with = "sort_includes")]
+        shuffle_includes: bool,
+        /// Apply Unicode normalization FORM to the text output
+        #[arg(long, value_enum, value_name = "FORM")]
+        normalize_unicode: Option<UnicodeForm>,
+        /// Replace typographic quotes, dashes, ellipses, and special spaces with ASCII
+        #[arg(long)]
+        ascii_fold: bool,
+        /// Seed for --shuffle-includes; the same seed gives the same order
+        #[arg(long, value_name = "N", requires = "shuffle_includes")]
+        seed: Option<u64>,
+        /// Soft-wrap text output at N columns on spaces, leaving fenced code alone (0 = off)
+        #[arg(long, value_name = "N", default_value_t = 0)]
+        wrap: usize,
+        /// Fail if any `{{...}}` placeholder is left unreplaced (write `\{\{` for a literal `{{`)
+        #[arg(long)]
+        strict_vars: bool,
+        /// Render fragment bodies with ENGINE, using --var and --env-file values as context
+        #[arg(long, value_enum, value_name = "ENGINE", default_value_t = engine::TemplateEngine::Simple)]
+        template_engine: engine::TemplateEngine,
+        /// Wrap the whole text output in TEMPLATE, which must contain `{{body}}` exactly once
+        #[arg(long, value_name = "TEMPLATE", value_parser = parse_out_template)]
+        out_template: Option<String>,
+        /// How to read fragments that are not valid UTF-8 (lossy replaces bad bytes, with a warning)
+        #[arg(long, value_enum, default_value_t = Encoding::Utf8)]
+        encoding: Encoding,
+        /// Line endings of text output, whatever the fragments use (native = CRLF on Windows)
+        #[arg(long, value_enum, default_value_t = LineEndings::Lf)]
+        line_endings: LineEndings,
+        /// Write the rendered prompt to stderr (JSON render output stays on stdout)
+        #[arg(long, conflicts_with = "all")]
+        to_stderr: bool,
+        /// Print the profiles that contribute content, in render order, to stderr before rendering
+        #[arg(long, conflicts_with_all = ["all", "check", "count_includes"])]
+        print_order: bool,
+        /// Write the render to TARGET: a file or named pipe, `fd:N`, `/dev/stdout`, or `/dev/stderr`
+        #[arg(long, value_name = "TARGET", value_parser = OutputTarget::parse, conflicts_with_all = ["all", "to_stderr", "check", "count_includes"])]
+        output: Option<OutputTarget>,
+        /// Print a hex digest of the text output instead of it (a `hash` field with --json)
+        #[arg(long, value_enum, value_name = "ALGO", num_args = 0..=1, require_equals = true, default_missing_value = "sha256", conflicts_with_all = ["all", "check", "count_includes"])]
+        hash: Option<HashAlgorithm>,
+        /// Warn when the text output is estimated at fewer than N tokens (about 4 characters each)
+        #[arg(long, value_name = "N", conflicts_with_all = ["check", "count_includes"])]
+        min_tokens: Option<usize>,
+        /// Fail instead of warning when --min-tokens is not met
+        #[arg(long, requires = "min_tokens")]
+        fail_on_warning: bool,
+        /// Append a JSON line of render metrics (size, estimated tokens, timing) to PATH
+        #[arg(long, value_name = "PATH", conflicts_with_all = ["all", "check", "count_includes", "list_vars"])]
+        metrics_json: Option<PathBuf>,
+        /// Compare the render with PATH instead of printing it; print a unified diff and fail on mismatch
+        #[arg(long, value_name = "PATH", conflicts_with_all = ["all", "check", "count_includes", "list_vars", "output", "to_stderr"])]
+        diff_against: Option<PathBuf>,
+        /// Leave out the date and system line (implied by --diff-against)
+        #[arg(long)]
+        no_system_info: bool,
+        /// Render without printing; report problems and unresolved variables, or print OK
+        #[arg(long, conflicts_with_all = ["all", "to_stderr"])]
+        check: bool,
+        /// Instead of rendering, print how many times each profile is included
+        #[arg(long, conflicts_with_all = ["all", "to_stderr", "check"])]
+        count_includes: bool,
+        /// Instead of rendering, list the `{{...}}` variables the profiles use and which have values
+        #[arg(long, conflicts_with_all = ["all", "to_stderr", "check", "count_includes", "output", "hash"])]
+        list_vars: bool,
+        /// Render every profile into --output-dir instead of stdout
+        #[arg(long, requires = "output_dir", conflicts_with_all = ["profiles", "profile", "profiles_glob", "after", "before"])]
+        all: bool,
+        /// Directory for --all output (one file per profile, namespaces as subdirectories)
+        #[arg(long, value_name = "DIR", requires = "all")]
+        output_dir: Option<PathBuf>,
+        /// Re-render only profiles whose includes changed in git since REV (default HEAD)
+        #[arg(long, value_name = "REV", num_args = 0..=1, require_equals = true, default_missing_value = "HEAD", requires = "all")]
+        only_changed: Option<String>,
+        /// Render --all output on N threads (default: one per CPU)
+        #[arg(long, value_name = "N", num_args = 0..=1, default_missing_value = "0", requires = "all")]
+        parallel: Option<usize>,
+        /// Render --all output without reading or writing the render cache
+        #[arg(long, requires = "all")]
+        no_cache: bool,
+        /// Empty the render cache before rendering --all output
+        #[arg(long, requires = "all", conflicts_with = "no_cache")]
+        clear_cache: bool,
+        /// Stop --all at the first profile that fails to render
+        #[arg(long, requires = "all", conflicts_with = "keep_going")]
+        fail_fast: bool,
+        /// Render every --all profile, then report all failures (the default)
+        #[arg(long, requires = "all")]
+        keep_going: bool,
+        /// Re-render the profiles and config of history entry N (1 = most recent)
+        #[arg(long, value_name = "N", conflicts_with_all = ["profiles", "profile", "profiles_glob", "after", "before", "all"])]
+        repeat: Option<usize>,
+    },
+    /// Show recently rendered profiles, most recent first
+    History,
+    /// Print the path of the config file that defines a profile
+    Which {
+        /// Profile to look up
+        profile: String,
+    },
+    /// Print a profile's metadata and fragment frontmatter without its body
+    Meta {
+        /// Profile to describe
+        #[arg(required_unless_present = "all")]
+        profile: Option<String>,
+        /// Describe every profile
+        #[arg(long, conflicts_with = "profile")]
+        all: bool,
+    },
+    /// Bundle the configuration and every file it includes into one file
+    Export {
+        /// Bundle to write (JSON when it ends in .json, TOML otherwise)
+        output: PathBuf,
+    },
+    /// Create config.toml and library/ from a bundle written by `export`
+    Import {
+        /// Bundle to read
+        bundle: PathBuf,
+        /// Directory to create the configuration and library in
+        #[arg(long, value_name = "DIR", default_value = ".")]
+        into: PathBuf,
+    },
+    /// Print a JSON Schema for the configuration file, for editor validation
+    ConfigSchema,
+    /// Serve profile listings and renders over HTTP (needs the `server` feature)
+    Serve {
+        /// Address to listen on
+        #[arg(long, value_name = "HOST:PORT", default_value = serve::DEFAULT_ADDR)]
+        addr: String,
     },
     /// Generate shell completion scripts
     Completions {
         /// Shell to generate completions for
         #[arg(value_enum)]
         shell: clap_complete::Shell,
+        /// Command the generated helpers run to list profiles (defaults to the invoked binary name)
+        #[arg(long, value_name = "CMD")]
+        dynamic_command: Option<String>,
+        /// Bake the current profile names into the script instead of listing them at
+        /// completion time (regenerate after changing profiles)
+        #[arg(long, conflicts_with = "dynamic_command")]
+        eager: bool,
     },
     /// Check health and configuration status
-    Doctor,
+    Doctor {
+        /// Also run the full validator (includes resolve, no cycles, no duplicates)
+        #[arg(long)]
+        deep: bool,
+        /// Create a missing config or library (as `init` does), then run the checks
+        #[arg(long)]
+        fix: bool,
+        /// Do not ask for confirmation before --fix creates files
+        #[arg(long, requires = "fix")]
+        yes: bool,
+    },
 }
 
 /// Application execution modes after parsing command-line arguments.
@@ -103,40 +655,152 @@ pub enum AppMode {
     Run {
         /// Profile name(s) to render
         profiles: Vec<String>,
-        /// Optional separator between concatenated files
-        separator: Option<String>,
-        /// Optional custom pre-prompt text
-        pre_prompt: Option<String>,
-        /// Optional custom post-prompt text
-        post_prompt: Option<String>,
-        /// Optional configuration file override
-        config: Option<PathBuf>,
+        /// Name patterns whose sorted matches are rendered after `profiles`
+        profiles_glob: Vec<String>,
+        /// Profiles inserted next to anchors, applied after glob expansion
+        splices: Vec<Splice>,
+        /// Scenario whose profiles come first and whose settings fill unset options
+        scenario: Option<String>,
+        /// Ask for the profiles with the interactive picker
+        pick: bool,
+        /// Rendering options (separator, prompts, output format)
+        options: RenderOptions,
+        /// Configuration to load
+        config: ConfigSource,
         /// Output in JSON format
         json: bool,
     },
-    /// List all available profiles using an optional config override
-    List {
-        /// Optional configuration file override
-        config: Option<PathBuf>,
+    /// Render profiles without printing them, reporting any problems
+    RunCheck {
+        /// Profile name(s) to render
+        profiles: Vec<String>,
+        /// Rendering options (separator, prompts, output format)
+        options: RenderOptions,
+        /// Configuration to load
+        config: ConfigSource,
+        /// Output in JSON format
+        json: bool,
+        /// Suppress the success message
+        quiet: bool,
+    },
+    /// Count how often each profile is included in a render
+    CountIncludes {
+        /// Profiles to resolve, in order
+        profiles: Vec<String>,
+        /// Options affecting the include tree
+        options: RenderOptions,
+        /// Configuration to load
+        config: ConfigSource,
+        /// Output in JSON format
+        json: bool,
+    },
+    /// List the variables a render of profiles uses
+    ListVars {
+        /// Profiles to scan, in order
+        profiles: Vec<String>,
+        /// Options affecting the include tree and variable values
+        options: RenderOptions,
+        /// Configuration to load
+        config: ConfigSource,
         /// Output in JSON format
         json: bool,
     },
+    /// Render every profile into a directory
+    RunAll {
+        /// Directory receiving one file per profile
+        output_dir: PathBuf,
+        /// Rendering options (separator, prompts, output format)
+        options: RenderOptions,
+        /// Configuration to load
+        config: ConfigSource,
+        /// Output in JSON format
+        json: bool,
+        /// Suppress the summary line
+        quiet: bool,
+        /// Threads, render cache use, and which profiles to re-render
+        batch: batch::BatchOptions,
+    },
+    /// Re-render an entry from the render history
+    Repeat {
+        /// History entry to replay (1 = most recent)
+        index: usize,
+        /// Rendering options (separator, prompts, output format)
+        options: RenderOptions,
+        /// Limits on remote includes (`--offline`)
+        remote: remote::RemoteAccess,
+        /// Output in JSON format
+        json: bool,
+    },
+    /// Show recently rendered profiles
+    History {
+        /// Output in JSON format
+        json: bool,
+    },
+    /// List all available profiles using an optional config override
+    List {
+        /// Configuration to load
+        config: ConfigSource,
+        /// Filtering and ordering options
+        options: ListOptions,
+        /// Output format
+        format: ListFormat,
+    },
     /// Show dependency tree for profiles
     Tree {
-        /// Optional configuration file override
-        config: Option<PathBuf>,
+        /// Configuration to load
+        config: ConfigSource,
         /// Output in JSON format
         json: bool,
     },
     /// Validate configuration and library references with an optional config override
     Validate {
-        /// Optional configuration file override
-        config: Option<PathBuf>,
+        /// Configuration to load
+        config: ConfigSource,
+        /// Structural limits to enforce
+        options: ValidateOptions,
+        /// Output in JSON format
+        json: bool,
+        /// Write a `JUnit` XML report with one testcase per profile
+        junit: bool,
+        /// Suppress the success message
+        quiet: bool,
+    },
+    /// Summarize profile sizes, include depth, and reuse across the library
+    Stats {
+        /// Configuration to load
+        config: ConfigSource,
+        /// Output in JSON format
+        json: bool,
+    },
+    /// Report style problems in the fragments profiles include
+    Lint {
+        /// Configuration to load
+        config: ConfigSource,
+        /// Lint rule settings
+        options: lint::LintOptions,
+        /// Output in JSON format
+        json: bool,
+        /// Fail when any warning is reported
+        fail_on_warning: bool,
+    },
+    /// Find and replace across library fragments
+    Replace {
+        /// Pattern, replacement, and frontmatter handling
+        options: replace::ReplaceOptions,
+        /// Configuration to load
+        config: ConfigSource,
+        /// Write the changes instead of previewing them
+        apply: bool,
         /// Output in JSON format
         json: bool,
     },
     /// Initialize default configuration and library
-    Init,
+    Init {
+        /// Built-in starter library to create
+        template: String,
+        /// Suppress progress and success messages
+        quiet: bool,
+    },
     /// Show version information
     Version {
         /// Output in JSON format
@@ -146,75 +810,763 @@ pub enum AppMode {
     License,
     /// Show help information
     Help,
+    /// Print the config file that defines a profile
+    Which {
+        /// Profile to look up
+        profile: String,
+        /// Configuration to load
+        config: ConfigSource,
+        /// Output in JSON format
+        json: bool,
+    },
+    /// Print profile metadata and fragment frontmatter
+    Meta {
+        /// Profile to describe (`None` for every profile)
+        profile: Option<String>,
+        /// Configuration to load
+        config: ConfigSource,
+        /// Output in JSON format (TOML otherwise)
+        json: bool,
+    },
+    /// Write the configuration and its files to a single bundle
+    Export {
+        /// Configuration to load
+        config: ConfigSource,
+        /// Bundle file to write
+        output: PathBuf,
+        /// Suppress the success message
+        quiet: bool,
+    },
+    /// Scaffold a configuration and library from a bundle
+    Import {
+        /// Bundle file to read
+        bundle: PathBuf,
+        /// Directory to create `config.toml` and `library/` in
+        dir: PathBuf,
+        /// Suppress the success message
+        quiet: bool,
+    },
+    /// Print the resolved configuration and exit
+    DumpConfig {
+        /// Configuration to load
+        config: ConfigSource,
+        /// Output in JSON format
+        json: bool,
+    },
+    /// Validate the configuration before running a config-reading mode
+    Checked {
+        /// Configuration to load
+        config: ConfigSource,
+        /// Output in JSON format
+        json: bool,
+        /// Mode to run once the configuration is valid
+        mode: Box<Self>,
+    },
+    /// Print the configuration JSON Schema
+    ConfigSchema,
+    /// Serve profile listings and renders over HTTP
+    Serve {
+        /// Address to listen on
+        addr: String,
+        /// Configuration to load for every request
+        config: ConfigSource,
+        /// Suppress the startup line
+        quiet: bool,
+    },
     /// Generate shell completion scripts
     Completions {
         /// Shell to generate completions for
         shell: clap_complete::Shell,
+        /// Optional override for the command used to list profiles
+        dynamic_command: Option<String>,
+        /// Configuration whose profile names are baked into the script, with `--eager`
+        eager: Option<ConfigSource>,
     },
     /// Check health and configuration status
     Doctor {
         /// Output in JSON format
         json: bool,
+        /// Suppress the report unless errors are found
+        quiet: bool,
+        /// Also run the full validator
+        deep: bool,
+        /// Default configuration, with any `--include-base` override
+        config: ConfigSource,
+        /// Create a missing config or library before checking
+        fix: bool,
+        /// Skip the --fix confirmation prompt
+        yes: bool,
     },
 }
 
+/// Why a command line could not be parsed into an [`AppMode`].
+///
+/// Each variant carries the full message clap would print, usage included.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    /// `--help` was given; carries the rendered help
+    Help(String),
+    /// `--version` was given; carries the version line
+    Version(String),
+    /// An unknown subcommand or flag was given
+    Unknown(String),
+    /// Flags that cannot be used together were given
+    Conflict(String),
+    /// A required argument or subcommand is missing
+    Missing(String),
+    /// A flag or argument has a value in the wrong form or number
+    InvalidValue(String),
+    /// Any other malformed command line
+    Other(String),
+}
+
+impl ParseError {
+    /// The message to print, as clap renders it.
+    #[must_use]
+    pub fn message(&self) -> &str {
+        match self {
+            Self::Help(message)
+            | Self::Version(message)
+            | Self::Unknown(message)
+            | Self::Conflict(message)
+            | Self::Missing(message)
+            | Self::InvalidValue(message)
+            | Self::Other(message) => message,
+        }
+    }
+
+    /// Whether this is a requested `--help` or `--version` rather than a failure.
+    #[must_use]
+    pub const fn is_informational(&self) -> bool {
+        matches!(self, Self::Help(_) | Self::Version(_))
+    }
+}
+
+impl From<clap::Error> for ParseError {
+    fn from(error: clap::Error) -> Self {
+        use clap::error::ErrorKind;
+
+        let message = error.to_string();
+        match error.kind() {
+            ErrorKind::DisplayHelp => Self::Help(message),
+            ErrorKind::DisplayVersion => Self::Version(message),
+            ErrorKind::UnknownArgument | ErrorKind::InvalidSubcommand => Self::Unknown(message),
+            ErrorKind::ArgumentConflict => Self::Conflict(message),
+            ErrorKind::MissingRequiredArgument
+            | ErrorKind::MissingSubcommand
+            | ErrorKind::DisplayHelpOnMissingArgumentOrSubcommand => Self::Missing(message),
+            ErrorKind::InvalidValue
+            | ErrorKind::ValueValidation
+            | ErrorKind::NoEquals
+            | ErrorKind::TooManyValues
+            | ErrorKind::TooFewValues
+            | ErrorKind::WrongNumberOfValues
+            | ErrorKind::InvalidUtf8 => Self::InvalidValue(message),
+            _ => Self::Other(message),
+        }
+    }
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.message())
+    }
+}
+
 /// Parse command-line arguments and return the resolved application mode.
 ///
 /// This function takes raw command-line arguments and uses clap to parse them
-/// into a structured `AppMode` enum.
+/// into a structured `AppMode` enum. The global `--color` choice is applied
+/// process-wide as a side effect.
 ///
 /// # Arguments
 /// * `args` - Vector of command-line arguments including program name
 ///
 /// # Returns
 /// * `Ok(AppMode)` - Successfully parsed application mode
-/// * `Err(String)` - Error message if parsing fails
+/// * `Err(ParseError)` - Why parsing failed, or the requested help or version
 ///
 /// # Errors
 /// Returns an error if:
 /// - Invalid command-line syntax is provided
 /// - Required arguments are missing
 /// - Conflicting options are specified
-pub fn parse_args_from(args: Vec<String>) -> Result<AppMode, String> {
-    let cli = Cli::try_parse_from(args).map_err(|e| e.to_string())?;
-
-    match cli.command {
-        Commands::Version => Ok(AppMode::Version { json: cli.json }),
-        Commands::License => Ok(AppMode::License),
-        Commands::Init => Ok(AppMode::Init),
-        Commands::List => Ok(AppMode::List {
-            config: cli.config,
-            json: cli.json,
-        }),
-        Commands::Tree => Ok(AppMode::Tree {
-            config: cli.config,
-            json: cli.json,
-        }),
-        Commands::Validate => Ok(AppMode::Validate {
-            config: cli.config,
+/// - `--help` or `--version` is given
+pub fn parse_args_from(args: Vec<String>) -> Result<AppMode, ParseError> {
+    parse_command_line(args).map(|(mode, _)| mode)
+}
+
+/// Parse command-line arguments like [`parse_args_from`], also returning the
+/// [`GlobalOptions`] that the caller applies to the whole invocation.
+///
+/// # Errors
+/// Returns the same errors as [`parse_args_from`].
+pub fn parse_command_line(args: Vec<String>) -> Result<(AppMode, GlobalOptions), ParseError> {
+    let matches = Cli::command().try_get_matches_from(args)?;
+    let cli = Cli::from_arg_matches(&matches)?;
+    let globals = GlobalOptions {
+        json_layout: cli.json_layout(),
+        json_errors: cli.json_errors,
+        progress: cli.progress && !cli.quiet,
+        verbose: cli.verbose,
+        color: cli.color,
+    };
+
+    let config_check = cli.config_check;
+    let config = ConfigSource::from_args(cli.config.clone(), cli.config_inline.clone())
+        .relative_to(cli.relative_to.clone())
+        .include_base(cli.include_base.clone())
+        .input_format(cli.input_format)
+        .remote_access(remote::RemoteAccess {
+            offline: cli.offline,
+            deny: cli.deny_remote,
+            allow_http: cli.allow_http,
+        });
+    let json = cli.json;
+    if cli.dump_config {
+        return Ok((AppMode::DumpConfig { config, json }, globals));
+    }
+    let mode = mode_from_cli(cli, globals, &matches, &config)?;
+    // `validate` already checks everything the preflight would.
+    let reads_config = matches!(
+        mode,
+        AppMode::Run { .. }
+            | AppMode::RunCheck { .. }
+            | AppMode::CountIncludes { .. }
+            | AppMode::ListVars { .. }
+            | AppMode::RunAll { .. }
+            | AppMode::List { .. }
+            | AppMode::Tree { .. }
+            | AppMode::Stats { .. }
+            | AppMode::Lint { .. }
+            | AppMode::Replace { .. }
+            | AppMode::Which { .. }
+            | AppMode::Meta { .. }
+            | AppMode::Export { .. }
+            | AppMode::Serve { .. }
+            | AppMode::Completions { eager: Some(_), .. }
+    );
+    if config_check && reads_config {
+        let mode = AppMode::Checked {
+            config,
+            json,
+            mode: Box::new(mode),
+        };
+        return Ok((mode, globals));
+    }
+    Ok((mode, globals))
+}
+
+fn mode_from_cli(
+    cli: Cli,
+    globals: GlobalOptions,
+    matches: &ArgMatches,
+    config: &ConfigSource,
+) -> Result<AppMode, ParseError> {
+    let Some(command) = cli.command else {
+        return Err(Cli::command()
+            .error(
+                clap::error::ErrorKind::MissingSubcommand,
+                "a subcommand is required unless --dump-config is given",
+            )
+            .into());
+    };
+    Ok(match command {
+        Commands::Version => AppMode::Version { json: cli.json },
+        Commands::License => AppMode::License,
+        Commands::Init { template } => AppMode::Init {
+            template,
+            quiet: cli.quiet,
+        },
+        Commands::History => AppMode::History { json: cli.json },
+        Commands::ConfigSchema => AppMode::ConfigSchema,
+        Commands::Completions {
+            shell,
+            dynamic_command,
+            eager,
+        } => AppMode::Completions {
+            shell,
+            dynamic_command,
+            eager: eager.then(|| config.clone()),
+        },
+        Commands::Doctor { deep, fix, yes } => AppMode::Doctor {
             json: cli.json,
-        }),
-        Commands::Completions { shell } => Ok(AppMode::Completions { shell }),
-        Commands::Doctor => Ok(AppMode::Doctor { json: cli.json }),
-        Commands::Run {
+            quiet: cli.quiet,
+            deep,
+            config: ConfigSource::Default
+                .include_base(cli.include_base)
+                .input_format(cli.input_format),
+            fix,
+            yes,
+        },
+        run @ Commands::Run { .. } => run_mode(run, cli.json, cli.quiet, globals, matches, config)?,
+        library => library_mode(library, cli.json, cli.quiet, globals, config),
+    })
+}
+
+/// Resolve the commands that inspect, edit, or package the configured library.
+#[allow(clippy::too_many_lines)] // one arm per command
+fn library_mode(
+    command: Commands,
+    json: bool,
+    quiet: bool,
+    globals: GlobalOptions,
+    config: &ConfigSource,
+) -> AppMode {
+    match command {
+        Commands::List {
+            since,
+            sort,
+            format,
+            grouped,
+            scenarios,
+            limit,
+            offset,
+        } => AppMode::List {
+            config: config.clone(),
+            options: ListOptions {
+                since,
+                sort,
+                grouped,
+                scenarios,
+                limit,
+                offset,
+                json_layout: globals.json_layout,
+            },
+            format: format.unwrap_or(if json {
+                ListFormat::Json
+            } else {
+                ListFormat::Text
+            }),
+        },
+        Commands::Tree => AppMode::Tree {
+            config: config.clone(),
+            json,
+        },
+        Commands::Validate {
+            max_depth,
+            review,
+            strict_includes,
+            format,
+        } => AppMode::Validate {
+            config: config.clone(),
+            options: ValidateOptions {
+                max_depth,
+                review,
+                strict_includes,
+            },
+            json: format.map_or(json, |f| f == ValidateFormat::Json),
+            junit: format == Some(ValidateFormat::Junit),
+            quiet,
+        },
+        Commands::Stats => AppMode::Stats {
+            config: config.clone(),
+            json,
+        },
+        Commands::Lint {
+            max_line_length,
+            fail_on_warning,
+        } => AppMode::Lint {
+            config: config.clone(),
+            options: lint::LintOptions { max_line_length },
+            json,
+            fail_on_warning,
+        },
+        Commands::Sed {
+            pattern,
+            replacement,
+            literal,
+            apply,
+            include_frontmatter,
+            fail_fast,
+            keep_going,
+        } => AppMode::Replace {
+            options: replace::ReplaceOptions {
+                pattern,
+                replacement,
+                literal,
+                include_frontmatter,
+                failures: FailurePolicy::from_flags(fail_fast, keep_going, FailurePolicy::FailFast),
+                progress: globals.progress,
+            },
+            config: config.clone(),
+            apply,
+            json,
+        },
+        Commands::Which { profile } => AppMode::Which {
+            profile,
+            config: config.clone(),
+            json,
+        },
+        Commands::Meta { profile, all: _ } => AppMode::Meta {
+            profile,
+            config: config.clone(),
+            json,
+        },
+        Commands::Export { output } => AppMode::Export {
+            config: config.clone(),
+            output,
+            quiet,
+        },
+        Commands::Import { bundle, into } => AppMode::Import {
+            bundle,
+            dir: into,
+            quiet,
+        },
+        Commands::Serve { addr } => AppMode::Serve {
+            addr,
+            config: config.clone(),
+            quiet,
+        },
+        other => unreachable!("library_mode called with {other:?}"),
+    }
+}
+
+/// Resolve `prompter run` into a single render, a batch render, a check, an
+/// include count, a variable listing, or a history replay. Without profiles, a plain render asks
+/// for them with the picker when it can, and is a usage error otherwise.
+fn run_mode(
+    command: Commands,
+    json: bool,
+    quiet: bool,
+    globals: GlobalOptions,
+    matches: &ArgMatches,
+    config: &ConfigSource,
+) -> Result<AppMode, ParseError> {
+    let Commands::Run {
+        check,
+        count_includes,
+        list_vars,
+        ref output_dir,
+        repeat,
+        ref profiles_glob,
+        ref scenario,
+        no_interactive,
+        ..
+    } = command
+    else {
+        unreachable!("run_mode called with a non-run command");
+    };
+    let scenario = scenario.clone();
+    let output_dir = output_dir.clone();
+    let batch = batch_options(&command, globals.progress);
+    let profiles_glob = profiles_glob.clone();
+    let run_matches = matches.subcommand_matches("run");
+    let profiles = run_matches
+        .map(|m| values_in_command_line_order(m, &["profiles", "profile"]))
+        .unwrap_or_default();
+    let splices = run_matches
+        .map(splices_in_command_line_order)
+        .unwrap_or_default();
+    let options = RenderOptions {
+        json_layout: globals.json_layout,
+        quiet,
+        ..render_options(command, json)
+    };
+    let config = config.clone();
+    if let Some(index) = repeat {
+        return Ok(AppMode::Repeat {
+            index,
+            options,
+            remote: config.remote_access_limits(),
+            json,
+        });
+    }
+    if let Some(output_dir) = output_dir {
+        return Ok(AppMode::RunAll {
+            output_dir,
+            options,
+            config,
+            json,
+            quiet,
+            batch,
+        });
+    }
+    let pick = profiles.is_empty() && profiles_glob.is_empty() && scenario.is_none();
+    if pick && (no_interactive || check || count_includes || list_vars || !picker::can_pick()) {
+        let mut cli = Cli::command();
+        cli.build();
+        let mut run = cli
+            .find_subcommand_mut("run")
+            .map_or_else(Cli::command, |run| run.clone());
+        return Err(run
+            .error(
+                clap::error::ErrorKind::MissingRequiredArgument,
+                "the following required arguments were not provided:\n  <PROFILES>...",
+            )
+            .into());
+    }
+    if check {
+        return Ok(AppMode::RunCheck {
             profiles,
-            separator,
-            pre_prompt,
-            post_prompt,
-        } => {
-            let sep = separator.as_ref().map(|s| unescape(s));
-            let pre = pre_prompt.as_ref().map(|s| unescape(s));
-            let post = post_prompt.as_ref().map(|s| unescape(s));
-            Ok(AppMode::Run {
-                profiles,
-                separator: sep,
-                pre_prompt: pre,
-                post_prompt: post,
-                config: cli.config,
-                json: cli.json,
-            })
+            options,
+            config,
+            json,
+            quiet,
+        });
+    }
+    if count_includes {
+        return Ok(AppMode::CountIncludes {
+            profiles,
+            options,
+            config,
+            json,
+        });
+    }
+    if list_vars {
+        return Ok(AppMode::ListVars {
+            profiles,
+            options,
+            config,
+            json,
+        });
+    }
+    Ok(AppMode::Run {
+        profiles,
+        profiles_glob,
+        splices,
+        scenario,
+        pick,
+        options,
+        config,
+        json,
+    })
+}
+
+/// Batch options from the `--all` flags of `prompter run`.
+fn batch_options(command: &Commands, progress: bool) -> batch::BatchOptions {
+    let Commands::Run {
+        parallel,
+        no_cache,
+        clear_cache,
+        ref only_changed,
+        fail_fast,
+        keep_going,
+        ..
+    } = *command
+    else {
+        unreachable!("batch_options called with a non-run command");
+    };
+    batch::BatchOptions {
+        jobs: parallel.map_or(1, batch::worker_count),
+        cache: if no_cache {
+            cache::CacheMode::Bypass
+        } else if clear_cache {
+            cache::CacheMode::Clear
+        } else {
+            cache::CacheMode::Use
+        },
+        only_changed: only_changed.clone(),
+        failures: FailurePolicy::from_flags(fail_fast, keep_going, FailurePolicy::KeepGoing),
+        progress,
+    }
+}
+
+/// Render options from the flags of `prompter run`.
+#[allow(clippy::too_many_lines)] // one line or two per flag
+fn render_options(command: Commands, json: bool) -> RenderOptions {
+    let Commands::Run {
+        separator,
+        separator_between,
+        pre_separator,
+        post_separator,
+        pre_prompt,
+        pre_prompt_file,
+        post_prompt,
+        prepend_file,
+        append_file,
+        format,
+        pre_prompt_role,
+        body_role,
+        exclude,
+        comment_out,
+        on_missing,
+        strict_includes,
+        include_only,
+        comment_prefix,
+        vars,
+        env_file,
+        capture_env,
+        capture_env_optional,
+        profile_arg,
+        date_format,
+        strip_blank_runs,
+        trim,
+        sort_includes,
+        shuffle_includes,
+        seed,
+        normalize_unicode,
+        ascii_fold,
+        wrap,
+        strict_vars,
+        template_engine,
+        out_template,
+        encoding,
+        line_endings,
+        to_stderr,
+        print_order,
+        output,
+        hash,
+        min_tokens,
+        fail_on_warning,
+        metrics_json,
+        diff_against,
+        no_system_info,
+        ..
+    } = command
+    else {
+        unreachable!("render_options called with a non-run command");
+    };
+    // Later sources win: --env-file, then --capture-env, then --var.
+    let global_vars = env_file
+        .into_iter()
+        .flat_map(|file| file.0)
+        .chain(capture_env_optional)
+        .chain(capture_env)
+        .chain(vars);
+    let default_format = if json {
+        RenderFormat::Json
+    } else {
+        RenderFormat::Text
+    };
+    RenderOptions {
+        separator: separator.as_ref().map(|s| unescape(s)),
+        separator_between,
+        pre_separator: pre_separator.as_ref().map(|s| unescape(s)),
+        post_separator: post_separator.as_ref().map(|s| unescape(s)),
+        pre_prompt: pre_prompt.as_ref().map(|s| unescape(s)),
+        post_prompt: post_prompt.as_ref().map(|s| unescape(s)),
+        format: format.unwrap_or(default_format),
+        chat_roles: ChatRoles {
+            pre_prompt: pre_prompt_role,
+            body: body_role,
+        },
+        exclude,
+        comment_out,
+        on_missing,
+        strict_includes,
+        include_only,
+        comment_prefix,
+        vars: variables_from_args(global_vars, profile_arg, &date_format),
+        strip_blank_runs,
+        trim,
+        sort_includes,
+        shuffle_seed: shuffle_includes.then(|| seed.unwrap_or_else(random_seed)),
+        seed_generated: shuffle_includes && seed.is_none(),
+        normalize_unicode,
+        ascii_fold,
+        wrap,
+        strict_vars,
+        template_engine,
+        out_template,
+        encoding,
+        line_endings,
+        to_stderr,
+        print_order,
+        output,
+        hash,
+        min_tokens,
+        fail_on_warning,
+        no_system_info: no_system_info || diff_against.is_some(),
+        metrics_json,
+        diff_against,
+        styled: false,
+        json_layout: JsonLayout::Pretty,
+        quiet: false,
+        pre_prompt_files: pre_prompt_file,
+        prepend_files: prepend_file,
+        append_files: append_file,
+    }
+}
+
+/// A seed for `--shuffle-includes` without `--seed`; the render prints it
+/// (see [`RenderOptions::announce_seed`]) so the order can be reproduced.
+fn random_seed() -> u64 {
+    std::hash::BuildHasher::hash_one(&std::collections::hash_map::RandomState::new(), 0)
+}
+
+/// Group `--var` and `--profile-arg` values over the built-in date
+/// variables; later assignments win.
+fn variables_from_args(
+    global: impl IntoIterator<Item = (String, String)>,
+    scoped: Vec<(String, String, String)>,
+    date_format: &str,
+) -> vars::Variables {
+    let mut variables = vars::Variables {
+        builtin: vars::Variables::date_builtins(Local::now(), date_format),
+        global: global.into_iter().collect(),
+        ..vars::Variables::default()
+    };
+    for (profile, key, value) in scoped {
+        variables
+            .profile
+            .entry(profile)
+            .or_default()
+            .insert(key, value);
+    }
+    variables
+}
+
+/// Collect the string values of several arguments, ordered by their position
+/// on the command line.
+fn values_in_command_line_order(matches: &ArgMatches, ids: &[&str]) -> Vec<String> {
+    let mut indexed: Vec<(usize, String)> = Vec::new();
+    for id in ids {
+        if let (Some(indices), Some(values)) =
+            (matches.indices_of(id), matches.get_many::<String>(id))
+        {
+            indexed.extend(indices.zip(values.cloned()));
+        }
+    }
+    indexed.sort_by_key(|(index, _)| *index);
+    indexed.into_iter().map(|(_, value)| value).collect()
+}
+
+/// Collect `run --after` and `--before` pairs, ordered by their position on
+/// the command line.
+fn splices_in_command_line_order(matches: &ArgMatches) -> Vec<Splice> {
+    let mut indexed: Vec<(usize, Splice)> = Vec::new();
+    for (id, before) in [("after", false), ("before", true)] {
+        if let (Some(indices), Some(values)) =
+            (matches.indices_of(id), matches.get_many::<String>(id))
+        {
+            let values: Vec<(usize, &String)> = indices.zip(values).collect();
+            indexed.extend(values.chunks_exact(2).map(|pair| {
+                let splice = Splice {
+                    anchor: pair[0].1.clone(),
+                    profile: pair[1].1.clone(),
+                    before,
+                };
+                (pair[0].0, splice)
+            }));
         }
     }
+    indexed.sort_by_key(|(index, _)| *index);
+    indexed.into_iter().map(|(_, splice)| splice).collect()
+}
+
+/// Parse a duration such as `7d`, `12h`, or `30m`.
+///
+/// # Errors
+/// Returns an error if the value is not a whole number followed by `d`, `h`, or `m`.
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let invalid =
+        || format!("Invalid duration '{s}': expected a number followed by d, h, or m (e.g. 7d)");
+    let trimmed = s.trim();
+    let unit = trimmed.chars().last().ok_or_else(invalid)?;
+    let seconds_per_unit = match unit {
+        'd' => 86_400,
+        'h' => 3_600,
+        'm' => 60,
+        _ => return Err(invalid()),
+    };
+    let amount: u64 = trimmed[..trimmed.len() - 1]
+        .parse()
+        .map_err(|_| invalid())?;
+    amount
+        .checked_mul(seconds_per_unit)
+        .map(Duration::from_secs)
+        .ok_or_else(invalid)
 }
 
 /// Unescape special characters in strings.
@@ -258,7 +1610,7 @@ pub fn unescape(s: &str) -> String {
     out
 }
 
-fn home_dir() -> Result<PathBuf, String> {
+pub(crate) fn home_dir() -> Result<PathBuf, String> {
     env::var("HOME")
         .map(PathBuf::from)
         .map_err(|_| "$HOME not set".into())
@@ -294,10 +1646,6 @@ fn is_terminal() -> bool {
     std::io::stdout().is_terminal()
 }
 
-fn should_use_terminal_effects(json_mode: bool) -> bool {
-    !json_mode && is_terminal()
-}
-
 fn default_pre_prompt() -> String {
     "You are an LLM coding agent. Here are invariants that you must adhere to. Please respond with 'Got it' when you have studied these and understand them. At that point, the operator will give you further instructions. You are *not* to do anything to the contents of this directory until you have been explicitly asked to, by the operator.\n\n".to_string()
 }
@@ -306,12 +1654,21 @@ fn default_post_prompt() -> String {
     "Now, read the @AGENTS.md and @CLAUDE.md files in this directory, if they exist.".to_string()
 }
 
-fn format_system_prefix() -> String {
+fn plain_system_info() -> String {
     let date = Local::now().format("%Y-%m-%d").to_string();
     let os = env::consts::OS;
     let arch = env::consts::ARCH;
+    format!("Today is {date}, and you are running on a {arch}/{os} system.")
+}
 
-    if is_terminal() {
+/// The system info line of text output, decorated with emoji and color when
+/// `styled` (it is going to a terminal).
+fn format_system_prefix(styled: bool) -> String {
+    let date = Local::now().format("%Y-%m-%d").to_string();
+    let os = env::consts::OS;
+    let arch = env::consts::ARCH;
+
+    if styled {
         format!(
             "🗓️  Today is {}, and you are running on a {}/{} system.\n\n",
             date.bright_cyan(),
@@ -339,1129 +1696,6614 @@ fn info_message(msg: &str) -> String {
     }
 }
 
-fn read_config_with_path(path: &Path) -> Result<String, String> {
-    fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))
+/// Where the configuration is loaded from.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum ConfigSource {
+    /// `~/.config/prompter/config.toml`, with the library at `~/.local/prompter/library`
+    #[default]
+    Default,
+    /// An explicit file (`--config`), with the library in a sibling `library/` directory
+    File(PathBuf),
+    /// Several files (`--config` repeated) merged in order, with the library
+    /// beside the first
+    Layered(Vec<PathBuf>),
+    /// TOML text (`--config-inline`), with fragments resolved relative to the cwd
+    Inline(String),
+    /// Another source with its library and `@path` includes rooted at a
+    /// directory (`--relative-to`), overriding any `relative_to` key
+    RelativeTo(Box<Self>, PathBuf),
+    /// Another source with its library at a directory (`--include-base`),
+    /// overriding any `include_base` key
+    IncludeBase(Box<Self>, PathBuf),
+    /// Another source read in a given syntax (`--input-format`) instead of
+    /// the one its file extension implies
+    InputFormat(Box<Self>, ConfigFormat),
+    /// Another source whose remote includes are limited (`--offline`)
+    Remote(Box<Self>, remote::RemoteAccess),
 }
 
-fn resolve_config_path(config_override: Option<&Path>) -> Result<PathBuf, String> {
-    config_override.map_or_else(config_path, config_path_override)
-}
+impl ConfigSource {
+    /// Build a source from the `--config` and `--config-inline` options.
+    #[must_use]
+    pub fn from_args(mut files: Vec<PathBuf>, inline: Option<String>) -> Self {
+        match (inline, files.len()) {
+            (Some(text), _) => Self::Inline(text),
+            (None, 0) => Self::Default,
+            (None, 1) => Self::File(files.remove(0)),
+            (None, _) => Self::Layered(files),
+        }
+    }
 
-fn library_path_for_config_override(
-    config_override: Option<&Path>,
-    resolved_config: &Path,
-) -> Result<PathBuf, String> {
-    if config_override.is_some() {
-        library_dir_for_config(resolved_config)
-    } else {
-        library_dir()
+    /// Root this source's library and `@path` includes at `dir`, if given.
+    #[must_use]
+    pub fn relative_to(self, dir: Option<PathBuf>) -> Self {
+        match (self, dir) {
+            (Self::Remote(source, access), dir) => {
+                Self::Remote(Box::new(source.relative_to(dir)), access)
+            }
+            (Self::InputFormat(source, format), dir) => {
+                Self::InputFormat(Box::new(source.relative_to(dir)), format)
+            }
+            (Self::IncludeBase(source, base), dir) => {
+                Self::IncludeBase(Box::new(source.relative_to(dir)), base)
+            }
+            (Self::RelativeTo(source, _), Some(dir)) => Self::RelativeTo(source, dir),
+            (source, Some(dir)) => Self::RelativeTo(Box::new(source), dir),
+            (source, None) => source,
+        }
     }
-}
 
-/// Parse TOML configuration into a Config structure.
-///
-/// Processes TOML input containing profile definitions and their dependencies,
-/// handling multi-line arrays and comment stripping.
-///
-/// # Arguments
-/// * `input` - TOML configuration text
-///
-/// # Returns
-/// * `Ok(Config)` - Successfully parsed configuration
-/// * `Err(String)` - Error message describing parsing failure
-///
-/// # Errors
-/// Returns an error if:
-/// - TOML syntax is invalid
-/// - Profile sections are malformed
-/// - `depends_on` arrays have invalid syntax
-pub fn parse_config_toml(input: &str) -> Result<Config, String> {
-    let mut profiles: HashMap<String, Vec<String>> = HashMap::new();
-    let mut current: Option<String> = None;
-    let mut post_prompt: Option<String> = None;
+    /// Use `dir` as this source's library directory, if given.
+    #[must_use]
+    pub fn include_base(self, dir: Option<PathBuf>) -> Self {
+        match (self, dir) {
+            (Self::Remote(source, access), dir) => {
+                Self::Remote(Box::new(source.include_base(dir)), access)
+            }
+            (Self::InputFormat(source, format), dir) => {
+                Self::InputFormat(Box::new(source.include_base(dir)), format)
+            }
+            (Self::IncludeBase(source, _), Some(dir)) => Self::IncludeBase(source, dir),
+            (source, Some(dir)) => Self::IncludeBase(Box::new(source), dir),
+            (source, None) => source,
+        }
+    }
 
-    let mut collecting = false;
-    let mut buffer = String::new();
+    /// Read this source's configuration as `format`, if given.
+    #[must_use]
+    pub fn input_format(self, format: Option<ConfigFormat>) -> Self {
+        match (self, format) {
+            (Self::Remote(source, access), format) => {
+                Self::Remote(Box::new(source.input_format(format)), access)
+            }
+            (Self::InputFormat(source, _), Some(format)) => Self::InputFormat(source, format),
+            (source, Some(format)) => Self::InputFormat(Box::new(source), format),
+            (source, None) => source,
+        }
+    }
 
-    for raw_line in input.lines() {
-        let line = strip_comments(raw_line).trim().to_string();
-        if line.is_empty() {
-            continue;
+    /// The format given with [`Self::input_format`], if any.
+    #[must_use]
+    pub fn forced_format(&self) -> Option<ConfigFormat> {
+        match self {
+            Self::InputFormat(_, format) => Some(*format),
+            Self::RelativeTo(source, _)
+            | Self::IncludeBase(source, _)
+            | Self::Remote(source, _) => source.forced_format(),
+            _ => None,
         }
+    }
 
-        if collecting {
-            buffer.push(' ');
-            buffer.push_str(&line);
-            if contains_closing_bracket_outside_quotes(&buffer) {
-                let items = parse_array_items(&buffer).map_err(|e| {
-                    format!(
-                        "Invalid depends_on array for [{}]: {}",
-                        current.clone().unwrap_or_default(),
-                        e
-                    )
-                })?;
-                let name = current
-                    .clone()
-                    .ok_or_else(|| "depends_on outside of a profile section".to_string())?;
-                profiles.insert(name, items);
-                collecting = false;
-                buffer.clear();
-            }
-            continue;
+    /// The limits given with [`Self::remote_access`].
+    #[must_use]
+    pub fn remote_access_limits(&self) -> remote::RemoteAccess {
+        match self {
+            Self::Remote(_, access) => *access,
+            _ => remote::RemoteAccess::default(),
         }
+    }
 
-        if line.starts_with('[') && line.ends_with(']') {
-            let name = line[1..line.len() - 1].trim().to_string();
-            if name.is_empty() {
-                return Err("Empty section name []".into());
-            }
-            current = Some(name);
-            continue;
+    /// Limit the remote includes of configurations loaded from this source,
+    /// unless `access` is the default (no limits).
+    #[must_use]
+    pub fn remote_access(self, access: remote::RemoteAccess) -> Self {
+        match self {
+            Self::Remote(source, _) => source.remote_access(access),
+            source if access == remote::RemoteAccess::default() => source,
+            source => Self::Remote(Box::new(source), access),
         }
+    }
 
-        if let Some(eq_pos) = line.find('=') {
-            let key = line[..eq_pos].trim();
-            let value = line[eq_pos + 1..].trim();
+    /// Syntax to read the configuration at `path` (`None` for inline text) in.
+    #[must_use]
+    pub fn format_of(&self, path: Option<&Path>) -> ConfigFormat {
+        formats::input_format(self.forced_format(), path)
+    }
 
-            if key == "post_prompt" {
-                if !value.starts_with('"') || !value.ends_with('"') {
-                    return Err("post_prompt must be a string".into());
-                }
-                let unquoted = &value[1..value.len() - 1];
-                post_prompt = Some(unescape(unquoted));
-                continue;
+    /// Parse the configuration and locate its library directory.
+    ///
+    /// A root from `--relative-to` (relative to the cwd) or the `relative_to`
+    /// key (relative to the config file's directory) replaces the default
+    /// locations: the library becomes `ROOT/library` and `@path` includes
+    /// resolve against `ROOT`. A base from `--include-base` or the
+    /// `include_base` key (resolved the same way) then replaces the library
+    /// alone.
+    ///
+    /// # Errors
+    /// Returns an error if the configuration cannot be read or parsed, or the
+    /// library location cannot be determined.
+    pub fn load(&self) -> Result<(Config, PathBuf), String> {
+        let format = self.forced_format();
+        let (mut cfg, lib) = verbose::timed("config load", || self.load_rooted(format))?;
+        cfg.remote_access = self.remote_access_limits();
+        log::debug!("loaded {} with library {}", self.describe(), lib.display());
+        Ok((cfg, lib))
+    }
+
+    /// Load with every configuration file read as `format` when given.
+    fn load_rooted(&self, format: Option<ConfigFormat>) -> Result<(Config, PathBuf), String> {
+        match self {
+            Self::IncludeBase(source, dir) => {
+                let (cfg, _) = source.load_rooted(format)?;
+                return Ok((cfg, config_path_override(dir)?));
+            }
+            Self::InputFormat(source, _) | Self::Remote(source, _) => {
+                return source.load_rooted(format);
             }
+            _ => {}
+        }
+        let (source, root) = match self {
+            Self::RelativeTo(source, dir) => (source.as_ref(), Some(config_path_override(dir)?)),
+            source => (source, None),
+        };
+        let (mut cfg, lib) = source.load_unrooted(format)?;
+        let config_dir = cfg.base_dir.clone();
+        let from_config_dir = |dir: &Path| {
+            config_dir
+                .as_deref()
+                .map_or_else(|| dir.to_path_buf(), |base| base.join(dir))
+        };
+        let root = root.or_else(|| cfg.relative_to.as_deref().map(from_config_dir));
+        let lib = match (cfg.include_base.as_deref(), &root) {
+            (Some(base), _) => from_config_dir(base),
+            (None, Some(root)) => root.join("library"),
+            (None, None) => lib,
+        };
+        if root.is_some() {
+            cfg.base_dir = root;
+        }
+        Ok((cfg, lib))
+    }
 
-            if key != "depends_on" {
-                continue;
+    fn load_unrooted(&self, format: Option<ConfigFormat>) -> Result<(Config, PathBuf), String> {
+        let file = match self {
+            Self::RelativeTo(source, _) | Self::IncludeBase(source, _) => {
+                return source.load_unrooted(format);
             }
-            if !value.starts_with('[') {
-                return Err("depends_on must be an array".into());
+            Self::InputFormat(source, forced) => {
+                return source.load_unrooted(format.or(Some(*forced)));
             }
-            buffer.clear();
-            buffer.push_str(value);
-            if contains_closing_bracket_outside_quotes(&buffer) {
-                let items = parse_array_items(&buffer).map_err(|e| {
-                    format!(
-                        "Invalid depends_on array for [{}]: {}",
-                        current.clone().unwrap_or_default(),
-                        e
-                    )
-                })?;
-                let name = current
-                    .clone()
-                    .ok_or_else(|| "depends_on outside of a profile section".to_string())?;
-                profiles.insert(name, items);
-                buffer.clear();
-            } else {
-                collecting = true;
+            Self::Remote(source, _) => return source.load_unrooted(format),
+            Self::Inline(text) => {
+                let mut cfg = parse_config(text, formats::input_format(format, None))?;
+                let cwd = env::current_dir()
+                    .map_err(|e| format!("Failed to resolve working directory: {e}"))?;
+                cfg.base_dir = Some(cwd.clone());
+                return Ok((cfg, cwd));
+            }
+            Self::Layered(paths) => {
+                let mut layers = paths.iter().map(|path| read_config_layer(path, format));
+                let (first, first_path) = layers.next().ok_or("No config files given")??;
+                let mut cfg = first;
+                for layer in layers {
+                    merge_config(&mut cfg, layer?.0);
+                }
+                finish_config(&mut cfg)?;
+                return Ok((cfg, library_dir_for_config(&first_path)?));
+            }
+            Self::File(path) => Some(path.as_path()),
+            Self::Default => None,
+        };
+        let cfg_path = resolve_config_path(file)?;
+        let format = formats::input_format(format, Some(&cfg_path));
+        let mut cfg = parse_config(&read_config_with_path(&cfg_path)?, format)?;
+        cfg.base_dir = cfg_path.parent().map(Path::to_path_buf);
+        let lib = if file.is_some() {
+            library_dir_for_config(&cfg_path)?
+        } else {
+            library_dir()?
+        };
+        Ok((cfg, lib))
+    }
+
+    /// Absolute path of the configuration file, or `None` for `--config-inline`.
+    ///
+    /// # Errors
+    /// Returns an error if the home or working directory cannot be determined.
+    pub fn config_file(&self) -> Result<Option<PathBuf>, String> {
+        match self {
+            Self::Inline(_) => Ok(None),
+            Self::File(path) => config_path_override(path).map(Some),
+            Self::Layered(paths) => paths.first().map(|p| config_path_override(p)).transpose(),
+            Self::RelativeTo(source, _)
+            | Self::IncludeBase(source, _)
+            | Self::InputFormat(source, _)
+            | Self::Remote(source, _) => source.config_file(),
+            Self::Default => config_path().map(Some),
+        }
+    }
+
+    /// The config file whose sections define `profile`: for layered
+    /// configurations, the last file that mentions it.
+    ///
+    /// # Errors
+    /// Returns an error if a config path cannot be determined, or a layer
+    /// cannot be read or parsed.
+    pub fn file_defining(&self, profile: &str) -> Result<Option<PathBuf>, String> {
+        self.file_defining_as(profile, self.forced_format())
+    }
+
+    /// [`Self::file_defining`], reading the layers as `format` when given.
+    fn file_defining_as(
+        &self,
+        profile: &str,
+        format: Option<ConfigFormat>,
+    ) -> Result<Option<PathBuf>, String> {
+        match self {
+            Self::Layered(paths) => {
+                for path in paths.iter().rev() {
+                    let (layer, cfg_path) = read_config_layer(path, format)?;
+                    if layer.profiles.contains_key(profile) || layer.metadata.contains_key(profile)
+                    {
+                        return Ok(Some(cfg_path));
+                    }
+                }
+                self.config_file()
             }
+            Self::RelativeTo(source, _)
+            | Self::IncludeBase(source, _)
+            | Self::InputFormat(source, _)
+            | Self::Remote(source, _) => source.file_defining_as(profile, format),
+            _ => self.config_file(),
         }
     }
 
-    Ok(Config {
-        profiles,
-        post_prompt,
-    })
+    /// Human-readable name of the source for messages.
+    #[must_use]
+    pub fn describe(&self) -> String {
+        match self {
+            Self::Inline(_) => "inline config".to_string(),
+            Self::File(path) => path.display().to_string(),
+            Self::Layered(paths) => paths
+                .iter()
+                .map(|p| p.display().to_string())
+                .collect::<Vec<_>>()
+                .join(" + "),
+            Self::RelativeTo(source, _)
+            | Self::IncludeBase(source, _)
+            | Self::InputFormat(source, _)
+            | Self::Remote(source, _) => source.describe(),
+            Self::Default => config_path().map_or_else(
+                |_| "default config".to_string(),
+                |p| p.display().to_string(),
+            ),
+        }
+    }
 }
 
-fn strip_comments(s: &str) -> String {
-    let mut out = String::with_capacity(s.len());
-    let mut in_str = false;
-    for c in s.chars() {
-        if c == '"' {
-            out.push(c);
-            in_str = !in_str;
-            continue;
+fn read_config_with_path(path: &Path) -> Result<String, String> {
+    read_text(path, Encoding::Utf8, false)
+}
+
+/// Read one file of a layered configuration, unmerged and unfinished, with
+/// its `relative_to` and `include_base` keys made absolute so they keep
+/// resolving against this file's directory once merged.
+fn read_config_layer(
+    path: &Path,
+    format: Option<ConfigFormat>,
+) -> Result<(Config, PathBuf), String> {
+    let cfg_path = config_path_override(path)?;
+    let format = formats::input_format(format, Some(&cfg_path));
+    let mut cfg = parse_config_layer_as(&read_config_with_path(&cfg_path)?, format)
+        .map_err(|e| format!("{}: {e}", cfg_path.display()))?;
+    let dir = cfg_path.parent().map(Path::to_path_buf).unwrap_or_default();
+    cfg.relative_to = cfg.relative_to.map(|p| dir.join(p));
+    cfg.include_base = cfg.include_base.map(|p| dir.join(p));
+    cfg.base_dir = Some(dir);
+    Ok((cfg, cfg_path))
+}
+
+/// Merge a later configuration layer into `base`: settings the overlay sets
+/// replace the base's, profile sections and `[profiles]` entries replace
+/// same-named profiles, and descriptive keys merge key by key.
+pub(crate) fn merge_config(base: &mut Config, overlay: Config) {
+    for name in overlay.inline_profiles.keys() {
+        base.profiles.remove(name);
+        base.metadata.remove(name);
+    }
+    for name in overlay.profiles.keys().chain(overlay.metadata.keys()) {
+        base.inline_profiles.remove(name);
+    }
+    base.profiles.extend(overlay.profiles);
+    base.inline_profiles.extend(overlay.inline_profiles);
+    base.scenarios.extend(overlay.scenarios);
+    for (name, meta) in overlay.metadata {
+        let merged = base.metadata.entry(name).or_default();
+        merged.description = meta.description.or_else(|| merged.description.take());
+        if !meta.tags.is_empty() {
+            merged.tags = meta.tags;
         }
-        if !in_str && c == '#' {
-            break;
+        merged.extends = meta.extends.or_else(|| merged.extends.take());
+        merged.review_after = meta.review_after.or(merged.review_after);
+    }
+    base.post_prompt = overlay.post_prompt.or_else(|| base.post_prompt.take());
+    base.comment_prefix = overlay
+        .comment_prefix
+        .or_else(|| base.comment_prefix.take());
+    base.relative_to = overlay.relative_to.or_else(|| base.relative_to.take());
+    base.include_base = overlay.include_base.or_else(|| base.include_base.take());
+    base.case_insensitive_profiles = overlay
+        .case_insensitive_profiles
+        .or(base.case_insensitive_profiles);
+    base.allowed_hosts = overlay.allowed_hosts.or_else(|| base.allowed_hosts.take());
+}
+
+/// How file contents that are not valid UTF-8 are handled.
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Encoding {
+    /// Fail, naming the file and the offset of the first invalid byte
+    #[default]
+    Utf8,
+    /// Replace invalid bytes with U+FFFD and print a warning
+    Lossy,
+}
+
+/// Unicode normalization form applied to rendered output.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnicodeForm {
+    /// Canonical composition: same characters, composed where possible
+    Nfc,
+    /// Compatibility composition: also folds look-alikes such as ligatures and no-break spaces
+    Nfkc,
+}
+
+/// What a command that processes many profiles or files does when one fails.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FailurePolicy {
+    /// Stop at the first failure
+    #[default]
+    FailFast,
+    /// Carry on, then report every failure and exit non-zero
+    KeepGoing,
+}
+
+impl FailurePolicy {
+    /// The policy chosen by `--fail-fast` or `--keep-going`, else `default`.
+    #[must_use]
+    pub const fn from_flags(fail_fast: bool, keep_going: bool, default: Self) -> Self {
+        if fail_fast {
+            Self::FailFast
+        } else if keep_going {
+            Self::KeepGoing
+        } else {
+            default
         }
-        out.push(c);
     }
-    out
 }
 
-fn contains_closing_bracket_outside_quotes(s: &str) -> bool {
-    let mut in_str = false;
-    for c in s.chars() {
-        if c == '"' {
-            in_str = !in_str;
+/// Fragment boundaries that `--separator` is written at.
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SeparatorPlacement {
+    /// Only where the content of one requested profile ends
+    Profiles,
+    /// Only between fragments of the same requested profile
+    Includes,
+    /// After every fragment
+    #[default]
+    Both,
+}
+
+impl SeparatorPlacement {
+    /// Whether the separator follows `files[i]`.
+    fn after(self, files: &[ResolvedFragment], i: usize) -> bool {
+        let profile = |f: &ResolvedFragment| f.via.first().cloned();
+        let ends_profile = files
+            .get(i + 1)
+            .is_none_or(|next| profile(next) != profile(&files[i]));
+        match self {
+            Self::Profiles => ends_profile,
+            Self::Includes => !ends_profile,
+            Self::Both => true,
         }
-        if !in_str && c == ']' {
-            return true;
+    }
+}
+
+/// Digest algorithm of `run --hash`.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashAlgorithm {
+    /// SHA-256 (the default)
+    Sha256,
+    /// SHA-512
+    Sha512,
+}
+
+impl HashAlgorithm {
+    /// Lowercase hex digest of `bytes`.
+    #[must_use]
+    pub fn hex_digest(self, bytes: &[u8]) -> String {
+        use sha2::Digest;
+        use std::fmt::Write as _;
+        let digest = match self {
+            Self::Sha256 => sha2::Sha256::digest(bytes).to_vec(),
+            Self::Sha512 => sha2::Sha512::digest(bytes).to_vec(),
+        };
+        digest.iter().fold(String::new(), |mut hex, byte| {
+            let _ = write!(hex, "{byte:02x}");
+            hex
+        })
+    }
+}
+
+/// Where `run --output` writes the render.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OutputTarget {
+    /// Standard output (`-`, `/dev/stdout`, or `fd:1`)
+    Stdout,
+    /// Standard error (`/dev/stderr` or `fd:2`)
+    Stderr,
+    /// A file descriptor inherited from the parent process (`fd:N`)
+    Fd(u32),
+    /// A file (created or truncated) or named pipe
+    Path(PathBuf),
+}
+
+impl OutputTarget {
+    /// Parse an `--output` target.
+    ///
+    /// # Errors
+    /// Returns an error if an `fd:` target does not name a descriptor number,
+    /// or names standard input.
+    pub fn parse(target: &str) -> Result<Self, String> {
+        if let Some(fd) = target.strip_prefix("fd:") {
+            return match fd.parse() {
+                Ok(0) => Err("fd:0 is standard input and cannot be written to".to_string()),
+                Ok(1) => Ok(Self::Stdout),
+                Ok(2) => Ok(Self::Stderr),
+                Ok(fd) => Ok(Self::Fd(fd)),
+                Err(_) => Err(format!(
+                    "expected fd:N with a descriptor number, got '{target}'"
+                )),
+            };
+        }
+        Ok(match target {
+            "-" | "/dev/stdout" => Self::Stdout,
+            "/dev/stderr" => Self::Stderr,
+            path => Self::Path(path.into()),
+        })
+    }
+
+    /// Open the target for writing.
+    ///
+    /// # Errors
+    /// Returns an error if the file or descriptor cannot be opened.
+    pub fn open(&self) -> Result<Box<dyn Write>, String> {
+        match self {
+            Self::Stdout => Ok(Box::new(io::stdout().lock())),
+            Self::Stderr => Ok(Box::new(io::stderr().lock())),
+            Self::Fd(fd) => inherited_fd(*fd),
+            Self::Path(path) => fs::File::create(path)
+                .map(|file| Box::new(file) as Box<dyn Write>)
+                .map_err(|e| format!("Cannot write to {}: {e}", path.display())),
         }
     }
-    false
 }
 
-fn parse_array_items(s: &str) -> Result<Vec<String>, String> {
-    let mut items = Vec::new();
-    let mut in_str = false;
-    let mut buf = String::new();
-    let mut escaped = false;
-    let mut started = false;
+/// A writer on the inherited descriptor `fd`. The descriptor itself is used,
+/// not reopened, so its offset and flags (`O_APPEND` from `3>>log`) hold and
+/// pipes and sockets work.
+#[cfg(unix)]
+#[allow(unsafe_code)]
+fn inherited_fd(fd: u32) -> Result<Box<dyn Write>, String> {
+    use std::os::fd::{FromRawFd, OwnedFd};
+
+    let raw = i32::try_from(fd).map_err(|_| format!("Cannot write to fd:{fd}: out of range"))?;
+    // Refuse a descriptor that is not open rather than adopt a number that
+    // nothing holds.
+    fs::metadata(format!("/dev/fd/{fd}")).map_err(|e| format!("Cannot write to fd:{fd}: {e}"))?;
+    // SAFETY: the descriptor is open (checked above) and was inherited from
+    // the parent; fd 0-2 never get here, so nothing else in the process owns it.
+    let file = fs::File::from(unsafe { OwnedFd::from_raw_fd(raw) });
+    Ok(Box::new(io::BufWriter::new(file)))
+}
 
-    for c in s.chars() {
-        if !started {
-            if c == '[' {
-                started = true;
+#[cfg(not(unix))]
+fn inherited_fd(fd: u32) -> Result<Box<dyn Write>, String> {
+    Err(format!("Cannot write to fd:{fd}: only supported on Unix"))
+}
+
+/// What `run` does with a requested profile that does not exist.
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MissingProfilePolicy {
+    /// Fail the render
+    #[default]
+    Error,
+    /// Leave it out, with a warning on stderr
+    Skip,
+    /// Render `<!-- missing: NAME -->` in its place
+    Placeholder,
+}
+
+/// Whitespace trimmed from each included file before composition.
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TrimPolicy {
+    /// Leave whitespace as written
+    #[default]
+    None,
+    /// Strip trailing whitespace from every line
+    Lines,
+    /// Strip trailing whitespace and leading/trailing blank lines
+    All,
+}
+
+impl TrimPolicy {
+    /// Trim `text`, one included file's content, under this policy.
+    #[must_use]
+    pub fn apply(self, text: String) -> String {
+        if self == Self::None {
+            return text;
+        }
+        let mut out = String::with_capacity(text.len());
+        for line in text.split_inclusive('\n') {
+            out.push_str(line.trim_end());
+            if line.ends_with('\n') {
+                out.push('\n');
             }
-            continue;
         }
-        if c == ']' && !in_str {
-            break;
+        if self == Self::All {
+            let body = out.trim_start_matches('\n').trim_end_matches('\n');
+            let newline = if !body.is_empty() && out.ends_with('\n') {
+                "\n"
+            } else {
+                ""
+            };
+            out = format!("{body}{newline}");
         }
-        if in_str {
-            if escaped {
-                buf.push(c);
-                escaped = false;
-                continue;
-            }
-            if c == '\\' {
-                escaped = true;
-                continue;
-            }
-            if c == '"' {
-                in_str = false;
-                items.push(buf.clone());
-                buf.clear();
-                continue;
-            }
-            buf.push(c);
-        } else if c == '"' {
-            in_str = true;
+        out
+    }
+}
+
+/// Line-ending style of rendered text output.
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LineEndings {
+    /// `\n`
+    #[default]
+    Lf,
+    /// `\r\n`
+    Crlf,
+    /// CRLF on Windows, LF elsewhere
+    Native,
+}
+
+impl LineEndings {
+    const fn newline(self) -> &'static [u8] {
+        match self {
+            Self::Crlf => b"\r\n",
+            Self::Native if cfg!(windows) => b"\r\n",
+            Self::Lf | Self::Native => b"\n",
         }
     }
 
-    if in_str {
-        return Err("Unterminated string in array".into());
+    /// Rewrite every line ending in `text` (LF or CRLF) to this style.
+    #[must_use]
+    pub fn apply(self, text: &str) -> String {
+        let mut out = LineEndingWriter::new(Vec::with_capacity(text.len()), self);
+        // Writing to a Vec cannot fail.
+        let _ = out.write_all(text.as_bytes());
+        let _ = out.flush();
+        String::from_utf8_lossy(&out.inner).into_owned()
     }
-    Ok(items)
 }
 
-/// Errors that can occur during profile resolution.
+/// Writer adapter that rewrites LF and CRLF line endings as it streams.
 ///
-/// These errors represent various failure modes when resolving
-/// profile dependencies and validating file references.
-#[derive(Debug, PartialEq, Eq)]
-pub enum ResolveError {
-    /// Referenced profile name does not exist in configuration
-    UnknownProfile(String),
-    /// Circular dependency detected in profile references
-    Cycle(Vec<String>),
-    /// Referenced markdown file does not exist
-    MissingFile(PathBuf, String), // (path, referenced_by)
+/// A trailing `\r` is held back until the next write shows whether it starts
+/// a CRLF pair, so callers must `flush` when done.
+struct LineEndingWriter<W> {
+    inner: W,
+    style: LineEndings,
+    pending_cr: bool,
 }
 
-/// Node type in the dependency tree
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
-#[serde(rename_all = "lowercase")]
-pub enum TreeNodeType {
-    /// Profile node
-    Profile,
-    /// Fragment (markdown file) node
-    Fragment,
+impl<W: Write> LineEndingWriter<W> {
+    const fn new(inner: W, style: LineEndings) -> Self {
+        Self {
+            inner,
+            style,
+            pending_cr: false,
+        }
+    }
 }
 
-/// Tree node representing a profile or fragment in the dependency tree
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct TreeNode {
-    /// Type of node (profile or fragment)
-    #[serde(rename = "type")]
-    pub node_type: TreeNodeType,
-    /// Name of profile or path of fragment
-    pub name: String,
-    /// Children of this node
-    #[serde(skip_serializing_if = "Vec::is_empty")]
-    pub children: Vec<TreeNode>,
+impl<W: Write> Write for LineEndingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut out = Vec::with_capacity(buf.len() + 16);
+        for &byte in buf {
+            if std::mem::take(&mut self.pending_cr) && byte != b'\n' {
+                out.push(b'\r');
+            }
+            match byte {
+                b'\r' => self.pending_cr = true,
+                b'\n' => out.extend_from_slice(self.style.newline()),
+                _ => out.push(byte),
+            }
+        }
+        if !out.is_empty() {
+            self.inner.write_all(&out)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if std::mem::take(&mut self.pending_cr) {
+            self.inner.write_all(b"\r")?;
+        }
+        self.inner.flush()
+    }
 }
 
-/// Complete tree structure for JSON output
-#[derive(Debug, Serialize, Deserialize)]
-pub struct TreeOutput {
-    /// List of root trees (top-level profiles)
-    pub trees: Vec<TreeNode>,
+/// Read a text file, decoding it as UTF-8 according to `encoding`; the
+/// warning for replaced bytes is left out when `quiet`.
+fn read_text(path: &Path, encoding: Encoding, quiet: bool) -> Result<String, String> {
+    let bytes = fs::read(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    match String::from_utf8(bytes) {
+        Ok(text) => Ok(text),
+        Err(e) => {
+            let offset = e.utf8_error().valid_up_to();
+            match encoding {
+                Encoding::Utf8 => Err(format!(
+                    "{} is not valid UTF-8 (invalid byte at offset {offset}); \
+                     re-save it as UTF-8",
+                    path.display()
+                )),
+                Encoding::Lossy => {
+                    if !quiet {
+                        eprintln!(
+                            "Warning: {} is not valid UTF-8 (invalid byte at offset {offset}); \
+                         invalid bytes were replaced",
+                            path.display()
+                        );
+                    }
+                    Ok(String::from_utf8_lossy(e.as_bytes()).into_owned())
+                }
+            }
+        }
+    }
 }
 
-/// Recursively resolve a profile's dependencies into a list of file paths.
+fn resolve_config_path(config_override: Option<&Path>) -> Result<PathBuf, String> {
+    config_override.map_or_else(config_path, config_path_override)
+}
+
+/// Parse TOML configuration into a Config structure.
 ///
-/// Performs depth-first traversal of profile dependencies, handling both
-/// direct file references and recursive profile dependencies. Implements
-/// cycle detection and file deduplication.
+/// Processes TOML input containing profile definitions and their dependencies,
+/// handling multi-line arrays and comment stripping.
 ///
 /// # Arguments
-/// * `name` - Profile name to resolve
-/// * `cfg` - Configuration containing profile definitions
-/// * `lib` - Library root directory for resolving file paths
-/// * `seen_files` - Set tracking already included files for deduplication
-/// * `stack` - Stack for cycle detection during recursion
-/// * `out` - Output vector to collect resolved file paths
+/// * `input` - TOML configuration text
 ///
 /// # Returns
-/// * `Ok(())` - Profile successfully resolved
-/// * `Err(ResolveError)` - Resolution failed due to missing files, cycles, or unknown profiles
+/// * `Ok(Config)` - Successfully parsed configuration
+/// * `Err(String)` - Error message describing parsing failure
 ///
 /// # Errors
 /// Returns an error if:
-/// - Profile name is not found in configuration
-/// - Circular dependency is detected
-/// - Referenced markdown file does not exist
-#[allow(clippy::implicit_hasher)]
-pub fn resolve_profile(
-    name: &str,
-    cfg: &Config,
-    lib: &Path,
-    seen_files: &mut HashSet<PathBuf>,
-    stack: &mut Vec<String>,
-    out: &mut Vec<PathBuf>,
-) -> Result<(), ResolveError> {
-    if stack.contains(&name.to_string()) {
-        let mut cycle = stack.clone();
-        cycle.push(name.to_string());
-        return Err(ResolveError::Cycle(cycle));
-    }
-    let deps = cfg
-        .profiles
-        .get(name)
-        .ok_or_else(|| ResolveError::UnknownProfile(name.to_string()))?;
-    stack.push(name.to_string());
-    for dep in deps {
-        if std::path::Path::new(dep)
-            .extension()
-            .is_some_and(|ext| ext.eq_ignore_ascii_case("md"))
-        {
-            let path = lib.join(dep);
-            if !path.exists() {
-                return Err(ResolveError::MissingFile(path, name.to_string()));
-            }
-            if seen_files.insert(path.clone()) {
-                out.push(path);
-            }
-        } else {
-            resolve_profile(dep, cfg, lib, seen_files, stack, out)?;
-        }
-    }
-    stack.pop();
-    Ok(())
+/// - TOML syntax is invalid
+/// - Profile sections are malformed
+/// - `depends_on` arrays have invalid syntax
+pub fn parse_config_toml(input: &str) -> Result<Config, String> {
+    parse_config(input, ConfigFormat::Toml)
 }
 
-/// JSON output structure for list command
-#[derive(Debug, Serialize)]
-struct ListOutput {
-    profiles: Vec<ProfileInfo>,
-    fragments: Vec<String>,
+/// Parse a configuration document written in `format` (TOML, JSON, or YAML).
+///
+/// # Errors
+/// Returns an error if the document is not valid `format`, or describes an
+/// invalid configuration.
+pub fn parse_config(input: &str, format: ConfigFormat) -> Result<Config, String> {
+    let mut cfg = parse_config_layer_as(input, format)?;
+    finish_config(&mut cfg)?;
+    Ok(cfg)
 }
 
-/// Profile information for JSON output
-#[derive(Debug, Serialize)]
-struct ProfileInfo {
-    name: String,
-    dependencies: Vec<String>,
+/// Parse one configuration document written in `format`, unfinished.
+fn parse_config_layer_as(input: &str, format: ConfigFormat) -> Result<Config, String> {
+    let mut cfg = match format {
+        ConfigFormat::Toml => parse_config_layer(input)?,
+        ConfigFormat::Json | ConfigFormat::Yaml => formats::parse_document(input, format)?,
+    };
+    expand_config_env(&mut cfg, |name| env::var(name).ok())?;
+    Ok(cfg)
 }
 
-/// List all available profiles to a writer.
-///
-/// Outputs all profile names from the configuration in alphabetical order,
-/// one per line (text mode) or as JSON (json mode).
-///
-/// # Arguments
-/// * `cfg` - Configuration containing profile definitions
-/// * `lib` - Library root directory for finding fragments
-/// * `json` - Whether to output JSON format
-/// * `w` - Writer to output profile names to
-///
-/// # Returns
-/// * `Ok(())` - All profiles listed successfully
-/// * `Err(String)` - Operation failed
-///
-/// # Errors
-/// Returns an error if writing to the output fails.
-pub fn list_profiles(
-    cfg: &Config,
-    lib: &Path,
-    json: bool,
-    mut w: impl Write,
+/// Expand `${VAR}` references in the path values of `cfg` (`relative_to`,
+/// `include_base`, and `depends_on` entries), looking variables up with `lookup`.
+fn expand_config_env(
+    cfg: &mut Config,
+    lookup: impl Fn(&str) -> Option<String>,
 ) -> Result<(), String> {
-    if json {
-        // Collect all fragments from library directory
-        let mut fragments = Vec::new();
-        if lib.exists() {
-            collect_fragments(lib, lib, &mut fragments)?;
+    for (key, path) in [
+        ("relative_to", &mut cfg.relative_to),
+        ("include_base", &mut cfg.include_base),
+    ] {
+        if let Some(value) = path.as_mut() {
+            let expanded = expand_env(&value.to_string_lossy(), &lookup)
+                .map_err(|e| format!("{e} (in {key})"))?;
+            *value = expanded.into();
         }
-        fragments.sort();
-
-        // Build profile info
-        let mut profiles: Vec<ProfileInfo> = cfg
-            .profiles
-            .iter()
-            .map(|(name, deps)| ProfileInfo {
-                name: name.clone(),
-                dependencies: deps.clone(),
-            })
-            .collect();
-        profiles.sort_by(|a, b| a.name.cmp(&b.name));
+    }
+    for (name, deps) in &mut cfg.profiles {
+        for dep in deps {
+            *dep = expand_env(dep, &lookup).map_err(|e| format!("{e} (in [{name}])"))?;
+        }
+    }
+    Ok(())
+}
 
-        let output = ListOutput {
-            profiles,
-            fragments,
+/// Replace `${VAR}` in `text` with the value of `VAR`, and `${VAR:-default}`
+/// with `default` when `VAR` is unset or empty. `$${` is a literal `${`.
+///
+/// # Errors
+/// Returns an error if a variable without a default is unset, or a `${` is
+/// not closed.
+fn expand_env(text: &str, lookup: impl Fn(&str) -> Option<String>) -> Result<String, String> {
+    if !text.contains("${") {
+        return Ok(text.to_string());
+    }
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("${") {
+        if rest[..start].ends_with('$') {
+            out.push_str(&rest[..start - 1]);
+            out.push_str("${");
+            rest = &rest[start + 2..];
+            continue;
+        }
+        out.push_str(&rest[..start]);
+        let Some(len) = rest[start + 2..].find('}') else {
+            return Err(format!("Unclosed ${{ in '{text}'"));
         };
-        let json_output = serde_json::to_string_pretty(&output)
-            .map_err(|e| format!("JSON serialization error: {e}"))?;
-        writeln!(&mut w, "{json_output}").map_err(|e| format!("Write error: {e}"))?;
-    } else {
-        let mut names: Vec<_> = cfg.profiles.keys().cloned().collect();
-        names.sort();
-        for n in names {
-            writeln!(&mut w, "{n}").map_err(|e| format!("Write error: {e}"))?;
+        let reference = &rest[start + 2..start + 2 + len];
+        let (name, default) = match reference.split_once(":-") {
+            Some((name, default)) => (name, Some(default)),
+            None => (reference, None),
+        };
+        match (lookup(name), default) {
+            (Some(value), Some(default)) if value.is_empty() => out.push_str(default),
+            (Some(value), _) => out.push_str(&value),
+            (None, Some(default)) => out.push_str(default),
+            (None, None) => return Err(format!("Environment variable {name} is not set")),
         }
+        rest = &rest[start + 3 + len..];
     }
-    Ok(())
+    out.push_str(rest);
+    Ok(out)
 }
 
-/// Recursively collect all .md files from a directory
-fn collect_fragments(root: &Path, dir: &Path, fragments: &mut Vec<String>) -> Result<(), String> {
-    let entries = fs::read_dir(dir)
-        .map_err(|e| format!("Failed to read directory {}: {}", dir.display(), e))?;
+/// Parse one configuration document without resolving inline profiles or
+/// inheritance, so that several documents can be merged first.
+fn parse_config_layer(input: &str) -> Result<Config, String> {
+    let mut cfg = Config::default();
+    let mut current: Option<String> = None;
 
-    for entry in entries {
-        let entry = entry.map_err(|e| format!("Failed to read directory entry: {e}"))?;
-        let path = entry.path();
+    // Key of a multi-line array still being collected, and its text so far
+    let mut collecting: Option<String> = None;
+    let mut buffer = String::new();
 
-        if path.is_dir() {
-            collect_fragments(root, &path, fragments)?;
-        } else if path
-            .extension()
-            .is_some_and(|ext| ext.eq_ignore_ascii_case("md"))
-        {
-            if let Ok(rel_path) = path.strip_prefix(root) {
-                fragments.push(rel_path.display().to_string());
-            }
+    for raw_line in input.lines() {
+        let line = strip_comments(raw_line).trim().to_string();
+        if line.is_empty() {
+            continue;
         }
-    }
-
-    Ok(())
-}
 
-/// Validate configuration and library file references.
-///
-/// Checks that all profile dependencies are valid, including:
-/// - Referenced profiles exist in configuration
-/// - Referenced markdown files exist in library
-/// - No circular dependencies exist
-///
-/// # Arguments
-/// * `cfg` - Configuration to validate
-/// * `lib` - Library root directory for file validation
-///
-/// # Returns
-/// * `Ok(())` - Configuration is valid
-/// * `Err(String)` - Validation errors found
-///
-/// # Errors
-/// Returns an error if:
-/// - Referenced profiles don't exist
-/// - Referenced files don't exist
-/// - Circular dependencies are detected
-pub fn validate(cfg: &Config, lib: &Path) -> Result<(), String> {
-    let mut errors: Vec<String> = Vec::new();
+        if let Some(key) = &collecting {
+            buffer.push(' ');
+            buffer.push_str(&line);
+            if contains_closing_bracket_outside_quotes(&buffer) {
+                store_array(&mut cfg, current.as_deref(), key, &buffer)?;
+                collecting = None;
+                buffer.clear();
+            }
+            continue;
+        }
 
-    for (profile, deps) in &cfg.profiles {
-        for dep in deps {
-            if std::path::Path::new(dep)
-                .extension()
-                .is_some_and(|ext| ext.eq_ignore_ascii_case("md"))
-            {
-                let path = lib.join(dep);
-                if !path.exists() {
-                    errors.push(format!(
-                        "Missing file: {} (referenced by [{}])",
-                        path.display(),
-                        profile
-                    ));
-                }
-            } else if !cfg.profiles.contains_key(dep) {
-                errors.push(format!(
-                    "Unknown profile: {dep} (referenced by [{profile}])"
-                ));
+        if line.starts_with('[') && line.ends_with(']') {
+            let name = line[1..line.len() - 1].trim().to_string();
+            if name.is_empty() {
+                return Err("Empty section name []".into());
             }
+            current = Some(name);
+            continue;
         }
-    }
 
-    for name in cfg.profiles.keys() {
-        let mut seen_files = HashSet::new();
-        let mut stack = Vec::new();
-        let mut out = Vec::new();
-        if let Err(ResolveError::Cycle(cycle)) =
-            resolve_profile(name, cfg, lib, &mut seen_files, &mut stack, &mut out)
-        {
-            let chain = cycle.join(" -> ");
-            errors.push(format!("Cycle detected: {chain}"));
+        let Some(eq_pos) = line.find('=') else {
+            continue;
+        };
+        let key = line[..eq_pos].trim();
+        let value = line[eq_pos + 1..].trim();
+        if current.as_deref() == Some(INLINE_PROFILES_SECTION) {
+            store_inline_profile(&mut cfg, key, value)?;
+            continue;
+        }
+        if let Some(scenario) = scenario_section(current.as_deref())? {
+            if key != "profiles" {
+                store_scenario_setting(&mut cfg, scenario, key, value)?;
+                continue;
+            }
+        }
+        // [remote] holds only allowed_hosts, which means nothing elsewhere
+        if (current.as_deref() == Some(REMOTE_SECTION)) != (key == "allowed_hosts") {
+            continue;
+        }
+        match key {
+            "post_prompt" => cfg.post_prompt = Some(parse_string_value(key, value)?),
+            "comment_prefix" => cfg.comment_prefix = Some(parse_string_value(key, value)?),
+            "relative_to" => cfg.relative_to = Some(parse_string_value(key, value)?.into()),
+            "include_base" => cfg.include_base = Some(parse_string_value(key, value)?.into()),
+            "case_insensitive_profiles" => {
+                cfg.case_insensitive_profiles = Some(match value {
+                    "true" => true,
+                    "false" => false,
+                    _ => return Err(format!("{key} must be true or false, got {value}")),
+                });
+            }
+            "description" => {
+                let name = section_for(current.as_deref(), key)?;
+                cfg.metadata.entry(name).or_default().description =
+                    Some(parse_string_value(key, value)?);
+            }
+            "review_after" => {
+                let name = section_for(current.as_deref(), key)?;
+                let date = parse_review_after(&name, &parse_string_value(key, value)?)?;
+                cfg.metadata.entry(name).or_default().review_after = Some(date);
+            }
+            "extends" => {
+                let name = section_for(current.as_deref(), key)?;
+                if value.starts_with('[') {
+                    return Err(format!("extends for [{name}] must name a single profile"));
+                }
+                let parent = parse_string_value(key, value)?;
+                let meta = cfg.metadata.entry(name.clone()).or_default();
+                if meta.extends.is_some() {
+                    return Err(format!("Multiple extends for [{name}]"));
+                }
+                meta.extends = Some(parent);
+            }
+            "profiles"
+                if current
+                    .as_deref()
+                    .is_none_or(|c| !c.starts_with(SCENARIO_PREFIX)) => {}
+            "depends_on" | "tags" | "profiles" | "allowed_hosts" => {
+                if !value.starts_with('[') {
+                    return Err(format!("{key} must be an array"));
+                }
+                buffer.clear();
+                buffer.push_str(value);
+                if contains_closing_bracket_outside_quotes(&buffer) {
+                    store_array(&mut cfg, current.as_deref(), key, &buffer)?;
+                    buffer.clear();
+                } else {
+                    collecting = Some(key.to_string());
+                }
+            }
+            _ => {}
         }
     }
 
-    if errors.is_empty() {
-        Ok(())
-    } else {
-        Err(errors.join("\n"))
-    }
+    Ok(cfg)
 }
 
-/// Build a tree node for a profile or fragment
-fn build_tree_node(name: &str, cfg: &Config) -> TreeNode {
-    // Check if it's a fragment (ends with .md)
-    if std::path::Path::new(name)
-        .extension()
-        .is_some_and(|ext| ext.eq_ignore_ascii_case("md"))
-    {
-        return TreeNode {
-            node_type: TreeNodeType::Fragment,
-            name: name.to_string(),
-            children: Vec::new(),
-        };
-    }
+/// Parse the `review_after` date of profile `name`.
+pub(crate) fn parse_review_after(name: &str, date: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .map_err(|_| format!("review_after for [{name}] must be a YYYY-MM-DD date, got '{date}'"))
+}
 
-    // It's a profile - recursively build children
-    let children = cfg
-        .profiles
-        .get(name)
-        .map(|deps| deps.iter().map(|dep| build_tree_node(dep, cfg)).collect())
-        .unwrap_or_default();
+/// Register inline profiles and flatten inheritance in a parsed configuration.
+fn finish_config(cfg: &mut Config) -> Result<(), String> {
+    merge_inline_profiles(cfg)?;
+    normalize_profile_names(cfg)?;
+    apply_extends(cfg)
+}
 
-    TreeNode {
-        node_type: TreeNodeType::Profile,
-        name: name.to_string(),
-        children,
+/// With `case_insensitive_profiles = true`, rewrite every profile reference
+/// (`depends_on`, `extends`, scenario `profiles`) to the spelling of the
+/// profile it names, rejecting profiles whose names differ only in case.
+fn normalize_profile_names(cfg: &mut Config) -> Result<(), String> {
+    if cfg.case_insensitive_profiles != Some(true) {
+        return Ok(());
+    }
+    let mut names: Vec<&String> = cfg.profiles.keys().chain(cfg.metadata.keys()).collect();
+    names.sort();
+    names.dedup();
+    let mut defined: HashMap<String, String> = HashMap::new();
+    for name in names {
+        if let Some(other) = defined.insert(name.to_lowercase(), name.clone()) {
+            return Err(format!(
+                "Profiles '{other}' and '{name}' differ only in case, which is ambiguous with case_insensitive_profiles = true"
+            ));
+        }
+    }
+    for dep in cfg.profiles.values_mut().flatten() {
+        *dep = canonical_reference(&defined, dep);
     }
+    let parents = cfg.metadata.values_mut().filter_map(|m| m.extends.as_mut());
+    let scenario_profiles = cfg.scenarios.values_mut().flat_map(|s| &mut s.profiles);
+    for name in parents.chain(scenario_profiles) {
+        *name = canonical_reference(&defined, name);
+    }
+    Ok(())
 }
 
-/// Find root profiles (profiles that are not referenced by any other profile)
-fn find_root_profiles(cfg: &Config) -> Vec<String> {
-    let mut referenced = HashSet::new();
-
-    // Collect all profiles that are referenced by others
-    for deps in cfg.profiles.values() {
-        for dep in deps {
-            // Only track profile references (not .md files)
-            if !std::path::Path::new(dep)
-                .extension()
-                .is_some_and(|ext| ext.eq_ignore_ascii_case("md"))
-            {
-                referenced.insert(dep.clone());
+/// A dependency entry with the profile it names (on either side of an
+/// override arrow) spelled as `defined` maps its lowercase name.
+fn canonical_reference(defined: &HashMap<String, String>, entry: &str) -> String {
+    if let Some((old, new)) = entry.split_once(OVERRIDE_ARROW) {
+        return format!(
+            "{} {OVERRIDE_ARROW} {}",
+            canonical_reference(defined, old.trim()),
+            canonical_reference(defined, new.trim())
+        );
+    }
+    let (target, optional) = split_optional(entry);
+    match defined.get(&target.to_lowercase()) {
+        Some(name) if !is_fragment(target) => {
+            if optional {
+                format!("?{name}")
+            } else {
+                name.clone()
             }
         }
+        _ => entry.to_string(),
     }
-
-    // Find profiles that are never referenced
-    let mut roots: Vec<String> = cfg
-        .profiles
-        .keys()
-        .filter(|profile| !referenced.contains(*profile))
-        .cloned()
-        .collect();
-
-    roots.sort();
-    roots
 }
 
-/// Build complete tree structure for all root profiles
-fn build_trees(cfg: &Config) -> TreeOutput {
-    let root_profiles = find_root_profiles(cfg);
-    let trees = root_profiles
-        .iter()
-        .map(|profile| build_tree_node(profile, cfg))
-        .collect();
-
-    TreeOutput { trees }
-}
+/// Section whose keys define profiles by inline body (`name = "text"`).
+const INLINE_PROFILES_SECTION: &str = "profiles";
 
-/// Print tree structure in traditional tree format
-fn print_tree(node: &TreeNode, prefix: &str, is_last: bool, w: &mut impl Write) -> io::Result<()> {
-    // Print current node with appropriate connector
-    let connector = if is_last { "└── " } else { "├── " };
-    writeln!(w, "{prefix}{connector}{}", node.name)?;
+/// Section holding the remote include policy (`allowed_hosts`).
+const REMOTE_SECTION: &str = "remote";
 
-    // Prepare prefix for children
-    let child_prefix = format!("{}{}", prefix, if is_last { "    " } else { "│   " });
+/// Section name prefix of scenarios (`[scenarios.NAME]`).
+const SCENARIO_PREFIX: &str = "scenarios.";
 
-    // Print children
-    for (i, child) in node.children.iter().enumerate() {
-        let is_last_child = i == node.children.len() - 1;
-        print_tree(child, &child_prefix, is_last_child, w)?;
+/// The scenario named by `section`, when it is a `[scenarios.NAME]` section.
+fn scenario_section(section: Option<&str>) -> Result<Option<&str>, String> {
+    match section.and_then(|s| s.strip_prefix(SCENARIO_PREFIX)) {
+        Some("") => Err(format!("Empty scenario name [{SCENARIO_PREFIX}]")),
+        name => Ok(name),
     }
+}
 
+/// Store a render setting of a scenario; unknown keys are ignored.
+fn store_scenario_setting(
+    cfg: &mut Config,
+    scenario: &str,
+    key: &str,
+    value: &str,
+) -> Result<(), String> {
+    let slot = {
+        let entry = cfg.scenarios.entry(scenario.to_string()).or_default();
+        match key {
+            "separator" => &mut entry.separator,
+            "pre_prompt" => &mut entry.pre_prompt,
+            "post_prompt" => &mut entry.post_prompt,
+            _ => return Ok(()),
+        }
+    };
+    *slot = Some(parse_string_value(
+        &format!("{key} for [{SCENARIO_PREFIX}{scenario}]"),
+        value,
+    )?);
     Ok(())
 }
 
-/// Show tree structure for all profiles
-pub fn show_tree(cfg: &Config, json: bool, mut w: impl Write) -> Result<(), String> {
-    let trees = build_trees(cfg);
+/// Store an inline profile body from the `[profiles]` table.
+fn store_inline_profile(cfg: &mut Config, key: &str, value: &str) -> Result<(), String> {
+    let name = key
+        .strip_prefix('"')
+        .and_then(|k| k.strip_suffix('"'))
+        .unwrap_or(key);
+    if name.is_empty() {
+        return Err(format!("Empty profile name in [{INLINE_PROFILES_SECTION}]"));
+    }
+    let body = parse_string_value(&format!("[{INLINE_PROFILES_SECTION}] {name}"), value)?;
+    if cfg.inline_profiles.insert(name.to_string(), body).is_some() {
+        return Err(format!(
+            "Profile '{name}' is defined twice in [{INLINE_PROFILES_SECTION}]"
+        ));
+    }
+    Ok(())
+}
 
-    if json {
-        let json_output = serde_json::to_string_pretty(&trees)
-            .map_err(|e| format!("JSON serialization error: {e}"))?;
-        writeln!(&mut w, "{json_output}").map_err(|e| format!("Write error: {e}"))?;
-    } else {
-        for (i, tree) in trees.trees.iter().enumerate() {
-            // Print root profile name
-            writeln!(&mut w, "{}", tree.name).map_err(|e| format!("Write error: {e}"))?;
+/// Register inline profiles alongside section-defined ones, rejecting names
+/// defined both ways.
+fn merge_inline_profiles(cfg: &mut Config) -> Result<(), String> {
+    let mut names: Vec<&String> = cfg.inline_profiles.keys().collect();
+    names.sort();
+    for name in names {
+        if cfg.profiles.contains_key(name) || cfg.metadata.contains_key(name) {
+            return Err(format!(
+                "Profile '{name}' is defined both in [{INLINE_PROFILES_SECTION}] and as [{name}]"
+            ));
+        }
+        cfg.profiles.insert(name.clone(), Vec::new());
+    }
+    Ok(())
+}
 
-            // Print children with tree structure
-            for (j, child) in tree.children.iter().enumerate() {
-                let is_last = j == tree.children.len() - 1;
-                print_tree(child, "", is_last, &mut w).map_err(|e| format!("Write error: {e}"))?;
-            }
+/// Separator of an override entry (`"old.md => new.md"`) in an extending profile.
+const OVERRIDE_ARROW: &str = "=>";
 
-            // Add blank line between trees (except after last one)
-            if i < trees.trees.len() - 1 {
-                writeln!(&mut w).map_err(|e| format!("Write error: {e}"))?;
-            }
+/// Replace each extending profile's dependencies with its parent's, with
+/// override entries applied in place and other entries appended.
+fn apply_extends(cfg: &mut Config) -> Result<(), String> {
+    let mut names: Vec<String> = cfg.profiles.keys().cloned().collect();
+    for (name, meta) in &cfg.metadata {
+        if meta.extends.is_some() && !cfg.profiles.contains_key(name) {
+            names.push(name.clone());
         }
     }
-
+    names.sort();
+    let mut flattened = HashMap::new();
+    for name in &names {
+        if let Some(parent) = extends_of(cfg, name) {
+            inherited_deps(name, parent, cfg, &mut flattened, &mut Vec::new())?;
+        } else if let Some(entry) = cfg.profiles[name]
+            .iter()
+            .find(|d| d.contains(OVERRIDE_ARROW))
+        {
+            return Err(format!(
+                "[{name}] overrides '{entry}' but does not extend a profile"
+            ));
+        }
+    }
+    cfg.profiles.extend(flattened);
     Ok(())
 }
 
-/// Show tree structure to stdout
-pub fn run_tree_stdout(config_override: Option<&Path>, json: bool) -> Result<(), String> {
-    let cfg_path = resolve_config_path(config_override)?;
-    let cfg_text = read_config_with_path(&cfg_path)?;
-    let cfg = parse_config_toml(&cfg_text)?;
-    show_tree(&cfg, json, io::stdout())
+fn extends_of<'a>(cfg: &'a Config, name: &str) -> Option<&'a str> {
+    cfg.metadata.get(name).and_then(|m| m.extends.as_deref())
 }
 
-/// Initialize default configuration and library structure.
-///
-/// Creates the default directory structure and configuration files
-/// for prompter, including sample profiles and library files.
-/// Only creates files that don't already exist (non-destructive).
-///
-/// # Returns
-/// * `Ok(())` - Initialization completed successfully
-/// * `Err(String)` - Initialization failed
-///
-/// # Errors
-/// Returns an error if:
-/// - Directory creation fails
-/// - File writing fails
-/// - HOME environment variable is not set
-///
-/// # Panics
-/// Panics if the progress bar template is invalid (should not happen with the
-/// hardcoded template string).
-pub fn init_scaffold() -> Result<(), String> {
-    let pb = if is_terminal() {
-        let pb = ProgressBar::new_spinner();
-        pb.set_style(
-            ProgressStyle::default_spinner()
-                .tick_chars("⠁⠂⠄⡀⢀⠠⠐⠈ ")
-                .template("{spinner:.green} {msg}")
-                .unwrap(),
-        );
-        pb.set_message("Initializing prompter...");
-        pb.enable_steady_tick(std::time::Duration::from_millis(120));
-        Some(pb)
-    } else {
-        None
+/// Effective dependencies of `name`, which extends `parent`.
+fn inherited_deps(
+    name: &str,
+    parent: &str,
+    cfg: &Config,
+    flattened: &mut HashMap<String, Vec<String>>,
+    chain: &mut Vec<String>,
+) -> Result<Vec<String>, String> {
+    if let Some(deps) = flattened.get(name) {
+        return Ok(deps.clone());
+    }
+    chain.push(name.to_string());
+    if chain.iter().any(|c| c == parent) {
+        chain.push(parent.to_string());
+        return Err(format!("Inheritance cycle: {}", chain.join(" -> ")));
+    }
+    let mut deps = match (extends_of(cfg, parent), cfg.profiles.get(parent)) {
+        (Some(grandparent), _) => inherited_deps(parent, grandparent, cfg, flattened, chain)?,
+        (None, Some(deps)) => deps.clone(),
+        (None, None) => return Err(format!("[{name}] extends unknown profile '{parent}'")),
     };
+    chain.pop();
+    for entry in cfg.profiles.get(name).into_iter().flatten() {
+        match entry.split_once(OVERRIDE_ARROW) {
+            Some((old, new)) => {
+                let (old, new) = (old.trim(), new.trim());
+                let slot = deps.iter_mut().find(|d| *d == old).ok_or_else(|| {
+                    format!("[{name}] overrides '{old}', which [{parent}] does not include")
+                })?;
+                *slot = new.to_string();
+            }
+            None => deps.push(entry.clone()),
+        }
+    }
+    flattened.insert(name.to_string(), deps.clone());
+    Ok(deps)
+}
 
-    let cfg_path = config_path()?;
-    let cfg_dir = cfg_path
-        .parent()
-        .ok_or_else(|| "Invalid config path".to_string())?;
+/// Name of the enclosing profile section for a per-profile `key`.
+fn section_for(current: Option<&str>, key: &str) -> Result<String, String> {
+    current
+        .map(str::to_string)
+        .ok_or_else(|| format!("{key} outside of a profile section"))
+}
 
-    if let Some(ref pb) = pb {
-        pb.set_message("Creating config directory...");
+/// Parse a complete array value for `key` and store it on the current profile.
+fn store_array(
+    cfg: &mut Config,
+    current: Option<&str>,
+    key: &str,
+    text: &str,
+) -> Result<(), String> {
+    let items = parse_array_items(text).map_err(|e| {
+        format!(
+            "Invalid {key} array for [{}]: {e}",
+            current.unwrap_or_default()
+        )
+    })?;
+    if let Some(scenario) = scenario_section(current)? {
+        cfg.scenarios
+            .entry(scenario.to_string())
+            .or_default()
+            .profiles = items;
+        return Ok(());
+    }
+    if current == Some(REMOTE_SECTION) {
+        cfg.allowed_hosts = Some(items);
+        return Ok(());
     }
-    fs::create_dir_all(cfg_dir)
-        .map_err(|e| format!("Failed to create {}: {}", cfg_dir.display(), e))?;
+    let name = section_for(current, key)?;
+    if key == "tags" {
+        cfg.metadata.entry(name).or_default().tags = items;
+    } else {
+        cfg.profiles.insert(name, items);
+    }
+    Ok(())
+}
 
-    let lib = library_dir()?;
-    if let Some(ref pb) = pb {
-        pb.set_message("Creating library directory...");
+/// Parse a quoted TOML string value, unescaping its contents.
+fn parse_string_value(key: &str, value: &str) -> Result<String, String> {
+    if value.len() < 2 || !value.starts_with('"') || !value.ends_with('"') {
+        return Err(format!("{key} must be a string"));
     }
-    fs::create_dir_all(&lib).map_err(|e| format!("Failed to create {}: {}", lib.display(), e))?;
+    Ok(unescape(&value[1..value.len() - 1]))
+}
 
-    if !cfg_path.exists() {
-        if let Some(ref pb) = pb {
-            pb.set_message("Writing default config...");
+fn strip_comments(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut in_str = false;
+    for c in s.chars() {
+        if c == '"' {
+            out.push(c);
+            in_str = !in_str;
+            continue;
+        }
+        if !in_str && c == '#' {
+            break;
         }
-        let default_cfg = r#"# Prompter configuration
-# Profiles map to sets of markdown files and/or other profiles.
-# Files are relative to $HOME/.local/prompter/library
+        out.push(c);
+    }
+    out
+}
 
-[python.api]
-depends_on = ["a/b/c.md", "f/g/h.md"]
+fn contains_closing_bracket_outside_quotes(s: &str) -> bool {
+    let mut in_str = false;
+    for c in s.chars() {
+        if c == '"' {
+            in_str = !in_str;
+        }
+        if !in_str && c == ']' {
+            return true;
+        }
+    }
+    false
+}
 
-[general.testing]
-depends_on = ["python.api", "a/b/d.md"]
-"#;
-        fs::write(&cfg_path, default_cfg)
-            .map_err(|e| format!("Failed to write {}: {}", cfg_path.display(), e))?;
-    }
-
-    let paths_and_contents: Vec<(PathBuf, &str)> = vec![
-        (
-            lib.join("a/b/c.md"),
-            "# a/b/c.md\nExample snippet for python.api.\n",
-        ),
-        (lib.join("a/b.md"), "# a/b.md\nFolder-level notes.\n"),
-        (
-            lib.join("a/b/d.md"),
-            "# a/b/d.md\nGeneral testing snippet.\n",
-        ),
-        (lib.join("f/g/h.md"), "# f/g/h.md\nShared helper snippet.\n"),
-    ];
+fn parse_array_items(s: &str) -> Result<Vec<String>, String> {
+    let mut items = Vec::new();
+    let mut in_str = false;
+    let mut buf = String::new();
+    let mut escaped = false;
+    let mut started = false;
 
-    for (path, contents) in paths_and_contents {
-        if let Some(ref pb) = pb {
-            pb.set_message(format!(
-                "Creating {}",
-                path.file_name().unwrap_or_default().to_string_lossy()
-            ));
+    for c in s.chars() {
+        if !started {
+            if c == '[' {
+                started = true;
+            }
+            continue;
         }
-        if let Some(parent) = path.parent() {
-            fs::create_dir_all(parent)
-                .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+        if c == ']' && !in_str {
+            break;
         }
-        if !path.exists() {
-            fs::write(&path, contents)
-                .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+        if in_str {
+            if escaped {
+                buf.push(c);
+                escaped = false;
+                continue;
+            }
+            if c == '\\' {
+                escaped = true;
+                continue;
+            }
+            if c == '"' {
+                in_str = false;
+                items.push(buf.clone());
+                buf.clear();
+                continue;
+            }
+            buf.push(c);
+        } else if c == '"' {
+            in_str = true;
         }
     }
 
-    if let Some(pb) = pb {
-        pb.finish_with_message("Initialization complete!");
-        std::thread::sleep(std::time::Duration::from_millis(200)); // Brief pause to show completion
+    if in_str {
+        return Err("Unterminated string in array".into());
     }
+    Ok(items)
+}
 
-    println!(
-        "{}",
-        success_message(&format!("Initialized config at {}", cfg_path.display()))
-    );
-    println!(
-        "{}",
-        info_message(&format!("Library root at {}", lib.display()))
-    );
-    Ok(())
+/// Whether a dependency entry names a file (markdown fragment, `@path`
+/// include, or remote `https://` include) rather than a profile.
+fn is_fragment(dep: &str) -> bool {
+    is_file_include(dep)
+        || remote::is_remote(dep)
+        || Path::new(split_line_range(dep).0)
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("md"))
 }
 
-/// List profiles to stdout.
-///
-/// Convenience function that reads configuration and lists all profiles
-/// to standard output.
-///
-/// # Arguments
-/// * `config_override` - Optional configuration file override
-/// * `json` - Whether to output in JSON format
-///
-/// # Returns
-/// * `Ok(())` - Profiles listed successfully
-/// * `Err(String)` - Operation failed
-///
-/// # Errors
-/// Returns an error if:
-/// - Configuration file cannot be read or parsed
-/// - Writing to stdout fails
-pub fn run_list_stdout(config_override: Option<&Path>, json: bool) -> Result<(), String> {
-    let cfg_path = resolve_config_path(config_override)?;
-    let cfg_text = read_config_with_path(&cfg_path)?;
-    let cfg = parse_config_toml(&cfg_text)?;
-    let lib = library_path_for_config_override(config_override, &cfg_path)?;
-    list_profiles(&cfg, &lib, json, io::stdout())
+/// Split the `?` marker off an optional dependency entry (`"?local/notes.md"`),
+/// which is skipped when its file or profile does not exist.
+fn split_optional(dep: &str) -> (&str, bool) {
+    dep.strip_prefix('?')
+        .map_or((dep, false), |target| (target, true))
 }
 
-/// JSON output for successful validation
-#[derive(Debug, Serialize)]
-struct ValidateOutput {
-    valid: bool,
+/// Whether a dependency entry is an `@path` include of an arbitrary file.
+fn is_file_include(dep: &str) -> bool {
+    dep.starts_with('@')
 }
 
-/// Validate configuration and output results to stdout.
-///
-/// Convenience function that reads configuration and validates it,
-/// outputting any errors found.
-///
-/// # Arguments
-/// * `config_override` - Optional configuration file override
-/// * `json` - Whether to output in JSON format
-///
-/// # Returns
-/// * `Ok(())` - Configuration is valid
-/// * `Err(String)` - Validation errors found
-///
-/// # Errors
-/// Returns an error if:
-/// - Configuration file cannot be read or parsed
-/// - Validation finds missing files or circular dependencies
-pub fn run_validate_stdout(config_override: Option<&Path>, json: bool) -> Result<(), String> {
-    let cfg_path = resolve_config_path(config_override)?;
-    let cfg_text = read_config_with_path(&cfg_path)?;
-    let cfg = parse_config_toml(&cfg_text)?;
-    let lib = library_path_for_config_override(config_override, &cfg_path)?;
-    validate(&cfg, &lib)?;
+/// Location of a fragment path (without its `#range`): `@path` includes
+/// resolve against the config directory, remote includes to their cached copy,
+/// and everything else against the library.
+fn fragment_path(file: &str, cfg: &Config, lib: &Path) -> PathBuf {
+    if remote::is_remote(file) {
+        return remote::cache_path(file);
+    }
+    file.strip_prefix('@').map_or_else(
+        || lib.join(file),
+        |rest| cfg.base_dir.as_deref().unwrap_or(lib).join(rest),
+    )
+}
 
-    if json {
-        let output = ValidateOutput { valid: true };
-        let json_output = serde_json::to_string_pretty(&output)
-            .map_err(|e| format!("JSON serialization error: {e}"))?;
-        println!("{json_output}");
+/// Like [`fragment_path`], but fetches a remote include into its cached copy
+/// first.
+fn fetch_fragment(file: &str, cfg: &Config, lib: &Path) -> Result<PathBuf, String> {
+    if remote::is_remote(file) {
+        return remote::fetch(file, cfg.allowed_hosts.as_deref(), cfg.remote_access);
     }
+    Ok(fragment_path(file, cfg, lib))
+}
 
-    Ok(())
+/// Like [`fragment_path`], but a remote include must pass the remote policy
+/// first. Nothing is fetched.
+fn checked_fragment_path(file: &str, cfg: &Config, lib: &Path) -> Result<PathBuf, String> {
+    if remote::is_remote(file) {
+        remote::check_allowed(file, cfg.allowed_hosts.as_deref(), cfg.remote_access)?;
+    }
+    Ok(fragment_path(file, cfg, lib))
 }
 
-/// JSON structure for a single fragment
-#[derive(Debug, Serialize)]
-struct FragmentOutput {
-    path: String,
-    content: String,
+/// Split a dependency entry into its path and optional `#range` suffix. Only
+/// a suffix shaped like a range (`N`, `N-M`, optionally `L`-prefixed as in
+/// `#L10-L20`) is split off; any other `#` is part of the path.
+fn split_line_range(dep: &str) -> (&str, Option<&str>) {
+    match dep.rsplit_once('#') {
+        Some((path, range)) if is_range_spec(range) => (path, Some(range)),
+        _ => (dep, None),
+    }
 }
 
-/// JSON output structure for render command
-#[derive(Debug, Serialize)]
-struct RenderOutput {
-    profile: String,
-    pre_prompt: String,
-    system_info: String,
-    fragments: Vec<FragmentOutput>,
+/// Whether `spec` has the shape `L?N` or `L?N-L?M`.
+fn is_range_spec(spec: &str) -> bool {
+    let is_line = |part: &str| {
+        let digits = part.strip_prefix('L').unwrap_or(part);
+        !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit())
+    };
+    let (start, end) = spec.split_once('-').unwrap_or((spec, spec));
+    is_line(start) && is_line(end)
 }
 
-/// Render one or more profiles' content to a writer.
-///
-/// Resolves profile dependencies and writes the concatenated content
-/// to the provided writer, including pre-prompt, system info, file
-/// contents with optional separators, and post-prompt. When multiple
-/// profiles are provided, files are deduplicated across all profiles
-/// (first occurrence wins).
-///
-/// # Arguments
-/// * `cfg` - Configuration containing profile definitions
-/// * `lib` - Library root directory for file resolution
-/// * `w` - Writer to output rendered content to
-/// * `profiles` - Profile names to render (deduplicated in order)
-/// * `separator` - Optional separator between files
-/// * `pre_prompt` - Optional custom pre-prompt (defaults to LLM instructions)
-/// * `post_prompt` - Optional custom post-prompt (defaults to @AGENTS/@CLAUDE instructions)
-/// * `json` - Whether to output in JSON format
-///
-/// # Returns
-/// * `Ok(())` - Profiles rendered successfully
-/// * `Err(String)` - Rendering failed
-///
-/// # Errors
-/// Returns an error if:
-/// - Profile resolution fails (missing files, cycles, unknown profiles)
-/// - Writing to output fails
-/// - File reading fails
-pub fn render_to_writer(
-    cfg: &Config,
-    lib: &Path,
-    mut w: impl Write,
-    profiles: &[String],
-    separator: Option<&str>,
-    pre_prompt: Option<&str>,
-    post_prompt: Option<&str>,
-    json: bool,
-) -> Result<(), String> {
-    let mut seen_files = HashSet::new();
-    let mut files = Vec::new();
+/// Inclusive, 1-based range of lines selected from a fragment (`file.md#10-20`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct LineRange {
+    /// First line to include
+    pub start: usize,
+    /// Last line to include
+    pub end: usize,
+}
 
-    // Resolve all profiles with shared deduplication
-    for profile in profiles {
-        let mut stack = Vec::new();
-        resolve_profile(profile, cfg, lib, &mut seen_files, &mut stack, &mut files).map_err(
-            |e| match e {
-                ResolveError::UnknownProfile(p) => format!("Unknown profile: {p}"),
-                ResolveError::Cycle(c) => format!("Cycle detected: {}", c.join(" -> ")),
-                ResolveError::MissingFile(path, prof) => format!(
-                    "Missing file: {} (referenced by [{}])",
-                    path.display(),
-                    prof
-                ),
-            },
-        )?;
+impl LineRange {
+    /// Parse `N` or `N-M` (each optionally `L`-prefixed) with `1 <= N <= M`.
+    fn parse(spec: &str) -> Option<Self> {
+        let (start, end) = spec.split_once('-').unwrap_or((spec, spec));
+        let line = |part: &str| part.strip_prefix('L').unwrap_or(part).parse().ok();
+        let start: usize = line(start)?;
+        let end: usize = line(end)?;
+        (start >= 1 && start <= end).then_some(Self { start, end })
     }
 
-    if json {
-        // JSON output mode
-        let default_pre = default_pre_prompt();
-        let pre_prompt_text = pre_prompt.unwrap_or(&default_pre).to_string();
-
-        let date = Local::now().format("%Y-%m-%d").to_string();
-        let os = env::consts::OS;
-        let arch = env::consts::ARCH;
-        let system_info = format!("Today is {date}, and you are running on a {arch}/{os} system.");
-
-        let mut fragments = Vec::new();
-        for path in &files {
-            let content = fs::read_to_string(path)
-                .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
-            let rel_path = path.strip_prefix(lib).unwrap_or(path).display().to_string();
-            fragments.push(FragmentOutput {
-                path: rel_path,
-                content,
-            });
+    /// The selected lines of `content`, or `None` if the range runs past its end.
+    fn slice(self, content: &str) -> Option<&str> {
+        let lines: Vec<&str> = content.split_inclusive('\n').collect();
+        if self.end > lines.len() {
+            return None;
         }
+        let offset: usize = lines[..self.start - 1].iter().map(|l| l.len()).sum();
+        let len: usize = lines[self.start - 1..self.end]
+            .iter()
+            .map(|l| l.len())
+            .sum();
+        Some(&content[offset..offset + len])
+    }
+}
 
-        let output = RenderOutput {
-            profile: profiles.join(", "),
-            pre_prompt: pre_prompt_text,
-            system_info,
-            fragments,
-        };
-
-        let json_output = serde_json::to_string_pretty(&output)
-            .map_err(|e| format!("JSON serialization error: {e}"))?;
-        writeln!(&mut w, "{json_output}").map_err(|e| format!("Write error: {e}"))?;
-    } else {
-        // Text output mode
-        // Write pre-prompt (defaults if not provided)
-        let default_pre = default_pre_prompt();
-        let pre_prompt_text = pre_prompt.unwrap_or(&default_pre);
-        w.write_all(pre_prompt_text.as_bytes())
-            .map_err(|e| format!("Write error: {e}"))?;
-
-        // Write system prefix with two newlines before
-        w.write_all(b"\n")
-            .map_err(|e| format!("Write error: {e}"))?;
-        let prefix = format_system_prefix();
-        w.write_all(prefix.as_bytes())
-            .map_err(|e| format!("Write error: {e}"))?;
+impl std::fmt::Display for LineRange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}-{}", self.start, self.end)
+    }
+}
 
-        let sep = separator.unwrap_or("");
-        for path in files {
-            // Two newlines before each file
-            w.write_all(b"\n")
-                .map_err(|e| format!("Write error: {e}"))?;
+/// Errors that can occur during profile resolution.
+///
+/// These errors represent various failure modes when resolving
+/// profile dependencies and validating file references.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ResolveError {
+    /// Referenced profile name does not exist in configuration
+    UnknownProfile(String),
+    /// Circular dependency detected in profile references
+    Cycle(Vec<String>),
+    /// Referenced markdown file does not exist
+    MissingFile(PathBuf, String), // (path, referenced_by)
+    /// Fragment reference has a malformed `#range` suffix
+    InvalidLineRange(String, String), // (reference, referenced_by)
+    /// Remote include could not be fetched
+    Remote(String, String), // (error, referenced_by)
+}
 
-            match fs::read(&path) {
-                Ok(bytes) => w
-                    .write_all(&bytes)
-                    .map_err(|e| format!("Write error: {e}"))?,
-                Err(e) => return Err(format!("Failed to read {}: {}", path.display(), e)),
+impl std::fmt::Display for ResolveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnknownProfile(p) => write!(f, "Unknown profile: {p}"),
+            Self::Cycle(c) => write!(f, "Cycle detected: {}", c.join(" -> ")),
+            Self::MissingFile(path, prof) => write!(
+                f,
+                "Missing file: {} (referenced by [{}])",
+                path.display(),
+                prof
+            ),
+            Self::InvalidLineRange(dep, prof) => {
+                write!(f, "Invalid line range: {dep} (referenced by [{prof}])")
             }
+            Self::Remote(e, prof) => write!(f, "{e} (referenced by [{prof}])"),
+        }
+    }
+}
+
+/// A render failure, attributed to the requested profile that caused it.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct RenderError {
+    /// What went wrong
+    pub error: String,
+    /// Requested profile whose rendering failed, when known
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub failed_profile: Option<String>,
+    /// Profiles from the failing requested profile down to the failure point
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub include_chain: Vec<String>,
+}
+
+impl RenderError {
+    /// Error raised while rendering content reached through `chain`
+    /// (outermost profile first).
+    fn in_chain(error: String, chain: &[String]) -> Self {
+        Self {
+            error,
+            failed_profile: chain.first().cloned(),
+            include_chain: chain.to_vec(),
+        }
+    }
+}
+
+impl From<String> for RenderError {
+    fn from(error: String) -> Self {
+        Self {
+            error,
+            ..Self::default()
+        }
+    }
+}
+
+impl std::fmt::Display for RenderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.failed_profile {
+            Some(profile) => write!(f, "Failed to render profile '{profile}': {}", self.error)?,
+            None => write!(f, "{}", self.error)?,
+        }
+        if self.include_chain.len() > 1 {
+            write!(f, "\n  include chain: {}", self.include_chain.join(" -> "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Node type in the dependency tree
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TreeNodeType {
+    /// Profile node
+    Profile,
+    /// Fragment (markdown file) node
+    Fragment,
+    /// Arbitrary file included with `@path`
+    File,
+}
+
+/// Kind of directive connecting a tree node to its parent profile
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum IncludeKind {
+    /// Plain `depends_on` entry; rendering fails when its target is missing
+    Hard,
+    /// `?` entry, skipped when its target is missing
+    Soft,
+    /// Parent profile named by `extends`
+    Extends,
+}
+
+/// How a tree node is included by its parent
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TreeEdge {
+    /// Directive that includes the node
+    pub kind: IncludeKind,
+    /// Lines selected from a fragment (`file.md#10-20`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lines: Option<LineRange>,
+}
+
+/// Tree node representing a profile or fragment in the dependency tree
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TreeNode {
+    /// Stable identifier: the profile name, or `fragment:PATH` / `file:PATH`
+    #[serde(default)]
+    pub id: String,
+    /// Type of node (profile or fragment)
+    #[serde(rename = "type")]
+    pub node_type: TreeNodeType,
+    /// Name of profile or path of fragment
+    pub name: String,
+    /// Identifier of the enclosing profile node (`null` for roots)
+    #[serde(default)]
+    pub parent: Option<String>,
+    /// Parent profile reached through `extends` rather than an include
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub extends: bool,
+    /// Included with a `?` entry, which is skipped when the target is missing
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub optional: bool,
+    /// The include connecting this node to its parent (`null` for roots)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub edge: Option<TreeEdge>,
+    /// Children of this node
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<Self>,
+}
+
+/// Complete tree structure for JSON output
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TreeOutput {
+    /// List of root trees (top-level profiles)
+    pub trees: Vec<TreeNode>,
+}
+
+/// Recursively resolve a profile's dependencies into a list of file paths.
+///
+/// Performs depth-first traversal of profile dependencies, handling both
+/// direct file references and recursive profile dependencies. Implements
+/// cycle detection and file deduplication.
+///
+/// # Arguments
+/// * `name` - Profile name to resolve
+/// * `cfg` - Configuration containing profile definitions
+/// * `lib` - Library root directory for resolving file paths
+/// * `seen_files` - Set tracking already included files for deduplication
+/// * `stack` - Stack for cycle detection during recursion
+/// * `out` - Output vector to collect resolved file paths
+///
+/// # Returns
+/// * `Ok(())` - Profile successfully resolved
+/// * `Err(ResolveError)` - Resolution failed due to missing files, cycles, or unknown profiles
+///
+/// # Errors
+/// Returns an error if:
+/// - Profile name is not found in configuration
+/// - Circular dependency is detected
+/// - Referenced markdown file does not exist
+#[allow(clippy::implicit_hasher)]
+pub fn resolve_profile(
+    name: &str,
+    cfg: &Config,
+    lib: &Path,
+    seen_files: &mut HashSet<PathBuf>,
+    stack: &mut Vec<String>,
+    out: &mut Vec<PathBuf>,
+) -> Result<(), ResolveError> {
+    let mut res = Resolution {
+        seen_files: seen_files.drain().map(|path| (path, None)).collect(),
+        stack: std::mem::take(stack),
+        ..Resolution::default()
+    };
+    let result = res.resolve(name, cfg, lib, &ProfileFilter::default());
+    seen_files.extend(res.seen_files.into_iter().map(|(path, _)| path));
+    *stack = res.stack;
+    out.extend(res.files.into_iter().map(|f| f.path));
+    result
+}
+
+/// A fragment file selected for rendering, optionally narrowed to a line range.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ResolvedFragment {
+    path: PathBuf,
+    lines: Option<LineRange>,
+    /// Profiles it was included through, outermost first
+    via: Vec<String>,
+    /// Included with `@path`: rendered as-is, without comment stripping or variables
+    verbatim: bool,
+    /// Body of an inline `[profiles]` entry, rendered in place of reading `path`
+    inline: Option<String>,
+}
+
+/// Profiles to drop from, or restrict, a render's include tree, and the order
+/// siblings are visited in.
+#[derive(Debug, Default)]
+struct ProfileFilter {
+    /// Profiles skipped wherever they appear
+    exclude: HashSet<String>,
+    /// Profiles replaced by an omission marker wherever they appear
+    comment_out: HashSet<String>,
+    /// When set, only fragments reached through these profiles are rendered
+    include_only: Option<HashSet<String>>,
+    /// Visit each profile's includes alphabetically rather than as listed
+    sorted: bool,
+    /// Visit each profile's includes in an order shuffled from this seed
+    shuffle_seed: Option<u64>,
+    /// Treat `?` includes like plain ones (`--strict-includes`)
+    strict_includes: bool,
+    /// Fetch remote includes; otherwise only their cached copies are used and
+    /// an uncached one is left out, so metadata commands stay off the network
+    fetch_remote: bool,
+}
+
+impl ProfileFilter {
+    fn from_options(cfg: &Config, opts: &RenderOptions) -> Self {
+        let names = |names: &[String]| -> HashSet<String> {
+            names
+                .iter()
+                .map(|name| cfg.profile_name(name).to_string())
+                .collect()
+        };
+        Self {
+            exclude: names(&opts.exclude),
+            comment_out: names(&opts.comment_out),
+            include_only: (!opts.include_only.is_empty()).then(|| names(&opts.include_only)),
+            sorted: opts.sort_includes,
+            shuffle_seed: opts.shuffle_seed,
+            strict_includes: opts.strict_includes,
+            fetch_remote: true,
+        }
+    }
+
+    fn selects(&self, name: &str) -> bool {
+        self.include_only
+            .as_ref()
+            .is_some_and(|only| only.contains(name))
+    }
+}
+
+/// Shuffle the includes of profile `name` from `seed`. The order depends only
+/// on the seed and the profile's name, so editing one profile leaves the
+/// others' orders alone, and the generator (`SplitMix64`) is part of the code
+/// rather than a dependency so a seed reproduces across versions.
+fn shuffle<T>(items: &mut [T], seed: u64, name: &str) {
+    // FNV-1a, so the per-profile stream does not depend on the std hasher
+    let mut state = name.bytes().fold(0xcbf2_9ce4_8422_2325_u64 ^ seed, |h, b| {
+        (h ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3)
+    });
+    let mut next = || {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    };
+    for i in (1..items.len()).rev() {
+        let j = usize::try_from(next() % (i as u64 + 1)).unwrap_or(i);
+        items.swap(i, j);
+    }
+}
+
+/// Accumulated state of a depth-first resolution over one or more profiles.
+#[derive(Debug, Default)]
+struct Resolution {
+    /// Fragments already included (first occurrence wins)
+    seen_files: HashSet<(PathBuf, Option<LineRange>)>,
+    /// Profiles currently being expanded, for cycle detection
+    stack: Vec<String>,
+    /// Resolved fragments in render order
+    files: Vec<ResolvedFragment>,
+    /// Every profile name encountered, including filtered ones
+    visited_profiles: HashSet<String>,
+    /// Number of times each expanded profile appears in the include tree
+    include_counts: HashMap<String, usize>,
+}
+
+impl Resolution {
+    /// Resolve a top-level profile, honoring `filter`.
+    fn resolve(
+        &mut self,
+        name: &str,
+        cfg: &Config,
+        lib: &Path,
+        filter: &ProfileFilter,
+    ) -> Result<(), ResolveError> {
+        self.visited_profiles.insert(name.to_string());
+        if filter.exclude.contains(name) {
+            return Ok(());
+        }
+        let selected = filter.include_only.is_none() || filter.selects(name);
+        if filter.comment_out.contains(name) {
+            return self.omit(name, cfg, selected);
+        }
+        self.expand(name, cfg, lib, filter, selected)
+    }
+
+    /// Stand in for the content of profile `name` with an omission marker.
+    fn omit(&mut self, name: &str, cfg: &Config, selected: bool) -> Result<(), ResolveError> {
+        if !cfg.profiles.contains_key(name) {
+            return Err(ResolveError::UnknownProfile(name.to_string()));
+        }
+        if selected {
+            self.marker("omitted", name);
+        }
+        Ok(())
+    }
+
+    /// Render `<!-- KIND: name -->` once, in place of profile `name`.
+    fn marker(&mut self, kind: &str, name: &str) {
+        let path = PathBuf::from(format!("[{kind}].{name}"));
+        if self.seen_files.insert((path.clone(), None)) {
+            let mut via = self.stack.clone();
+            via.push(name.to_string());
+            self.files.push(ResolvedFragment {
+                path,
+                lines: None,
+                via,
+                verbatim: true,
+                inline: Some(format!("<!-- {kind}: {name} -->\n")),
+            });
+        }
+    }
+
+    fn expand(
+        &mut self,
+        name: &str,
+        cfg: &Config,
+        lib: &Path,
+        filter: &ProfileFilter,
+        selected: bool,
+    ) -> Result<(), ResolveError> {
+        if self.stack.iter().any(|s| s == name) {
+            let mut cycle = self.stack.clone();
+            cycle.push(name.to_string());
+            return Err(ResolveError::Cycle(cycle));
+        }
+        let deps = cfg
+            .profiles
+            .get(name)
+            .ok_or_else(|| ResolveError::UnknownProfile(name.to_string()))?;
+        *self.include_counts.entry(name.to_string()).or_default() += 1;
+        self.stack.push(name.to_string());
+        if let Some(body) = cfg.inline_profiles.get(name) {
+            let path = PathBuf::from(format!("[{INLINE_PROFILES_SECTION}].{name}"));
+            if selected && self.seen_files.insert((path.clone(), None)) {
+                self.files.push(ResolvedFragment {
+                    path,
+                    lines: None,
+                    via: self.stack.clone(),
+                    verbatim: false,
+                    inline: Some(body.clone()),
+                });
+            }
+        }
+        let mut deps: Vec<(&str, bool)> = deps
+            .iter()
+            .map(|dep| split_optional(dep))
+            .map(|(dep, optional)| (dep, optional && !filter.strict_includes))
+            .collect();
+        if filter.sorted {
+            deps.sort_by_key(|(dep, _)| *dep);
+        } else if let Some(seed) = filter.shuffle_seed {
+            shuffle(&mut deps, seed, name);
+        }
+        for (dep, optional) in deps {
+            if is_fragment(dep) {
+                let (file, range) = split_line_range(dep);
+                if remote::is_remote(file) {
+                    // A blocked include is an error even when optional
+                    remote::check_allowed(file, cfg.allowed_hosts.as_deref(), cfg.remote_access)
+                        .map_err(|e| ResolveError::Remote(e, name.to_string()))?;
+                }
+                let path = if filter.fetch_remote {
+                    match fetch_fragment(file, cfg, lib) {
+                        Ok(path) => path,
+                        Err(e) if optional => {
+                            log::warn!("skipping optional include: {e}");
+                            continue;
+                        }
+                        Err(e) => return Err(ResolveError::Remote(e, name.to_string())),
+                    }
+                } else {
+                    fragment_path(file, cfg, lib)
+                };
+                if !filter.fetch_remote && remote::is_remote(file) && !path.exists() {
+                    log::debug!("leaving out {file}: not cached");
+                    continue;
+                }
+                if !path.exists() {
+                    if optional {
+                        continue;
+                    }
+                    return Err(ResolveError::MissingFile(path, name.to_string()));
+                }
+                let lines = range
+                    .map(|spec| {
+                        LineRange::parse(spec).ok_or_else(|| {
+                            ResolveError::InvalidLineRange(dep.to_string(), name.to_string())
+                        })
+                    })
+                    .transpose()?;
+                if selected && self.seen_files.insert((path.clone(), lines)) {
+                    self.files.push(ResolvedFragment {
+                        path,
+                        lines,
+                        via: self.stack.clone(),
+                        verbatim: is_file_include(dep),
+                        inline: None,
+                    });
+                }
+            } else {
+                if optional && !cfg.profiles.contains_key(dep) {
+                    continue;
+                }
+                self.visited_profiles.insert(dep.to_string());
+                if filter.exclude.contains(dep) {
+                    continue;
+                }
+                let child_selected = selected || filter.selects(dep);
+                if filter.comment_out.contains(dep) {
+                    self.omit(dep, cfg, child_selected)?;
+                    continue;
+                }
+                self.expand(dep, cfg, lib, filter, child_selected)?;
+            }
+        }
+        self.stack.pop();
+        Ok(())
+    }
+}
+
+/// Ordering applied to `list` output.
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ListSort {
+    /// Alphabetical by profile name
+    #[default]
+    Name,
+    /// Most recently modified first
+    Mtime,
+}
+
+/// Output format for `validate`.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValidateFormat {
+    /// Errors as text lines
+    Text,
+    /// A JSON object (`{"valid": true}` or an error object)
+    Json,
+    /// A `JUnit` XML report with one testcase per profile, for CI dashboards
+    Junit,
+}
+
+/// Output format for `list`.
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ListFormat {
+    /// Profile names, one per line
+    #[default]
+    Text,
+    /// A single JSON document with profile metadata and library fragments
+    Json,
+    /// One JSON profile object per line, flushed as each is written
+    Ndjson,
+}
+
+/// Filtering and ordering options for listing profiles.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ListOptions {
+    /// Only include profiles modified within this duration
+    pub since: Option<Duration>,
+    /// Ordering of listed profiles
+    pub sort: ListSort,
+    /// Group profiles by the namespace before the first `/` in their names
+    pub grouped: bool,
+    /// List scenarios instead of profiles
+    pub scenarios: bool,
+    /// List at most this many profiles (`--limit`)
+    pub limit: Option<usize>,
+    /// Skip this many profiles first (`--offset`)
+    pub offset: usize,
+    /// Layout of the JSON document (NDJSON lines are always compact)
+    pub json_layout: JsonLayout,
+}
+
+/// Structural checks applied by `validate` on top of reference checks.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ValidateOptions {
+    /// Maximum number of nested profile includes below any profile
+    pub max_depth: Option<usize>,
+    /// Warn about profiles past their `review_after` date or without metadata
+    pub review: bool,
+    /// Treat missing `?` includes as errors
+    pub strict_includes: bool,
+}
+
+/// Latest modification time among the files a profile resolves to.
+fn profile_mtime(name: &str, cfg: &Config, lib: &Path) -> Result<Option<SystemTime>, String> {
+    let mut seen = HashSet::new();
+    let mut stack = Vec::new();
+    let mut files = Vec::new();
+    resolve_profile(name, cfg, lib, &mut seen, &mut stack, &mut files)
+        .map_err(|e| e.to_string())?;
+    let mut latest = None;
+    for file in files {
+        let modified = fs::metadata(&file)
+            .and_then(|m| m.modified())
+            .map_err(|e| format!("Failed to read {}: {}", file.display(), e))?;
+        latest = latest.max(Some(modified));
+    }
+    Ok(latest)
+}
+
+/// Size in bytes of a profile's rendered fragments, honoring line ranges
+/// but before comment stripping.
+pub(crate) fn profile_body_bytes(name: &str, cfg: &Config, lib: &Path) -> Result<u64, String> {
+    let mut res = Resolution::default();
+    res.resolve(name, cfg, lib, &ProfileFilter::default())
+        .map_err(|e| e.to_string())?;
+    let cache = FileCache::default();
+    let mut total = 0;
+    for fragment in &res.files {
+        total += read_fragment(fragment, "", &cache)?.len() as u64;
+    }
+    Ok(total)
+}
+
+/// Profile names selected and ordered according to `opts`.
+///
+/// A profile that fails to resolve is warned about and treated as having no
+/// modification time, so one broken profile does not hide the rest.
+pub(crate) fn listed_profile_names(cfg: &Config, lib: &Path, opts: &ListOptions) -> Vec<String> {
+    let mut names: Vec<String> = cfg.profiles.keys().cloned().collect();
+    names.sort();
+    if opts.since.is_none() && opts.sort == ListSort::Name {
+        return names;
+    }
+
+    let mut timed = Vec::with_capacity(names.len());
+    for name in names {
+        let mtime = profile_mtime(&name, cfg, lib).unwrap_or_else(|e| {
+            eprintln!("Warning: profile '{name}' has no modification time: {e}");
+            None
+        });
+        timed.push((name, mtime));
+    }
+    if let Some(since) = opts.since {
+        let cutoff = SystemTime::now()
+            .checked_sub(since)
+            .unwrap_or(SystemTime::UNIX_EPOCH);
+        timed.retain(|(_, mtime)| mtime.is_some_and(|t| t >= cutoff));
+    }
+    if opts.sort == ListSort::Mtime {
+        timed.sort_by_key(|(_, mtime)| std::cmp::Reverse(*mtime));
+    }
+    timed.into_iter().map(|(name, _)| name).collect()
+}
+
+/// JSON output structure for list command
+#[derive(Debug, Serialize)]
+struct ListOutput {
+    profiles: Vec<ProfileInfo>,
+    /// Number of profiles before `--offset` and `--limit` were applied
+    total: usize,
+    fragments: Vec<String>,
+}
+
+/// One scenario in `list --scenarios` JSON output
+#[derive(Debug, Serialize)]
+struct ScenarioInfo<'a> {
+    name: &'a str,
+    profiles: &'a [String],
+}
 
-            // Write separator after each file if provided
-            if !sep.is_empty() {
-                w.write_all(sep.as_bytes())
+/// JSON output structure for `list --grouped`: profiles keyed by namespace,
+/// with profiles outside any namespace under `""`
+#[derive(Debug, Serialize)]
+struct GroupedListOutput {
+    profiles: BTreeMap<String, Vec<ProfileInfo>>,
+    fragments: Vec<String>,
+}
+
+/// Profile information for JSON output
+#[derive(Debug, Serialize)]
+struct ProfileInfo {
+    name: String,
+    dependencies: Vec<String>,
+    /// Rendered body size in bytes, or `null` if the profile does not resolve
+    size: Option<u64>,
+    tags: Vec<String>,
+    description: Option<String>,
+}
+
+/// List all available profiles to a writer.
+///
+/// Outputs profile names from the configuration, alphabetically unless
+/// another order is requested, one per line (text format), as a JSON
+/// document (json format), or as one JSON object per line (ndjson format).
+///
+/// # Arguments
+/// * `cfg` - Configuration containing profile definitions
+/// * `lib` - Library root directory for finding fragments
+/// * `opts` - Filtering and ordering options
+/// * `format` - Output format
+/// * `w` - Writer to output profile names to
+///
+/// # Returns
+/// * `Ok(())` - All profiles listed successfully
+/// * `Err(String)` - Operation failed
+///
+/// # Errors
+/// Returns an error if writing to the output fails, or if filtering by
+/// modification time requires resolving a profile that fails to resolve.
+pub fn list_profiles(
+    cfg: &Config,
+    lib: &Path,
+    opts: &ListOptions,
+    format: ListFormat,
+    mut w: impl Write,
+) -> Result<(), String> {
+    if opts.scenarios {
+        return list_scenarios(cfg, format, opts.json_layout, w);
+    }
+    let names = listed_profile_names(cfg, lib, opts);
+    if opts.grouped {
+        return list_grouped(cfg, lib, names, format, opts.json_layout, w);
+    }
+    let total = names.len();
+    let names = names
+        .into_iter()
+        .skip(opts.offset)
+        .take(opts.limit.unwrap_or(usize::MAX));
+    match format {
+        ListFormat::Text => {
+            for n in names {
+                writeln!(&mut w, "{n}").map_err(|e| format!("Write error: {e}"))?;
+            }
+        }
+        ListFormat::Json => {
+            let output = ListOutput {
+                profiles: names.map(|name| profile_info(name, cfg, lib)).collect(),
+                total,
+                fragments: library_fragments(lib)?,
+            };
+            let json_output = to_json(&output, opts.json_layout)?;
+            writeln!(&mut w, "{json_output}").map_err(|e| format!("Write error: {e}"))?;
+        }
+        ListFormat::Ndjson => {
+            for name in names {
+                let line = serde_json::to_string(&profile_info(name, cfg, lib))
+                    .map_err(|e| format!("JSON serialization error: {e}"))?;
+                writeln!(&mut w, "{line}")
+                    .and_then(|()| w.flush())
                     .map_err(|e| format!("Write error: {e}"))?;
             }
         }
+    }
+    Ok(())
+}
+
+/// Write scenario names, sorted: one per line in text, as a `scenarios` array
+/// of names and profiles in JSON, or one such object per line in NDJSON.
+fn list_scenarios(
+    cfg: &Config,
+    format: ListFormat,
+    json_layout: JsonLayout,
+    mut w: impl Write,
+) -> Result<(), String> {
+    let mut scenarios: Vec<ScenarioInfo> = cfg
+        .scenarios
+        .iter()
+        .map(|(name, scenario)| ScenarioInfo {
+            name,
+            profiles: &scenario.profiles,
+        })
+        .collect();
+    scenarios.sort_by_key(|scenario| scenario.name);
+    let lines = match format {
+        ListFormat::Text => scenarios
+            .iter()
+            .map(|scenario| scenario.name.to_string())
+            .collect(),
+        ListFormat::Json => vec![to_json(
+            &BTreeMap::from([("scenarios", &scenarios)]),
+            json_layout,
+        )?],
+        ListFormat::Ndjson => scenarios
+            .iter()
+            .map(|scenario| {
+                serde_json::to_string(scenario)
+                    .map_err(|e| format!("JSON serialization error: {e}"))
+            })
+            .collect::<Result<Vec<_>, _>>()?,
+    };
+    for line in lines {
+        writeln!(&mut w, "{line}").map_err(|e| format!("Write error: {e}"))?;
+    }
+    Ok(())
+}
+
+/// The namespace of profile `name`: the part before its first `/`, or `""`.
+fn profile_namespace(name: &str) -> &str {
+    name.split_once('/').map_or("", |(namespace, _)| namespace)
+}
+
+/// Write `names` grouped by namespace: in text, a `namespace/` heading over
+/// its profiles, indented, after the profiles outside any namespace; in JSON,
+/// a map from namespace to profiles. Profiles keep their order within a group.
+fn list_grouped(
+    cfg: &Config,
+    lib: &Path,
+    names: Vec<String>,
+    format: ListFormat,
+    json_layout: JsonLayout,
+    mut w: impl Write,
+) -> Result<(), String> {
+    let mut groups: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for name in names {
+        groups
+            .entry(profile_namespace(&name).to_string())
+            .or_default()
+            .push(name);
+    }
+    match format {
+        ListFormat::Text => {
+            for (namespace, names) in groups {
+                let indent = if namespace.is_empty() {
+                    ""
+                } else {
+                    writeln!(&mut w, "{namespace}/").map_err(|e| format!("Write error: {e}"))?;
+                    "  "
+                };
+                for name in names {
+                    writeln!(&mut w, "{indent}{name}").map_err(|e| format!("Write error: {e}"))?;
+                }
+            }
+        }
+        ListFormat::Json => {
+            let output = GroupedListOutput {
+                profiles: groups
+                    .into_iter()
+                    .map(|(namespace, names)| {
+                        let infos = names
+                            .into_iter()
+                            .map(|name| profile_info(name, cfg, lib))
+                            .collect();
+                        (namespace, infos)
+                    })
+                    .collect(),
+                fragments: library_fragments(lib)?,
+            };
+            let json_output = to_json(&output, json_layout)?;
+            writeln!(&mut w, "{json_output}").map_err(|e| format!("Write error: {e}"))?;
+        }
+        ListFormat::Ndjson => return Err("--grouped does not apply to --format ndjson".into()),
+    }
+    Ok(())
+}
+
+/// Metadata reported for a profile in JSON listings.
+fn profile_info(name: String, cfg: &Config, lib: &Path) -> ProfileInfo {
+    let meta = cfg.metadata.get(&name).cloned().unwrap_or_default();
+    ProfileInfo {
+        dependencies: cfg.profiles[&name].clone(),
+        size: profile_body_bytes(&name, cfg, lib).ok(),
+        tags: meta.tags,
+        description: meta.description,
+        name,
+    }
+}
+
+/// All .md files under the library, as relative paths in byte order.
+///
+/// `read_dir` order differs between platforms and filesystems, and locale-aware
+/// collation differs between machines; sorting the raw bytes keeps listings
+/// (and anything rendered from them) reproducible everywhere.
+pub(crate) fn library_fragments(lib: &Path) -> Result<Vec<String>, String> {
+    let mut fragments = Vec::new();
+    if lib.exists() {
+        verbose::timed("library scan", || {
+            collect_fragments(lib, lib, &mut fragments)
+        })?;
+    }
+    log::debug!("found {} fragment(s) in {}", fragments.len(), lib.display());
+    fragments.sort_unstable_by(|a, b| a.as_bytes().cmp(b.as_bytes()));
+    Ok(fragments)
+}
+
+/// Recursively collect all .md files from a directory
+fn collect_fragments(root: &Path, dir: &Path, fragments: &mut Vec<String>) -> Result<(), String> {
+    let entries = fs::read_dir(dir)
+        .map_err(|e| format!("Failed to read directory {}: {}", dir.display(), e))?;
+
+    for entry in entries {
+        let entry = entry.map_err(|e| format!("Failed to read directory entry: {e}"))?;
+        let path = entry.path();
+
+        if path.is_dir() {
+            collect_fragments(root, &path, fragments)?;
+        } else if path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("md"))
+        {
+            if let Ok(rel_path) = path.strip_prefix(root) {
+                fragments.push(rel_path.display().to_string());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Validate configuration and library file references.
+///
+/// Checks that all profile dependencies are valid, including:
+/// - Referenced profiles exist in configuration
+/// - Referenced markdown files exist in library
+/// - No circular dependencies exist
+/// - No include chain is deeper than `opts.max_depth`, when set
+///
+/// # Arguments
+/// * `cfg` - Configuration to validate
+/// * `lib` - Library root directory for file validation
+/// * `opts` - Optional structural limits
+///
+/// # Returns
+/// * `Ok(())` - Configuration is valid
+/// * `Err(String)` - Validation errors found
+///
+/// # Errors
+/// Returns an error if:
+/// - Referenced profiles don't exist
+/// - Referenced files don't exist
+/// - Circular dependencies are detected
+/// - An include chain exceeds the maximum depth
+pub fn validate(cfg: &Config, lib: &Path, opts: &ValidateOptions) -> Result<(), String> {
+    let errors: Vec<String> = validate_profiles(cfg, lib, opts)
+        .into_iter()
+        .flat_map(|report| report.errors)
+        .collect();
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors.join("\n"))
+    }
+}
+
+/// Validation result for one profile.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProfileValidation {
+    /// Profile name
+    pub profile: String,
+    /// Problems attributed to the profile (empty when it is valid)
+    pub errors: Vec<String>,
+    /// Optional includes that were not found, which do not make it invalid
+    pub notes: Vec<String>,
+    /// Hygiene problems reported with `--review`, which do not make it invalid
+    pub warnings: Vec<String>,
+}
+
+/// The `--review` warning for `name` as of `today`: its `review_after` date
+/// has passed, or it has no descriptive metadata at all.
+fn review_warning(cfg: &Config, name: &str, today: NaiveDate) -> Option<String> {
+    let meta = cfg.metadata.get(name);
+    if let Some(date) = meta.and_then(|m| m.review_after) {
+        return (date < today).then(|| format!("Profile [{name}] was due for review on {date}"));
+    }
+    meta.is_none_or(|m| m.description.is_none() && m.tags.is_empty())
+        .then(|| format!("Profile [{name}] has no metadata (description, tags, or review_after)"))
+}
+
+/// Validate every profile, attributing each problem to the profile that has
+/// it; sorted by profile name. See [`validate`] for the checks performed.
+#[must_use]
+pub fn validate_profiles(
+    cfg: &Config,
+    lib: &Path,
+    opts: &ValidateOptions,
+) -> Vec<ProfileValidation> {
+    let mut errors: BTreeMap<&str, Vec<String>> = cfg
+        .profiles
+        .keys()
+        .map(|name| (name.as_str(), Vec::new()))
+        .collect();
+    let mut notes: HashMap<&str, Vec<String>> = HashMap::new();
+
+    for (profile, deps) in &cfg.profiles {
+        let errors = errors.entry(profile.as_str()).or_default();
+        for dep in deps {
+            let (dep, optional) = split_optional(dep);
+            let optional = optional && !opts.strict_includes;
+            let problem = if is_fragment(dep) {
+                let (file, range) = split_line_range(dep);
+                match checked_fragment_path(file, cfg, lib) {
+                    // An uncached remote include cannot be checked offline
+                    Ok(path) if !path.exists() && remote::is_remote(file) => continue,
+                    Ok(path) if path.exists() => {
+                        if let Some(spec) = range {
+                            errors.extend(check_line_range(dep, spec, &path, profile));
+                        }
+                        continue;
+                    }
+                    Ok(path) => format!("file: {}", path.display()),
+                    Err(e) => format!("remote include: {e}"),
+                }
+            } else if cfg.profiles.contains_key(dep) {
+                continue;
+            } else {
+                format!("profile: {dep}")
+            };
+            if optional {
+                notes.entry(profile).or_default().push(format!(
+                    "Optional include not found, skipped: {problem} (referenced by [{profile}])"
+                ));
+            } else {
+                let kind = if is_fragment(dep) {
+                    "Missing"
+                } else {
+                    "Unknown"
+                };
+                errors.push(format!("{kind} {problem} (referenced by [{profile}])"));
+            }
+        }
+    }
+
+    for (name, errors) in &mut errors {
+        let mut seen_files = HashSet::new();
+        let mut stack = Vec::new();
+        let mut out = Vec::new();
+        if let Err(ResolveError::Cycle(cycle)) =
+            resolve_profile(name, cfg, lib, &mut seen_files, &mut stack, &mut out)
+        {
+            let chain = cycle.join(" -> ");
+            errors.push(format!("Cycle detected: {chain}"));
+        }
+    }
+
+    // Depth is only well-defined once the include graph is known to be acyclic.
+    let valid = errors.values().all(Vec::is_empty);
+    if let Some(max_depth) = opts.max_depth.filter(|_| valid) {
+        let mut memo = HashMap::new();
+        for (name, errors) in &mut errors {
+            let chain = longest_chain(name, cfg, &mut memo);
+            let depth = chain.len() - 1;
+            if depth > max_depth {
+                errors.push(format!(
+                    "Include depth {depth} exceeds maximum {max_depth}: {}",
+                    chain.join(" -> ")
+                ));
+            }
+        }
+    }
+
+    let mut scenarios: Vec<(&String, &Scenario)> = cfg.scenarios.iter().collect();
+    scenarios.sort_by_key(|(name, _)| *name);
+    let mut validations: Vec<ProfileValidation> = errors
+        .into_iter()
+        .map(|(profile, errors)| ProfileValidation {
+            profile: profile.to_string(),
+            errors,
+            notes: notes.remove(profile).unwrap_or_default(),
+            warnings: opts
+                .review
+                .then(|| review_warning(cfg, profile, Local::now().date_naive()))
+                .flatten()
+                .into_iter()
+                .collect(),
+        })
+        .collect();
+    for (name, scenario) in scenarios {
+        let section = format!("{SCENARIO_PREFIX}{name}");
+        let errors = scenario
+            .profiles
+            .iter()
+            .filter(|profile| !cfg.profiles.contains_key(*profile))
+            .map(|profile| format!("Unknown profile: {profile} (referenced by [{section}])"))
+            .collect();
+        validations.push(ProfileValidation {
+            profile: section,
+            errors,
+            notes: Vec::new(),
+            warnings: Vec::new(),
+        });
+    }
+    validations
+}
+
+/// Library fragments referenced directly by profiles, once each, paired with
+/// the first profile (by name) that references them. `@path` includes are
+/// skipped, and line ranges are dropped from the paths.
+pub(crate) fn referenced_fragments(cfg: &Config) -> Vec<(&str, &str)> {
+    let mut names: Vec<&String> = cfg.profiles.keys().collect();
+    names.sort();
+    let mut seen = HashSet::new();
+    let mut fragments = Vec::new();
+    for name in names {
+        for dep in &cfg.profiles[name] {
+            let dep = split_optional(dep).0;
+            if !is_fragment(dep) || is_file_include(dep) {
+                continue;
+            }
+            let (file, _) = split_line_range(dep);
+            if seen.insert(file) {
+                fragments.push((name.as_str(), file));
+            }
+        }
+    }
+    fragments
+}
+
+/// Entries listed more than once in the same profile's `depends_on`, as
+/// messages sorted by profile.
+#[must_use]
+pub fn duplicate_dependencies(cfg: &Config) -> Vec<String> {
+    let mut names: Vec<&String> = cfg.profiles.keys().collect();
+    names.sort();
+    let mut messages = Vec::new();
+    for name in names {
+        let mut seen = HashSet::new();
+        let mut reported = HashSet::new();
+        for dep in &cfg.profiles[name] {
+            if !seen.insert(dep) && reported.insert(dep) {
+                messages.push(format!("Duplicate dependency: {dep} (in [{name}])"));
+            }
+        }
+    }
+    messages
+}
+
+/// Longest profile-to-profile chain starting at `name`.
+///
+/// Resolution has already rejected cycles, so memoizing per profile is safe.
+pub(crate) fn longest_chain(
+    name: &str,
+    cfg: &Config,
+    memo: &mut HashMap<String, Vec<String>>,
+) -> Vec<String> {
+    if let Some(chain) = memo.get(name) {
+        return chain.clone();
+    }
+    let mut best: Vec<String> = Vec::new();
+    if let Some(deps) = cfg.profiles.get(name) {
+        let deps = deps.iter().map(|d| split_optional(d).0);
+        for dep in deps.filter(|d| !is_fragment(d) && cfg.profiles.contains_key(*d)) {
+            let chain = longest_chain(dep, cfg, memo);
+            if chain.len() > best.len() {
+                best = chain;
+            }
+        }
+    }
+    let mut chain = Vec::with_capacity(best.len() + 1);
+    chain.push(name.to_string());
+    chain.extend(best);
+    memo.insert(name.to_string(), chain.clone());
+    chain
+}
+
+/// Validate the `#range` suffix of a fragment reference against its file.
+fn check_line_range(dep: &str, spec: &str, path: &Path, profile: &str) -> Option<String> {
+    let Some(range) = LineRange::parse(spec) else {
+        return Some(format!(
+            "Invalid line range: {dep} (referenced by [{profile}])"
+        ));
+    };
+    let content = match read_text(path, Encoding::Utf8, false) {
+        Ok(content) => content,
+        Err(e) => return Some(e),
+    };
+    range.slice(&content).is_none().then(|| {
+        format!(
+            "Line range out of bounds: {dep} has {} lines (referenced by [{profile}])",
+            content.lines().count()
+        )
+    })
+}
+
+/// Stable identifier of a dependency entry in the tree: profiles are known
+/// by name, fragments and files by their path (including any line range).
+fn tree_node_id(name: &str) -> String {
+    match name.strip_prefix('@') {
+        Some(path) => format!("file:{path}"),
+        None if is_fragment(name) => format!("fragment:{name}"),
+        None => name.to_string(),
+    }
+}
+
+/// Build a tree node for a profile or fragment below the profile `parent`
+fn build_tree_node(name: &str, parent: Option<&str>, cfg: &Config) -> TreeNode {
+    let (name, optional) = split_optional(name);
+    let id = tree_node_id(name);
+    let parent = parent.map(str::to_string);
+    let edge = parent.as_ref().map(|_| TreeEdge {
+        kind: if optional {
+            IncludeKind::Soft
+        } else {
+            IncludeKind::Hard
+        },
+        lines: None,
+    });
+    // Check if it's a file (`@path` or ending in .md)
+    if is_fragment(name) {
+        return TreeNode {
+            id,
+            node_type: if is_file_include(name) {
+                TreeNodeType::File
+            } else {
+                TreeNodeType::Fragment
+            },
+            name: name.to_string(),
+            parent,
+            extends: false,
+            optional,
+            edge: edge.map(|edge| TreeEdge {
+                lines: split_line_range(name).1.and_then(LineRange::parse),
+                ..edge
+            }),
+            children: Vec::new(),
+        };
+    }
+
+    // It's a profile - recursively build children; an extending profile shows
+    // its parent first, then only the entries it adds or overrides
+    let deps = cfg
+        .profiles
+        .get(name)
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+    let mut children = Vec::new();
+    let inherited: &[String] = if let Some(base) = extends_of(cfg, name) {
+        children.push(TreeNode {
+            extends: true,
+            edge: Some(TreeEdge {
+                kind: IncludeKind::Extends,
+                lines: None,
+            }),
+            ..build_tree_node(base, Some(&id), cfg)
+        });
+        cfg.profiles
+            .get(base)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    } else {
+        &[]
+    };
+    children.extend(
+        deps.iter()
+            .filter(|dep| !inherited.contains(dep))
+            .map(|dep| build_tree_node(dep, Some(&id), cfg)),
+    );
+
+    TreeNode {
+        id,
+        node_type: TreeNodeType::Profile,
+        name: name.to_string(),
+        parent,
+        extends: false,
+        optional,
+        edge,
+        children,
+    }
+}
+
+/// Find root profiles (profiles that are not referenced by any other profile)
+fn find_root_profiles(cfg: &Config) -> Vec<String> {
+    let mut referenced = HashSet::new();
+
+    // Collect all profiles that are referenced or extended by others
+    referenced.extend(cfg.metadata.values().filter_map(|m| m.extends.clone()));
+    for deps in cfg.profiles.values() {
+        for dep in deps {
+            // Only track profile references (not .md files)
+            let dep = split_optional(dep).0;
+            if !is_fragment(dep) {
+                referenced.insert(dep.to_string());
+            }
+        }
+    }
+
+    // Find profiles that are never referenced
+    let mut roots: Vec<String> = cfg
+        .profiles
+        .keys()
+        .filter(|profile| !referenced.contains(*profile))
+        .cloned()
+        .collect();
+
+    roots.sort();
+    roots
+}
+
+/// Build complete tree structure for all root profiles
+fn build_trees(cfg: &Config) -> TreeOutput {
+    let root_profiles = find_root_profiles(cfg);
+    let trees = root_profiles
+        .iter()
+        .map(|profile| build_tree_node(profile, None, cfg))
+        .collect();
+
+    TreeOutput { trees }
+}
+
+/// Label for a tree node, colorized by node type when colors are enabled
+fn tree_node_label(node: &TreeNode) -> String {
+    let label = match node.node_type {
+        TreeNodeType::Profile => node.name.bright_cyan().bold().to_string(),
+        TreeNodeType::Fragment => node.name.normal().to_string(),
+        TreeNodeType::File => node.name.yellow().to_string(),
+    };
+    if node.extends {
+        format!("{} {label}", "extends".magenta())
+    } else if node.optional {
+        format!("{label} {}", "(optional)".dimmed())
+    } else {
+        label
+    }
+}
+
+/// Print tree structure in traditional tree format
+fn print_tree(node: &TreeNode, prefix: &str, is_last: bool, w: &mut impl Write) -> io::Result<()> {
+    // Print current node with appropriate connector
+    let connector = if is_last { "└── " } else { "├── " };
+    writeln!(w, "{prefix}{connector}{}", tree_node_label(node))?;
+
+    // Prepare prefix for children
+    let child_prefix = format!("{}{}", prefix, if is_last { "    " } else { "│   " });
+
+    // Print children
+    for (i, child) in node.children.iter().enumerate() {
+        let is_last_child = i == node.children.len() - 1;
+        print_tree(child, &child_prefix, is_last_child, w)?;
+    }
+
+    Ok(())
+}
+
+/// Show tree structure for all profiles
+///
+/// # Errors
+/// Returns an error if JSON serialization or writing to the output fails.
+pub fn show_tree(
+    cfg: &Config,
+    json: bool,
+    json_layout: JsonLayout,
+    mut w: impl Write,
+) -> Result<(), String> {
+    let trees = build_trees(cfg);
+
+    if json {
+        let json_output = to_json(&trees, json_layout)?;
+        writeln!(&mut w, "{json_output}").map_err(|e| format!("Write error: {e}"))?;
+    } else {
+        for (i, tree) in trees.trees.iter().enumerate() {
+            // Print root profile name
+            writeln!(&mut w, "{}", tree_node_label(tree))
+                .map_err(|e| format!("Write error: {e}"))?;
+
+            // Print children with tree structure
+            for (j, child) in tree.children.iter().enumerate() {
+                let is_last = j == tree.children.len() - 1;
+                print_tree(child, "", is_last, &mut w).map_err(|e| format!("Write error: {e}"))?;
+            }
+
+            // Add blank line between trees (except after last one)
+            if i < trees.trees.len() - 1 {
+                writeln!(&mut w).map_err(|e| format!("Write error: {e}"))?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Show tree structure to stdout
+///
+/// # Errors
+/// Returns an error if the configuration cannot be read or parsed, or if
+/// writing to stdout fails.
+pub fn run_tree_stdout(
+    config: &ConfigSource,
+    json: bool,
+    json_layout: JsonLayout,
+) -> Result<(), String> {
+    let (cfg, _) = config.load()?;
+    show_tree(&cfg, json, json_layout, io::stdout())
+}
+
+/// Initialize default configuration and library structure.
+///
+/// Creates the default directory structure and configuration files
+/// for prompter, including the sample profiles and library files of the
+/// named built-in `template` (see [`templates::TEMPLATES`]).
+/// Only creates files that don't already exist (non-destructive). If a step
+/// fails, the files and directories created so far are removed again, so a
+/// failed init can simply be retried. With `quiet`, the progress spinner and
+/// summary messages are suppressed.
+///
+/// # Returns
+/// * `Ok(())` - Initialization completed successfully
+/// * `Err(String)` - Initialization failed
+///
+/// # Errors
+/// Returns an error if:
+/// - `template` is not a built-in template name
+/// - Directory creation fails
+/// - File writing fails
+/// - HOME environment variable is not set
+///
+/// # Panics
+/// Panics if the progress bar template is invalid (should not happen with the
+/// hardcoded template string).
+pub fn init_scaffold(template: &str, quiet: bool) -> Result<(), String> {
+    let template = templates::find_template(template)?;
+    let pb = if is_terminal() && !quiet {
+        let pb = ProgressBar::new_spinner();
+        pb.set_style(
+            ProgressStyle::default_spinner()
+                .tick_chars("⠁⠂⠄⡀⢀⠠⠐⠈ ")
+                .template("{spinner:.green} {msg}")
+                .unwrap(),
+        );
+        pb.set_message("Initializing prompter...");
+        pb.enable_steady_tick(std::time::Duration::from_millis(120));
+        Some(pb)
+    } else {
+        None
+    };
+
+    let cfg_path = config_path()?;
+    let lib = library_dir()?;
+    let mut scaffold = Scaffold::default();
+    if let Err(e) = scaffold.write_template(template, &cfg_path, &lib, pb.as_ref()) {
+        scaffold.roll_back();
+        if let Some(pb) = pb {
+            pb.finish_and_clear();
+        }
+        return Err(e);
+    }
+
+    if let Some(pb) = pb {
+        pb.finish_with_message("Initialization complete!");
+        std::thread::sleep(std::time::Duration::from_millis(200)); // Brief pause to show completion
+    }
+
+    if quiet {
+        return Ok(());
+    }
+
+    println!(
+        "{}",
+        success_message(&format!("Initialized config at {}", cfg_path.display()))
+    );
+    println!(
+        "{}",
+        info_message(&format!("Library root at {}", lib.display()))
+    );
+    Ok(())
+}
+
+/// Files and directories created by `init`, so a failed run can remove them.
+#[derive(Default)]
+struct Scaffold {
+    created: Vec<PathBuf>,
+}
+
+impl Scaffold {
+    /// Write the template's config and library files that do not exist yet.
+    fn write_template(
+        &mut self,
+        template: &templates::InitTemplate,
+        cfg_path: &Path,
+        lib: &Path,
+        pb: Option<&ProgressBar>,
+    ) -> Result<(), String> {
+        let cfg_dir = cfg_path
+            .parent()
+            .ok_or_else(|| "Invalid config path".to_string())?;
+        if let Some(pb) = pb {
+            pb.set_message("Creating config directory...");
+        }
+        self.create_dir_all(cfg_dir)?;
+        if let Some(pb) = pb {
+            pb.set_message("Creating library directory...");
+        }
+        self.create_dir_all(lib)?;
+
+        if !cfg_path.exists() {
+            if let Some(pb) = pb {
+                pb.set_message("Writing default config...");
+            }
+            self.write(cfg_path, template.config)?;
+        }
+
+        for (rel_path, contents) in template.files {
+            let path = lib.join(rel_path);
+            if let Some(pb) = pb {
+                pb.set_message(format!(
+                    "Creating {}",
+                    path.file_name().unwrap_or_default().to_string_lossy()
+                ));
+            }
+            if let Some(parent) = path.parent() {
+                self.create_dir_all(parent)?;
+            }
+            if !path.exists() {
+                self.write(&path, contents)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn create_dir_all(&mut self, dir: &Path) -> Result<(), String> {
+        let mut missing: Vec<PathBuf> = dir
+            .ancestors()
+            .take_while(|d| !d.exists())
+            .map(Path::to_path_buf)
+            .collect();
+        fs::create_dir_all(dir)
+            .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+        missing.reverse();
+        self.created.append(&mut missing);
+        Ok(())
+    }
+
+    fn write(&mut self, path: &Path, contents: &str) -> Result<(), String> {
+        fs::write(path, contents)
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+        self.created.push(path.to_path_buf());
+        Ok(())
+    }
+
+    /// Remove everything created so far, newest first. Best effort: the
+    /// original error is what gets reported.
+    fn roll_back(&mut self) {
+        for path in self.created.drain(..).rev() {
+            let _ = if path.is_dir() {
+                fs::remove_dir(&path)
+            } else {
+                fs::remove_file(&path)
+            };
+        }
+    }
+}
+
+/// List profiles to stdout.
+///
+/// Convenience function that reads configuration and lists all profiles
+/// to standard output.
+///
+/// # Arguments
+/// * `config` - Configuration source
+/// * `opts` - Filtering and ordering options
+/// * `json` - Whether to output in JSON format
+///
+/// # Returns
+/// * `Ok(())` - Profiles listed successfully
+/// * `Err(String)` - Operation failed
+///
+/// # Errors
+/// Returns an error if:
+/// - Configuration file cannot be read or parsed
+/// - Writing to stdout fails
+pub fn run_list_stdout(
+    config: &ConfigSource,
+    opts: &ListOptions,
+    format: ListFormat,
+) -> Result<(), String> {
+    let (cfg, lib) = config.load()?;
+    list_profiles(&cfg, &lib, opts, format, io::stdout())
+}
+
+/// JSON output for successful validation
+#[derive(Debug, Serialize)]
+struct ValidateOutput {
+    valid: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    warnings: Vec<String>,
+}
+
+/// Validate configuration and output results to stdout.
+///
+/// Convenience function that reads configuration and validates it,
+/// outputting any errors found.
+///
+/// # Arguments
+/// * `config` - Configuration source
+/// * `json` - Whether to output in JSON format
+///
+/// # Returns
+/// * `Ok(())` - Configuration is valid
+/// * `Err(String)` - Validation errors found
+///
+/// # Errors
+/// Returns an error if:
+/// - Configuration file cannot be read or parsed
+/// - Validation finds missing files or circular dependencies
+pub fn run_validate_stdout(
+    config: &ConfigSource,
+    opts: &ValidateOptions,
+    json: bool,
+    json_layout: JsonLayout,
+) -> Result<(), String> {
+    let (cfg, lib) = config.load()?;
+    let reports = validate_profiles(&cfg, &lib, opts);
+    let warnings: Vec<String> = reports.iter().flat_map(|r| r.warnings.clone()).collect();
+    if !json {
+        for note in reports.iter().flat_map(|r| &r.notes) {
+            eprintln!("Note: {note}");
+        }
+        for warning in &warnings {
+            eprintln!("Warning: {warning}");
+        }
+    }
+    let errors: Vec<&str> = reports
+        .iter()
+        .flat_map(|r| &r.errors)
+        .map(String::as_str)
+        .collect();
+    if !errors.is_empty() {
+        return Err(errors.join("\n"));
+    }
+
+    if json {
+        let output = ValidateOutput {
+            valid: true,
+            warnings,
+        };
+        let json_output = to_json(&output, json_layout)?;
+        println!("{json_output}");
+    }
+
+    Ok(())
+}
+
+/// Escape text for use in XML element content or attribute values.
+fn xml_escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Write validation results as a `JUnit` XML report: one testsuite named
+/// `suite`, one testcase per profile, and a failure for each invalid profile
+/// carrying all of its errors.
+///
+/// # Errors
+/// Returns an error if writing to the output fails.
+pub fn write_junit(
+    suite: &str,
+    reports: &[ProfileValidation],
+    mut w: impl Write,
+) -> Result<(), String> {
+    let failures = reports.iter().filter(|r| !r.errors.is_empty()).count();
+    let counts = format!("tests=\"{}\" failures=\"{failures}\"", reports.len());
+    let mut xml = vec![
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>".to_string(),
+        format!("<testsuites {counts}>"),
+        format!("  <testsuite name=\"{}\" {counts}>", xml_escape(suite)),
+    ];
+    for report in reports {
+        let testcase = format!(
+            "    <testcase classname=\"prompter.validate\" name=\"{}\"",
+            xml_escape(&report.profile)
+        );
+        match report.errors.first() {
+            None => xml.push(format!("{testcase}/>")),
+            Some(first) => xml.push(format!(
+                "{testcase}>\n      <failure message=\"{}\">{}</failure>\n    </testcase>",
+                xml_escape(first),
+                xml_escape(&report.errors.join("\n"))
+            )),
+        }
+    }
+    xml.push("  </testsuite>\n</testsuites>".to_string());
+    writeln!(w, "{}", xml.join("\n")).map_err(|e| format!("Write error: {e}"))
+}
+
+/// Validate every profile and print a `JUnit` XML report to stdout.
+///
+/// # Errors
+/// Returns an error if the configuration cannot be loaded, writing fails, or
+/// any profile failed validation (after the report has been written).
+pub fn run_validate_junit_stdout(
+    config: &ConfigSource,
+    opts: &ValidateOptions,
+) -> Result<(), String> {
+    let (cfg, lib) = config.load()?;
+    let reports = validate_profiles(&cfg, &lib, opts);
+    write_junit(&config.describe(), &reports, io::stdout())?;
+    let failed = reports.iter().filter(|r| !r.errors.is_empty()).count();
+    if failed > 0 {
+        return Err(format!(
+            "{failed} of {} profiles failed validation",
+            reports.len()
+        ));
+    }
+    Ok(())
+}
+
+/// Preflight for `--config-check`: load the configuration and resolve every
+/// profile's includes before another subcommand runs.
+///
+/// # Errors
+/// Returns an error naming the configuration file if it cannot be read or
+/// parsed, or if validation finds problems.
+pub fn run_config_check(config: &ConfigSource) -> Result<(), String> {
+    config
+        .load()
+        .and_then(|(cfg, lib)| validate(&cfg, &lib, &ValidateOptions::default()))
+        .map_err(|e| format!("Config check failed for {}:\n{e}", config.describe()))
+}
+
+/// Resolved configuration printed by `--dump-config`
+#[derive(Debug, Serialize)]
+struct ConfigDump {
+    /// Where the configuration was loaded from
+    source: String,
+    /// Library directory fragments resolve against
+    library: PathBuf,
+    /// Directory `@path` includes resolve against
+    #[serde(skip_serializing_if = "Option::is_none")]
+    base_dir: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    relative_to: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    include_base: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    post_prompt: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    comment_prefix: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    case_insensitive_profiles: Option<bool>,
+    profiles: BTreeMap<String, ProfileDump>,
+}
+
+/// A profile as the loader produced it, with inherited dependencies flattened
+#[derive(Debug, Serialize)]
+struct ProfileDump {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    extends: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    review_after: Option<String>,
+    /// Body of an inline `[profiles]` entry
+    #[serde(skip_serializing_if = "Option::is_none")]
+    body: Option<String>,
+    depends_on: Vec<String>,
+}
+
+/// Write the loaded configuration and its resolved paths as TOML, or as JSON
+/// with `json`.
+///
+/// # Errors
+/// Returns an error if serialization or writing to the output fails.
+pub fn dump_config(
+    cfg: &Config,
+    lib: &Path,
+    source: &ConfigSource,
+    json: bool,
+    json_layout: JsonLayout,
+    mut w: impl Write,
+) -> Result<(), String> {
+    let profiles = cfg
+        .profiles
+        .iter()
+        .map(|(name, deps)| {
+            let meta = cfg.metadata.get(name).cloned().unwrap_or_default();
+            let dump = ProfileDump {
+                description: meta.description,
+                tags: meta.tags,
+                extends: meta.extends,
+                review_after: meta.review_after.map(|d| d.to_string()),
+                body: cfg.inline_profiles.get(name).cloned(),
+                depends_on: deps.clone(),
+            };
+            (name.clone(), dump)
+        })
+        .collect();
+    let dump = ConfigDump {
+        source: source.describe(),
+        library: lib.to_path_buf(),
+        base_dir: cfg.base_dir.clone(),
+        relative_to: cfg.relative_to.clone(),
+        include_base: cfg.include_base.clone(),
+        post_prompt: cfg.post_prompt.clone(),
+        comment_prefix: cfg.comment_prefix.clone(),
+        case_insensitive_profiles: cfg.case_insensitive_profiles,
+        profiles,
+    };
+    let text = if json {
+        to_json(&dump, json_layout)?
+    } else {
+        toml::to_string_pretty(&dump).map_err(|e| format!("TOML serialization error: {e}"))?
+    };
+    writeln!(w, "{}", text.trim_end()).map_err(|e| format!("Write error: {e}"))
+}
+
+/// Print the resolved configuration for `--dump-config` to stdout.
+///
+/// # Errors
+/// Returns an error if the configuration cannot be loaded or written.
+pub fn run_dump_config_stdout(
+    config: &ConfigSource,
+    json: bool,
+    json_layout: JsonLayout,
+) -> Result<(), String> {
+    let (cfg, lib) = config.load()?;
+    dump_config(&cfg, &lib, config, json, json_layout, io::stdout())
+}
+
+/// Where a profile is defined, as reported by `which`.
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub struct ProfileLocation {
+    /// Profile name
+    pub profile: String,
+    /// Config file defining the profile; `None` for inline profiles and
+    /// `--config-inline` text
+    pub path: Option<PathBuf>,
+    /// `file` for `[name]` sections, `inline` for `[profiles]` entries
+    pub source: &'static str,
+}
+
+/// Locate the definition of `profile` in the configuration from `source`.
+///
+/// # Errors
+/// Returns an error if the profile does not exist or the config file path
+/// cannot be determined.
+pub fn which(
+    cfg: &Config,
+    source: &ConfigSource,
+    profile: &str,
+) -> Result<ProfileLocation, String> {
+    let profile = cfg.profile_name(profile);
+    if !cfg.profiles.contains_key(profile) {
+        return Err(format!("Unknown profile: {profile}"));
+    }
+    let inline = cfg.inline_profiles.contains_key(profile);
+    Ok(ProfileLocation {
+        profile: profile.to_string(),
+        path: if inline {
+            None
+        } else {
+            source.file_defining(profile)?
+        },
+        source: if inline { "inline" } else { "file" },
+    })
+}
+
+/// Print the config file that defines `profile`, or its location as JSON.
+///
+/// In text mode, inline profiles and `--config-inline` text are errors since
+/// there is no file to print.
+///
+/// # Errors
+/// Returns an error if the configuration cannot be loaded, the profile does
+/// not exist, or (in text mode) it has no backing file.
+pub fn run_which_stdout(
+    profile: &str,
+    config: &ConfigSource,
+    json: bool,
+    json_layout: JsonLayout,
+) -> Result<(), String> {
+    let (cfg, _) = config.load()?;
+    let location = which(&cfg, config, profile)?;
+    if json {
+        println!("{}", to_json(&location, json_layout)?);
+        return Ok(());
+    }
+    match location.path {
+        Some(path) => {
+            println!("{}", path.display());
+            Ok(())
+        }
+        None if location.source == "inline" => Err(format!(
+            "Profile '{profile}' is inline (defined in [profiles] of {}) and has no file",
+            config.describe()
+        )),
+        None => Err(format!(
+            "Profile '{profile}' comes from --config-inline and has no file"
+        )),
+    }
+}
+
+/// Output format for rendered profiles.
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RenderFormat {
+    /// Plain concatenated text
+    #[default]
+    Text,
+    /// Structured JSON with the pre-prompt, system info, and fragments
+    Json,
+    /// JSON array of chat messages (`{"role", "content"}`) for chat-completion APIs
+    Chat,
+}
+
+/// Roles assigned to messages in chat format output.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChatRoles {
+    /// Role of the message carrying the pre-prompt
+    pub pre_prompt: String,
+    /// Role of the message carrying the composed body
+    pub body: String,
+}
+
+impl Default for ChatRoles {
+    fn default() -> Self {
+        Self {
+            pre_prompt: "system".to_string(),
+            body: "user".to_string(),
+        }
+    }
+}
+
+/// Options controlling how profiles are rendered.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct RenderOptions {
+    /// Separator written after each file
+    pub separator: Option<String>,
+    /// Which fragment boundaries the separator is written at
+    pub separator_between: SeparatorPlacement,
+    /// Separator written between the preamble (pre-prompt and system info) and
+    /// the body, instead of `separator`
+    pub pre_separator: Option<String>,
+    /// Separator written between the body and the post-prompt, instead of `separator`
+    pub post_separator: Option<String>,
+    /// Custom pre-prompt (defaults to LLM instructions)
+    pub pre_prompt: Option<String>,
+    /// Custom post-prompt (defaults to config value, then @AGENTS/@CLAUDE instructions)
+    pub post_prompt: Option<String>,
+    /// Output format
+    pub format: RenderFormat,
+    /// Message roles used by the chat format
+    pub chat_roles: ChatRoles,
+    /// Profiles skipped wherever they appear in the include tree
+    pub exclude: Vec<String>,
+    /// Profiles replaced by an `<!-- omitted: name -->` marker wherever they appear
+    pub comment_out: Vec<String>,
+    /// Handling of requested profiles that do not exist
+    pub on_missing: MissingProfilePolicy,
+    /// Fail on missing `?` includes instead of skipping them
+    pub strict_includes: bool,
+    /// When non-empty, only content reached through these profiles is rendered
+    pub include_only: Vec<String>,
+    /// Comment line prefix override (empty disables comment stripping)
+    pub comment_prefix: Option<String>,
+    /// `{{name}}` substitution values
+    pub vars: vars::Variables,
+    /// Collapse long runs of blank lines in text and chat output
+    pub strip_blank_runs: bool,
+    /// Whitespace trimmed from each included file
+    pub trim: TrimPolicy,
+    /// Expand each profile's includes in alphabetical order
+    pub sort_includes: bool,
+    /// Expand each profile's includes in an order shuffled from this seed
+    pub shuffle_seed: Option<u64>,
+    /// Whether `shuffle_seed` was generated because `--seed` was not given
+    pub seed_generated: bool,
+    /// Unicode normalization applied to text and chat output
+    pub normalize_unicode: Option<UnicodeForm>,
+    /// Replace typographic characters with ASCII in text and chat output
+    pub ascii_fold: bool,
+    /// Soft-wrap text and chat output at this many columns (0 disables)
+    pub wrap: usize,
+    /// Fail instead of rendering when a `{{...}}` placeholder is left in the output
+    pub strict_vars: bool,
+    /// Engine fragment bodies are rendered with
+    pub template_engine: engine::TemplateEngine,
+    /// Template the whole text output is placed into, at its `{{body}}` slot
+    pub out_template: Option<String>,
+    /// Handling of fragment files that are not valid UTF-8
+    pub encoding: Encoding,
+    /// Line endings of text output
+    pub line_endings: LineEndings,
+    /// Send text and chat renders to stderr instead of stdout
+    pub to_stderr: bool,
+    /// Print the contributing profiles in render order to stderr first
+    pub print_order: bool,
+    /// Write the render here instead of stdout (or stderr with `to_stderr`)
+    pub output: Option<OutputTarget>,
+    /// Print a digest of the text output instead of it (or add it to JSON output)
+    pub hash: Option<HashAlgorithm>,
+    /// Warn when the text output is estimated at fewer tokens than this
+    pub min_tokens: Option<usize>,
+    /// Fail instead of warning when `min_tokens` is not met
+    pub fail_on_warning: bool,
+    /// Append render metrics to this file as JSON lines
+    pub metrics_json: Option<PathBuf>,
+    /// Compare the render with this file instead of printing it
+    pub diff_against: Option<PathBuf>,
+    /// Leave out the date and system line, so the output is the same on any
+    /// day and machine
+    pub no_system_info: bool,
+    /// Decorate the system info line of text output with emoji and color; set
+    /// only when the render goes straight to a terminal
+    pub styled: bool,
+    /// Layout of JSON and chat output
+    pub json_layout: JsonLayout,
+    /// Leave warnings off stderr (`--quiet`)
+    pub quiet: bool,
+    /// Files concatenated into the pre-prompt when `pre_prompt` is not set
+    pub pre_prompt_files: Vec<PathBuf>,
+    /// Files rendered verbatim before the composed body
+    pub prepend_files: Vec<PathBuf>,
+    /// Files rendered verbatim after the composed body
+    pub append_files: Vec<PathBuf>,
+}
+
+impl RenderOptions {
+    /// Print a generated shuffle seed to stderr, unless `quiet`, so the include
+    /// order can be reproduced with `--seed`.
+    pub fn announce_seed(&self) {
+        if let Some(seed) = self
+            .shuffle_seed
+            .filter(|_| self.seed_generated && !self.quiet)
+        {
+            eprintln!("Shuffle seed: {seed} (pass --seed {seed} to reproduce this order)");
+        }
+    }
+}
+
+/// JSON structure for a single chat message
+#[derive(Debug, Serialize)]
+struct ChatMessage {
+    role: String,
+    content: String,
+}
+
+/// JSON structure for a single fragment
+#[derive(Debug, Serialize)]
+struct FragmentOutput {
+    path: String,
+    content: String,
+}
+
+/// JSON output structure for render command
+#[derive(Debug, Serialize)]
+struct RenderOutput {
+    profile: String,
+    pre_prompt: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system_info: Option<String>,
+    fragments: Vec<FragmentOutput>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hash: Option<String>,
+}
+
+/// Render one or more profiles' content to a writer.
+///
+/// Resolves profile dependencies and writes the concatenated content
+/// to the provided writer, including pre-prompt, system info, file
+/// contents with optional separators, and post-prompt. When multiple
+/// profiles are provided, files are deduplicated across all profiles
+/// (first occurrence wins).
+///
+/// # Arguments
+/// * `cfg` - Configuration containing profile definitions
+/// * `lib` - Library root directory for file resolution
+/// * `w` - Writer to output rendered content to
+/// * `profiles` - Profile names to render (deduplicated in order)
+/// * `opts` - Separator, pre/post-prompt overrides, and output format
+///
+/// # Returns
+/// * `Ok(())` - Profiles rendered successfully
+/// * `Err(String)` - Rendering failed
+///
+/// # Errors
+/// Returns an error if:
+/// - Profile resolution fails (missing files, cycles, unknown profiles)
+/// - Writing to output fails
+/// - File reading fails
+///
+/// Failures name the requested profile and include chain that caused them.
+pub fn render_to_writer(
+    cfg: &Config,
+    lib: &Path,
+    w: impl Write,
+    profiles: &[String],
+    opts: &RenderOptions,
+) -> Result<(), RenderError> {
+    let files = verbose::timed("include resolution", || {
+        resolve_render_files(cfg, lib, profiles, opts)
+    })?;
+    log::debug!("resolved {} fragment(s)", files.len());
+    if opts.print_order {
+        eprintln!("Render order:");
+        for (depth, name) in render_order(&files) {
+            eprintln!("{}{name}", "  ".repeat(depth + 1));
+        }
+    }
+    let cache = FileCache::new(opts);
+    if opts.strict_vars {
+        let mut errors = unresolved_vars(&files, cfg, opts, &cache)?;
+        if errors.is_empty() {
+            errors = leftover_placeholders(cfg, lib, &files, profiles, opts)?;
+        }
+        if !errors.is_empty() {
+            return Err(errors.join("\n").into());
+        }
+    }
+    let started = log::log_enabled!(log::Level::Info).then(Instant::now);
+    let mut result = write_rendered(cfg, lib, &files, profiles, opts, w, &cache);
+    if let (Ok(()), Some(min)) = (&result, opts.min_tokens) {
+        result = check_min_tokens(cfg, lib, &files, profiles, opts, &cache, min);
+    }
+    if let Some(started) = started {
+        // Reads and substitution happen while composing; report them apart.
+        let (read, substituted) = (cache.read_clock.total(), cache.substitute_clock.total());
+        verbose::phase_took("body read", read);
+        verbose::phase_took("substitution", substituted);
+        verbose::phase_took(
+            "composition",
+            started.elapsed().saturating_sub(read + substituted),
+        );
+    }
+    result
+}
+
+/// The profiles `files` were included through, each with its nesting depth,
+/// in the order their content is rendered (first appearance wins).
+fn render_order(files: &[ResolvedFragment]) -> Vec<(usize, &str)> {
+    let mut seen = HashSet::new();
+    let mut order = Vec::new();
+    for fragment in files {
+        for (depth, name) in fragment.via.iter().enumerate() {
+            if seen.insert(name.as_str()) {
+                order.push((depth, name.as_str()));
+            }
+        }
+    }
+    order
+}
+
+/// Write already-resolved `files` in the requested output format.
+fn write_rendered(
+    cfg: &Config,
+    lib: &Path,
+    files: &[ResolvedFragment],
+    profiles: &[String],
+    opts: &RenderOptions,
+    mut w: impl Write,
+    cache: &FileCache,
+) -> Result<(), RenderError> {
+    if let Some(algorithm) = opts.hash.filter(|_| opts.format != RenderFormat::Json) {
+        let digest = text_digest(cfg, lib, files, profiles, opts, cache, algorithm)?;
+        return writeln!(w, "{digest}").map_err(|e| format!("Write error: {e}").into());
+    }
+    match opts.format {
+        RenderFormat::Text => {
+            let mut w = LineEndingWriter::new(w, opts.line_endings);
+            if has_text_passes(opts) {
+                let mut out = Vec::new();
+                write_text(cfg, files, opts, cache, &mut out)?;
+                let text = finish_text(String::from_utf8_lossy(&out).into_owned(), opts);
+                w.write_all(text.as_bytes())
+                    .map_err(|e| format!("Write error: {e}"))?;
+            } else {
+                write_text(cfg, files, opts, cache, &mut w)?;
+            }
+            w.flush().map_err(|e| format!("Write error: {e}").into())
+        }
+        RenderFormat::Json => write_json(cfg, lib, profiles, files, opts, cache, &mut w),
+        RenderFormat::Chat => write_chat(cfg, files, opts, cache, &mut w),
+    }
+}
+
+/// The text output `files` render to with `opts`, whatever its format, with
+/// the plain system info line so it is the same on a terminal and in a pipe.
+fn text_output(
+    cfg: &Config,
+    lib: &Path,
+    files: &[ResolvedFragment],
+    profiles: &[String],
+    opts: &RenderOptions,
+    cache: &FileCache,
+) -> Result<Vec<u8>, RenderError> {
+    let text_opts = RenderOptions {
+        format: RenderFormat::Text,
+        hash: None,
+        min_tokens: None,
+        styled: false,
+        ..opts.clone()
+    };
+    let mut text = Vec::new();
+    write_rendered(cfg, lib, files, profiles, &text_opts, &mut text, cache)?;
+    Ok(text)
+}
+
+/// Digest of the text output `files` render to with `opts`.
+fn text_digest(
+    cfg: &Config,
+    lib: &Path,
+    files: &[ResolvedFragment],
+    profiles: &[String],
+    opts: &RenderOptions,
+    cache: &FileCache,
+    algorithm: HashAlgorithm,
+) -> Result<String, RenderError> {
+    let text = text_output(cfg, lib, files, profiles, opts, cache)?;
+    Ok(algorithm.hex_digest(&text))
+}
+
+/// Rough token count of `text`: one token per four characters, rounded up.
+#[must_use]
+pub fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(4)
+}
+
+/// Warn on stderr (or fail, with `fail_on_warning`) when the text output of
+/// `files` is estimated at fewer than `min` tokens.
+fn check_min_tokens(
+    cfg: &Config,
+    lib: &Path,
+    files: &[ResolvedFragment],
+    profiles: &[String],
+    opts: &RenderOptions,
+    cache: &FileCache,
+    min: usize,
+) -> Result<(), RenderError> {
+    let text = text_output(cfg, lib, files, profiles, opts, cache)?;
+    let tokens = estimate_tokens(&String::from_utf8_lossy(&text));
+    if tokens >= min {
+        return Ok(());
+    }
+    let message = format!(
+        "Rendered output of {} is about {tokens} token(s), below --min-tokens {min}",
+        profiles.join(", ")
+    );
+    if opts.fail_on_warning {
+        return Err(message.into());
+    }
+    eprintln!("Warning: {message}");
+    Ok(())
+}
+
+/// Whether `opts` selects any pass that `finish_text` applies.
+const fn has_text_passes(opts: &RenderOptions) -> bool {
+    opts.strip_blank_runs
+        || opts.wrap > 0
+        || opts.out_template.is_some()
+        || opts.normalize_unicode.is_some()
+        || opts.ascii_fold
+}
+
+/// Apply the whole-output passes selected in `opts` to composed text.
+fn finish_text(mut text: String, opts: &RenderOptions) -> String {
+    if opts.strip_blank_runs {
+        text = strip_blank_runs(&text);
+    }
+    if opts.wrap > 0 {
+        text = wrap_lines(&text, opts.wrap);
+    }
+    if let Some(template) = &opts.out_template {
+        text = template.replacen(OUT_TEMPLATE_SLOT, &text, 1);
+    }
+    normalize_text(text, opts)
+}
+
+/// Apply `--normalize-unicode` and then `--ascii-fold` to `text`.
+fn normalize_text(mut text: String, opts: &RenderOptions) -> String {
+    use unicode_normalization::UnicodeNormalization;
+    match opts.normalize_unicode {
+        Some(UnicodeForm::Nfc) => text = text.nfc().collect(),
+        Some(UnicodeForm::Nfkc) => text = text.nfkc().collect(),
+        None => {}
+    }
+    if opts.ascii_fold {
+        text = ascii_fold(&text);
+    }
+    text
+}
+
+/// Replace common typographic characters in `text` with ASCII equivalents;
+/// zero-width characters are dropped and everything else is kept.
+fn ascii_fold(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\u{2018}' | '\u{2019}' | '\u{201A}' | '\u{201B}' | '\u{2032}' => out.push('\''),
+            '\u{201C}' | '\u{201D}' | '\u{201E}' | '\u{201F}' | '\u{2033}' => out.push('"'),
+            '\u{2010}'..='\u{2013}' | '\u{2212}' => out.push('-'),
+            '\u{2014}' | '\u{2015}' => out.push_str("--"),
+            '\u{2026}' => out.push_str("..."),
+            '\u{2022}' => out.push('*'),
+            '\u{00A0}' | '\u{2000}'..='\u{200A}' | '\u{202F}' | '\u{205F}' | '\u{3000}' => {
+                out.push(' ');
+            }
+            '\u{200B}'..='\u{200D}' | '\u{2060}' | '\u{FEFF}' => {}
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Placeholder an `--out-template` puts the rendered output in.
+const OUT_TEMPLATE_SLOT: &str = "{{body}}";
+
+/// Parse an `--out-template` value, unescaping it like the prompt options.
+///
+/// # Errors
+/// Returns an error unless the template contains `{{body}}` exactly once.
+pub fn parse_out_template(s: &str) -> Result<String, String> {
+    let template = unescape(s);
+    match template.matches(OUT_TEMPLATE_SLOT).count() {
+        1 => Ok(template),
+        n => Err(format!(
+            "template must contain {OUT_TEMPLATE_SLOT} exactly once, found {n}"
+        )),
+    }
+}
+
+/// Soft-wrap lines longer than `width` columns at spaces, keeping their
+/// indentation on continuation lines. Only the spaces a line is broken at are
+/// dropped; other whitespace, tabs and runs of spaces included, is kept as is.
+/// Words are never split, and lines in fenced code blocks (and the fences)
+/// are left as they are.
+fn wrap_lines(text: &str, width: usize) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut in_fence = false;
+    for line in text.split_inclusive('\n') {
+        let (body, newline) = line
+            .strip_suffix('\n')
+            .map_or((line, ""), |body| (body, "\n"));
+        let fence = body.trim_start().starts_with("```");
+        if fence {
+            in_fence = !in_fence;
+        }
+        if fence || in_fence || body.chars().count() <= width {
+            out.push_str(line);
+            continue;
+        }
+        let indent = &body[..body.len() - body.trim_start().len()];
+        let mut words = body[indent.len()..].split(' ');
+        let mut current = indent.to_string();
+        current.push_str(words.next().unwrap_or_default());
+        let mut spaces = 0;
+        for word in words {
+            spaces += 1;
+            if word.is_empty() {
+                continue;
+            }
+            if current.chars().count() + spaces + word.chars().count() > width {
+                out.push_str(&current);
+                out.push('\n');
+                current = indent.to_string();
+            } else {
+                current.push_str(&" ".repeat(spaces));
+            }
+            current.push_str(word);
+            spaces = 0;
+        }
+        current.push_str(&" ".repeat(spaces));
+        out.push_str(&current);
+        out.push_str(newline);
+    }
+    out
+}
+
+/// Collapse runs of three or more blank lines into one and drop leading and
+/// trailing blank lines.
+fn strip_blank_runs(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut blanks = 0;
+    for line in text.lines() {
+        if line.trim().is_empty() {
+            blanks += 1;
+            continue;
+        }
+        if !out.is_empty() {
+            let keep = if blanks >= 3 { 1 } else { blanks };
+            out.push_str(&"\n".repeat(keep));
+        }
+        blanks = 0;
+        out.push_str(line);
+        out.push('\n');
+    }
+    out
+}
+
+/// Resolve all requested profiles into files, with shared deduplication.
+fn resolve_render_files(
+    cfg: &Config,
+    lib: &Path,
+    profiles: &[String],
+    opts: &RenderOptions,
+) -> Result<Vec<ResolvedFragment>, RenderError> {
+    let mut res = resolve_profiles(cfg, lib, profiles, opts)?;
+    let mut files = extra_files("Prepend", &opts.prepend_files)?;
+    files.append(&mut res.files);
+    files.extend(extra_files("Append", &opts.append_files)?);
+    Ok(files)
+}
+
+/// Resolve `profiles` in order into one resolution, warning about
+/// `--exclude`/`--include-only` names that are not part of the tree.
+fn resolve_profiles(
+    cfg: &Config,
+    lib: &Path,
+    profiles: &[String],
+    opts: &RenderOptions,
+) -> Result<Resolution, RenderError> {
+    let filter = ProfileFilter::from_options(cfg, opts);
+    let mut res = Resolution::default();
+    for profile in profiles {
+        let profile = &cfg.profile_name(profile).to_string();
+        log::debug!("resolving profile '{profile}'");
+        res.stack.clear();
+        if !cfg.profiles.contains_key(profile) {
+            match opts.on_missing {
+                MissingProfilePolicy::Error => {}
+                MissingProfilePolicy::Skip => {
+                    if !opts.quiet {
+                        eprintln!("Warning: skipping unknown profile '{profile}'");
+                    }
+                    continue;
+                }
+                MissingProfilePolicy::Placeholder => {
+                    res.marker("missing", profile);
+                    continue;
+                }
+            }
+        }
+        res.resolve(profile, cfg, lib, &filter).map_err(|e| {
+            if res.stack.is_empty() {
+                RenderError::in_chain(e.to_string(), std::slice::from_ref(profile))
+            } else {
+                RenderError::in_chain(e.to_string(), &res.stack)
+            }
+        })?;
+    }
+    for (kind, names) in [
+        ("excluded", &opts.exclude),
+        ("commented-out", &opts.comment_out),
+        ("include-only", &opts.include_only),
+    ] {
+        for name in names
+            .iter()
+            .filter(|n| !opts.quiet && !res.visited_profiles.contains(cfg.profile_name(n)))
+        {
+            eprintln!("Warning: {kind} profile '{name}' is not part of the rendered tree");
+        }
+    }
+    Ok(res)
+}
+
+/// Fragments for `--prepend-file`/`--append-file`, rendered verbatim.
+fn extra_files(kind: &str, paths: &[PathBuf]) -> Result<Vec<ResolvedFragment>, String> {
+    paths
+        .iter()
+        .map(|path| {
+            if !path.is_file() {
+                return Err(format!("{kind} file not found: {}", path.display()));
+            }
+            Ok(ResolvedFragment {
+                path: path.clone(),
+                lines: None,
+                via: Vec::new(),
+                verbatim: true,
+                inline: None,
+            })
+        })
+        .collect()
+}
+
+/// Effective comment prefix: CLI override, then config; empty (no stripping)
+/// when neither sets one.
+fn effective_comment_prefix<'a>(cfg: &'a Config, opts: &'a RenderOptions) -> &'a str {
+    opts.comment_prefix
+        .as_deref()
+        .or(cfg.comment_prefix.as_deref())
+        .unwrap_or_default()
+}
+
+/// Remove lines starting with `prefix`, leaving fenced code blocks untouched.
+///
+/// A line starting with a backslash followed by `prefix` is kept with the
+/// backslash removed, so the prefix can still be rendered literally.
+fn strip_comment_lines(content: &str, prefix: &str) -> String {
+    if prefix.is_empty() {
+        return content.to_string();
+    }
+    kept_lines(content, prefix).map(|(_, line)| line).collect()
+}
+
+/// Lines of `content` that [`strip_comment_lines`] keeps, each with its
+/// 0-based index in `content`.
+fn kept_lines<'a>(content: &'a str, prefix: &'a str) -> impl Iterator<Item = (usize, &'a str)> {
+    let mut in_fence = false;
+    content
+        .split_inclusive('\n')
+        .enumerate()
+        .filter_map(move |(i, line)| {
+            if prefix.is_empty() {
+                return Some((i, line));
+            }
+            if line.trim_start().starts_with("```") {
+                in_fence = !in_fence;
+            } else if !in_fence {
+                if line.starts_with(prefix) {
+                    return None;
+                }
+                if let Some(rest) = line.strip_prefix('\\') {
+                    if rest.starts_with(prefix) {
+                        return Some((i, rest));
+                    }
+                }
+            }
+            Some((i, line))
+        })
+}
+
+/// Contents of files read during one render, so each file is read at most
+/// once however many profiles or line ranges include it.
+#[derive(Debug, Default)]
+struct FileCache {
+    files: RefCell<HashMap<PathBuf, Rc<str>>>,
+    reads: Cell<usize>,
+    encoding: Encoding,
+    quiet: bool,
+    /// Render escaped `\{\{` as `{ {` (the `--strict-vars` output scan)
+    hide_escapes: bool,
+    read_clock: verbose::PhaseClock,
+    substitute_clock: verbose::PhaseClock,
+}
+
+impl FileCache {
+    fn new(opts: &RenderOptions) -> Self {
+        Self {
+            encoding: opts.encoding,
+            quiet: opts.quiet,
+            ..Self::default()
+        }
+    }
+
+    fn read(&self, path: &Path) -> Result<Rc<str>, String> {
+        if let Some(content) = self.files.borrow().get(path) {
+            log::trace!("reusing {}", path.display());
+            return Ok(Rc::clone(content));
+        }
+        let content: Rc<str> = self
+            .read_clock
+            .time(|| read_text(path, self.encoding, self.quiet))?
+            .into();
+        log::debug!("read {} ({} bytes)", path.display(), content.len());
+        self.reads.set(self.reads.get() + 1);
+        self.files
+            .borrow_mut()
+            .insert(path.to_path_buf(), Rc::clone(&content));
+        Ok(content)
+    }
+}
+
+/// Read a fragment for rendering, applying its line range and stripping comment
+/// lines (except in `@path` includes).
+fn read_fragment(
+    fragment: &ResolvedFragment,
+    comment_prefix: &str,
+    cache: &FileCache,
+) -> Result<String, String> {
+    with_fragment_body(fragment, cache, |body| {
+        if fragment.verbatim {
+            body.to_string()
+        } else {
+            strip_comment_lines(body, comment_prefix)
+        }
+    })
+}
+
+/// Call `f` with a fragment's text after applying its line range.
+fn with_fragment_body<T>(
+    fragment: &ResolvedFragment,
+    cache: &FileCache,
+    f: impl FnOnce(&str) -> T,
+) -> Result<T, String> {
+    let path = &fragment.path;
+    let content = match &fragment.inline {
+        Some(body) => Rc::from(body.as_str()),
+        None => cache.read(path)?,
+    };
+    let body = match fragment.lines {
+        Some(range) => range.slice(&content).ok_or_else(|| {
+            format!(
+                "Line range {range} out of bounds for {} ({} lines)",
+                path.display(),
+                content.lines().count()
+            )
+        })?,
+        None => &*content,
+    };
+    Ok(f(body))
+}
+
+/// Line of a fragment's file that line `line` (1-based) of its text from
+/// [`read_fragment`] came from, counting the lines stripped as comments.
+fn source_line(
+    fragment: &ResolvedFragment,
+    comment_prefix: &str,
+    cache: &FileCache,
+    line: usize,
+) -> usize {
+    let first = fragment.lines.map_or(1, |range| range.start);
+    let prefix = if fragment.verbatim {
+        ""
+    } else {
+        comment_prefix
+    };
+    let index = with_fragment_body(fragment, cache, |body| {
+        kept_lines(body, prefix).nth(line - 1).map(|(i, _)| i)
+    });
+    first + index.ok().flatten().unwrap_or(line - 1)
+}
+
+/// Rendered text of a fragment: line range, comment stripping, then variables.
+fn fragment_text(
+    fragment: &ResolvedFragment,
+    cfg: &Config,
+    opts: &RenderOptions,
+    cache: &FileCache,
+) -> Result<String, RenderError> {
+    let comment_prefix = effective_comment_prefix(cfg, opts);
+    let content = read_fragment(fragment, comment_prefix, cache)
+        .map_err(|e| RenderError::in_chain(e, &fragment.via))?;
+    if fragment.verbatim {
+        return Ok(content);
+    }
+    let content = if cache.hide_escapes {
+        vars::hide_escapes(&content)
+    } else {
+        content
+    };
+    let content = cache
+        .substitute_clock
+        .time(|| {
+            engine::render(
+                opts.template_engine,
+                &content,
+                &opts.vars.context(&fragment.via),
+                &fragment.path.display().to_string(),
+                &|line| source_line(fragment, comment_prefix, cache, line),
+                opts.strict_vars,
+            )
+        })
+        .map_err(|e| RenderError::in_chain(e, &fragment.via))?;
+    Ok(opts.trim.apply(content))
+}
+
+/// Effective pre-prompt: `--pre-prompt`, then the `--pre-prompt-file` files
+/// joined by the pre-separator, then the default.
+fn effective_pre_prompt(opts: &RenderOptions) -> Result<String, String> {
+    if let Some(text) = &opts.pre_prompt {
+        return Ok(text.clone());
+    }
+    if opts.pre_prompt_files.is_empty() {
+        return Ok(default_pre_prompt());
+    }
+    let texts = opts
+        .pre_prompt_files
+        .iter()
+        .map(|path| {
+            fs::read_to_string(path)
+                .map_err(|e| format!("Failed to read pre-prompt file {}: {}", path.display(), e))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(texts.join(opts.pre_separator.as_deref().unwrap_or("")))
+}
+
+/// Separator between the preamble and the body: `--pre-separator`, falling
+/// back to `--separator`.
+fn effective_pre_separator(opts: &RenderOptions) -> &str {
+    opts.pre_separator
+        .as_deref()
+        .or(opts.separator.as_deref())
+        .unwrap_or_default()
+}
+
+/// Separator between the body and the post-prompt: `--post-separator`,
+/// falling back to `--separator`.
+fn effective_post_separator(opts: &RenderOptions) -> &str {
+    opts.post_separator
+        .as_deref()
+        .or(opts.separator.as_deref())
+        .unwrap_or_default()
+}
+
+/// Whether the separator goes after `files[i]`: where `--separator-between`
+/// places it, except after the last file when a post-separator follows, so the
+/// two are never written back to back.
+fn separator_after(files: &[ResolvedFragment], i: usize, opts: &RenderOptions) -> bool {
+    opts.separator_between.after(files, i)
+        && (i + 1 < files.len() || effective_post_separator(opts).is_empty())
+}
+
+/// Effective post-prompt: CLI override, then config, then the default.
+fn effective_post_prompt(cfg: &Config, opts: &RenderOptions) -> String {
+    opts.post_prompt
+        .clone()
+        .or_else(|| cfg.post_prompt.clone())
+        .unwrap_or_else(default_post_prompt)
+}
+
+fn write_text(
+    cfg: &Config,
+    files: &[ResolvedFragment],
+    opts: &RenderOptions,
+    cache: &FileCache,
+    w: &mut impl Write,
+) -> Result<(), RenderError> {
+    // Write pre-prompt (defaults if not provided)
+    let pre_prompt_text = effective_pre_prompt(opts)?;
+    w.write_all(pre_prompt_text.as_bytes())
+        .map_err(|e| format!("Write error: {e}"))?;
+
+    // Write system prefix with two newlines before
+    if !opts.no_system_info {
+        w.write_all(b"\n")
+            .map_err(|e| format!("Write error: {e}"))?;
+        let prefix = format_system_prefix(opts.styled);
+        w.write_all(prefix.as_bytes())
+            .map_err(|e| format!("Write error: {e}"))?;
+    }
+    w.write_all(effective_pre_separator(opts).as_bytes())
+        .map_err(|e| format!("Write error: {e}"))?;
+
+    let sep = opts.separator.as_deref().unwrap_or("");
+    for (i, fragment) in files.iter().enumerate() {
+        // Two newlines before each file
+        w.write_all(b"\n")
+            .map_err(|e| format!("Write error: {e}"))?;
+
+        let content = fragment_text(fragment, cfg, opts, cache)?;
+        w.write_all(content.as_bytes())
+            .map_err(|e| format!("Write error: {e}"))?;
+
+        // Write separator after the file if provided and placed here
+        if !sep.is_empty() && separator_after(files, i, opts) {
+            w.write_all(sep.as_bytes())
+                .map_err(|e| format!("Write error: {e}"))?;
+        }
+    }
+
+    w.write_all(effective_post_separator(opts).as_bytes())
+        .map_err(|e| format!("Write error: {e}"))?;
+
+    // Two newlines before post-prompt (defaults if not provided)
+    w.write_all(b"\n\n")
+        .map_err(|e| format!("Write error: {e}"))?;
+    w.write_all(effective_post_prompt(cfg, opts).as_bytes())
+        .map_err(|e| format!("Write error: {e}").into())
+}
+
+fn write_json(
+    cfg: &Config,
+    lib: &Path,
+    profiles: &[String],
+    files: &[ResolvedFragment],
+    opts: &RenderOptions,
+    cache: &FileCache,
+    w: &mut impl Write,
+) -> Result<(), RenderError> {
+    let mut fragments = Vec::new();
+    for fragment in files {
+        let content = fragment_text(fragment, cfg, opts, cache)?;
+        let path = &fragment.path;
+        let mut rel_path = path.strip_prefix(lib).unwrap_or(path).display().to_string();
+        if let Some(range) = fragment.lines {
+            rel_path = format!("{rel_path}#{range}");
+        }
+        fragments.push(FragmentOutput {
+            path: rel_path,
+            content,
+        });
+    }
+
+    let output = RenderOutput {
+        profile: profiles.join(", "),
+        pre_prompt: effective_pre_prompt(opts)?,
+        system_info: (!opts.no_system_info).then(plain_system_info),
+        fragments,
+        hash: opts
+            .hash
+            .map(|algorithm| text_digest(cfg, lib, files, profiles, opts, cache, algorithm))
+            .transpose()?,
+    };
+
+    let json_output = to_json(&output, opts.json_layout)?;
+    writeln!(w, "{json_output}").map_err(|e| format!("Write error: {e}").into())
+}
+
+fn write_chat(
+    cfg: &Config,
+    files: &[ResolvedFragment],
+    opts: &RenderOptions,
+    cache: &FileCache,
+    w: &mut impl Write,
+) -> Result<(), RenderError> {
+    let sep = opts.separator.as_deref().unwrap_or("");
+    let mut body = if opts.no_system_info {
+        String::new()
+    } else {
+        format!("{}\n\n", plain_system_info())
+    };
+    body.push_str(effective_pre_separator(opts));
+    for (i, fragment) in files.iter().enumerate() {
+        let content = fragment_text(fragment, cfg, opts, cache)?;
+        body.push('\n');
+        body.push_str(&content);
+        if separator_after(files, i, opts) {
+            body.push_str(sep);
+        }
+    }
+    body.push_str(effective_post_separator(opts));
+    body.push_str("\n\n");
+    body.push_str(&effective_post_prompt(cfg, opts));
+    let body = finish_text(body, opts);
+
+    let pre_prompt_text = normalize_text(effective_pre_prompt(opts)?, opts);
+    let mut messages = Vec::new();
+    if !pre_prompt_text.is_empty() {
+        messages.push(ChatMessage {
+            role: opts.chat_roles.pre_prompt.clone(),
+            content: pre_prompt_text,
+        });
+    }
+    messages.push(ChatMessage {
+        role: opts.chat_roles.body.clone(),
+        content: body,
+    });
+
+    let json_output = to_json(&messages, opts.json_layout)?;
+    writeln!(w, "{json_output}").map_err(|e| format!("Write error: {e}").into())
+}
+
+/// Render one or more profiles to stdout.
+///
+/// Convenience function that reads configuration and renders the specified
+/// profiles to standard output with optional separator, pre-prompt, and post-prompt.
+/// When multiple profiles are provided, files are deduplicated across all profiles.
+/// Successful renders are recorded in the history used by `prompter history`.
+/// With `opts.to_stderr`, text and chat output goes to stderr instead; the
+/// JSON format always goes to stdout. `opts.output` sends every format to its
+/// target instead.
+///
+/// # Arguments
+/// * `profiles` - Profile names to render (deduplicated in order)
+/// * `globs` - Name patterns whose sorted matches are rendered after `profiles`
+/// * `splices` - Profiles inserted next to anchors once globs are expanded
+/// * `scenario` - Scenario whose profiles come first and whose settings fill unset options
+/// * `opts` - Separator, pre/post-prompt overrides, and output format
+/// * `config` - Configuration source
+///
+/// # Returns
+/// * `Ok(())` - Profiles rendered successfully
+/// * `Err(RenderError)` - Rendering failed, with the offending profile when known
+///
+/// # Errors
+/// Returns an error if:
+/// - Configuration file cannot be read or parsed
+/// - `scenario` is not defined
+/// - A pattern in `globs` matches no profile
+/// - A splice anchor is not in the requested profiles
+/// - Profile resolution fails
+/// - The `opts.output` target cannot be opened
+/// - Writing to stdout fails
+pub fn run_render_stdout(
+    profiles: &[String],
+    globs: &[String],
+    splices: &[Splice],
+    scenario: Option<&str>,
+    opts: &RenderOptions,
+    config: &ConfigSource,
+) -> Result<(), RenderError> {
+    let started = Instant::now();
+    let (cfg, lib) = config.load()?;
+    let (profiles, mut opts) = match scenario {
+        Some(name) => apply_scenario(&cfg, name, profiles, opts)?,
+        None => (profiles.to_vec(), opts.clone()),
+    };
+    let to_stderr = opts.to_stderr && opts.format != RenderFormat::Json;
+    opts.styled = opts.output.is_none()
+        && if to_stderr {
+            io::stderr().is_terminal()
+        } else {
+            io::stdout().is_terminal()
+        };
+    let opts = &opts;
+    let profiles = &splice_profiles(expand_profiles_glob(&cfg, &profiles, globs)?, splices)?;
+    let mut w: Box<dyn Write> = if let Some(target) = &opts.output {
+        target.open()?
+    } else if to_stderr {
+        Box::new(io::stderr().lock())
+    } else {
+        Box::new(io::stdout().lock())
+    };
+    if opts.metrics_json.is_none() && opts.diff_against.is_none() {
+        render_to_writer(&cfg, &lib, &mut w, profiles, opts)?;
+        w.flush().map_err(|e| format!("Write error: {e}"))?;
+    } else {
+        // Buffered so the output can be measured or compared once it is complete.
+        let mut out = Vec::new();
+        render_to_writer(&cfg, &lib, &mut out, profiles, opts)?;
+        if let Some(path) = &opts.metrics_json {
+            let measured = metrics::RenderMetrics::new(profiles, &out, started.elapsed());
+            metrics::append_metrics(path, &measured)?;
+        }
+        if let Some(golden) = &opts.diff_against {
+            compare_render(golden, &out, profiles, w)?;
+        } else {
+            w.write_all(&out)
+                .and_then(|()| w.flush())
+                .map_err(|e| format!("Write error: {e}"))?;
+        }
+    }
+    // History is a convenience; failing to record it must not fail the render.
+    let _ = history::record(profiles, config);
+    Ok(())
+}
+
+/// Check a render against the `--diff-against` file, writing the unified diff
+/// to `w` when they differ.
+fn compare_render(
+    golden: &Path,
+    rendered: &[u8],
+    profiles: &[String],
+    mut w: impl Write,
+) -> Result<(), String> {
+    let Some(diff) = diff::diff_against_file(golden, rendered)? else {
+        return Ok(());
+    };
+    w.write_all(diff.as_bytes())
+        .map_err(|e| format!("Write error: {e}"))?;
+    Err(format!(
+        "Rendered output of {} differs from {}",
+        profiles.join(", "),
+        golden.display()
+    ))
+}
+
+/// The profiles and options of a run with `--scenario name`: the scenario's
+/// profiles come before `profiles`, and its settings fill options left unset.
+///
+/// # Errors
+/// Returns an error if the scenario is not defined.
+pub fn apply_scenario(
+    cfg: &Config,
+    name: &str,
+    profiles: &[String],
+    opts: &RenderOptions,
+) -> Result<(Vec<String>, RenderOptions), String> {
+    let scenario = cfg
+        .scenarios
+        .get(name)
+        .ok_or_else(|| format!("Unknown scenario: {name}"))?;
+    let mut merged = scenario.profiles.clone();
+    merged.extend(profiles.iter().cloned());
+    let opts = RenderOptions {
+        separator: opts
+            .separator
+            .clone()
+            .or_else(|| scenario.separator.clone()),
+        pre_prompt: opts.pre_prompt.clone().or_else(|| {
+            // --pre-prompt-file on the command line also beats the scenario
+            let files = &opts.pre_prompt_files;
+            scenario.pre_prompt.clone().filter(|_| files.is_empty())
+        }),
+        post_prompt: opts
+            .post_prompt
+            .clone()
+            .or_else(|| scenario.post_prompt.clone()),
+        ..opts.clone()
+    };
+    Ok((merged, opts))
+}
+
+/// Append the profiles matching each pattern in `globs`, in sorted order, to
+/// `profiles`, skipping names already present.
+///
+/// # Errors
+/// Returns an error if a pattern matches no profile.
+pub fn expand_profiles_glob(
+    cfg: &Config,
+    profiles: &[String],
+    globs: &[String],
+) -> Result<Vec<String>, String> {
+    let mut names: Vec<&String> = cfg.profiles.keys().collect();
+    names.sort();
+    let mut expanded = profiles.to_vec();
+    for pattern in globs {
+        let matched: Vec<&String> = names
+            .iter()
+            .copied()
+            .filter(|name| glob_matches(pattern, name))
+            .collect();
+        if matched.is_empty() {
+            return Err(format!("No profiles match --profiles-glob '{pattern}'"));
+        }
+        for name in matched {
+            if !expanded.contains(name) {
+                expanded.push(name.clone());
+            }
+        }
+    }
+    Ok(expanded)
+}
+
+/// A profile inserted next to an anchor in the requested order
+/// (`run --after` / `--before`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Splice {
+    /// Requested profile the insertion is positioned against
+    pub anchor: String,
+    /// Profile to insert
+    pub profile: String,
+    /// Insert before the anchor instead of after it
+    pub before: bool,
+}
+
+/// Apply `splices` to `profiles` in order; each anchor refers to the list as
+/// modified by earlier splices.
+///
+/// # Errors
+/// Returns an error if an anchor is not in the list.
+pub fn splice_profiles(
+    mut profiles: Vec<String>,
+    splices: &[Splice],
+) -> Result<Vec<String>, String> {
+    for splice in splices {
+        let flag = if splice.before { "--before" } else { "--after" };
+        let index = profiles
+            .iter()
+            .position(|name| *name == splice.anchor)
+            .ok_or_else(|| {
+                format!(
+                    "{flag} anchor '{}' is not among the requested profiles",
+                    splice.anchor
+                )
+            })?;
+        let at = if splice.before { index } else { index + 1 };
+        profiles.insert(at, splice.profile.clone());
+    }
+    Ok(profiles)
+}
+
+/// Match `name` against `pattern`, where `*` matches any run of characters
+/// (including `.` and `/`) and `?` matches exactly one.
+fn glob_matches(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                Some((star_p, star_n)) => {
+                    p = star_p + 1;
+                    n = star_n + 1;
+                    star = Some((star_p, n));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// One message per `{{...}}` placeholder left without a value in `files`
+/// (outside `@path` includes), located by fragment path and line.
+fn unresolved_vars(
+    files: &[ResolvedFragment],
+    cfg: &Config,
+    opts: &RenderOptions,
+    cache: &FileCache,
+) -> Result<Vec<String>, RenderError> {
+    let mut errors = Vec::new();
+    if opts.template_engine != engine::TemplateEngine::Simple {
+        // The engine reports undefined values itself, with `strict_vars`.
+        return Ok(errors);
+    }
+    for fragment in files.iter().filter(|f| !f.verbatim) {
+        let content = read_fragment(fragment, effective_comment_prefix(cfg, opts), cache)
+            .map_err(|e| RenderError::in_chain(e, &fragment.via))?;
+        let offset = fragment.lines.map_or(0, |range| range.start - 1);
+        for (line, name) in vars::unresolved(&content, &opts.vars.context(&fragment.via)) {
+            errors.push(format!(
+                "Unresolved variable {{{{{name}}}}} at {}:{}",
+                fragment.path.display(),
+                offset + line
+            ));
+        }
+    }
+    Ok(errors)
+}
+
+/// Placeholders left in the final text output of `files`, for `--strict-vars`:
+/// ones outside fragment bodies (prompts, `--out-template`, `@path` and
+/// prepended files) or brought in by substituted values. Escaped `\{\{` is
+/// not reported.
+fn leftover_placeholders(
+    cfg: &Config,
+    lib: &Path,
+    files: &[ResolvedFragment],
+    profiles: &[String],
+    opts: &RenderOptions,
+) -> Result<Vec<String>, RenderError> {
+    if opts.template_engine != engine::TemplateEngine::Simple {
+        return Ok(Vec::new());
+    }
+    let cache = FileCache {
+        hide_escapes: true,
+        ..FileCache::new(opts)
+    };
+    let text = text_output(cfg, lib, files, profiles, opts, &cache)?;
+    Ok(
+        vars::unresolved(&String::from_utf8_lossy(&text), &HashMap::new())
+            .into_iter()
+            .map(|(line, name)| {
+                format!("Unresolved variable {{{{{name}}}}} at line {line} of the rendered output")
+            })
+            .collect(),
+    )
+}
+
+/// Render `profiles` without writing the result, then report any
+/// `{{name}}` placeholders left without a value (outside `@path` includes).
+///
+/// # Errors
+/// Returns an error if rendering fails or any placeholder is unresolved.
+pub fn check_render(
+    cfg: &Config,
+    lib: &Path,
+    profiles: &[String],
+    opts: &RenderOptions,
+) -> Result<(), RenderError> {
+    let files = resolve_render_files(cfg, lib, profiles, opts)?;
+    let cache = FileCache::new(opts);
+    write_rendered(cfg, lib, &files, profiles, opts, io::sink(), &cache)?;
+    let errors = unresolved_vars(&files, cfg, opts, &cache)?;
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors.join("\n").into())
+    }
+}
+
+/// Check that `profiles` render cleanly without printing them.
+///
+/// # Errors
+/// Returns an error if the configuration cannot be loaded, rendering fails,
+/// or a placeholder is unresolved.
+pub fn run_check_stdout(
+    profiles: &[String],
+    opts: &RenderOptions,
+    config: &ConfigSource,
+) -> Result<(), RenderError> {
+    let (cfg, lib) = config.load()?;
+    check_render(&cfg, &lib, profiles, opts)
+}
+
+/// A `{{name}}` placeholder used by a render (`run --list-vars`).
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub struct ExpectedVar {
+    /// Variable name
+    pub name: String,
+    /// Whether some use of it has no value, so it must be passed with `--var`
+    pub required: bool,
+    /// Value it renders with: a built-in, or one from `--var`, `--env-file`, or `--capture-env`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default: Option<String>,
+    /// Where it is used, as `path:line`
+    pub locations: Vec<String>,
+}
+
+/// Every `{{name}}` placeholder in the fragments a render of `profiles`
+/// includes (outside `@path` includes), sorted by name.
+///
+/// # Errors
+/// Returns an error if any profile fails to resolve or a fragment cannot be read.
+pub fn expected_vars(
+    cfg: &Config,
+    lib: &Path,
+    profiles: &[String],
+    opts: &RenderOptions,
+) -> Result<Vec<ExpectedVar>, RenderError> {
+    let files = resolve_render_files(cfg, lib, profiles, opts)?;
+    let cache = FileCache::new(opts);
+    let mut found: BTreeMap<String, ExpectedVar> = BTreeMap::new();
+    for fragment in files.iter().filter(|f| !f.verbatim) {
+        let content = read_fragment(fragment, effective_comment_prefix(cfg, opts), &cache)
+            .map_err(|e| RenderError::in_chain(e, &fragment.via))?;
+        let ctx = opts.vars.context(&fragment.via);
+        let path = fragment.path.strip_prefix(lib).unwrap_or(&fragment.path);
+        let offset = fragment.lines.map_or(0, |range| range.start - 1);
+        for (i, line) in content.lines().enumerate() {
+            for name in vars::placeholders(line) {
+                let entry = found
+                    .entry(name.to_string())
+                    .or_insert_with(|| ExpectedVar {
+                        name: name.to_string(),
+                        required: false,
+                        default: None,
+                        locations: Vec::new(),
+                    });
+                match ctx.get(name) {
+                    Some(value) => {
+                        entry.default.get_or_insert_with(|| (*value).to_string());
+                    }
+                    None => entry.required = true,
+                }
+                entry
+                    .locations
+                    .push(format!("{}:{}", path.display(), offset + i + 1));
+            }
+        }
+    }
+    Ok(found.into_values().collect())
+}
+
+/// Write expected variables one per line (text), or as a JSON array.
+///
+/// # Errors
+/// Returns an error if JSON serialization or writing to the output fails.
+pub fn show_expected_vars(
+    expected: &[ExpectedVar],
+    json: bool,
+    json_layout: JsonLayout,
+    mut w: impl Write,
+) -> Result<(), String> {
+    if json {
+        let json_output = to_json(expected, json_layout)?;
+        return writeln!(w, "{json_output}").map_err(|e| format!("Write error: {e}"));
+    }
+    for var in expected {
+        match (&var.default, var.required) {
+            (Some(value), false) => writeln!(w, "{} = {value}", var.name),
+            _ => writeln!(w, "{} (required)", var.name),
+        }
+        .map_err(|e| format!("Write error: {e}"))?;
+    }
+    Ok(())
+}
+
+/// Print the variables a render of `profiles` uses.
+///
+/// # Errors
+/// Returns an error if the configuration cannot be loaded, a profile fails to
+/// resolve, or writing to stdout fails.
+pub fn run_list_vars_stdout(
+    profiles: &[String],
+    opts: &RenderOptions,
+    config: &ConfigSource,
+    json: bool,
+) -> Result<(), RenderError> {
+    let (cfg, lib) = config.load()?;
+    let expected = expected_vars(&cfg, &lib, profiles, opts)?;
+    Ok(show_expected_vars(
+        &expected,
+        json,
+        opts.json_layout,
+        io::stdout(),
+    )?)
+}
+
+/// Number of times each profile appears in the include tree of a render of
+/// `profiles`, by profile name. Fragments are not counted.
+///
+/// # Errors
+/// Returns an error if any profile fails to resolve.
+pub fn count_includes(
+    cfg: &Config,
+    lib: &Path,
+    profiles: &[String],
+    opts: &RenderOptions,
+) -> Result<BTreeMap<String, usize>, RenderError> {
+    let res = resolve_profiles(cfg, lib, profiles, opts)?;
+    Ok(res.include_counts.into_iter().collect())
+}
+
+/// Write include counts, most included first (text), or as a JSON object of
+/// name to count.
+///
+/// # Errors
+/// Returns an error if JSON serialization or writing to the output fails.
+pub fn show_include_counts(
+    counts: &BTreeMap<String, usize>,
+    json: bool,
+    json_layout: JsonLayout,
+    mut w: impl Write,
+) -> Result<(), String> {
+    if json {
+        let json_output = to_json(counts, json_layout)?;
+        return writeln!(w, "{json_output}").map_err(|e| format!("Write error: {e}"));
+    }
+    let mut ordered: Vec<(&String, &usize)> = counts.iter().collect();
+    ordered.sort_by_key(|(_, count)| std::cmp::Reverse(**count));
+    for (name, count) in ordered {
+        writeln!(w, "{count:>4}  {name}").map_err(|e| format!("Write error: {e}"))?;
+    }
+    Ok(())
+}
+
+/// Print how often each profile is included when rendering `profiles`.
+///
+/// # Errors
+/// Returns an error if the configuration cannot be loaded, a profile fails to
+/// resolve, or writing to stdout fails.
+pub fn run_count_includes_stdout(
+    profiles: &[String],
+    opts: &RenderOptions,
+    config: &ConfigSource,
+    json: bool,
+) -> Result<(), RenderError> {
+    let (cfg, lib) = config.load()?;
+    let counts = count_includes(&cfg, &lib, profiles, opts)?;
+    Ok(show_include_counts(
+        &counts,
+        json,
+        opts.json_layout,
+        io::stdout(),
+    )?)
+}
+
+/// A path under the system temp directory, unique to this call, for tests to
+/// create files in. Nothing is created.
+#[cfg(test)]
+pub(crate) fn mk_tmp(prefix: &str) -> PathBuf {
+    let unique = format!(
+        "{}_{}_{}",
+        prefix,
+        std::process::id(),
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos()
+    );
+    env::temp_dir().join(unique)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unescape() {
+        assert_eq!(unescape("a\\nb\\t\\\"\\\\c"), "a\nb\t\"\\c");
+        assert_eq!(unescape("line1\\rline2"), "line1\rline2");
+        assert_eq!(unescape("noesc"), "noesc");
+    }
+
+    #[test]
+    fn test_strip_comments_and_brackets_detection() {
+        let s = r"ab#cd";
+        assert_eq!(strip_comments(s), "ab");
+        let s = r#""ab#cd" # trailing"#;
+        assert_eq!(strip_comments(s), "\"ab#cd\" ");
+        assert!(contains_closing_bracket_outside_quotes("[\"not]here\"]]"));
+        assert!(!contains_closing_bracket_outside_quotes("[\"no]close\""));
+    }
+
+    #[test]
+    fn test_parse_array_items_escape_and_error() {
+        let s = r#"["a\"b", "c"]"#;
+        let items = parse_array_items(s).unwrap();
+        assert_eq!(items, vec!["a\"b", "c"]);
+        let err = parse_array_items("[\"unterminated").unwrap_err();
+        assert!(err.contains("Unterminated"));
+    }
+
+    #[test]
+    fn test_parse_config_errors() {
+        let err = parse_config_toml("[]\n").unwrap_err();
+        assert!(err.contains("Empty section name"));
+        let err = parse_config_toml("[p]\ndepends_on = \"x\"\n").unwrap_err();
+        assert!(err.contains("must be an array"));
+        let err = parse_config_toml("depends_on = [\"a.md\"]\n").unwrap_err();
+        assert!(err.contains("outside of a profile section"));
+    }
+
+    #[test]
+    fn test_validate_success_and_unknowns() {
+        let cfg = Config {
+            profiles: HashMap::from([
+                ("p1".into(), vec!["a.md".into()]),
+                ("p2".into(), vec!["p1".into(), "b.md".into()]),
+            ]),
+            post_prompt: None,
+            ..Config::default()
+        };
+        let lib = mk_tmp("prompter_validate_ok");
+        fs::create_dir_all(&lib).unwrap();
+        fs::write(lib.join("a.md"), b"A").unwrap();
+        fs::write(lib.join("b.md"), b"B").unwrap();
+        assert!(validate(&cfg, &lib, &ValidateOptions::default()).is_ok());
+        let cfg2 = Config {
+            profiles: HashMap::from([("root".into(), vec!["nope".into()])]),
+            post_prompt: None,
+            ..Config::default()
+        };
+        let err = validate(&cfg2, &lib, &ValidateOptions::default()).unwrap_err();
+        assert!(err.contains("Unknown profile"));
+    }
+
+    #[test]
+    fn test_resolve_errors_and_dedup() {
+        let cfg = Config {
+            profiles: HashMap::from([("root".into(), vec!["missing.md".into()])]),
+            post_prompt: None,
+            ..Config::default()
+        };
+        let lib = mk_tmp("prompter_resolve_errs");
+        fs::create_dir_all(&lib).unwrap();
+        let mut seen = HashSet::new();
+        let mut stack = Vec::new();
+        let mut out = Vec::new();
+        let err = resolve_profile("root", &cfg, &lib, &mut seen, &mut stack, &mut out).unwrap_err();
+        match err {
+            ResolveError::MissingFile(_, p) => assert_eq!(p, "root"),
+            _ => panic!("expected missing file"),
+        }
+
+        let cfg2 = Config {
+            profiles: HashMap::from([
+                ("A".into(), vec!["a/b.md".into()]),
+                ("B".into(), vec!["A".into(), "a/b.md".into()]),
+            ]),
+            post_prompt: None,
+            ..Config::default()
+        };
+        fs::create_dir_all(lib.join("a")).unwrap();
+        fs::write(lib.join("a/b.md"), b"X").unwrap();
+        let mut seen = HashSet::new();
+        let mut stack = Vec::new();
+        let mut out = Vec::new();
+        resolve_profile("B", &cfg2, &lib, &mut seen, &mut stack, &mut out).unwrap();
+        assert_eq!(out.len(), 1);
+    }
+
+    #[test]
+    fn test_parse_args_errors() {
+        // unknown flag
+        let args = vec!["prompter".into(), "--bogus".into()];
+        let err = parse_args_from(args).unwrap_err();
+        assert!(matches!(err, ParseError::Unknown(_)), "{err:?}");
+        assert!(err.to_string().contains("unexpected argument"));
+        // missing required subcommand
+        let args = vec!["prompter".into()];
+        let err = parse_args_from(args).unwrap_err();
+        assert!(matches!(err, ParseError::Missing(_)), "{err:?}");
+        assert!(err.message().contains("Usage:") || err.message().contains("COMMAND"));
+        // conflicting flags and bad values
+        let args = vec![
+            "prompter".into(),
+            "--pretty".into(),
+            "--compact".into(),
+            "tree".into(),
+        ];
+        assert!(matches!(
+            parse_args_from(args).unwrap_err(),
+            ParseError::Conflict(_)
+        ));
+        let args = vec![
+            "prompter".into(),
+            "--color".into(),
+            "sometimes".into(),
+            "tree".into(),
+        ];
+        assert!(matches!(
+            parse_args_from(args).unwrap_err(),
+            ParseError::InvalidValue(_)
+        ));
+        // help and version are not failures
+        let err = parse_args_from(vec!["prompter".into(), "--version".into()]).unwrap_err();
+        assert!(err.is_informational());
+        assert!(err.message().starts_with("prompter "));
+        // --dump-config stands in for a subcommand
+        let args = vec!["prompter".into(), "--dump-config".into()];
+        assert!(matches!(
+            parse_args_from(args).unwrap(),
+            AppMode::DumpConfig {
+                config: ConfigSource::Default,
+                json: false
+            }
+        ));
+        // global settings come back beside the mode
+        let args = vec!["prompter".into(), "--compact".into(), "tree".into()];
+        let (mode, globals) = parse_command_line(args).unwrap();
+        assert!(matches!(mode, AppMode::Tree { json: false, .. }));
+        assert_eq!(globals.json_layout, JsonLayout::Compact);
+    }
+
+    #[test]
+    fn test_init_rolls_back_on_failure() {
+        let root = mk_tmp("prompter_init_rollback");
+        let cfg_path = root.join("config/config.toml");
+        let lib = root.join("library");
+        fs::create_dir_all(&lib).unwrap();
+        fs::write(lib.join("keep.md"), b"mine").unwrap();
+        // A file where the coding template needs the `f/` directory.
+        fs::write(lib.join("f"), b"blocker").unwrap();
+
+        let template = templates::find_template("coding").unwrap();
+        let mut scaffold = Scaffold::default();
+        let err = scaffold
+            .write_template(template, &cfg_path, &lib, None)
+            .unwrap_err();
+        assert!(err.contains("Failed to create"), "err={err}");
+        assert!(lib.join("a/b/c.md").exists());
+        scaffold.roll_back();
+
+        assert!(!root.join("config").exists());
+        assert!(!lib.join("a").exists());
+        assert!(lib.join("keep.md").exists() && lib.join("f").exists());
+    }
+
+    #[test]
+    fn test_which() {
+        let cfg = parse_config_toml("[profiles]\nhello = \"Hi\"\n\n[p]\ndepends_on = [\"a.md\"]\n")
+            .unwrap();
+        let source = ConfigSource::File(PathBuf::from("/etc/prompter.toml"));
+        assert_eq!(
+            which(&cfg, &source, "p").unwrap(),
+            ProfileLocation {
+                profile: "p".into(),
+                path: Some(PathBuf::from("/etc/prompter.toml")),
+                source: "file",
+            }
+        );
+        let inline = which(&cfg, &source, "hello").unwrap();
+        assert_eq!((inline.path, inline.source), (None, "inline"));
+        let from_text = which(&cfg, &ConfigSource::Inline(String::new()), "p").unwrap();
+        assert_eq!(from_text.path, None);
+        assert_eq!(
+            which(&cfg, &source, "nope").unwrap_err(),
+            "Unknown profile: nope"
+        );
+    }
+
+    #[test]
+    fn test_dump_config() {
+        let mut cfg = parse_config_toml(
+            r#"
+comment_prefix = "%%"
+[profiles]
+greet = "hi"
+[base]
+description = "Base"
+depends_on = ["a.md", "greet"]
+[child]
+extends = "base"
+depends_on = ["b.md"]
+"#,
+        )
+        .unwrap();
+        cfg.base_dir = Some(PathBuf::from("/cfg"));
+        let lib = Path::new("/cfg/library");
+
+        let mut out = Vec::new();
+        dump_config(
+            &cfg,
+            lib,
+            &ConfigSource::Default,
+            false,
+            JsonLayout::Pretty,
+            &mut out,
+        )
+        .unwrap();
+        let dump: toml::Value = toml::from_str(&String::from_utf8(out).unwrap()).unwrap();
+        assert_eq!(dump["library"].as_str(), Some("/cfg/library"));
+        assert_eq!(dump["comment_prefix"].as_str(), Some("%%"));
+        let profiles = &dump["profiles"];
+        assert_eq!(profiles["greet"]["body"].as_str(), Some("hi"));
+        assert_eq!(profiles["base"]["description"].as_str(), Some("Base"));
+        assert_eq!(profiles["child"]["extends"].as_str(), Some("base"));
+        assert_eq!(
+            profiles["child"]["depends_on"],
+            toml::Value::Array(vec!["a.md".into(), "greet".into(), "b.md".into()])
+        );
+
+        let mut out = Vec::new();
+        dump_config(
+            &cfg,
+            lib,
+            &ConfigSource::Default,
+            true,
+            JsonLayout::Pretty,
+            &mut out,
+        )
+        .unwrap();
+        let dump: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(dump["base_dir"], "/cfg");
+        assert!(dump.get("post_prompt").is_none());
+        assert_eq!(
+            dump["profiles"]["greet"]["depends_on"],
+            serde_json::json!([])
+        );
+    }
+
+    #[test]
+    fn test_list_profiles_order() {
+        let cfg = Config {
+            profiles: HashMap::from([("b".into(), vec![]), ("a".into(), vec![])]),
+            post_prompt: None,
+            ..Config::default()
+        };
+        let lib = mk_tmp("prompter_list_order");
+        fs::create_dir_all(&lib).unwrap();
+        let mut out = Vec::new();
+        super::list_profiles(
+            &cfg,
+            &lib,
+            &ListOptions::default(),
+            ListFormat::Text,
+            &mut out,
+        )
+        .unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "a\nb\n");
+    }
+
+    #[test]
+    fn test_parse_config_extends() {
+        let cfg = parse_config_toml(
+            r#"
+[base]
+depends_on = ["tone.md", "rules.md", "examples.md"]
+
+[python]
+extends = "base"
+depends_on = ["rules.md => python/rules.md", "python/extra.md"]
+
+[python.strict]
+extends = "python"
+"#,
+        )
+        .unwrap();
+        let expected = [
+            "tone.md",
+            "python/rules.md",
+            "examples.md",
+            "python/extra.md",
+        ];
+        assert_eq!(cfg.profiles["python"], expected);
+        assert_eq!(cfg.profiles["python.strict"], expected);
+
+        let mut out = Vec::new();
+        show_tree(&cfg, true, JsonLayout::Pretty, &mut out).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        let root = &json["trees"][0];
+        assert_eq!(root["name"], "python.strict");
+        let python = &root["children"][0];
+        assert_eq!(python["extends"], true);
+        let names: Vec<&str> = python["children"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|c| c["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(names, ["base", "python/rules.md", "python/extra.md"]);
+        assert_eq!(python["children"][0]["extends"], true);
+        assert!(python["children"][1].get("extends").is_none());
+        assert_eq!(root["id"], "python.strict");
+        assert!(root["parent"].is_null());
+        assert_eq!(python["id"], "python");
+        assert_eq!(python["parent"], "python.strict");
+        assert_eq!(python["children"][1]["id"], "fragment:python/rules.md");
+        assert_eq!(python["children"][1]["parent"], "python");
+        assert!(root.get("edge").is_none());
+        assert_eq!(python["edge"], serde_json::json!({"kind": "extends"}));
+        assert_eq!(
+            python["children"][1]["edge"],
+            serde_json::json!({"kind": "hard"})
+        );
+
+        for (input, err) in [
+            (
+                "[a]\nextends = \"b\"\nextends = \"c\"\n",
+                "Multiple extends for [a]",
+            ),
+            (
+                "[a]\nextends = [\"b\", \"c\"]\n",
+                "extends for [a] must name a single profile",
+            ),
+            (
+                "[a]\nextends = \"nope\"\n",
+                "[a] extends unknown profile 'nope'",
+            ),
+            (
+                "[a]\nextends = \"b\"\n[b]\nextends = \"a\"\n",
+                "Inheritance cycle: a -> b -> a",
+            ),
+            (
+                "[b]\ndepends_on = [\"x.md\"]\n[a]\nextends = \"b\"\ndepends_on = [\"y.md => z.md\"]\n",
+                "[a] overrides 'y.md', which [b] does not include",
+            ),
+            (
+                "[a]\ndepends_on = [\"y.md => z.md\"]\n",
+                "[a] overrides 'y.md => z.md' but does not extend a profile",
+            ),
+        ] {
+            assert_eq!(parse_config_toml(input).err().as_deref(), Some(err));
+        }
+    }
+
+    #[test]
+    fn test_render_prepend_and_append_files() {
+        let root = mk_tmp("prompter_extra_files");
+        let lib = root.join("library");
+        fs::create_dir_all(&lib).unwrap();
+        fs::write(lib.join("a.md"), b"BODY\n").unwrap();
+        fs::write(root.join("head.txt"), b"HEAD\n").unwrap();
+        fs::write(root.join("tail.txt"), b"// TAIL\n").unwrap();
+        let cfg = Config {
+            profiles: HashMap::from([("p".into(), vec!["a.md".into()])]),
+            ..Config::default()
+        };
+        let opts = RenderOptions {
+            separator: Some("--\n".into()),
+            prepend_files: vec![root.join("head.txt")],
+            append_files: vec![root.join("tail.txt")],
+            ..RenderOptions::default()
+        };
+        let mut out = Vec::new();
+        super::render_to_writer(&cfg, &lib, &mut out, &["p".to_string()], &opts).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("\nHEAD\n--\n\nBODY\n--\n\n// TAIL\n--\n"));
+
+        let opts = RenderOptions {
+            append_files: vec![root.join("nope.txt")],
+            ..RenderOptions::default()
+        };
+        let err =
+            super::render_to_writer(&cfg, &lib, Vec::new(), &["p".to_string()], &opts).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!("Append file not found: {}", root.join("nope.txt").display())
+        );
+    }
+
+    #[test]
+    fn test_render_pre_prompt_files() {
+        let root = mk_tmp("prompter_pre_prompt_files");
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("role.md"), b"You are a reviewer.").unwrap();
+        fs::write(root.join("rules.md"), b"Be brief.\n").unwrap();
+        let mut opts = RenderOptions {
+            pre_prompt_files: vec![root.join("role.md"), root.join("rules.md")],
+            pre_separator: Some("\n\n".into()),
+            ..RenderOptions::default()
+        };
+        assert_eq!(
+            effective_pre_prompt(&opts).unwrap(),
+            "You are a reviewer.\n\nBe brief.\n"
+        );
+        let cfg = Config {
+            profiles: HashMap::from([("p".into(), Vec::new())]),
+            ..Config::default()
+        };
+        let mut out = Vec::new();
+        super::render_to_writer(&cfg, &root, &mut out, &["p".to_string()], &opts).unwrap();
+        assert!(
+            String::from_utf8(out)
+                .unwrap()
+                .starts_with("You are a reviewer.\n\nBe brief.\n")
+        );
+
+        opts.pre_prompt = Some("Inline".into());
+        assert_eq!(effective_pre_prompt(&opts).unwrap(), "Inline");
+        opts.pre_prompt = None;
+        opts.pre_prompt_files.push(root.join("nope.md"));
+        let err = effective_pre_prompt(&opts).unwrap_err();
+        assert!(err.starts_with("Failed to read pre-prompt file"), "{err}");
+    }
+
+    #[test]
+    fn test_inline_profiles() {
+        let lib = mk_tmp("prompter_inline_profiles");
+        fs::create_dir_all(&lib).unwrap();
+        fs::write(lib.join("a.md"), b"FILE\n").unwrap();
+        let cfg = parse_config_toml(
+            r#"
+[profiles]
+greeting = "Hello {{name}}.\n"
+"team.rules" = "Be kind."
+
+[combined]
+depends_on = ["greeting", "a.md", "team.rules"]
+"#,
+        )
+        .unwrap();
+        assert!(validate(&cfg, &lib, &ValidateOptions::default()).is_ok());
+        let mut opts = RenderOptions {
+            separator: Some("|".into()),
+            ..RenderOptions::default()
+        };
+        opts.vars.global.insert("name".into(), "N".into());
+        let mut out = Vec::new();
+        super::render_to_writer(&cfg, &lib, &mut out, &["combined".to_string()], &opts).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("Hello N.\n|\nFILE\n|\nBe kind.|"));
+
+        let err = parse_config_toml("[profiles]\nx = \"a\"\n[x]\ndepends_on = []\n").unwrap_err();
+        assert_eq!(err, "Profile 'x' is defined both in [profiles] and as [x]");
+        let err = parse_config_toml("[profiles]\nx = [\"a\"]\n").unwrap_err();
+        assert_eq!(err, "[profiles] x must be a string");
+    }
+
+    #[test]
+    fn test_expected_vars() {
+        let lib = mk_tmp("prompter_expected_vars");
+        fs::create_dir_all(&lib).unwrap();
+        fs::write(
+            lib.join("a.md"),
+            "{{ region }} in {{year}}\n// {{hidden}}\n",
+        )
+        .unwrap();
+        fs::write(lib.join("b.md"), "\n{{region}} {{team}} \\{\\{escaped}}\n").unwrap();
+        let cfg = Config {
+            profiles: HashMap::from([
+                ("back".into(), vec!["a.md".into()]),
+                ("front".into(), vec!["b.md".into()]),
+            ]),
+            ..Config::default()
+        };
+        let opts = RenderOptions {
+            vars: vars::Variables {
+                builtin: BTreeMap::from([("year".into(), "2024".into())]),
+                profile: BTreeMap::from([(
+                    "back".into(),
+                    BTreeMap::from([("region".into(), "eu".into())]),
+                )]),
+                ..vars::Variables::default()
+            },
+            comment_prefix: Some("//".into()),
+            ..RenderOptions::default()
+        };
+        let profiles = ["back".to_string(), "front".to_string()];
+        let expected = expected_vars(&cfg, &lib, &profiles, &opts).unwrap();
+        let mut out = Vec::new();
+        show_expected_vars(&expected, false, JsonLayout::Pretty, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "region (required)\nteam (required)\nyear = 2024\n"
+        );
+        assert_eq!(expected[0].default.as_deref(), Some("eu"));
+        assert_eq!(expected[0].locations, ["a.md:1", "b.md:2"]);
+    }
+
+    #[test]
+    fn test_count_includes() {
+        let lib = mk_tmp("prompter_count_includes");
+        fs::create_dir_all(&lib).unwrap();
+        fs::write(lib.join("x.md"), b"X\n").unwrap();
+        let cfg = Config {
+            profiles: HashMap::from([
+                ("top".into(), vec!["a".into(), "b".into()]),
+                ("a".into(), vec!["shared".into()]),
+                ("b".into(), vec!["shared".into(), "x.md".into()]),
+                ("shared".into(), vec!["x.md".into()]),
+            ]),
+            ..Config::default()
+        };
+        let profiles = ["top".to_string(), "a".to_string()];
+        let counts = count_includes(&cfg, &lib, &profiles, &RenderOptions::default()).unwrap();
+        let expected = [("a", 2), ("b", 1), ("shared", 3), ("top", 1)];
+        assert_eq!(
+            counts
+                .iter()
+                .map(|(k, v)| (k.as_str(), *v))
+                .collect::<Vec<_>>(),
+            expected
+        );
+
+        let mut out = Vec::new();
+        show_include_counts(&counts, false, JsonLayout::Pretty, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "   3  shared\n   2  a\n   1  b\n   1  top\n"
+        );
+        let mut out = Vec::new();
+        show_include_counts(&counts, true, JsonLayout::Pretty, &mut out).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(json["shared"], 3);
+    }
+
+    #[test]
+    fn test_non_utf8_fragments() {
+        let lib = mk_tmp("prompter_non_utf8");
+        fs::create_dir_all(&lib).unwrap();
+        fs::write(lib.join("latin1.md"), b"caf\xe9 au lait\n").unwrap();
+        let cfg = Config {
+            profiles: HashMap::from([("p".into(), vec!["latin1.md".into()])]),
+            ..Config::default()
+        };
+        let profiles = ["p".to_string()];
+        let err =
+            super::render_to_writer(&cfg, &lib, Vec::new(), &profiles, &RenderOptions::default())
+                .unwrap_err();
+        assert!(err.error.starts_with(&format!(
+            "{} is not valid UTF-8 (invalid byte at offset 3)",
+            lib.join("latin1.md").display()
+        )));
+
+        let lossy = RenderOptions {
+            encoding: Encoding::Lossy,
+            quiet: true,
+            ..RenderOptions::default()
+        };
+        let mut out = Vec::new();
+        super::render_to_writer(&cfg, &lib, &mut out, &profiles, &lossy).unwrap();
+        assert!(
+            String::from_utf8(out)
+                .unwrap()
+                .contains("caf\u{fffd} au lait")
+        );
+    }
+
+    #[test]
+    fn test_render_strict_vars() {
+        let lib = mk_tmp("prompter_strict_vars");
+        fs::create_dir_all(&lib).unwrap();
+        fs::write(
+            lib.join("a.md"),
+            b"Hi {{name}}\n\\{\\{literal}}\n{{ missing }}\n",
+        )
+        .unwrap();
+        fs::write(lib.join("b.md"), b"one\ntwo {{}}\n").unwrap();
+        let cfg = Config {
+            profiles: HashMap::from([("p".into(), vec!["a.md".into(), "b.md#2".into()])]),
+            ..Config::default()
+        };
+        let profiles = ["p".to_string()];
+        let mut opts = RenderOptions {
+            strict_vars: true,
+            ..RenderOptions::default()
+        };
+        opts.vars.global.insert("name".into(), "N".into());
+        let err = super::render_to_writer(&cfg, &lib, Vec::new(), &profiles, &opts).unwrap_err();
+        assert_eq!(
+            err.error,
+            format!(
+                "Unresolved variable {{{{missing}}}} at {}:3\nUnresolved variable {{{{}}}} at {}:2",
+                lib.join("a.md").display(),
+                lib.join("b.md").display()
+            )
+        );
+
+        fs::write(lib.join("b.md"), b"one\ntwo\n").unwrap();
+        opts.vars.global.insert("missing".into(), "M".into());
+        let mut out = Vec::new();
+        super::render_to_writer(&cfg, &lib, &mut out, &profiles, &opts).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("Hi N\n{{literal}}\nM\n"));
+
+        // Placeholders outside fragment bodies are found in the final output
+        opts.post_prompt = Some("Reply to {{ reviewer }}".into());
+        opts.vars
+            .global
+            .insert("missing".into(), "{{nested}}".into());
+        let err = super::render_to_writer(&cfg, &lib, Vec::new(), &profiles, &opts).unwrap_err();
+        let lines: Vec<&str> = err.error.lines().collect();
+        assert_eq!(lines.len(), 2, "{}", err.error);
+        assert!(lines[0].starts_with("Unresolved variable {{nested}} at line "));
+        assert!(lines[1].starts_with("Unresolved variable {{reviewer}} at line "));
+        assert!(lines[1].ends_with(" of the rendered output"));
+    }
+
+    #[test]
+    fn test_render_reads_each_file_once() {
+        let lib = mk_tmp("prompter_file_cache");
+        fs::create_dir_all(&lib).unwrap();
+        fs::write(lib.join("legal.md"), b"one\ntwo\nthree\nfour\n").unwrap();
+        fs::write(lib.join("other.md"), b"OTHER\n").unwrap();
+        let cfg = Config {
+            profiles: HashMap::from([
+                ("a".into(), vec!["legal.md#1-2".into(), "other.md".into()]),
+                ("b".into(), vec!["legal.md#3-4".into(), "a".into()]),
+                ("c".into(), vec!["legal.md".into(), "b".into()]),
+            ]),
+            ..Config::default()
+        };
+        let profiles = ["c".to_string(), "a".to_string()];
+        for format in [RenderFormat::Text, RenderFormat::Json, RenderFormat::Chat] {
+            let opts = RenderOptions {
+                format,
+                ..RenderOptions::default()
+            };
+            let files = resolve_render_files(&cfg, &lib, &profiles, &opts).unwrap();
+            assert_eq!(files.len(), 4);
+            let cache = FileCache::default();
+            write_rendered(&cfg, &lib, &files, &profiles, &opts, io::sink(), &cache).unwrap();
+            assert_eq!(cache.reads.get(), 2);
+        }
+    }
+
+    #[test]
+    fn test_render_error_names_failing_profile_and_chain() {
+        let lib = mk_tmp("prompter_render_error");
+        fs::create_dir_all(&lib).unwrap();
+        fs::write(lib.join("ok.md"), b"OK\n").unwrap();
+        let cfg = Config {
+            profiles: HashMap::from([
+                ("good".into(), vec!["ok.md".into()]),
+                ("top".into(), vec!["mid".into()]),
+                ("mid".into(), vec!["ok.md".into(), "gone.md".into()]),
+            ]),
+            ..Config::default()
+        };
+        let profiles = ["good".to_string(), "top".to_string()];
+        let err =
+            super::render_to_writer(&cfg, &lib, Vec::new(), &profiles, &RenderOptions::default())
+                .unwrap_err();
+        assert_eq!(err.failed_profile.as_deref(), Some("top"));
+        assert_eq!(err.include_chain, ["top", "mid"]);
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "Failed to render profile 'top': Missing file: {} (referenced by [mid])\n  include chain: top -> mid",
+                lib.join("gone.md").display()
+            )
+        );
+
+        let profiles = ["good".to_string(), "nope".to_string()];
+        let err =
+            super::render_to_writer(&cfg, &lib, Vec::new(), &profiles, &RenderOptions::default())
+                .unwrap_err();
+        assert_eq!(err.failed_profile.as_deref(), Some("nope"));
+        assert_eq!(
+            err.to_string(),
+            "Failed to render profile 'nope': Unknown profile: nope"
+        );
+    }
+
+    #[test]
+    fn test_library_fragments_byte_order() {
+        let lib = mk_tmp("prompter_fragment_order");
+        fs::create_dir_all(lib.join("a")).unwrap();
+        // Locale-aware collation would give roughly a, a/x, B, é, _x, Z.
+        for name in ["é.md", "a.md", "Z.md", "_x.md", "B.md", "a/x.md"] {
+            fs::write(lib.join(name), b"x").unwrap();
+        }
+        assert_eq!(
+            library_fragments(&lib).unwrap(),
+            ["B.md", "Z.md", "_x.md", "a.md", "a/x.md", "é.md"]
+        );
+        assert!(library_fragments(&lib.join("missing")).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_list_profiles_json_metadata() {
+        let lib = mk_tmp("prompter_list_json");
+        fs::create_dir_all(&lib).unwrap();
+        fs::write(lib.join("a.md"), b"12345").unwrap();
+        let cfg = parse_config_toml(
+            r#"
+[documented]
+description = "Has metadata"
+tags = [
+  "x",
+  "y",
+]
+depends_on = ["a.md"]
+
+[broken]
+depends_on = ["missing.md"]
+"#,
+        )
+        .unwrap();
+        let mut out = Vec::new();
+        super::list_profiles(
+            &cfg,
+            &lib,
+            &ListOptions::default(),
+            ListFormat::Json,
+            &mut out,
+        )
+        .unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        let profiles = json["profiles"].as_array().unwrap();
+        assert_eq!(profiles[0]["name"], "broken");
+        assert!(profiles[0]["size"].is_null());
+        assert_eq!(profiles[0]["tags"], serde_json::json!([]));
+        assert_eq!(profiles[1]["name"], "documented");
+        assert_eq!(profiles[1]["size"], 5);
+        assert_eq!(profiles[1]["tags"], serde_json::json!(["x", "y"]));
+        assert_eq!(profiles[1]["description"], "Has metadata");
+
+        let mut out = Vec::new();
+        super::list_profiles(
+            &cfg,
+            &lib,
+            &ListOptions::default(),
+            ListFormat::Ndjson,
+            &mut out,
+        )
+        .unwrap();
+        let lines: Vec<serde_json::Value> = String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0], profiles[0]);
+        assert_eq!(lines[1], profiles[1]);
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("7d"), Ok(Duration::from_secs(7 * 86_400)));
+        assert_eq!(parse_duration("12h"), Ok(Duration::from_secs(12 * 3_600)));
+        assert_eq!(parse_duration("30m"), Ok(Duration::from_secs(30 * 60)));
+        for bad in ["", "d", "7", "7w", "-1d", "1.5h"] {
+            let err = parse_duration(bad).unwrap_err();
+            assert!(err.contains("Invalid duration"), "{bad}: {err}");
+        }
+    }
+
+    #[test]
+    fn test_list_profiles_since_and_mtime_sort() {
+        let lib = mk_tmp("prompter_list_since");
+        fs::create_dir_all(&lib).unwrap();
+        fs::write(lib.join("old.md"), b"OLD").unwrap();
+        fs::write(lib.join("new.md"), b"NEW").unwrap();
+        let old = SystemTime::now() - Duration::from_secs(30 * 86_400);
+        fs::File::options()
+            .write(true)
+            .open(lib.join("old.md"))
+            .unwrap()
+            .set_modified(old)
+            .unwrap();
+        let cfg = Config {
+            profiles: HashMap::from([
+                ("a_old".into(), vec!["old.md".into()]),
+                ("b_new".into(), vec!["new.md".into()]),
+                ("c_both".into(), vec!["old.md".into(), "new.md".into()]),
+            ]),
+            post_prompt: None,
+            ..Config::default()
+        };
+
+        let opts = ListOptions {
+            since: Some(Duration::from_secs(7 * 86_400)),
+            sort: ListSort::Name,
+            grouped: false,
+            scenarios: false,
+            limit: None,
+            offset: 0,
+            json_layout: JsonLayout::Pretty,
+        };
+        let mut out = Vec::new();
+        super::list_profiles(&cfg, &lib, &opts, ListFormat::Text, &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "b_new\nc_both\n");
+
+        let opts = ListOptions {
+            since: None,
+            sort: ListSort::Mtime,
+            grouped: false,
+            scenarios: false,
+            limit: None,
+            offset: 0,
+            json_layout: JsonLayout::Pretty,
+        };
+        let mut out = Vec::new();
+        super::list_profiles(&cfg, &lib, &opts, ListFormat::Text, &mut out).unwrap();
+        assert!(String::from_utf8(out).unwrap().ends_with("a_old\n"));
+
+        let opts = ListOptions {
+            since: None,
+            sort: ListSort::Name,
+            grouped: false,
+            scenarios: false,
+            limit: Some(1),
+            offset: 1,
+            json_layout: JsonLayout::Pretty,
+        };
+        let mut out = Vec::new();
+        super::list_profiles(&cfg, &lib, &opts, ListFormat::Json, &mut out).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(json["total"], 3);
+        assert_eq!(json["profiles"].as_array().unwrap().len(), 1);
+        assert_eq!(json["profiles"][0]["name"], "b_new");
+        let opts = ListOptions { offset: 5, ..opts };
+        let mut out = Vec::new();
+        super::list_profiles(&cfg, &lib, &opts, ListFormat::Text, &mut out).unwrap();
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn test_list_profiles_by_mtime_survives_a_broken_profile() {
+        let lib = mk_tmp("prompter_list_broken");
+        fs::create_dir_all(&lib).unwrap();
+        fs::write(lib.join("ok.md"), b"OK").unwrap();
+        let cfg = Config {
+            profiles: HashMap::from([
+                ("a_broken".into(), vec!["missing.md".into()]),
+                ("b_ok".into(), vec!["ok.md".into()]),
+            ]),
+            post_prompt: None,
+            ..Config::default()
+        };
+
+        let opts = ListOptions {
+            sort: ListSort::Mtime,
+            ..ListOptions::default()
+        };
+        let mut out = Vec::new();
+        super::list_profiles(&cfg, &lib, &opts, ListFormat::Text, &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "b_ok\na_broken\n");
+
+        let opts = ListOptions {
+            since: Some(Duration::from_secs(86_400)),
+            ..ListOptions::default()
+        };
+        let mut out = Vec::new();
+        super::list_profiles(&cfg, &lib, &opts, ListFormat::Text, &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "b_ok\n");
+    }
+
+    #[test]
+    fn test_list_profiles_grouped() {
+        let lib = mk_tmp("prompter_list_grouped");
+        let cfg = Config {
+            profiles: HashMap::from([
+                ("python/api".into(), vec![]),
+                ("python/cli/args".into(), vec![]),
+                ("general".into(), vec![]),
+                ("rust/errors".into(), vec![]),
+            ]),
+            ..Config::default()
+        };
+        let opts = ListOptions {
+            grouped: true,
+            ..ListOptions::default()
+        };
+        let list = |format| {
+            let mut out = Vec::new();
+            super::list_profiles(&cfg, &lib, &opts, format, &mut out).map(|()| out)
+        };
+        assert_eq!(
+            String::from_utf8(list(ListFormat::Text).unwrap()).unwrap(),
+            "general\npython/\n  python/api\n  python/cli/args\nrust/\n  rust/errors\n"
+        );
+        let json: serde_json::Value =
+            serde_json::from_slice(&list(ListFormat::Json).unwrap()).unwrap();
+        assert_eq!(json["profiles"][""][0]["name"], "general");
+        assert_eq!(json["profiles"]["python"][1]["name"], "python/cli/args");
+        assert_eq!(json["profiles"]["rust"].as_array().unwrap().len(), 1);
+        assert!(list(ListFormat::Ndjson).is_err());
+    }
+
+    #[test]
+    fn test_list_scenarios() {
+        let lib = mk_tmp("prompter_list_scenarios");
+        let mut cfg = Config::default();
+        for (name, profiles) in [("review", vec!["a", "b"]), ("onboard", vec!["c"])] {
+            cfg.scenarios.insert(
+                name.into(),
+                Scenario {
+                    profiles: profiles.into_iter().map(String::from).collect(),
+                    ..Scenario::default()
+                },
+            );
+        }
+        let opts = ListOptions {
+            scenarios: true,
+            limit: None,
+            offset: 0,
+            ..ListOptions::default()
+        };
+        let list = |format| {
+            let mut out = Vec::new();
+            super::list_profiles(&cfg, &lib, &opts, format, &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        assert_eq!(list(ListFormat::Text), "onboard\nreview\n");
+        let json: serde_json::Value = serde_json::from_str(&list(ListFormat::Json)).unwrap();
+        assert_eq!(json["scenarios"][1]["profiles"][1], "b");
+        assert_eq!(
+            list(ListFormat::Ndjson).lines().next(),
+            Some(r#"{"name":"onboard","profiles":["c"]}"#)
+        );
+    }
+
+    #[test]
+    fn test_validate_cycle_detected() {
+        let cfg = Config {
+            profiles: HashMap::from([
+                ("A".into(), vec!["B".into()]),
+                ("B".into(), vec!["A".into()]),
+            ]),
+            post_prompt: None,
+            ..Config::default()
+        };
+        let lib = mk_tmp("prompter_cycle");
+        fs::create_dir_all(&lib).unwrap();
+        let err = validate(&cfg, &lib, &ValidateOptions::default()).unwrap_err();
+        assert!(err.contains("Cycle detected"));
+    }
+
+    #[test]
+    fn test_render_profile_scoped_variables() {
+        let lib = mk_tmp("prompter_render_vars");
+        fs::create_dir_all(&lib).unwrap();
+        fs::write(lib.join("b.md"), b"backend in {{region}}\n").unwrap();
+        fs::write(lib.join("f.md"), b"frontend in {{ region }}\n").unwrap();
+        let cfg = Config {
+            profiles: HashMap::from([
+                ("backend".into(), vec!["b.md".into()]),
+                ("frontend".into(), vec!["f.md".into()]),
+            ]),
+            ..Config::default()
+        };
+        let args = vec![
+            "prompter".into(),
+            "run".into(),
+            "backend".into(),
+            "frontend".into(),
+            "--var".into(),
+            "region=eu".into(),
+            "--profile-arg".into(),
+            "backend:region=us-east".into(),
+        ];
+        let AppMode::Run {
+            profiles, options, ..
+        } = parse_args_from(args).unwrap()
+        else {
+            panic!("expected run mode");
+        };
+        let mut out = Vec::new();
+        super::render_to_writer(&cfg, &lib, &mut out, &profiles, &options).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("backend in us-east\n"));
+        assert!(out.contains("frontend in eu\n"));
+    }
+
+    #[test]
+    fn test_line_range_parse_and_slice() {
+        assert_eq!(split_line_range("a.md#2-3"), ("a.md", Some("2-3")));
+        assert_eq!(split_line_range("a.md#L2-L3"), ("a.md", Some("L2-L3")));
+        assert_eq!(split_line_range("a#b.md"), ("a#b.md", None));
+        assert_eq!(split_line_range("a#1.md"), ("a#1.md", None));
+        assert_eq!(split_line_range("a.md#2-"), ("a.md#2-", None));
+        assert_eq!(
+            LineRange::parse("L2-L3"),
+            Some(LineRange { start: 2, end: 3 })
+        );
+        assert!(is_fragment("shared/legal.md#1-5"));
+        assert!(is_fragment("https://example.com/snippets/legal"));
+        assert_eq!(LineRange::parse("4"), Some(LineRange { start: 4, end: 4 }));
+        assert_eq!(LineRange::parse("0-2"), None);
+        assert_eq!(LineRange::parse("3-2"), None);
+        assert_eq!(LineRange::parse("x"), None);
+
+        let range = LineRange { start: 2, end: 3 };
+        assert_eq!(range.slice("one\ntwo\nthree\nfour\n"), Some("two\nthree\n"));
+        assert_eq!(range.slice("one\ntwo"), None);
+    }
+
+    #[test]
+    fn test_render_and_validate_line_ranges() {
+        let lib = mk_tmp("prompter_line_range");
+        fs::create_dir_all(&lib).unwrap();
+        fs::write(lib.join("legal.md"), b"L1\nL2\nL3\nL4\n").unwrap();
+        let cfg = Config {
+            profiles: HashMap::from([("p".into(), vec!["legal.md#2-3".into()])]),
+            ..Config::default()
+        };
+        assert!(validate(&cfg, &lib, &ValidateOptions::default()).is_ok());
+        let mut out = Vec::new();
+        super::render_to_writer(
+            &cfg,
+            &lib,
+            &mut out,
+            &["p".to_string()],
+            &RenderOptions::default(),
+        )
+        .unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("L2\nL3\n"));
+        assert!(!out.contains("L1") && !out.contains("L4"));
+
+        let cfg = Config {
+            profiles: HashMap::from([
+                ("far".into(), vec!["legal.md#3-9".into()]),
+                ("bad".into(), vec!["legal.md#3-2".into()]),
+            ]),
+            ..Config::default()
+        };
+        let err = validate(&cfg, &lib, &ValidateOptions::default()).unwrap_err();
+        assert!(
+            err.contains(
+                "Line range out of bounds: legal.md#3-9 has 4 lines (referenced by [far])"
+            )
+        );
+        assert!(err.contains("Invalid line range: legal.md#3-2 (referenced by [bad])"));
+    }
+
+    #[test]
+    fn test_layered_configs_merge_in_order() {
+        let root = mk_tmp("prompter_layered");
+        fs::create_dir_all(root.join("team")).unwrap();
+        fs::create_dir_all(root.join("me")).unwrap();
+        let team = root.join("team/config.toml");
+        let mine = root.join("me/config.toml");
+        fs::write(
+            &team,
+            r#"post_prompt = "TEAM"
+comment_prefix = "//"
+[base]
+description = "Shared rules"
+depends_on = ["rules.md"]
+[style]
+depends_on = ["style.md"]
+[profiles]
+note = "team note"
+"#,
+        )
+        .unwrap();
+        fs::write(
+            &mine,
+            r#"post_prompt = "MINE"
+[style]
+depends_on = ["my-style.md"]
+[child]
+extends = "base"
+depends_on = ["extra.md"]
+[note]
+depends_on = ["note.md"]
+"#,
+        )
+        .unwrap();
+
+        let source = ConfigSource::from_args(vec![team.clone(), mine.clone()], None);
+        assert!(matches!(source, ConfigSource::Layered(_)));
+        let (cfg, lib) = source.load().unwrap();
+        assert_eq!(lib, root.join("team/library"));
+        assert_eq!(cfg.post_prompt.as_deref(), Some("MINE"));
+        assert_eq!(cfg.comment_prefix.as_deref(), Some("//"));
+        assert_eq!(cfg.profiles["style"], ["my-style.md"]);
+        assert_eq!(cfg.profiles["child"], ["rules.md", "extra.md"]);
+        assert_eq!(cfg.profiles["note"], ["note.md"]);
+        assert!(!cfg.inline_profiles.contains_key("note"));
+        assert_eq!(
+            cfg.metadata["base"].description.as_deref(),
+            Some("Shared rules")
+        );
+
+        assert_eq!(source.file_defining("style").unwrap(), Some(mine));
+        assert_eq!(source.file_defining("base").unwrap(), Some(team));
+    }
+
+    #[test]
+    fn test_relative_to_roots_library_and_file_includes() {
+        let root = mk_tmp("prompter_relative_to");
+        fs::create_dir_all(root.join("conf")).unwrap();
+        let config = root.join("conf/config.toml");
+        fs::write(
+            &config,
+            "relative_to = \"../checkout\"\n[p]\ndepends_on = []\n",
+        )
+        .unwrap();
+
+        let (cfg, lib) = ConfigSource::File(config.clone()).load().unwrap();
+        let checkout = root.join("conf/../checkout");
+        assert_eq!(lib, checkout.join("library"));
+        assert_eq!(cfg.base_dir, Some(checkout));
+
+        let (cfg, lib) = ConfigSource::File(config)
+            .relative_to(Some(root.join("ci")))
+            .load()
+            .unwrap();
+        assert_eq!(lib, root.join("ci/library"));
+        assert_eq!(cfg.base_dir, Some(root.join("ci")));
+
+        let (cfg, lib) = ConfigSource::Inline("[p]".into())
+            .relative_to(Some(root.clone()))
+            .load()
+            .unwrap();
+        assert_eq!(lib, root.join("library"));
+        assert_eq!(cfg.base_dir, Some(root));
+    }
+
+    #[test]
+    fn test_include_base_overrides_library_only() {
+        let root = mk_tmp("prompter_include_base");
+        fs::create_dir_all(root.join("conf")).unwrap();
+        let config = root.join("conf/config.toml");
+        fs::write(
+            &config,
+            "include_base = \"../prompts\"\nrelative_to = \"../checkout\"\n[p]\ndepends_on = []\n",
+        )
+        .unwrap();
+
+        let (cfg, lib) = ConfigSource::File(config.clone()).load().unwrap();
+        assert_eq!(lib, root.join("conf/../prompts"));
+        assert_eq!(cfg.base_dir, Some(root.join("conf/../checkout")));
+
+        let source = ConfigSource::File(config)
+            .include_base(Some(root.join("cli")))
+            .relative_to(Some(root.join("ci")));
+        let (cfg, lib) = source.load().unwrap();
+        assert_eq!(lib, root.join("cli"));
+        assert_eq!(cfg.base_dir, Some(root.join("ci")));
+    }
+
+    #[test]
+    fn test_file_includes_render_verbatim_from_config_dir() {
+        let root = mk_tmp("prompter_file_include");
+        let lib = root.join("library");
+        fs::create_dir_all(lib.join("a")).unwrap();
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::write(lib.join("a/x.md"), b"Intro {{name}}\n").unwrap();
+        fs::write(
+            root.join("src/config.rs"),
+            b"// keep me {{name}}\nfn main() {}\n",
+        )
+        .unwrap();
+        let cfg = Config {
+            profiles: HashMap::from([(
+                "p".into(),
+                vec!["a/x.md".into(), "@./src/config.rs".into()],
+            )]),
+            base_dir: Some(root.clone()),
+            ..Config::default()
+        };
+        assert!(validate(&cfg, &lib, &ValidateOptions::default()).is_ok());
+
+        let opts = RenderOptions {
+            vars: vars::Variables {
+                global: std::collections::BTreeMap::from([("name".into(), "N".into())]),
+                ..vars::Variables::default()
+            },
+            ..RenderOptions::default()
+        };
+        let mut out = Vec::new();
+        super::render_to_writer(&cfg, &lib, &mut out, &["p".to_string()], &opts).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("Intro N"));
+        assert!(out.contains("// keep me {{name}}\nfn main() {}"));
+
+        let trees = build_trees(&cfg);
+        let kinds: Vec<&TreeNodeType> = trees.trees[0]
+            .children
+            .iter()
+            .map(|c| &c.node_type)
+            .collect();
+        assert_eq!(kinds, [&TreeNodeType::Fragment, &TreeNodeType::File]);
+        assert_eq!(trees.trees[0].children[1].id, "file:./src/config.rs");
+
+        let cfg = Config {
+            profiles: HashMap::from([("p".into(), vec!["@missing.txt".into()])]),
+            base_dir: Some(root.clone()),
+            ..Config::default()
+        };
+        let err = validate(&cfg, &lib, &ValidateOptions::default()).unwrap_err();
+        assert!(err.contains(&format!(
+            "Missing file: {} (referenced by [p])",
+            root.join("missing.txt").display()
+        )));
+    }
+
+    #[test]
+    fn test_tree_edges() {
+        let cfg = parse_config_toml(
+            "[app]\ndepends_on = [\"?extra\", \"a.md#3-7\", \"?@notes.txt\"]\n\
+             [extra]\ndepends_on = []\n",
+        )
+        .unwrap();
+        let trees = build_trees(&cfg);
+        let app = &trees.trees[0];
+        assert_eq!(app.edge, None);
+        let edges: Vec<(&str, TreeEdge)> = app
+            .children
+            .iter()
+            .map(|c| (c.name.as_str(), c.edge.unwrap()))
+            .collect();
+        assert_eq!(
+            edges,
+            [
+                (
+                    "extra",
+                    TreeEdge {
+                        kind: IncludeKind::Soft,
+                        lines: None
+                    }
+                ),
+                (
+                    "a.md#3-7",
+                    TreeEdge {
+                        kind: IncludeKind::Hard,
+                        lines: Some(LineRange { start: 3, end: 7 })
+                    }
+                ),
+                (
+                    "@notes.txt",
+                    TreeEdge {
+                        kind: IncludeKind::Soft,
+                        lines: None
+                    }
+                ),
+            ]
+        );
+        let json = serde_json::to_value(&app.children[1]).unwrap();
+        assert_eq!(
+            json["edge"],
+            serde_json::json!({"kind": "hard", "lines": {"start": 3, "end": 7}})
+        );
+    }
+
+    #[test]
+    fn test_normalize_unicode_and_ascii_fold() {
+        let text =
+            "\u{201C}Cafe\u{301}\u{201D} \u{2014} it\u{2019}s\u{a0}\u{FB01}ne\u{2026}\u{200B}";
+        let opts = |normalize_unicode, ascii_fold| RenderOptions {
+            normalize_unicode,
+            ascii_fold,
+            ..RenderOptions::default()
+        };
+        let composed = normalize_text(text.into(), &opts(Some(UnicodeForm::Nfc), false));
+        assert!(composed.contains("Caf\u{e9}") && composed.contains('\u{FB01}'));
+        let folded = normalize_text(text.into(), &opts(Some(UnicodeForm::Nfkc), false));
+        assert!(folded.contains("it\u{2019}s fine"));
+        assert_eq!(
+            normalize_text(text.into(), &opts(Some(UnicodeForm::Nfkc), true)),
+            "\"Caf\u{e9}\" -- it's fine..."
+        );
+        assert_eq!(normalize_text(text.into(), &opts(None, false)), text);
+    }
+
+    #[test]
+    fn test_trim_policy() {
+        let text = || "\n \nA  \n\tB\t\r\n\n\n".to_string();
+        assert_eq!(TrimPolicy::None.apply(text()), text());
+        assert_eq!(TrimPolicy::Lines.apply(text()), "\n\nA\n\tB\n\n\n");
+        assert_eq!(TrimPolicy::All.apply(text()), "A\n\tB\n");
+        assert_eq!(TrimPolicy::All.apply("A ".into()), "A");
+        assert_eq!(TrimPolicy::All.apply(" \n\n".into()), "");
+    }
+
+    #[test]
+    fn test_strip_blank_runs() {
+        assert_eq!(
+            strip_blank_runs("\n \nA\n\nB\n\n\n\t\nC\n\n\n"),
+            "A\n\nB\n\nC\n"
+        );
+        assert_eq!(strip_blank_runs("A\n\n\nB"), "A\n\n\nB\n");
+        assert_eq!(strip_blank_runs("\n\n"), "");
+
+        let lib = mk_tmp("prompter_blank_runs");
+        fs::create_dir_all(&lib).unwrap();
+        fs::write(lib.join("a.md"), b"A\n\n\n\n").unwrap();
+        fs::write(lib.join("b.md"), b"\n\n\nB\n").unwrap();
+        let cfg = Config {
+            profiles: HashMap::from([("p".into(), vec!["a.md".into(), "b.md".into()])]),
+            ..Config::default()
+        };
+        let opts = RenderOptions {
+            pre_prompt: Some("\n\nPRE\n".into()),
+            post_prompt: Some("POST\n\n".into()),
+            strip_blank_runs: true,
+            ..RenderOptions::default()
+        };
+        let mut out = Vec::new();
+        super::render_to_writer(&cfg, &lib, &mut out, &["p".to_string()], &opts).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with("PRE\n"));
+        assert!(out.ends_with("POST\n"));
+        assert!(out.contains("A\n\nB\n"));
+        assert!(!out.contains("\n\n\n\n"));
+    }
+
+    #[test]
+    fn test_hash_replaces_text_output() {
+        assert_eq!(
+            HashAlgorithm::Sha256.hex_digest(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(HashAlgorithm::Sha512.hex_digest(b"abc").len(), 128);
+
+        let lib = mk_tmp("prompter_hash");
+        fs::create_dir_all(&lib).unwrap();
+        fs::write(lib.join("a.md"), b"A\n").unwrap();
+        let cfg = Config {
+            profiles: HashMap::from([("p".into(), vec!["a.md".into()])]),
+            ..Config::default()
+        };
+        let profiles = ["p".to_string()];
+        let render = |opts: &RenderOptions| {
+            let mut out = Vec::new();
+            super::render_to_writer(&cfg, &lib, &mut out, &profiles, opts).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        let text = render(&RenderOptions::default());
+        let expected = HashAlgorithm::Sha256.hex_digest(text.as_bytes());
+
+        let hashed = RenderOptions {
+            hash: Some(HashAlgorithm::Sha256),
+            ..RenderOptions::default()
+        };
+        assert_eq!(render(&hashed), format!("{expected}\n"));
+        let on_terminal = RenderOptions {
+            styled: true,
+            ..hashed.clone()
+        };
+        assert_ne!(
+            render(&RenderOptions {
+                hash: None,
+                ..on_terminal.clone()
+            }),
+            text
+        );
+        assert_eq!(render(&on_terminal), format!("{expected}\n"));
+        let json: serde_json::Value = serde_json::from_str(&render(&RenderOptions {
+            format: RenderFormat::Json,
+            ..hashed
+        }))
+        .unwrap();
+        assert_eq!(json["hash"], expected);
+        assert_eq!(json["fragments"][0]["content"], "A\n");
+    }
+
+    #[test]
+    fn test_min_tokens() {
+        assert_eq!(estimate_tokens(""), 0);
+        assert_eq!(estimate_tokens("abcde"), 2);
+        assert_eq!(estimate_tokens("éééé"), 1);
+
+        let lib = mk_tmp("prompter_min_tokens");
+        fs::create_dir_all(&lib).unwrap();
+        fs::write(lib.join("a.md"), b"A\n").unwrap();
+        let cfg = Config {
+            profiles: HashMap::from([("p".into(), vec!["a.md".into()])]),
+            ..Config::default()
+        };
+        let render = |opts: &RenderOptions| {
+            let mut out = Vec::new();
+            render_to_writer(&cfg, &lib, &mut out, &["p".to_string()], opts).map(|()| out)
+        };
+        let text = render(&RenderOptions::default()).unwrap();
+        let tokens = estimate_tokens(&String::from_utf8(text.clone()).unwrap());
+
+        let met = RenderOptions {
+            min_tokens: Some(tokens),
+            fail_on_warning: true,
+            ..RenderOptions::default()
+        };
+        assert_eq!(render(&met).unwrap(), text);
+        let short = RenderOptions {
+            min_tokens: Some(tokens + 1),
+            ..met
+        };
+        let err = render(&short).unwrap_err().to_string();
+        assert!(
+            err.contains(&format!("p is about {tokens} token(s), below --min-tokens")),
+            "{err}"
+        );
+        let warned = RenderOptions {
+            fail_on_warning: false,
+            ..short
+        };
+        assert_eq!(render(&warned).unwrap(), text);
+    }
 
-        // Write post-prompt (defaults if not provided)
-        let default_post = default_post_prompt();
-        let post_prompt_text = post_prompt
-            .or(cfg.post_prompt.as_deref())
-            .unwrap_or(&default_post);
+    #[test]
+    fn test_out_template() {
+        assert_eq!(
+            parse_out_template("<context>\\n{{body}}</context>"),
+            Ok("<context>\n{{body}}</context>".into())
+        );
+        assert!(parse_out_template("<context></context>").is_err());
+        assert!(parse_out_template("{{body}}{{body}}").is_err());
 
-        // Two newlines before post-prompt
-        w.write_all(b"\n\n")
-            .map_err(|e| format!("Write error: {e}"))?;
-        w.write_all(post_prompt_text.as_bytes())
-            .map_err(|e| format!("Write error: {e}"))?;
+        let lib = mk_tmp("prompter_out_template");
+        fs::create_dir_all(&lib).unwrap();
+        fs::write(lib.join("a.md"), b"{{who}} {{body}}\n").unwrap();
+        let cfg = Config {
+            profiles: HashMap::from([("p".into(), vec!["a.md".into()])]),
+            ..Config::default()
+        };
+        let mut opts = RenderOptions {
+            pre_prompt: Some("PRE\n".into()),
+            post_prompt: Some("POST".into()),
+            out_template: Some("<context>{{body}}</context>\n".into()),
+            ..RenderOptions::default()
+        };
+        opts.vars.global.insert("who".into(), "team".into());
+        let mut out = Vec::new();
+        super::render_to_writer(&cfg, &lib, &mut out, &["p".to_string()], &opts).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with("<context>PRE\n"), "{out}");
+        assert!(out.contains("team {{body}}\n"), "{out}");
+        assert!(out.ends_with("POST</context>\n"), "{out}");
     }
 
-    Ok(())
-}
+    #[test]
+    fn test_expand_profiles_glob() {
+        assert!(glob_matches("onboarding/*", "onboarding/setup"));
+        assert!(glob_matches("*.api", "python.api"));
+        assert!(glob_matches("a?c*", "abc"));
+        assert!(!glob_matches("a?c", "ac"));
+        assert!(!glob_matches("*.api", "python.api.v2"));
 
-/// Render one or more profiles to stdout.
-///
-/// Convenience function that reads configuration and renders the specified
-/// profiles to standard output with optional separator, pre-prompt, and post-prompt.
-/// When multiple profiles are provided, files are deduplicated across all profiles.
-///
-/// # Arguments
-/// * `profiles` - Profile names to render (deduplicated in order)
-/// * `separator` - Optional separator between files
-/// * `pre_prompt` - Optional custom pre-prompt text
-/// * `post_prompt` - Optional custom post-prompt text
-/// * `config_override` - Optional configuration file override
-/// * `json` - Whether to output in JSON format
-///
-/// # Returns
-/// * `Ok(())` - Profiles rendered successfully
-/// * `Err(String)` - Rendering failed
-///
-/// # Errors
-/// Returns an error if:
-/// - Configuration file cannot be read or parsed
-/// - Profile resolution fails
-/// - Writing to stdout fails
-pub fn run_render_stdout(
-    profiles: &[String],
-    separator: Option<&str>,
-    pre_prompt: Option<&str>,
-    post_prompt: Option<&str>,
-    config_override: Option<&Path>,
-    json: bool,
-) -> Result<(), String> {
-    let cfg_path = resolve_config_path(config_override)?;
-    let cfg_text = read_config_with_path(&cfg_path)?;
-    let cfg = parse_config_toml(&cfg_text)?;
-    let lib = library_path_for_config_override(config_override, &cfg_path)?;
-    let stdout = io::stdout();
-    let handle = stdout.lock();
-    render_to_writer(
-        &cfg,
-        &lib,
-        handle,
-        profiles,
-        separator,
-        pre_prompt,
-        post_prompt,
-        json,
-    )
-}
+        let cfg = Config {
+            profiles: HashMap::from([
+                ("team.b".into(), vec![]),
+                ("team.a".into(), vec![]),
+                ("other".into(), vec![]),
+            ]),
+            ..Config::default()
+        };
+        let expanded = expand_profiles_glob(&cfg, &["team.b".into()], &["team.*".into()]).unwrap();
+        assert_eq!(expanded, ["team.b", "team.a"]);
+        let err = expand_profiles_glob(&cfg, &[], &["nope*".into()]).unwrap_err();
+        assert_eq!(err, "No profiles match --profiles-glob 'nope*'");
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn test_splice_profiles() {
+        let args = [
+            "prompter", "run", "a", "c", "--before", "a", "first", "--after", "a", "b",
+        ];
+        let AppMode::Run {
+            profiles, splices, ..
+        } = parse_args_from(args.iter().map(|s| (*s).to_string()).collect()).unwrap()
+        else {
+            panic!("expected run mode");
+        };
+        assert_eq!(
+            splice_profiles(profiles.clone(), &splices).unwrap(),
+            ["first", "a", "b", "c"]
+        );
 
-    fn mk_tmp(prefix: &str) -> PathBuf {
-        let mut p = env::temp_dir();
-        let unique = format!(
-            "{}_{}_{}",
-            prefix,
-            std::process::id(),
-            std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap()
-                .as_nanos()
+        let missing = Splice {
+            anchor: "z".into(),
+            profile: "b".into(),
+            before: false,
+        };
+        assert_eq!(
+            splice_profiles(profiles, &[missing]).unwrap_err(),
+            "--after anchor 'z' is not among the requested profiles"
         );
-        p.push(unique);
-        p
     }
 
     #[test]
-    fn test_unescape() {
-        assert_eq!(unescape("a\\nb\\t\\\"\\\\c"), "a\nb\t\"\\c");
-        assert_eq!(unescape("line1\\rline2"), "line1\rline2");
-        assert_eq!(unescape("noesc"), "noesc");
-    }
+    fn test_wrap_lines() {
+        assert_eq!(
+            wrap_lines("one two three four\n  - indented item here\n", 10),
+            "one two\nthree four\n  -\n  indented\n  item\n  here\n"
+        );
+        assert_eq!(
+            wrap_lines("unbreakablewordhere x", 5),
+            "unbreakablewordhere\nx"
+        );
+        assert_eq!(wrap_lines("a  b\tc   d e\n", 7), "a  b\tc\nd e\n");
+        assert_eq!(
+            wrap_lines("\tkey:  value  more\n", 12),
+            "\tkey:  value\n\tmore\n"
+        );
+        let fenced = "```\nlet long_line = \"stays on one line\";\n```\nshort\n";
+        assert_eq!(wrap_lines(fenced, 8), fenced);
 
-    #[test]
-    fn test_strip_comments_and_brackets_detection() {
-        let s = r"ab#cd";
-        assert_eq!(strip_comments(s), "ab");
-        let s = r#""ab#cd" # trailing"#;
-        assert_eq!(strip_comments(s), "\"ab#cd\" ");
-        assert!(contains_closing_bracket_outside_quotes("[\"not]here\"]]"));
-        assert!(!contains_closing_bracket_outside_quotes("[\"no]close\""));
+        let lib = mk_tmp("prompter_wrap");
+        fs::create_dir_all(&lib).unwrap();
+        fs::write(lib.join("a.md"), b"alpha beta gamma\n").unwrap();
+        let cfg = Config {
+            profiles: HashMap::from([("p".into(), vec!["a.md".into()])]),
+            ..Config::default()
+        };
+        let opts = RenderOptions {
+            pre_prompt: Some(String::new()),
+            wrap: 11,
+            ..RenderOptions::default()
+        };
+        let mut out = Vec::new();
+        super::render_to_writer(&cfg, &lib, &mut out, &["p".to_string()], &opts).unwrap();
+        assert!(
+            String::from_utf8(out)
+                .unwrap()
+                .contains("alpha beta\ngamma\n")
+        );
     }
 
     #[test]
-    fn test_parse_array_items_escape_and_error() {
-        let s = r#"["a\"b", "c"]"#;
-        let items = parse_array_items(s).unwrap();
-        assert_eq!(items, vec!["a\"b", "c"]);
-        let err = parse_array_items("[\"unterminated").unwrap_err();
-        assert!(err.contains("Unterminated"));
+    fn test_line_endings() {
+        assert_eq!(LineEndings::Lf.apply("a\r\nb\nc"), "a\nb\nc");
+        assert_eq!(LineEndings::Crlf.apply("a\r\nb\nc"), "a\r\nb\r\nc");
+
+        let lib = mk_tmp("prompter_line_endings");
+        fs::create_dir_all(&lib).unwrap();
+        fs::write(lib.join("a.md"), b"one\r\ntwo\r\n").unwrap();
+        let cfg = Config {
+            profiles: HashMap::from([("p".into(), vec!["a.md".into()])]),
+            ..Config::default()
+        };
+        let render = |line_endings| {
+            let opts = RenderOptions {
+                pre_prompt: Some("PRE\n".into()),
+                post_prompt: Some(String::new()),
+                line_endings,
+                ..RenderOptions::default()
+            };
+            let mut out = Vec::new();
+            super::render_to_writer(&cfg, &lib, &mut out, &["p".to_string()], &opts).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        let lf = render(LineEndings::Lf);
+        assert!(lf.contains("one\ntwo\n") && !lf.contains('\r'));
+        let crlf = render(LineEndings::Crlf);
+        assert_eq!(crlf, lf.replace('\n', "\r\n"));
     }
 
     #[test]
-    fn test_parse_config_errors() {
-        let err = parse_config_toml("[]\n").unwrap_err();
-        assert!(err.contains("Empty section name"));
-        let err = parse_config_toml("[p]\ndepends_on = \"x\"\n").unwrap_err();
-        assert!(err.contains("must be an array"));
-        let err = parse_config_toml("depends_on = [\"a.md\"]\n").unwrap_err();
-        assert!(err.contains("outside of a profile section"));
+    fn test_duplicate_dependencies() {
+        let cfg = Config {
+            profiles: HashMap::from([
+                (
+                    "b".into(),
+                    vec!["x.md".into(), "a".into(), "x.md".into(), "x.md".into()],
+                ),
+                ("a".into(), vec!["y.md".into()]),
+            ]),
+            ..Config::default()
+        };
+        assert_eq!(
+            duplicate_dependencies(&cfg),
+            vec!["Duplicate dependency: x.md (in [b])"]
+        );
     }
 
     #[test]
-    fn test_validate_success_and_unknowns() {
+    fn test_validate_max_depth() {
+        let lib = mk_tmp("prompter_max_depth");
+        fs::create_dir_all(&lib).unwrap();
+        fs::write(lib.join("leaf.md"), b"x").unwrap();
         let cfg = Config {
             profiles: HashMap::from([
-                ("p1".into(), vec!["a.md".into()]),
-                ("p2".into(), vec!["p1".into(), "b.md".into()]),
+                ("base".into(), vec!["leaf.md".into()]),
+                ("mid".into(), vec!["base".into()]),
+                ("top".into(), vec!["mid".into(), "leaf.md".into()]),
             ]),
-            post_prompt: None,
+            ..Config::default()
         };
-        let lib = mk_tmp("prompter_validate_ok");
-        fs::create_dir_all(&lib).unwrap();
-        fs::write(lib.join("a.md"), b"A").unwrap();
-        fs::write(lib.join("b.md"), b"B").unwrap();
-        assert!(validate(&cfg, &lib).is_ok());
-        let cfg2 = Config {
-            profiles: HashMap::from([("root".into(), vec!["nope".into()])]),
-            post_prompt: None,
+        assert!(validate(&cfg, &lib, &ValidateOptions::default()).is_ok());
+        let opts = ValidateOptions {
+            max_depth: Some(2),
+            ..ValidateOptions::default()
         };
-        let err = validate(&cfg2, &lib).unwrap_err();
-        assert!(err.contains("Unknown profile"));
+        assert!(validate(&cfg, &lib, &opts).is_ok());
+
+        let opts = ValidateOptions {
+            max_depth: Some(1),
+            ..ValidateOptions::default()
+        };
+        let err = validate(&cfg, &lib, &opts).unwrap_err();
+        assert_eq!(err, "Include depth 2 exceeds maximum 1: top -> mid -> base");
     }
 
     #[test]
-    fn test_resolve_errors_and_dedup() {
+    fn test_optional_includes_skip_missing_targets() {
+        let lib = mk_tmp("prompter_optional");
+        fs::create_dir_all(&lib).unwrap();
+        fs::write(lib.join("a.md"), b"A\n").unwrap();
+        fs::write(lib.join("local.md"), b"L\n").unwrap();
         let cfg = Config {
-            profiles: HashMap::from([("root".into(), vec!["missing.md".into()])]),
-            post_prompt: None,
+            profiles: HashMap::from([
+                ("extra".into(), vec!["local.md".into()]),
+                (
+                    "p".into(),
+                    vec![
+                        "a.md".into(),
+                        "?gone.md".into(),
+                        "?nobody".into(),
+                        "?extra".into(),
+                    ],
+                ),
+            ]),
+            ..Config::default()
         };
-        let lib = mk_tmp("prompter_resolve_errs");
-        fs::create_dir_all(&lib).unwrap();
-        let mut seen = HashSet::new();
-        let mut stack = Vec::new();
         let mut out = Vec::new();
-        let err = resolve_profile("root", &cfg, &lib, &mut seen, &mut stack, &mut out).unwrap_err();
-        match err {
-            ResolveError::MissingFile(_, p) => assert_eq!(p, "root"),
-            _ => panic!("expected missing file"),
-        }
+        render_to_writer(
+            &cfg,
+            &lib,
+            &mut out,
+            &["p".into()],
+            &RenderOptions::default(),
+        )
+        .unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("A\n") && text.contains("L\n"));
 
-        let cfg2 = Config {
-            profiles: HashMap::from([
-                ("A".into(), vec!["a/b.md".into()]),
-                ("B".into(), vec!["A".into(), "a/b.md".into()]),
-            ]),
-            post_prompt: None,
+        let reports = validate_profiles(&cfg, &lib, &ValidateOptions::default());
+        assert!(reports.iter().all(|r| r.errors.is_empty()));
+        assert_eq!(reports[1].notes.len(), 2);
+        assert!(reports[1].notes[1].contains("profile: nobody (referenced by [p])"));
+
+        let strict = RenderOptions {
+            strict_includes: true,
+            ..RenderOptions::default()
         };
-        fs::create_dir_all(lib.join("a")).unwrap();
-        fs::write(lib.join("a/b.md"), b"X").unwrap();
+        let err = render_to_writer(&cfg, &lib, &mut Vec::new(), &["p".into()], &strict);
+        assert!(err.unwrap_err().error.starts_with("Missing file"));
+        let strict = ValidateOptions {
+            strict_includes: true,
+            ..ValidateOptions::default()
+        };
+        let reports = validate_profiles(&cfg, &lib, &strict);
+        assert_eq!(
+            reports[1].errors,
+            [
+                format!(
+                    "Missing file: {} (referenced by [p])",
+                    lib.join("gone.md").display()
+                ),
+                "Unknown profile: nobody (referenced by [p])".to_string(),
+            ]
+        );
+        assert!(reports[1].notes.is_empty());
+
+        let tree = build_tree_node("p", None, &cfg);
+        let optional: Vec<(&str, bool)> = tree
+            .children
+            .iter()
+            .map(|c| (c.name.as_str(), c.optional))
+            .collect();
+        assert_eq!(
+            optional,
+            [
+                ("a.md", false),
+                ("gone.md", true),
+                ("nobody", true),
+                ("extra", true)
+            ]
+        );
+        assert_eq!(tree.children[3].children.len(), 1);
+    }
+
+    #[test]
+    fn test_review_warnings() {
+        let cfg = parse_config_toml(
+            r#"
+[fresh]
+depends_on = []
+review_after = "2026-06-01"
+
+[stale]
+depends_on = []
+review_after = "2026-01-31"
+
+[described]
+description = "Has metadata"
+depends_on = []
+
+[bare]
+depends_on = []
+"#,
+        )
+        .unwrap();
+        let today = NaiveDate::from_ymd_opt(2026, 3, 1).unwrap();
+        let warnings: Vec<Option<String>> = ["fresh", "stale", "described", "bare"]
+            .iter()
+            .map(|name| review_warning(&cfg, name, today))
+            .collect();
+        assert_eq!(
+            warnings,
+            [
+                None,
+                Some("Profile [stale] was due for review on 2026-01-31".into()),
+                None,
+                Some("Profile [bare] has no metadata (description, tags, or review_after)".into()),
+            ]
+        );
+        let err = parse_config_toml("[p]\ndepends_on = []\nreview_after = \"soon\"\n").unwrap_err();
+        assert!(err.contains("must be a YYYY-MM-DD date"), "{err}");
+    }
+
+    #[test]
+    fn test_remote_allowed_hosts() {
+        let cfg = parse_config_toml(
+            r#"
+allowed_hosts = ["ignored.test"]
+
+[remote]
+allowed_hosts = [
+  "example.com",
+  "docs.example.com",
+]
+depends_on = ["a.md"]
+
+[p]
+depends_on = ["https://evil.test/a.md"]
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            cfg.allowed_hosts.as_deref(),
+            Some(&["example.com".to_string(), "docs.example.com".to_string()][..])
+        );
+        assert!(!cfg.profiles.contains_key("remote"));
         let mut seen = HashSet::new();
-        let mut stack = Vec::new();
+        let err = resolve_profile(
+            "p",
+            &cfg,
+            Path::new("."),
+            &mut seen,
+            &mut vec![],
+            &mut vec![],
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Blocked remote include https://evil.test/a.md: host 'evil.test' is not in \
+             [remote] allowed_hosts (referenced by [p])"
+        );
+        let optional = parse_config_toml(
+            "[remote]\nallowed_hosts = []\n[p]\ndepends_on = [\"?https://x.test/a.md\"]\n",
+        )
+        .unwrap();
+        assert!(
+            resolve_profile(
+                "p",
+                &optional,
+                Path::new("."),
+                &mut seen,
+                &mut vec![],
+                &mut vec![]
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_metadata_resolution_leaves_uncached_remote_includes_out() {
+        let lib = mk_tmp("prompter_remote_metadata");
+        fs::create_dir_all(&lib).unwrap();
+        fs::write(lib.join("a.md"), "A\n").unwrap();
+        let cfg =
+            parse_config_toml("[p]\ndepends_on = [\"https://uncached.invalid/a.md\", \"a.md\"]\n")
+                .unwrap();
         let mut out = Vec::new();
-        resolve_profile("B", &cfg2, &lib, &mut seen, &mut stack, &mut out).unwrap();
-        assert_eq!(out.len(), 1);
+        resolve_profile("p", &cfg, &lib, &mut HashSet::new(), &mut vec![], &mut out).unwrap();
+        assert_eq!(out, [lib.join("a.md")]);
+        assert_eq!(profile_body_bytes("p", &cfg, &lib), Ok(2));
+        let report = validate_profiles(&cfg, &lib, &ValidateOptions::default());
+        assert!(report[0].errors.is_empty(), "{:?}", report[0].errors);
     }
 
     #[test]
-    fn test_parse_args_errors() {
-        // unknown flag
-        let args = vec!["prompter".into(), "--bogus".into()];
-        let err = parse_args_from(args).unwrap_err();
-        assert!(err.contains("unexpected argument"));
-        // missing required subcommand
-        let args = vec!["prompter".into()];
-        let err = parse_args_from(args).unwrap_err();
-        assert!(err.contains("Usage:") || err.contains("COMMAND"));
+    fn test_scenarios() {
+        let cfg = parse_config_toml(
+            r#"
+[base]
+depends_on = []
+
+[scenarios.code-review]
+profiles = [
+  "base",
+  "missing",
+]
+separator = "\n--\n"
+pre_prompt = "Review this."
+"#,
+        )
+        .unwrap();
+        assert!(!cfg.profiles.contains_key("scenarios.code-review"));
+        assert!(cfg.post_prompt.is_none());
+        let scenario = &cfg.scenarios["code-review"];
+        assert_eq!(scenario.profiles, ["base", "missing"]);
+        assert_eq!(scenario.separator.as_deref(), Some("\n--\n"));
+
+        let opts = RenderOptions {
+            separator: Some("==".into()),
+            ..RenderOptions::default()
+        };
+        let (profiles, merged) =
+            apply_scenario(&cfg, "code-review", &["extra".into()], &opts).unwrap();
+        assert_eq!(profiles, ["base", "missing", "extra"]);
+        assert_eq!(merged.separator.as_deref(), Some("=="));
+        assert_eq!(merged.pre_prompt.as_deref(), Some("Review this."));
+        assert!(apply_scenario(&cfg, "nope", &[], &opts).is_err());
+        let opts = RenderOptions {
+            pre_prompt_files: vec!["preamble.md".into()],
+            ..RenderOptions::default()
+        };
+        let (_, merged) = apply_scenario(&cfg, "code-review", &[], &opts).unwrap();
+        assert_eq!(merged.pre_prompt, None);
+
+        let lib = mk_tmp("prompter_scenarios");
+        let reports = validate_profiles(&cfg, &lib, &ValidateOptions::default());
+        let errors: Vec<(&str, &[String])> = reports
+            .iter()
+            .map(|r| (r.profile.as_str(), r.errors.as_slice()))
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                ("base", &[][..]),
+                (
+                    "scenarios.code-review",
+                    &["Unknown profile: missing (referenced by [scenarios.code-review])".into()][..]
+                ),
+            ]
+        );
+        assert!(parse_config_toml("[scenarios.]\nprofiles = []\n").is_err());
     }
 
     #[test]
-    fn test_list_profiles_order() {
-        let cfg = Config {
-            profiles: HashMap::from([("b".into(), vec![]), ("a".into(), vec![])]),
-            post_prompt: None,
+    fn test_expand_env() {
+        let lookup = |name: &str| match name {
+            "ROOT" => Some("/srv/prompts".to_string()),
+            "EMPTY" => Some(String::new()),
+            _ => None,
         };
-        let lib = mk_tmp("prompter_list_order");
+        assert_eq!(
+            expand_env("${ROOT}/lib", lookup).unwrap(),
+            "/srv/prompts/lib"
+        );
+        assert_eq!(expand_env("${NOPE:-../lib}/x", lookup).unwrap(), "../lib/x");
+        assert_eq!(expand_env("${EMPTY:-d}${EMPTY}", lookup).unwrap(), "d");
+        assert_eq!(
+            expand_env("$${ROOT} $HOME", lookup).unwrap(),
+            "${ROOT} $HOME"
+        );
+        assert_eq!(
+            expand_env("${NOPE}/x", lookup).unwrap_err(),
+            "Environment variable NOPE is not set"
+        );
+        assert!(
+            expand_env("${ROOT", lookup)
+                .unwrap_err()
+                .contains("Unclosed")
+        );
+
+        let mut cfg = parse_config_layer(
+            "include_base = \"${ROOT}/lib\"\n[p]\ndepends_on = [\"@${ROOT}/notes.md\", \"a.md\"]\n",
+        )
+        .unwrap();
+        expand_config_env(&mut cfg, lookup).unwrap();
+        assert_eq!(cfg.include_base, Some(PathBuf::from("/srv/prompts/lib")));
+        assert_eq!(cfg.profiles["p"], ["@/srv/prompts/notes.md", "a.md"]);
+        let mut cfg = parse_config_layer("relative_to = \"${NOPE}\"\n").unwrap();
+        let err = expand_config_env(&mut cfg, lookup).unwrap_err();
+        assert_eq!(err, "Environment variable NOPE is not set (in relative_to)");
+    }
+
+    #[test]
+    fn test_case_insensitive_profiles() {
+        let lib = mk_tmp("prompter_case_insensitive");
         fs::create_dir_all(&lib).unwrap();
+        fs::write(lib.join("api.md"), "API\n").unwrap();
+        fs::write(lib.join("base.md"), "BASE\n").unwrap();
+        let toml = r#"
+case_insensitive_profiles = true
+
+[Shared]
+depends_on = ["base.md"]
+
+[Backend/API]
+depends_on = ["shared", "?MISSING", "api.md"]
+
+[child]
+extends = "backend/api"
+depends_on = ["SHARED => shared"]
+
+[scenarios.review]
+profiles = ["BACKEND/api"]
+"#;
+        let cfg = parse_config_toml(toml).unwrap();
+        assert_eq!(
+            cfg.profiles["Backend/API"],
+            ["Shared", "?MISSING", "api.md"]
+        );
+        assert_eq!(cfg.profiles["child"], ["Shared", "?MISSING", "api.md"]);
+        assert_eq!(cfg.scenarios["review"].profiles, ["Backend/API"]);
+        assert_eq!(cfg.profile_name("backend/api"), "Backend/API");
+        assert_eq!(cfg.profile_name("nope"), "nope");
+
         let mut out = Vec::new();
-        super::list_profiles(&cfg, &lib, false, &mut out).unwrap();
-        assert_eq!(String::from_utf8(out).unwrap(), "a\nb\n");
+        render_to_writer(
+            &cfg,
+            &lib,
+            &mut out,
+            &["BACKEND/API".into()],
+            &RenderOptions::default(),
+        )
+        .unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("BASE") && out.contains("API"), "{out}");
+
+        let sensitive = parse_config_toml(&toml.replace("= true", "= false")).unwrap_err();
+        assert_eq!(sensitive, "[child] extends unknown profile 'backend/api'");
+        let err = parse_config_toml(
+            "case_insensitive_profiles = true\n[a]\ndepends_on = []\n[A]\ndepends_on = []\n",
+        )
+        .unwrap_err();
+        assert!(err.contains("'A' and 'a' differ only in case"), "{err}");
+        assert!(parse_config_toml("case_insensitive_profiles = yes\n").is_err());
     }
 
     #[test]
-    fn test_validate_cycle_detected() {
+    fn test_validate_junit_report() {
+        let lib = mk_tmp("prompter_junit");
+        fs::create_dir_all(&lib).unwrap();
+        fs::write(lib.join("ok.md"), b"x").unwrap();
         let cfg = Config {
             profiles: HashMap::from([
-                ("A".into(), vec!["B".into()]),
-                ("B".into(), vec!["A".into()]),
+                ("good".into(), vec!["ok.md".into()]),
+                ("bad<1>".into(), vec!["gone.md".into(), "nope".into()]),
             ]),
-            post_prompt: None,
+            ..Config::default()
         };
-        let lib = mk_tmp("prompter_cycle");
-        fs::create_dir_all(&lib).unwrap();
-        let err = validate(&cfg, &lib).unwrap_err();
-        assert!(err.contains("Cycle detected"));
+        let reports = validate_profiles(&cfg, &lib, &ValidateOptions::default());
+        assert_eq!(
+            reports.iter().map(|r| r.errors.len()).collect::<Vec<_>>(),
+            [2, 0]
+        );
+
+        let mut out = Vec::new();
+        write_junit("cfg & co", &reports, &mut out).unwrap();
+        let xml = String::from_utf8(out).unwrap();
+        assert!(xml.contains(r#"<testsuite name="cfg &amp; co" tests="2" failures="1">"#));
+        assert!(xml.contains(r#"<testcase classname="prompter.validate" name="good"/>"#));
+        assert!(xml.contains(r#"name="bad&lt;1&gt;">"#));
+        assert!(xml.contains("Unknown profile: nope (referenced by [bad&lt;1&gt;])</failure>"));
     }
 
     #[test]
@@ -1496,6 +8338,7 @@ depends_on = [
                 ),
             ]),
             post_prompt: None,
+            ..Config::default()
         };
         let mut out = Vec::new();
         super::render_to_writer(
@@ -1503,10 +8346,10 @@ depends_on = [
             &lib,
             &mut out,
             &["root".to_string()],
-            Some("\n--\n"),
-            None,
-            None,
-            false,
+            &RenderOptions {
+                separator: Some("\n--\n".to_string()),
+                ..RenderOptions::default()
+            },
         )
         .unwrap();
 
@@ -1537,6 +8380,7 @@ depends_on = [
         let cfg = Config {
             profiles: HashMap::from([("test".into(), vec!["a/x.md".into()])]),
             post_prompt: None,
+            ..Config::default()
         };
         let mut out = Vec::new();
         super::render_to_writer(
@@ -1544,10 +8388,10 @@ depends_on = [
             &lib,
             &mut out,
             &["test".to_string()],
-            None,
-            Some("Custom pre-prompt\n\n"),
-            None,
-            false,
+            &RenderOptions {
+                pre_prompt: Some("Custom pre-prompt\n\n".to_string()),
+                ..RenderOptions::default()
+            },
         )
         .unwrap();
 
@@ -1574,6 +8418,7 @@ depends_on = [
         let cfg = Config {
             profiles: HashMap::from([("test".into(), vec!["a/x.md".into()])]),
             post_prompt: Some("Custom config post-prompt".to_string()),
+            ..Config::default()
         };
         let mut out = Vec::new();
         super::render_to_writer(
@@ -1581,10 +8426,7 @@ depends_on = [
             &lib,
             &mut out,
             &["test".to_string()],
-            None,
-            None,
-            None,
-            false,
+            &RenderOptions::default(),
         )
         .unwrap();
 
@@ -1599,10 +8441,10 @@ depends_on = [
             &lib,
             &mut out2,
             &["test".to_string()],
-            None,
-            None,
-            Some("CLI post-prompt"),
-            false,
+            &RenderOptions {
+                post_prompt: Some("CLI post-prompt".to_string()),
+                ..RenderOptions::default()
+            },
         )
         .unwrap();
 
@@ -1636,6 +8478,7 @@ depends_on = [
                 ),
             ]),
             post_prompt: None,
+            ..Config::default()
         };
 
         // Render both profiles together
@@ -1645,10 +8488,10 @@ depends_on = [
             &lib,
             &mut out,
             &["profile_a".to_string(), "profile_b".to_string()],
-            Some("\n---\n"),
-            None,
-            None,
-            false,
+            &RenderOptions {
+                separator: Some("\n---\n".to_string()),
+                ..RenderOptions::default()
+            },
         )
         .unwrap();
 
@@ -1677,6 +8520,369 @@ depends_on = [
         assert!(a_pos < b_pos, "A_SPECIFIC should come before B_SPECIFIC");
     }
 
+    #[test]
+    fn test_render_to_writer_chat_format() {
+        let lib = mk_tmp("prompter_render_chat");
+        fs::create_dir_all(&lib).unwrap();
+        fs::write(lib.join("x.md"), b"BODY\n").unwrap();
+        let cfg = Config {
+            profiles: HashMap::from([("p".into(), vec!["x.md".into()])]),
+            post_prompt: Some("POST".into()),
+            ..Config::default()
+        };
+
+        let mut out = Vec::new();
+        let opts = RenderOptions {
+            pre_prompt: Some("PRE".into()),
+            format: RenderFormat::Chat,
+            ..RenderOptions::default()
+        };
+        super::render_to_writer(&cfg, &lib, &mut out, &["p".to_string()], &opts).unwrap();
+        let messages: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(messages[0]["role"], "system");
+        assert_eq!(messages[0]["content"], "PRE");
+        assert_eq!(messages[1]["role"], "user");
+        let body = messages[1]["content"].as_str().unwrap();
+        assert!(body.starts_with("Today is "));
+        assert!(body.contains("BODY\n"));
+        assert!(body.ends_with("POST"));
+
+        // Custom roles, and an empty pre-prompt drops the first message
+        let mut out = Vec::new();
+        let opts = RenderOptions {
+            pre_prompt: Some(String::new()),
+            format: RenderFormat::Chat,
+            chat_roles: ChatRoles {
+                pre_prompt: "developer".into(),
+                body: "assistant".into(),
+            },
+            ..RenderOptions::default()
+        };
+        super::render_to_writer(&cfg, &lib, &mut out, &["p".to_string()], &opts).unwrap();
+        let messages: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(messages.as_array().unwrap().len(), 1);
+        assert_eq!(messages[0]["role"], "assistant");
+    }
+
+    #[test]
+    fn test_render_exclude_and_include_only() {
+        let lib = mk_tmp("prompter_render_filters");
+        fs::create_dir_all(&lib).unwrap();
+        for f in ["base", "heavy", "extra", "own"] {
+            fs::write(lib.join(format!("{f}.md")), f.to_uppercase()).unwrap();
+        }
+        let cfg = Config {
+            profiles: HashMap::from([
+                ("heavy".into(), vec!["heavy.md".into()]),
+                ("mid".into(), vec!["base.md".into(), "heavy".into()]),
+                ("extra".into(), vec!["extra.md".into()]),
+                (
+                    "root".into(),
+                    vec!["mid".into(), "extra".into(), "own.md".into()],
+                ),
+            ]),
+            post_prompt: None,
+            ..Config::default()
+        };
+        let render = |opts: &RenderOptions| {
+            let mut out = Vec::new();
+            super::render_to_writer(&cfg, &lib, &mut out, &["root".to_string()], opts).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        let out = render(&RenderOptions {
+            exclude: vec!["heavy".into(), "not-in-tree".into()],
+            ..RenderOptions::default()
+        });
+        assert!(out.contains("BASE") && out.contains("EXTRA") && out.contains("OWN"));
+        assert!(!out.contains("HEAVY"));
+
+        let out = render(&RenderOptions {
+            include_only: vec!["mid".into()],
+            ..RenderOptions::default()
+        });
+        assert!(out.contains("BASE") && out.contains("HEAVY"));
+        assert!(!out.contains("EXTRA") && !out.contains("OWN"));
+
+        let out = render(&RenderOptions {
+            include_only: vec!["mid".into()],
+            exclude: vec!["heavy".into()],
+            ..RenderOptions::default()
+        });
+        assert!(out.contains("BASE"));
+        assert!(!out.contains("HEAVY"));
+
+        let out = render(&RenderOptions {
+            comment_out: vec!["mid".into()],
+            ..RenderOptions::default()
+        });
+        assert!(out.contains("<!-- omitted: mid -->\n\nEXTRA"), "{out}");
+        assert!(!out.contains("BASE") && !out.contains("HEAVY"));
+    }
+
+    #[test]
+    fn test_render_on_missing_profile() {
+        let lib = mk_tmp("prompter_on_missing");
+        fs::create_dir_all(&lib).unwrap();
+        fs::write(lib.join("a.md"), "A\n").unwrap();
+        let cfg = Config {
+            profiles: HashMap::from([
+                ("a".into(), vec!["a.md".into()]),
+                ("broken".into(), vec!["gone".into()]),
+            ]),
+            ..Config::default()
+        };
+        let render = |profiles: &[&str], on_missing| {
+            let opts = RenderOptions {
+                on_missing,
+                ..RenderOptions::default()
+            };
+            let profiles: Vec<String> = profiles.iter().map(ToString::to_string).collect();
+            let mut out = Vec::new();
+            render_to_writer(&cfg, &lib, &mut out, &profiles, &opts)
+                .map(|()| String::from_utf8(out).unwrap())
+        };
+
+        let err = render(&["nope", "a"], MissingProfilePolicy::Error).unwrap_err();
+        assert_eq!(err.error, "Unknown profile: nope");
+        let out = render(&["nope", "a"], MissingProfilePolicy::Skip).unwrap();
+        assert!(out.contains("A\n") && !out.contains("nope"), "{out}");
+        let out = render(&["nope", "a", "nope"], MissingProfilePolicy::Placeholder).unwrap();
+        assert!(out.contains("<!-- missing: nope -->\n\nA\n"), "{out}");
+        assert_eq!(out.matches("missing: nope").count(), 1);
+        // Only requested profiles are covered; broken includes still fail
+        assert!(render(&["broken"], MissingProfilePolicy::Skip).is_err());
+    }
+
+    #[test]
+    fn test_render_sort_includes() {
+        let lib = mk_tmp("prompter_sort_includes");
+        fs::create_dir_all(&lib).unwrap();
+        for f in ["a", "b", "c", "d"] {
+            fs::write(
+                lib.join(format!("{f}.md")),
+                format!("{}\n", f.to_uppercase()),
+            )
+            .unwrap();
+        }
+        let cfg = Config {
+            profiles: HashMap::from([
+                ("inner".into(), vec!["d.md".into(), "b.md".into()]),
+                (
+                    "root".into(),
+                    vec!["c.md".into(), "inner".into(), "a.md".into()],
+                ),
+            ]),
+            post_prompt: None,
+            ..Config::default()
+        };
+        let order = |sort_includes: bool| {
+            let opts = RenderOptions {
+                sort_includes,
+                ..RenderOptions::default()
+            };
+            let mut out = Vec::new();
+            render_to_writer(&cfg, &lib, &mut out, &["root".into()], &opts).unwrap();
+            String::from_utf8(out)
+                .unwrap()
+                .lines()
+                .filter(|l| l.len() == 1)
+                .collect::<String>()
+        };
+        assert_eq!(order(false), "CDBA");
+        assert_eq!(order(true), "ACBD");
+    }
+
+    #[test]
+    fn test_render_shuffle_includes() {
+        let lib = mk_tmp("prompter_shuffle_includes");
+        fs::create_dir_all(&lib).unwrap();
+        for f in ["a", "b", "c", "d", "e"] {
+            fs::write(lib.join(format!("{f}.md")), f.to_uppercase()).unwrap();
+        }
+        let deps = ["a.md", "b.md", "c.md", "d.md", "e.md"];
+        let cfg = Config {
+            profiles: HashMap::from([("root".into(), deps.map(String::from).to_vec())]),
+            ..Config::default()
+        };
+        let order = |shuffle_seed: Option<u64>| {
+            let opts = RenderOptions {
+                shuffle_seed,
+                ..RenderOptions::default()
+            };
+            let mut out = Vec::new();
+            render_to_writer(&cfg, &lib, &mut out, &["root".into()], &opts).unwrap();
+            String::from_utf8(out)
+                .unwrap()
+                .lines()
+                .filter(|l| l.len() == 1)
+                .collect::<String>()
+        };
+        assert_eq!(order(None), "ABCDE");
+        let shuffled: Vec<String> = (0..8).map(|seed| order(Some(seed))).collect();
+        assert_eq!(
+            shuffled,
+            (0..8).map(|seed| order(Some(seed))).collect::<Vec<_>>()
+        );
+        assert!(shuffled.iter().any(|o| o != "ABCDE"));
+        for o in &shuffled {
+            let mut letters: Vec<char> = o.chars().collect();
+            letters.sort_unstable();
+            assert_eq!(letters.into_iter().collect::<String>(), "ABCDE");
+        }
+    }
+
+    #[test]
+    fn test_separator_between() {
+        let lib = mk_tmp("prompter_separator_between");
+        fs::create_dir_all(&lib).unwrap();
+        for f in ["a", "b", "c"] {
+            fs::write(lib.join(format!("{f}.md")), f.to_uppercase()).unwrap();
+        }
+        let cfg = Config {
+            profiles: HashMap::from([
+                ("inner".into(), vec!["b.md".into()]),
+                ("one".into(), vec!["a.md".into(), "inner".into()]),
+                ("two".into(), vec!["c.md".into()]),
+            ]),
+            post_prompt: None,
+            ..Config::default()
+        };
+        let body = |separator_between| {
+            let opts = RenderOptions {
+                separator: Some("|".into()),
+                separator_between,
+                pre_separator: Some(String::new()),
+                post_separator: Some(String::new()),
+                ..RenderOptions::default()
+            };
+            let mut out = Vec::new();
+            render_to_writer(&cfg, &lib, &mut out, &["one".into(), "two".into()], &opts).unwrap();
+            let out = String::from_utf8(out).unwrap();
+            out.lines()
+                .filter(|l| l.starts_with(['A', 'B', 'C', '|']))
+                .collect::<String>()
+        };
+        assert_eq!(body(SeparatorPlacement::Both), "A|B|C|");
+        assert_eq!(body(SeparatorPlacement::Profiles), "AB|C|");
+        assert_eq!(body(SeparatorPlacement::Includes), "A|BC");
+    }
+
+    #[test]
+    fn test_pre_and_post_separators() {
+        let lib = mk_tmp("prompter_pre_post_separator");
+        fs::create_dir_all(&lib).unwrap();
+        fs::write(lib.join("a.md"), "A\n").unwrap();
+        fs::write(lib.join("b.md"), "B\n").unwrap();
+        let cfg = Config {
+            profiles: HashMap::from([("p".into(), vec!["a.md".into(), "b.md".into()])]),
+            post_prompt: Some("POST".into()),
+            ..Config::default()
+        };
+        let render = |format, edges: Option<(&str, &str)>| {
+            let opts = RenderOptions {
+                separator: Some("|\n".into()),
+                pre_separator: edges.map(|(pre, _)| pre.into()),
+                post_separator: edges.map(|(_, post)| post.into()),
+                pre_prompt: Some("PRE\n".into()),
+                no_system_info: true,
+                format,
+                ..RenderOptions::default()
+            };
+            let mut out = Vec::new();
+            render_to_writer(&cfg, &lib, &mut out, &["p".into()], &opts).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        let edges = Some(("<<<\n", ">>>"));
+        assert_eq!(
+            render(RenderFormat::Text, edges),
+            "PRE\n<<<\n\nA\n|\n\nB\n>>>\n\nPOST"
+        );
+        let chat = render(RenderFormat::Chat, edges);
+        assert!(chat.contains(r"<<<\n\nA\n|\n\nB\n>>>\n\nPOST"), "{chat}");
+
+        // Unset, both fall back to --separator, which is then not repeated
+        // after the last file
+        assert_eq!(
+            render(RenderFormat::Text, None),
+            "PRE\n|\n\nA\n|\n\nB\n|\n\n\nPOST"
+        );
+        assert_eq!(
+            render(RenderFormat::Text, Some(("", ""))),
+            "PRE\n\nA\n|\n\nB\n|\n\n\nPOST"
+        );
+    }
+
+    #[test]
+    fn test_strip_comment_lines() {
+        let src = "keep\n// note\n\\// literal\n```\n// code\n```\n//tail";
+        assert_eq!(
+            strip_comment_lines(src, "//"),
+            "keep\n// literal\n```\n// code\n```\n"
+        );
+        assert_eq!(strip_comment_lines(src, ""), src);
+        assert_eq!(strip_comment_lines("# c\nx\n", "#"), "x\n");
+    }
+
+    #[cfg(feature = "template-engine")]
+    #[test]
+    fn test_template_errors_report_lines_before_comment_stripping() {
+        let lib = mk_tmp("prompter_template_lines");
+        fs::create_dir_all(&lib).unwrap();
+        fs::write(lib.join("a.md"), "// one\n// two\nok\n{% if %}\n").unwrap();
+        let cfg = Config {
+            profiles: HashMap::from([("p".into(), vec!["a.md".into()])]),
+            ..Config::default()
+        };
+        let opts = RenderOptions {
+            template_engine: engine::TemplateEngine::Minijinja,
+            comment_prefix: Some("//".into()),
+            ..RenderOptions::default()
+        };
+        let err = render_to_writer(&cfg, &lib, &mut Vec::new(), &["p".into()], &opts)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("a.md:4: "), "{err}");
+    }
+
+    #[test]
+    fn test_render_strips_comments_with_configurable_prefix() {
+        let lib = mk_tmp("prompter_render_comments");
+        fs::create_dir_all(&lib).unwrap();
+        fs::write(lib.join("x.md"), b"VISIBLE\n// HIDDEN\n%% OTHER\n").unwrap();
+        let mut cfg = Config {
+            profiles: HashMap::from([("p".into(), vec!["x.md".into()])]),
+            ..Config::default()
+        };
+        let render = |cfg: &Config, opts: &RenderOptions| {
+            let mut out = Vec::new();
+            super::render_to_writer(cfg, &lib, &mut out, &["p".to_string()], opts).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        let out = render(&cfg, &RenderOptions::default());
+        assert!(out.contains("// HIDDEN") && out.contains("%% OTHER"));
+
+        let slashes = RenderOptions {
+            comment_prefix: Some("//".into()),
+            ..RenderOptions::default()
+        };
+        let out = render(&cfg, &slashes);
+        assert!(out.contains("VISIBLE") && out.contains("%% OTHER"));
+        assert!(!out.contains("HIDDEN"));
+
+        cfg.comment_prefix = Some("%%".into());
+        let out = render(&cfg, &RenderOptions::default());
+        assert!(out.contains("// HIDDEN") && !out.contains("OTHER"));
+
+        let opts = RenderOptions {
+            comment_prefix: Some(String::new()),
+            ..RenderOptions::default()
+        };
+        let out = render(&cfg, &opts);
+        assert!(out.contains("// HIDDEN") && out.contains("%% OTHER"));
+    }
+
     #[test]
     fn test_parse_config_with_post_prompt() {
         let cfg = r#"
@@ -1691,6 +8897,13 @@ depends_on = ["file.md"]
             Some("Custom post prompt from config".to_string())
         );
         assert_eq!(parsed.profiles.get("profile").unwrap().len(), 1);
+
+        let parsed = parse_config_toml("comment_prefix = \"%%\"\n").unwrap();
+        assert_eq!(parsed.comment_prefix, Some("%%".to_string()));
+        let err = parse_config_toml("comment_prefix = 3\n").unwrap_err();
+        assert!(err.contains("comment_prefix must be a string"));
+        let err = parse_config_toml("description = \"orphan\"\n").unwrap_err();
+        assert!(err.contains("description outside of a profile section"));
     }
 
     #[test]
@@ -1713,17 +8926,16 @@ depends_on = ["file.md"]
         match parse_args_from(args).unwrap() {
             AppMode::Run {
                 profiles,
-                separator,
-                pre_prompt,
-                post_prompt,
+                options,
                 config,
                 json,
+                ..
             } => {
                 assert_eq!(profiles, vec!["profile".to_string()]);
-                assert_eq!(separator, Some("\n--\n".into()));
-                assert_eq!(pre_prompt, None);
-                assert_eq!(post_prompt, None);
-                assert!(config.is_none());
+                assert_eq!(options.separator, Some("\n--\n".into()));
+                assert_eq!(options.pre_prompt, None);
+                assert_eq!(options.post_prompt, None);
+                assert_eq!(config, ConfigSource::Default);
                 assert!(!json);
             }
             _ => panic!("expected run"),
@@ -1739,17 +8951,16 @@ depends_on = ["file.md"]
         match parse_args_from(args).unwrap() {
             AppMode::Run {
                 profiles,
-                separator,
-                pre_prompt,
-                post_prompt,
+                options,
                 config,
                 json,
+                ..
             } => {
                 assert_eq!(profiles, vec!["profile".to_string()]);
-                assert_eq!(separator, None);
-                assert_eq!(pre_prompt, Some("Custom pre-prompt".into()));
-                assert_eq!(post_prompt, None);
-                assert!(config.is_none());
+                assert_eq!(options.separator, None);
+                assert_eq!(options.pre_prompt, Some("Custom pre-prompt".into()));
+                assert_eq!(options.post_prompt, None);
+                assert_eq!(config, ConfigSource::Default);
                 assert!(!json);
             }
             _ => panic!("expected run"),
@@ -1766,11 +8977,10 @@ depends_on = ["file.md"]
         match parse_args_from(args).unwrap() {
             AppMode::Run {
                 profiles,
-                separator,
-                pre_prompt,
-                post_prompt,
+                options,
                 config,
                 json,
+                ..
             } => {
                 assert_eq!(
                     profiles,
@@ -1780,33 +8990,224 @@ depends_on = ["file.md"]
                         "profile3.nested".to_string()
                     ]
                 );
-                assert_eq!(separator, None);
-                assert_eq!(pre_prompt, None);
-                assert_eq!(post_prompt, None);
-                assert!(config.is_none());
+                assert_eq!(options.separator, None);
+                assert_eq!(options.pre_prompt, None);
+                assert_eq!(options.post_prompt, None);
+                assert_eq!(config, ConfigSource::Default);
                 assert!(!json);
             }
             _ => panic!("expected run"),
         }
 
+        let args = vec![
+            "prompter".into(),
+            "run".into(),
+            "--format".into(),
+            "chat".into(),
+            "--pre-prompt-role".into(),
+            "developer".into(),
+            "profile".into(),
+        ];
+        match parse_args_from(args).unwrap() {
+            AppMode::Run { options, .. } => {
+                assert_eq!(options.format, RenderFormat::Chat);
+                assert_eq!(options.chat_roles.pre_prompt, "developer");
+                assert_eq!(options.chat_roles.body, "user");
+            }
+            other => panic!("unexpected mode: {other:?}"),
+        }
+        let args = vec!["prompter".into(), "--json".into(), "run".into(), "p".into()];
+        match parse_args_from(args).unwrap() {
+            AppMode::Run { options, .. } => assert_eq!(options.format, RenderFormat::Json),
+            other => panic!("unexpected mode: {other:?}"),
+        }
+
+        // --profile flags merge with positional profiles in command-line order
+        let args = vec![
+            "prompter".into(),
+            "run".into(),
+            "first".into(),
+            "--profile".into(),
+            "second".into(),
+            "third".into(),
+            "--profile".into(),
+            "fourth".into(),
+        ];
+        match parse_args_from(args).unwrap() {
+            AppMode::Run { profiles, .. } => {
+                assert_eq!(profiles, vec!["first", "second", "third", "fourth"]);
+            }
+            other => panic!("unexpected mode: {other:?}"),
+        }
+        let args = vec![
+            "prompter".into(),
+            "run".into(),
+            "--profile".into(),
+            "only".into(),
+        ];
+        match parse_args_from(args).unwrap() {
+            AppMode::Run { profiles, .. } => assert_eq!(profiles, vec!["only"]),
+            other => panic!("unexpected mode: {other:?}"),
+        }
+        // Without profiles, only a terminal session opens the picker.
+        let err = parse_args_from(vec![
+            "prompter".into(),
+            "run".into(),
+            "--no-interactive".into(),
+        ])
+        .unwrap_err();
+        assert!(matches!(err, ParseError::Missing(_)), "{err:?}");
+        assert!(err.message().contains("<PROFILES>"), "{err}");
+
+        // --config-inline supplies the config text and excludes --config
+        let args = vec![
+            "prompter".into(),
+            "--config-inline".into(),
+            "[p]\ndepends_on = []".into(),
+            "tree".into(),
+        ];
+        match parse_args_from(args).unwrap() {
+            AppMode::Tree { config, .. } => {
+                assert_eq!(config, ConfigSource::Inline("[p]\ndepends_on = []".into()));
+                let (cfg, lib) = config.load().unwrap();
+                assert!(cfg.profiles.contains_key("p"));
+                assert_eq!(lib, env::current_dir().unwrap());
+            }
+            other => panic!("unexpected mode: {other:?}"),
+        }
+        let args = vec![
+            "prompter".into(),
+            "--config-inline".into(),
+            "[p]".into(),
+            "--config".into(),
+            "c.toml".into(),
+            "tree".into(),
+        ];
+        assert!(parse_args_from(args).is_err());
+
+        // --config-check wraps config-reading modes in a preflight
+        let args = vec!["prompter".into(), "--config-check".into(), "list".into()];
+        match parse_args_from(args).unwrap() {
+            AppMode::Checked { mode, .. } => assert!(matches!(*mode, AppMode::List { .. })),
+            other => panic!("unexpected mode: {other:?}"),
+        }
+        let args: Vec<String> = [
+            "prompter",
+            "--config-check",
+            "completions",
+            "bash",
+            "--eager",
+        ]
+        .map(Into::into)
+        .into();
+        match parse_args_from(args).unwrap() {
+            AppMode::Checked { mode, .. } => {
+                assert!(matches!(*mode, AppMode::Completions { eager: Some(_), .. }));
+            }
+            other => panic!("unexpected mode: {other:?}"),
+        }
+        let args: Vec<String> = ["prompter", "--config-check", "completions", "bash"]
+            .map(Into::into)
+            .into();
+        assert!(matches!(
+            parse_args_from(args).unwrap(),
+            AppMode::Completions { eager: None, .. }
+        ));
+        let args = vec![
+            "prompter".into(),
+            "validate".into(),
+            "--config-check".into(),
+        ];
+        assert!(matches!(
+            parse_args_from(args).unwrap(),
+            AppMode::Validate { .. }
+        ));
+
+        // --all renders into --output-dir; both are required together
+        let args = vec![
+            "prompter".into(),
+            "run".into(),
+            "--all".into(),
+            "--output-dir".into(),
+            "dist".into(),
+        ];
+        match parse_args_from(args).unwrap() {
+            AppMode::RunAll { output_dir, .. } => assert_eq!(output_dir, PathBuf::from("dist")),
+            other => panic!("unexpected mode: {other:?}"),
+        }
+        let jobs = |extra: &[&str]| {
+            let mut args: Vec<String> = ["prompter", "run", "--all", "--output-dir", "dist"]
+                .map(String::from)
+                .to_vec();
+            args.extend(extra.iter().map(|a| (*a).to_string()));
+            match parse_args_from(args).unwrap() {
+                AppMode::RunAll { batch, .. } => batch.jobs,
+                other => panic!("unexpected mode: {other:?}"),
+            }
+        };
+        assert_eq!(jobs(&[]), 1);
+        assert_eq!(jobs(&["--parallel=3"]), 3);
+        assert!(jobs(&["--parallel"]) >= 1);
+        let args = vec!["prompter".into(), "run".into(), "--all".into()];
+        assert!(parse_args_from(args).is_err());
+        let args = vec![
+            "prompter".into(),
+            "run".into(),
+            "--all".into(),
+            "--output-dir".into(),
+            "dist".into(),
+            "p".into(),
+        ];
+        assert!(parse_args_from(args).is_err());
+
         let args = vec!["prompter".into(), "list".into()];
         assert!(matches!(
             parse_args_from(args).unwrap(),
             AppMode::List {
-                config: None,
-                json: false
+                config: ConfigSource::Default,
+                format: ListFormat::Text,
+                ..
             }
         ));
         let args = vec!["prompter".into(), "validate".into()];
         assert!(matches!(
             parse_args_from(args).unwrap(),
             AppMode::Validate {
-                config: None,
-                json: false
+                config: ConfigSource::Default,
+                json: false,
+                ..
+            }
+        ));
+        let args = vec![
+            "prompter".into(),
+            "validate".into(),
+            "--max-depth".into(),
+            "3".into(),
+        ];
+        match parse_args_from(args).unwrap() {
+            AppMode::Validate { options, .. } => assert_eq!(options.max_depth, Some(3)),
+            other => panic!("unexpected mode: {other:?}"),
+        }
+        let args = vec![
+            "prompter".into(),
+            "--json".into(),
+            "validate".into(),
+            "--format".into(),
+            "junit".into(),
+        ];
+        assert!(matches!(
+            parse_args_from(args).unwrap(),
+            AppMode::Validate {
+                json: false,
+                junit: true,
+                ..
             }
         ));
         let args = vec!["prompter".into(), "init".into()];
-        assert!(matches!(parse_args_from(args).unwrap(), AppMode::Init));
+        assert!(matches!(
+            parse_args_from(args).unwrap(),
+            AppMode::Init { quiet: false, .. }
+        ));
         let args = vec!["prompter".into(), "version".into()];
         assert!(matches!(
             parse_args_from(args).unwrap(),
@@ -1820,12 +9221,32 @@ depends_on = ["file.md"]
             "list".into(),
         ];
         match parse_args_from(args).unwrap() {
-            AppMode::List { config, json } => {
-                assert_eq!(config, Some(PathBuf::from("custom/config.toml")));
-                assert!(!json);
+            AppMode::List { config, format, .. } => {
+                assert_eq!(
+                    config,
+                    ConfigSource::File(PathBuf::from("custom/config.toml"))
+                );
+                assert_eq!(format, ListFormat::Text);
             }
             other => panic!("unexpected mode: {other:?}"),
         }
+        for (args, expected) in [
+            (vec!["--json", "list"], ListFormat::Json),
+            (
+                vec!["--json", "list", "--format", "ndjson"],
+                ListFormat::Ndjson,
+            ),
+            (vec!["list", "--format", "ndjson"], ListFormat::Ndjson),
+        ] {
+            let args = std::iter::once("prompter")
+                .chain(args)
+                .map(String::from)
+                .collect();
+            match parse_args_from(args).unwrap() {
+                AppMode::List { format, .. } => assert_eq!(format, expected),
+                other => panic!("unexpected mode: {other:?}"),
+            }
+        }
 
         let args = vec![
             "prompter".into(),
@@ -1836,13 +9257,30 @@ depends_on = ["file.md"]
         ];
         match parse_args_from(args).unwrap() {
             AppMode::Run { config, json, .. } => {
-                assert_eq!(config, Some(PathBuf::from("custom/config.toml")));
+                assert_eq!(
+                    config,
+                    ConfigSource::File(PathBuf::from("custom/config.toml"))
+                );
                 assert!(!json);
             }
             other => panic!("unexpected mode: {other:?}"),
         }
     }
 
+    #[test]
+    fn test_color_choice() {
+        assert!(ColorChoice::Always.enabled());
+        assert!(!ColorChoice::Never.enabled());
+        assert!(ColorChoice::Always.enabled_for(false));
+        assert!(!ColorChoice::Auto.enabled_for(false));
+
+        let cli = Cli::try_parse_from(["prompter", "--color", "never", "list"]).unwrap();
+        assert_eq!(cli.color, ColorChoice::Never);
+        let cli = Cli::try_parse_from(["prompter", "list"]).unwrap();
+        assert_eq!(cli.color, ColorChoice::Auto);
+        assert!(Cli::try_parse_from(["prompter", "--color", "sometimes", "list"]).is_err());
+    }
+
     struct FailAfterN {
         writes_done: usize,
         fail_on: usize,
@@ -1871,6 +9309,7 @@ depends_on = ["file.md"]
         let cfg = Config {
             profiles: HashMap::from([("p".into(), vec!["a/x.md".into(), "a/y.md".into()])]),
             post_prompt: None,
+            ..Config::default()
         };
         let mut w = FailAfterN {
             writes_done: 0,
@@ -1881,13 +9320,13 @@ depends_on = ["file.md"]
             &lib,
             &mut w,
             &["p".to_string()],
-            Some("--"),
-            None,
-            None,
-            false,
+            &RenderOptions {
+                separator: Some("--".to_string()),
+                ..RenderOptions::default()
+            },
         )
         .unwrap_err();
-        assert!(err.contains("Write error"), "err={err}");
+        assert!(err.error.contains("Write error"), "err={err}");
     }
 
     #[test]
@@ -1898,6 +9337,7 @@ depends_on = ["file.md"]
         let cfg = Config {
             profiles: HashMap::from([("p".into(), vec!["a/x.md".into()])]),
             post_prompt: None,
+            ..Config::default()
         };
         let mut w = FailAfterN {
             writes_done: 0,
@@ -1908,13 +9348,13 @@ depends_on = ["file.md"]
             &lib,
             &mut w,
             &["p".to_string()],
-            Some("--"),
-            None,
-            None,
-            false,
+            &RenderOptions {
+                separator: Some("--".to_string()),
+                ..RenderOptions::default()
+            },
         )
         .unwrap_err();
-        assert!(err.contains("Write error"), "err={err}");
+        assert!(err.error.contains("Write error"), "err={err}");
     }
 
     #[test]
@@ -1941,8 +9381,23 @@ depends_on = ["child", "f/y.md"]
         unsafe {
             env::set_var("HOME", &home);
         }
-        assert!(super::run_validate_stdout(None, false).is_ok());
-        assert!(super::run_list_stdout(None, false).is_ok());
+        assert!(
+            super::run_validate_stdout(
+                &ConfigSource::Default,
+                &ValidateOptions::default(),
+                false,
+                JsonLayout::Pretty,
+            )
+            .is_ok()
+        );
+        assert!(
+            super::run_list_stdout(
+                &ConfigSource::Default,
+                &ListOptions::default(),
+                ListFormat::Text
+            )
+            .is_ok()
+        );
         if let Some(prev) = prev_home {
             unsafe {
                 env::set_var("HOME", prev);
@@ -1971,7 +9426,13 @@ depends_on = ["missing.md", "unknown_profile"]
         unsafe {
             env::set_var("HOME", &home);
         }
-        let err = super::run_validate_stdout(None, false).unwrap_err();
+        let err = super::run_validate_stdout(
+            &ConfigSource::Default,
+            &ValidateOptions::default(),
+            false,
+            JsonLayout::Pretty,
+        )
+        .unwrap_err();
         assert!(
             err.contains("Missing file") && err.contains("Unknown profile"),
             "err={err}"
@@ -0,0 +1,259 @@
+//! Style checks on library fragments for the `lint` subcommand.
+//!
+//! Unlike `validate`, which checks that a configuration resolves, `lint`
+//! reports opinionated style problems in the fragments profiles include:
+//! trailing whitespace, mixed tab/space indentation, a missing final newline,
+//! and overly long lines. Each rule is a plain function in [`RULES`].
+
+use serde::Serialize;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+use crate::{Config, ConfigSource, JsonLayout, referenced_fragments, to_json};
+
+/// Default maximum line length for the `long-line` rule.
+pub const DEFAULT_MAX_LINE_LENGTH: usize = 120;
+
+/// Settings shared by the lint rules.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LintOptions {
+    /// Lines longer than this many characters are reported
+    pub max_line_length: usize,
+}
+
+impl Default for LintOptions {
+    fn default() -> Self {
+        Self {
+            max_line_length: DEFAULT_MAX_LINE_LENGTH,
+        }
+    }
+}
+
+/// A rule's finding: the 1-based line it concerns (if any) and a message.
+pub type Finding = (Option<usize>, String);
+
+/// A lint rule: inspects a fragment's text and returns its findings.
+pub type Rule = fn(&str, &LintOptions) -> Vec<Finding>;
+
+/// Every lint rule, by name.
+pub const RULES: &[(&str, Rule)] = &[
+    ("trailing-whitespace", trailing_whitespace),
+    ("mixed-indentation", mixed_indentation),
+    ("missing-final-newline", missing_final_newline),
+    ("long-line", long_line),
+];
+
+/// A style problem in a fragment.
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct LintWarning {
+    /// Profile that includes the fragment (the first, by name, if several do)
+    pub profile: String,
+    /// Fragment path relative to the library
+    pub file: String,
+    /// 1-based line number, when the problem is on a specific line
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<usize>,
+    /// Name of the rule that reported the problem
+    pub rule: String,
+    /// Description of the problem
+    pub message: String,
+}
+
+fn trailing_whitespace(text: &str, _: &LintOptions) -> Vec<Finding> {
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| line.ends_with([' ', '\t']))
+        .map(|(i, _)| (Some(i + 1), "trailing whitespace".to_string()))
+        .collect()
+}
+
+fn mixed_indentation(text: &str, _: &LintOptions) -> Vec<Finding> {
+    let mut style = None;
+    let mut findings = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let indent: Vec<char> = line
+            .chars()
+            .take_while(|c| *c == ' ' || *c == '\t')
+            .collect();
+        let Some(&first) = indent.first() else {
+            continue;
+        };
+        let expected = *style.get_or_insert(first);
+        if indent.iter().any(|c| *c != expected) {
+            let name = if expected == '\t' { "tabs" } else { "spaces" };
+            findings.push((
+                Some(i + 1),
+                format!("indentation mixes tabs and spaces (file indents with {name})"),
+            ));
+        }
+    }
+    findings
+}
+
+fn missing_final_newline(text: &str, _: &LintOptions) -> Vec<Finding> {
+    if text.is_empty() || text.ends_with('\n') {
+        return Vec::new();
+    }
+    vec![(None, "missing newline at end of file".to_string())]
+}
+
+fn long_line(text: &str, opts: &LintOptions) -> Vec<Finding> {
+    text.lines()
+        .enumerate()
+        .filter_map(|(i, line)| {
+            let len = line.chars().count();
+            (len > opts.max_line_length).then(|| {
+                (
+                    Some(i + 1),
+                    format!("line is {len} characters (max {})", opts.max_line_length),
+                )
+            })
+        })
+        .collect()
+}
+
+/// Run every rule over `text`, attributing findings to `profile` and `file`.
+#[must_use]
+pub fn lint_text(profile: &str, file: &str, text: &str, opts: &LintOptions) -> Vec<LintWarning> {
+    RULES
+        .iter()
+        .flat_map(|(rule, check)| {
+            check(text, opts)
+                .into_iter()
+                .map(move |(line, message)| LintWarning {
+                    profile: profile.to_string(),
+                    file: file.to_string(),
+                    line,
+                    rule: (*rule).to_string(),
+                    message,
+                })
+        })
+        .collect()
+}
+
+/// Lint every library fragment referenced directly by a profile, once each.
+///
+/// Missing files are skipped (`validate` reports them), as are `@path`
+/// includes, which are embedded verbatim.
+///
+/// # Errors
+/// Returns an error if a fragment exists but cannot be read.
+pub fn lint(cfg: &Config, lib: &Path, opts: &LintOptions) -> Result<Vec<LintWarning>, String> {
+    let mut warnings = Vec::new();
+    for (name, file) in referenced_fragments(cfg) {
+        let path = lib.join(file);
+        if !path.is_file() {
+            continue;
+        }
+        let text = fs::read_to_string(&path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let mut found = lint_text(name, file, &text, opts);
+        found.sort_by_key(|w| w.line);
+        warnings.extend(found);
+    }
+    Ok(warnings)
+}
+
+/// Write lint warnings as text or JSON.
+///
+/// # Errors
+/// Returns an error if JSON serialization or writing to the output fails.
+pub fn show_lint(
+    warnings: &[LintWarning],
+    json: bool,
+    json_layout: JsonLayout,
+    mut w: impl Write,
+) -> Result<(), String> {
+    if json {
+        let json_output = to_json(warnings, json_layout)?;
+        return writeln!(w, "{json_output}").map_err(|e| format!("Write error: {e}"));
+    }
+    for warning in warnings {
+        let location = warning.line.map_or_else(
+            || warning.file.clone(),
+            |line| format!("{}:{line}", warning.file),
+        );
+        writeln!(
+            w,
+            "Warning: {location}: {} [{}] (in [{}])",
+            warning.message, warning.rule, warning.profile
+        )
+        .map_err(|e| format!("Write error: {e}"))?;
+    }
+    Ok(())
+}
+
+/// Lint the configured library and print warnings to stdout.
+///
+/// # Errors
+/// Returns an error if the configuration cannot be loaded, a fragment cannot
+/// be read, writing fails, or `fail_on_warning` is set and warnings were found.
+pub fn run_lint_stdout(
+    config: &ConfigSource,
+    opts: &LintOptions,
+    json: bool,
+    json_layout: JsonLayout,
+    fail_on_warning: bool,
+) -> Result<(), String> {
+    let (cfg, lib) = config.load()?;
+    let warnings = lint(&cfg, &lib, opts)?;
+    show_lint(&warnings, json, json_layout, io::stdout())?;
+    if fail_on_warning && !warnings.is_empty() {
+        return Err(format!("Lint found {} warning(s)", warnings.len()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn rules_report_file_and_line() {
+        let opts = LintOptions {
+            max_line_length: 10,
+        };
+        let text = "ok\n  spaced \n\ttabbed\nthis line is too long";
+        let found: Vec<(String, Option<usize>)> = lint_text("p", "a.md", text, &opts)
+            .into_iter()
+            .map(|w| (w.rule, w.line))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                ("trailing-whitespace".into(), Some(2)),
+                ("mixed-indentation".into(), Some(3)),
+                ("missing-final-newline".into(), None),
+                ("long-line".into(), Some(4)),
+            ]
+        );
+        assert!(lint_text("p", "a.md", "clean\n", &opts).is_empty());
+    }
+
+    #[test]
+    fn lint_visits_each_fragment_once() {
+        let lib = crate::mk_tmp("prompter_lint");
+        fs::create_dir_all(&lib).unwrap();
+        fs::write(lib.join("a.md"), b"no newline").unwrap();
+        let cfg = Config {
+            profiles: HashMap::from([
+                ("b".into(), vec!["a.md#1".into(), "missing.md".into()]),
+                ("c".into(), vec!["a.md".into(), "@a.md".into()]),
+            ]),
+            ..Config::default()
+        };
+        let warnings = lint(&cfg, &lib, &LintOptions::default()).unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].profile, "b");
+        assert_eq!(warnings[0].rule, "missing-final-newline");
+
+        let mut out = Vec::new();
+        show_lint(&warnings, false, JsonLayout::Pretty, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "Warning: a.md: missing newline at end of file [missing-final-newline] (in [b])\n"
+        );
+    }
+}
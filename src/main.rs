@@ -3,110 +3,456 @@
 //! Main entry point for the prompter command-line tool.
 
 use std::env;
+use std::io::{self, IsTerminal};
 
 use clap::Parser;
+use colored::Colorize;
+use prompter::batch::run_render_all_stdout;
 use prompter::{
-    AppMode, Cli, init_scaffold, parse_args_from, run_list_stdout, run_render_stdout,
-    run_tree_stdout, run_validate_stdout,
+    AppMode, Cli, ColorChoice, ConfigSource, GlobalOptions, JsonLayout, ParseError, RenderError,
+    ValidateOptions, init_scaffold, parse_command_line, run_check_stdout, run_config_check,
+    run_count_includes_stdout, run_dump_config_stdout, run_list_stdout, run_list_vars_stdout,
+    run_render_stdout, run_tree_stdout, run_validate_junit_stdout, run_validate_stdout,
+    run_which_stdout,
 };
 use workhelix_cli_common::LicenseType;
 
 mod doctor;
 
-fn parse_args() -> Result<AppMode, String> {
+fn parse_args() -> Result<(AppMode, GlobalOptions), ParseError> {
     let args: Vec<String> = env::args().collect();
-    parse_args_from(args)
+    parse_command_line(args)
+}
+
+/// Category of a failure, reported as `code` under `--json-errors`.
+#[derive(Debug, Clone, Copy)]
+enum ErrorCode {
+    /// The command line could not be parsed
+    Usage,
+    /// The `--config-check` preflight failed
+    ConfigCheck,
+    /// `validate` found problems
+    Validation,
+    /// A profile failed to render
+    Render,
+    /// Any other failure
+    Failed,
+}
+
+impl ErrorCode {
+    const fn as_str(self) -> &'static str {
+        match self {
+            Self::Usage => "usage",
+            Self::ConfigCheck => "config_check",
+            Self::Validation => "validation",
+            Self::Render => "render",
+            Self::Failed => "failed",
+        }
+    }
+}
+
+/// How failures are reported on stderr: as `{code, message, context}` objects
+/// under `--json-errors`, else as text (or an `{"error": ...}` object in JSON
+/// mode).
+#[derive(Debug, Clone, Copy)]
+struct Reporter {
+    /// Whether `--json-errors` was given
+    structured: bool,
+}
+
+impl Reporter {
+    /// Print an error to stderr and exit with `status`. Under `--json-errors`
+    /// it is a `{code, message, context}` object; otherwise `text` is printed,
+    /// or an `{"error": ...}` object in JSON mode.
+    fn exit_with(
+        self,
+        code: ErrorCode,
+        message: &str,
+        text: &str,
+        context: &serde_json::Value,
+        json: bool,
+        status: i32,
+    ) -> ! {
+        if self.structured {
+            let object = serde_json::json!({
+                "code": code.as_str(),
+                "message": message,
+                "context": context,
+            });
+            eprintln!("{object}");
+        } else if json {
+            eprintln!(r#"{{"error":"{}"}}"#, message.replace('"', "\\\""));
+        } else {
+            eprintln!("{text}");
+        }
+        std::process::exit(status);
+    }
+
+    /// Print an error to stderr (as a JSON object in JSON mode) and exit with status 1.
+    fn exit_with_error(self, message: &str, json: bool) -> ! {
+        self.exit_with(
+            ErrorCode::Failed,
+            message,
+            message,
+            &serde_json::json!({}),
+            json,
+            1,
+        );
+    }
+
+    /// Exit via [`Self::exit_with_error`] if `result` is an error.
+    fn exit_on_error(self, result: Result<(), String>, json: bool) {
+        if let Err(e) = result {
+            self.exit_with_error(&e, json);
+        }
+    }
+
+    /// Exit on a render failure; in JSON mode the error object also carries
+    /// `failed_profile` and `include_chain` when known.
+    fn exit_on_render_error(self, result: Result<(), RenderError>, json: bool) {
+        let Err(e) = result else {
+            return;
+        };
+        if self.structured {
+            let context = serde_json::json!({
+                "failed_profile": e.failed_profile,
+                "include_chain": e.include_chain,
+            });
+            self.exit_with(ErrorCode::Render, &e.error, &e.error, &context, json, 1);
+        }
+        if json {
+            match serde_json::to_string(&e) {
+                Ok(object) => eprintln!("{object}"),
+                Err(_) => self.exit_with_error(&e.to_string(), json),
+            }
+            std::process::exit(1);
+        }
+        self.exit_with_error(&e.to_string(), json);
+    }
+}
+
+/// Run `validate`, printing the success line unless `json` or `quiet` is set.
+fn validate(
+    config: &ConfigSource,
+    options: &ValidateOptions,
+    json: bool,
+    json_layout: JsonLayout,
+    quiet: bool,
+    color: ColorChoice,
+    report: Reporter,
+) {
+    match run_validate_stdout(config, options, json, json_layout) {
+        Ok(()) => {
+            if !json && !quiet {
+                println!("{}", "All profiles valid".green());
+            }
+        }
+        Err(errs) => {
+            // The errors go to stderr, so color them by whether stderr is a terminal
+            colored::control::set_override(color.enabled_for(io::stderr().is_terminal()));
+            let text = format!("{}\n{errs}", "Validation errors:".red().bold());
+            let context = serde_json::json!({ "errors": errs.lines().collect::<Vec<_>>() });
+            report.exit_with(ErrorCode::Validation, &errs, &text, &context, json, 1);
+        }
+    }
 }
 
 fn main() {
-    let mode = match parse_args() {
-        Ok(m) => m,
+    let (mode, globals) = match parse_args() {
+        Ok(parsed) => parsed,
+        Err(e) if e.is_informational() => {
+            print!("{e}");
+            std::process::exit(0);
+        }
         Err(e) => {
-            eprintln!("{e}");
-            std::process::exit(2);
+            let report = Reporter {
+                structured: env::args().any(|arg| arg == "--json-errors"),
+            };
+            let message = e.message();
+            report.exit_with(
+                ErrorCode::Usage,
+                message,
+                message,
+                &serde_json::json!({}),
+                false,
+                2,
+            );
         }
     };
+    globals.color.apply();
+    prompter::verbose::init(globals.verbose);
+    dispatch(mode, globals);
+}
+
+/// Print the version, as a JSON object in JSON mode.
+fn print_version(json: bool) {
+    if json {
+        println!(r#"{{"version":"{}"}}"#, env!("CARGO_PKG_VERSION"));
+    } else {
+        println!("prompter {}", env!("CARGO_PKG_VERSION"));
+    }
+}
 
+fn dispatch(mode: AppMode, globals: GlobalOptions) {
+    let report = Reporter {
+        structured: globals.json_errors,
+    };
     match mode {
+        AppMode::Checked { config, json, mode } => {
+            if let Err(e) = run_config_check(&config) {
+                let context = serde_json::json!({ "config": config.describe() });
+                report.exit_with(ErrorCode::ConfigCheck, &e, &e, &context, json, 1);
+            }
+            dispatch(*mode, globals);
+        }
+        AppMode::DumpConfig { config, json } => {
+            report.exit_on_error(
+                run_dump_config_stdout(&config, json, globals.json_layout),
+                json,
+            );
+        }
         AppMode::Help => {
             Cli::parse_from(["prompter", "--help"]);
         }
-        AppMode::Version { json } => {
-            if json {
-                println!(r#"{{"version":"{}"}}"#, env!("CARGO_PKG_VERSION"));
-            } else {
-                println!("prompter {}", env!("CARGO_PKG_VERSION"));
-            }
-        }
+        AppMode::Version { json } => print_version(json),
         AppMode::License => {
             println!(
                 "{}",
                 workhelix_cli_common::license::display_license("prompter", LicenseType::MIT)
             );
         }
-        AppMode::Completions { shell } => {
-            prompter::completions::generate(shell);
+        AppMode::Completions {
+            shell,
+            eager: Some(config),
+            ..
+        } => {
+            report.exit_on_error(prompter::completions::generate_eager(shell, &config), false);
+        }
+        AppMode::Completions {
+            shell,
+            dynamic_command,
+            eager: None,
+        } => {
+            prompter::completions::generate(shell, dynamic_command.as_deref());
         }
-        AppMode::Doctor { json } => {
-            let exit_code = doctor::run_doctor_with_json(json);
+        AppMode::Doctor {
+            json,
+            quiet,
+            deep,
+            config,
+            fix,
+            yes,
+        } => {
+            if fix {
+                if let Err(e) = doctor::fix_missing(yes, quiet || json) {
+                    report.exit_with_error(&format!("Fix failed: {e}"), json);
+                }
+            }
+            let exit_code =
+                doctor::run_doctor_with_json(json, globals.json_layout, quiet, deep, &config);
             std::process::exit(exit_code);
         }
-        AppMode::Init => {
-            if let Err(e) = init_scaffold() {
-                eprintln!("Init failed: {e}");
-                std::process::exit(1);
+        AppMode::Init { template, quiet } => {
+            if let Err(e) = init_scaffold(&template, quiet) {
+                report.exit_with_error(&format!("Init failed: {e}"), false);
             }
         }
-        AppMode::List { config, json } => {
-            if let Err(e) = run_list_stdout(config.as_deref(), json) {
-                eprintln!("{e}");
-                std::process::exit(1);
-            }
+        AppMode::History { json } => {
+            report.exit_on_error(
+                prompter::history::run_history_stdout(json, globals.json_layout),
+                json,
+            );
+        }
+        AppMode::ConfigSchema => {
+            report.exit_on_error(
+                prompter::schema::run_config_schema_stdout(globals.json_layout),
+                false,
+            );
+        }
+        other => dispatch_library(other, globals),
+    }
+}
+
+/// Run one of the commands that inspect or edit the configured library.
+fn dispatch_library(mode: AppMode, globals: GlobalOptions) {
+    let report = Reporter {
+        structured: globals.json_errors,
+    };
+    let json_layout = globals.json_layout;
+    match mode {
+        AppMode::Which {
+            profile,
+            config,
+            json,
+        } => report.exit_on_error(run_which_stdout(&profile, &config, json, json_layout), json),
+        AppMode::Meta {
+            profile,
+            config,
+            json,
+        } => report.exit_on_error(
+            prompter::meta::run_meta_stdout(profile.as_deref(), &config, json, json_layout),
+            json,
+        ),
+        AppMode::List {
+            config,
+            options,
+            format,
+        } => {
+            report.exit_on_error(run_list_stdout(&config, &options, format), false);
         }
         AppMode::Tree { config, json } => {
-            if let Err(e) = run_tree_stdout(config.as_deref(), json) {
-                eprintln!("{e}");
-                std::process::exit(1);
-            }
+            report.exit_on_error(run_tree_stdout(&config, json, json_layout), false);
         }
-        AppMode::Validate { config, json } => match run_validate_stdout(config.as_deref(), json) {
-            Ok(()) => {
-                if !json {
-                    println!("All profiles valid");
-                }
-            }
-            Err(errs) => {
-                if json {
-                    eprintln!(r#"{{"error":"{}"}}"#, errs.replace('"', "\\\""));
-                } else {
-                    eprintln!("Validation errors:\n{errs}");
-                }
-                std::process::exit(1);
-            }
-        },
+        AppMode::Export {
+            config,
+            output,
+            quiet,
+        } => report.exit_on_error(prompter::bundle::run_export(&config, &output, quiet), false),
+        AppMode::Import { bundle, dir, quiet } => {
+            report.exit_on_error(prompter::bundle::run_import(&bundle, &dir, quiet), false);
+        }
+        AppMode::Serve {
+            addr,
+            config,
+            quiet,
+        } => report.exit_on_error(prompter::serve::run_serve(&addr, &config, quiet), false),
+        AppMode::Stats { config, json } => {
+            report.exit_on_error(
+                prompter::stats::run_stats_stdout(&config, json, json_layout),
+                false,
+            );
+        }
+        AppMode::Lint {
+            config,
+            options,
+            json,
+            fail_on_warning,
+        } => report.exit_on_error(
+            prompter::lint::run_lint_stdout(&config, &options, json, json_layout, fail_on_warning),
+            false,
+        ),
+        AppMode::Validate {
+            config,
+            options,
+            junit: true,
+            ..
+        } => report.exit_on_error(run_validate_junit_stdout(&config, &options), false),
+        AppMode::Validate {
+            config,
+            options,
+            json,
+            quiet,
+            ..
+        } => validate(
+            &config,
+            &options,
+            json,
+            json_layout,
+            quiet,
+            globals.color,
+            report,
+        ),
+        AppMode::Replace {
+            options,
+            config,
+            apply,
+            json,
+        } => report.exit_on_error(
+            prompter::replace::run_replace_stdout(&config, &options, apply, json, json_layout),
+            json,
+        ),
+        run => dispatch_run(run, report),
+    }
+}
+
+/// Run one of the `prompter run` modes.
+fn dispatch_run(mode: AppMode, report: Reporter) {
+    if let AppMode::Run { options, .. }
+    | AppMode::RunCheck { options, .. }
+    | AppMode::CountIncludes { options, .. }
+    | AppMode::ListVars { options, .. }
+    | AppMode::Repeat { options, .. }
+    | AppMode::RunAll { options, .. } = &mode
+    {
+        options.announce_seed();
+    }
+    match mode {
         AppMode::Run {
             profiles,
-            separator,
-            pre_prompt,
-            post_prompt,
+            profiles_glob,
+            splices,
+            scenario,
+            pick,
+            options,
             config,
             json,
         } => {
-            if let Err(e) = run_render_stdout(
+            let profiles = if pick {
+                prompter::picker::pick_profiles(&config)
+                    .unwrap_or_else(|e| report.exit_with_error(&e, json))
+            } else {
+                profiles
+            };
+            let result = run_render_stdout(
                 &profiles,
-                separator.as_deref(),
-                pre_prompt.as_deref(),
-                post_prompt.as_deref(),
-                config.as_deref(),
-                json,
-            ) {
-                if json {
-                    eprintln!(r#"{{"error":"{}"}}"#, e.replace('"', "\\\""));
-                } else {
-                    eprintln!("{e}");
-                }
-                std::process::exit(1);
+                &profiles_glob,
+                &splices,
+                scenario.as_deref(),
+                &options,
+                &config,
+            );
+            report.exit_on_render_error(result, json);
+        }
+        AppMode::RunCheck {
+            profiles,
+            options,
+            config,
+            json,
+            quiet,
+        } => {
+            report.exit_on_render_error(run_check_stdout(&profiles, &options, &config), json);
+            if !json && !quiet {
+                println!("OK");
             }
         }
+        AppMode::CountIncludes {
+            profiles,
+            options,
+            config,
+            json,
+        } => {
+            let result = run_count_includes_stdout(&profiles, &options, &config, json);
+            report.exit_on_render_error(result, json);
+        }
+        AppMode::ListVars {
+            profiles,
+            options,
+            config,
+            json,
+        } => {
+            let result = run_list_vars_stdout(&profiles, &options, &config, json);
+            report.exit_on_render_error(result, json);
+        }
+        AppMode::Repeat {
+            index,
+            options,
+            remote,
+            json,
+        } => {
+            let result = prompter::history::run_repeat_stdout(index, &options, remote);
+            report.exit_on_render_error(result, json);
+        }
+        AppMode::RunAll {
+            output_dir,
+            options,
+            config,
+            json,
+            quiet,
+            batch,
+        } => {
+            let result = run_render_all_stdout(&output_dir, &options, &config, json, quiet, &batch);
+            report.exit_on_error(result, json);
+        }
+        other => unreachable!("dispatch_run called with {other:?}"),
     }
 }
@@ -0,0 +1,194 @@
+//! Profile metadata for the `meta` subcommand.
+//!
+//! Prints what is known about a profile without rendering it: the
+//! description, tags, parent, and review date from the configuration, plus
+//! the parsed `---` frontmatter block of every fragment it includes.
+
+use serde::Serialize;
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+use crate::replace::frontmatter;
+use crate::{Config, ConfigSource, JsonLayout, resolve_profile, to_json};
+
+/// Frontmatter of one fragment file.
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub struct FragmentMeta {
+    /// Fragment path relative to the library
+    pub path: String,
+    /// Parsed frontmatter block
+    pub frontmatter: serde_json::Value,
+}
+
+/// Everything known about a profile short of its rendered body.
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub struct ProfileMeta {
+    /// Profile name
+    pub profile: String,
+    /// One-line summary from the configuration
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Labels from the configuration
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Parent profile
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub extends: Option<String>,
+    /// Review date (`YYYY-MM-DD`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub review_after: Option<String>,
+    /// Direct dependencies, as written in the configuration
+    pub depends_on: Vec<String>,
+    /// Included fragments that have a frontmatter block, in render order
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub fragments: Vec<FragmentMeta>,
+}
+
+/// Metadata for every profile (`meta --all`).
+#[derive(Debug, Serialize)]
+struct MetaOutput<'a> {
+    profiles: &'a [ProfileMeta],
+}
+
+/// Collect the metadata of `profile`.
+///
+/// # Errors
+/// Returns an error if the profile does not exist or does not resolve, or a
+/// fragment cannot be read or has a frontmatter block that is not valid YAML.
+pub fn profile_meta(cfg: &Config, lib: &Path, profile: &str) -> Result<ProfileMeta, String> {
+    let profile = cfg.profile_name(profile);
+    let Some(deps) = cfg.profiles.get(profile) else {
+        return Err(format!("Unknown profile: {profile}"));
+    };
+    let mut seen = HashSet::new();
+    let mut stack = Vec::new();
+    let mut files = Vec::new();
+    resolve_profile(profile, cfg, lib, &mut seen, &mut stack, &mut files)
+        .map_err(|e| e.to_string())?;
+
+    let mut fragments = Vec::new();
+    for file in files {
+        let path = file
+            .strip_prefix(lib)
+            .unwrap_or(&file)
+            .display()
+            .to_string();
+        let text = fs::read_to_string(&file).map_err(|e| format!("Failed to read {path}: {e}"))?;
+        let Some(block) = frontmatter(&text).filter(|block| !block.trim().is_empty()) else {
+            continue;
+        };
+        let frontmatter = serde_yaml_ng::from_str(block)
+            .map_err(|e| format!("Invalid frontmatter in {path}: {e}"))?;
+        fragments.push(FragmentMeta { path, frontmatter });
+    }
+
+    let meta = cfg.metadata.get(profile).cloned().unwrap_or_default();
+    Ok(ProfileMeta {
+        profile: profile.to_string(),
+        description: meta.description,
+        tags: meta.tags,
+        extends: meta.extends,
+        review_after: meta.review_after.map(|date| date.to_string()),
+        depends_on: deps.clone(),
+        fragments,
+    })
+}
+
+/// Print the metadata of `profile`, or of every profile when it is `None`, as
+/// JSON or TOML.
+///
+/// # Errors
+/// Returns an error if the configuration cannot be loaded, a profile's
+/// metadata cannot be collected, or it cannot be written as TOML.
+pub fn run_meta_stdout(
+    profile: Option<&str>,
+    config: &ConfigSource,
+    json: bool,
+    json_layout: JsonLayout,
+) -> Result<(), String> {
+    let (cfg, lib) = config.load()?;
+    let metas = if let Some(profile) = profile {
+        vec![profile_meta(&cfg, &lib, profile)?]
+    } else {
+        let mut names: Vec<&String> = cfg.profiles.keys().collect();
+        names.sort();
+        names
+            .into_iter()
+            .map(|name| profile_meta(&cfg, &lib, name))
+            .collect::<Result<_, _>>()?
+    };
+    let text = match (profile, json) {
+        (Some(_), true) => to_json(&metas[0], json_layout)?,
+        (None, true) => to_json(&MetaOutput { profiles: &metas }, json_layout)?,
+        (Some(_), false) => toml_text(&metas[0])?,
+        (None, false) => toml_text(&MetaOutput { profiles: &metas })?,
+    };
+    println!("{}", text.trim_end());
+    Ok(())
+}
+
+fn toml_text<T: Serialize>(value: &T) -> Result<String, String> {
+    toml::to_string_pretty(value).map_err(|e| format!("TOML serialization error: {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ProfileMetadata;
+    use serde_json::json;
+    use std::collections::HashMap;
+
+    #[test]
+    fn meta_collects_config_and_frontmatter() {
+        let lib = crate::mk_tmp("prompter_meta");
+        fs::create_dir_all(&lib).unwrap();
+        fs::write(
+            lib.join("a.md"),
+            "---\ntitle: Style\ntags: [rust]\n---\nBody A\n",
+        )
+        .unwrap();
+        fs::write(lib.join("b.md"), "Body B\n").unwrap();
+        fs::write(lib.join("bad.md"), "---\n: [\n---\nBody\n").unwrap();
+        let cfg = Config {
+            profiles: HashMap::from([
+                ("base".into(), vec!["a.md".into()]),
+                ("top".into(), vec!["base".into(), "b.md".into()]),
+                ("broken".into(), vec!["bad.md".into()]),
+            ]),
+            metadata: HashMap::from([(
+                "top".into(),
+                ProfileMetadata {
+                    description: Some("Top".into()),
+                    tags: vec!["core".into()],
+                    ..ProfileMetadata::default()
+                },
+            )]),
+            ..Config::default()
+        };
+
+        let meta = profile_meta(&cfg, &lib, "top").unwrap();
+        assert_eq!(meta.description.as_deref(), Some("Top"));
+        assert_eq!(meta.tags, vec!["core"]);
+        assert_eq!(meta.depends_on, vec!["base", "b.md"]);
+        assert_eq!(
+            meta.fragments,
+            vec![FragmentMeta {
+                path: "a.md".into(),
+                frontmatter: json!({"title": "Style", "tags": ["rust"]}),
+            }]
+        );
+        assert!(
+            toml_text(&meta)
+                .unwrap()
+                .contains("[fragments.frontmatter]")
+        );
+
+        let err = profile_meta(&cfg, &lib, "broken").unwrap_err();
+        assert!(err.starts_with("Invalid frontmatter in bad.md"), "{err}");
+        assert_eq!(
+            profile_meta(&cfg, &lib, "nope").unwrap_err(),
+            "Unknown profile: nope"
+        );
+    }
+}
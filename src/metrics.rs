@@ -0,0 +1,91 @@
+//! Render metrics for `run --metrics-json PATH`.
+//!
+//! Each render appends one JSON line to PATH with its time, profiles, output
+//! size, estimated token count (see [`estimate_tokens`]), and duration, for
+//! ingestion into a time-series dashboard. Existing lines are never rewritten.
+
+use serde::Serialize;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::estimate_tokens;
+
+/// Measurements of one render.
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct RenderMetrics {
+    /// When the render finished (RFC 3339)
+    pub timestamp: String,
+    /// Profiles rendered, in order
+    pub profiles: Vec<String>,
+    /// Size of the output in bytes
+    pub output_bytes: usize,
+    /// Estimated token count of the output
+    pub estimated_tokens: usize,
+    /// Time from loading the configuration to the end of output, in milliseconds
+    pub elapsed_ms: u128,
+}
+
+impl RenderMetrics {
+    /// Metrics of a render of `profiles` that produced `output` in `elapsed`.
+    #[must_use]
+    pub fn new(profiles: &[String], output: &[u8], elapsed: Duration) -> Self {
+        Self {
+            timestamp: chrono::Local::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, false),
+            profiles: profiles.to_vec(),
+            output_bytes: output.len(),
+            estimated_tokens: estimate_tokens(&String::from_utf8_lossy(output)),
+            elapsed_ms: elapsed.as_millis(),
+        }
+    }
+}
+
+/// Append `metrics` to `path` as one JSON line, creating the file if needed.
+///
+/// # Errors
+/// Returns an error if the file cannot be created or written.
+pub fn append_metrics(path: &Path, metrics: &RenderMetrics) -> Result<(), String> {
+    let mut line =
+        serde_json::to_string(metrics).map_err(|e| format!("JSON serialization error: {e}"))?;
+    line.push('\n');
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+    }
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut file| file.write_all(line.as_bytes()))
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn append_adds_one_line_per_render() {
+        let path = crate::mk_tmp("prompter_metrics").join("metrics.jsonl");
+        let first = RenderMetrics::new(&["a".into()], b"Hello\n", Duration::from_millis(12));
+        assert_eq!(
+            (first.output_bytes, first.estimated_tokens, first.elapsed_ms),
+            (6, 2, 12)
+        );
+        append_metrics(&path, &first).unwrap();
+        let second = RenderMetrics::new(&["a".into(), "b".into()], b"", Duration::ZERO);
+        append_metrics(&path, &second).unwrap();
+
+        let text = fs::read_to_string(&path).unwrap();
+        let lines: Vec<serde_json::Value> = text
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["profiles"], serde_json::json!(["a"]));
+        assert_eq!(lines[1]["output_bytes"], 0);
+        assert_eq!(lines[1]["elapsed_ms"], 0);
+        assert!(lines[1]["timestamp"].is_string());
+    }
+}
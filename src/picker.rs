@@ -0,0 +1,88 @@
+//! Interactive profile picker for a bare `prompter run` on a terminal.
+//!
+//! Offers the same profiles `prompter list` prints, with their descriptions,
+//! in a fuzzy-filtered multi-select; the chosen set is rendered in list order.
+
+use inquire::{InquireError, MultiSelect};
+use std::fmt;
+use std::io::{self, IsTerminal};
+
+use crate::{Config, ConfigSource, ListOptions, listed_profile_names};
+
+/// One selectable profile, shown with its description when it has one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Choice {
+    name: String,
+    description: Option<String>,
+}
+
+impl fmt::Display for Choice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.description {
+            Some(description) => write!(f, "{} - {description}", self.name),
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
+
+/// Whether both stdin and stdout are terminals, so a picker can be shown.
+#[must_use]
+pub fn can_pick() -> bool {
+    io::stdin().is_terminal() && io::stdout().is_terminal()
+}
+
+/// The profiles offered by the picker, in `list` order.
+fn choices(cfg: &Config, names: Vec<String>) -> Vec<Choice> {
+    names
+        .into_iter()
+        .map(|name| Choice {
+            description: cfg.metadata.get(&name).and_then(|m| m.description.clone()),
+            name,
+        })
+        .collect()
+}
+
+/// Ask for one or more profiles from the configuration.
+///
+/// # Errors
+/// Returns an error if the configuration cannot be loaded, it has no
+/// profiles, the picker cannot be shown, or nothing was selected.
+pub fn pick_profiles(config: &ConfigSource) -> Result<Vec<String>, String> {
+    let (cfg, lib) = config.load()?;
+    let names = listed_profile_names(&cfg, &lib, &ListOptions::default());
+    if names.is_empty() {
+        return Err("No profiles to choose from".into());
+    }
+    let picked = MultiSelect::new("Profiles to render:", choices(&cfg, names))
+        .with_help_message("type to filter, space to select, enter to render")
+        .prompt()
+        .map_err(|e| match e {
+            InquireError::OperationCanceled | InquireError::OperationInterrupted => {
+                "No profiles selected".to_string()
+            }
+            e => format!("Profile picker failed: {e}"),
+        })?;
+    if picked.is_empty() {
+        return Err("No profiles selected".into());
+    }
+    Ok(picked.into_iter().map(|choice| choice.name).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_config_toml;
+
+    #[test]
+    fn choices_show_descriptions() {
+        let cfg = parse_config_toml(
+            "[a]\ndescription = \"First\"\ndepends_on = []\n[b]\ndepends_on = []\n",
+        )
+        .unwrap();
+        let shown: Vec<String> = choices(&cfg, vec!["a".into(), "b".into()])
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(shown, ["a - First", "b"]);
+    }
+}
@@ -0,0 +1,39 @@
+//! Progress bar on stderr for long batch operations (`--progress`).
+//!
+//! `run --all` and `sed` advance a bar once per file. It is only drawn when
+//! `--progress` was given, `--quiet` was not, and stderr is a terminal, so
+//! redirected output and scripts never see control characters. The first two
+//! reach [`progress_bar`] as `enabled`, through the batch and `sed` options.
+
+use indicatif::{ProgressBar, ProgressStyle};
+use is_terminal::IsTerminal;
+use std::io;
+
+/// A bar counting `total` files under `label`, or a hidden one unless
+/// `enabled` and stderr is a terminal.
+#[must_use]
+pub fn progress_bar(label: &'static str, total: usize, enabled: bool) -> ProgressBar {
+    if !enabled || !io::stderr().is_terminal() {
+        return ProgressBar::hidden();
+    }
+    let pb = ProgressBar::new(u64::try_from(total).unwrap_or(u64::MAX));
+    if let Ok(style) = ProgressStyle::with_template("{msg} [{bar}] {pos}/{len}") {
+        pb.set_style(style.progress_chars("#>-"));
+    }
+    pb.set_message(label);
+    pb
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bar_is_hidden_unless_enabled() {
+        let pb = progress_bar("rendering", 2, false);
+        assert!(pb.is_hidden());
+        pb.inc(2);
+        pb.finish_and_clear();
+        assert_eq!(pb.position(), 2);
+    }
+}
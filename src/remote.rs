@@ -0,0 +1,405 @@
+//! Remote includes: `https://` (or, with `--allow-http`, `http://`) dependency
+//! entries fetched at render time.
+//!
+//! A remote include is downloaded once per process and stored in the local
+//! cache (`~/.cache/prompter/remote`), and the cached copy is then rendered
+//! like any library fragment. A copy younger than [`FRESH_FOR`] is used without
+//! a request; an older one is revalidated with its `ETag`, so an unchanged
+//! include is not downloaded again. With `--offline` nothing is fetched: the
+//! cached copy is used, and an include that was never fetched fails
+//! immediately. Fetching needs the `remote` cargo feature; without it, only
+//! cached copies are usable.
+//! The command-line settings reach the loaded [`crate::Config`] as a
+//! [`RemoteAccess`], through [`crate::ConfigSource::remote_access`].
+//!
+//! Every remote include first passes the policy in [`check_allowed`]: plain
+//! `http://` is refused unless `--allow-http` is given, `--deny-remote` refuses
+//! all of them, and `[remote] allowed_hosts` refuses hosts not listed. A
+//! refused include fails the render, even when optional, and nothing is
+//! fetched or read for it.
+
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, SystemTime};
+
+/// How long a single fetch may take, connecting included.
+pub const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Age below which a cached copy is used without asking the server.
+pub const FRESH_FOR: Duration = Duration::from_secs(60 * 60);
+
+/// Remote includes already fetched by this process, by URL.
+static FETCHED: Mutex<Option<HashMap<String, PathBuf>>> = Mutex::new(None);
+
+/// How remote includes may be used, from the command line.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RemoteAccess {
+    /// Never fetch; use cached copies only (`--offline`)
+    pub offline: bool,
+    /// Refuse every remote include (`--deny-remote`)
+    pub deny: bool,
+    /// Accept plain `http://` includes (`--allow-http`)
+    pub allow_http: bool,
+}
+
+/// Whether a dependency entry is a remote include (`https://…`, or
+/// `http://…`, which [`check_allowed`] refuses without `--allow-http`).
+pub(crate) fn is_remote(dep: &str) -> bool {
+    dep.starts_with("https://") || dep.starts_with("http://")
+}
+
+/// Host of `url`, lowercased, without user info or port.
+fn host(url: &str) -> &str {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or_default();
+    let host = authority
+        .rsplit_once('@')
+        .map_or(authority, |(_, host)| host);
+    let host = host
+        .strip_prefix('[')
+        .map_or_else(|| host.split(':').next(), |ipv6| ipv6.split(']').next());
+    host.unwrap_or_default()
+}
+
+/// Check `url` against the HTTPS requirement (unless `--allow-http`),
+/// `--deny-remote`, and the `allowed_hosts` allowlist.
+///
+/// # Errors
+/// Returns an error naming `url` and the rule that refuses it.
+pub(crate) fn check_allowed(
+    url: &str,
+    allowed_hosts: Option<&[String]>,
+    access: RemoteAccess,
+) -> Result<(), String> {
+    let refused = if !url.starts_with("https://") && !access.allow_http {
+        "only https:// includes are allowed (use --allow-http for http://)".to_string()
+    } else if access.deny {
+        "remote includes are denied (--deny-remote)".to_string()
+    } else {
+        let host = host(url);
+        match allowed_hosts {
+            Some(allowed) if !allowed.iter().any(|h| h.eq_ignore_ascii_case(host)) => {
+                format!("host '{host}' is not in [remote] allowed_hosts")
+            }
+            _ => return Ok(()),
+        }
+    };
+    log::warn!("blocked remote include {url}: {refused}");
+    Err(format!("Blocked remote include {url}: {refused}"))
+}
+
+/// Location of the cached copy of `url`.
+pub(crate) fn cache_path(url: &str) -> PathBuf {
+    let root = crate::cache::cache_root().unwrap_or_else(|_| env::temp_dir().join("prompter"));
+    let name = crate::HashAlgorithm::Sha256.hex_digest(url.as_bytes());
+    root.join("remote").join(name)
+}
+
+/// Fetch `url` into the cache, returning the path of the cached copy.
+///
+/// # Errors
+/// Returns an error naming `url` if it is refused by [`check_allowed`], it is
+/// offline and not cached, the request fails or times out, the response status
+/// is not 200 (redirects are not followed, since the target would escape
+/// [`check_allowed`]), or the cache cannot be written.
+pub(crate) fn fetch(
+    url: &str,
+    allowed_hosts: Option<&[String]>,
+    access: RemoteAccess,
+) -> Result<PathBuf, String> {
+    check_allowed(url, allowed_hosts, access)?;
+    let fetched = || FETCHED.lock().unwrap_or_else(PoisonError::into_inner);
+    if let Some(path) = fetched().as_ref().and_then(|paths| paths.get(url)) {
+        return Ok(path.clone());
+    }
+    let path = cache_path(url);
+    fetch_to(url, &path, access.offline, TIMEOUT)?;
+    fetched()
+        .get_or_insert_with(HashMap::new)
+        .insert(url.to_string(), path.clone());
+    Ok(path)
+}
+
+/// Location of the `ETag` stored beside the cached copy at `path`.
+fn etag_path(path: &Path) -> PathBuf {
+    path.with_extension("etag")
+}
+
+/// Whether the cached copy at `path` was stored or revalidated within
+/// [`FRESH_FOR`].
+fn is_fresh(path: &Path) -> bool {
+    fs::metadata(path)
+        .and_then(|meta| meta.modified())
+        .is_ok_and(|modified| {
+            SystemTime::now()
+                .duration_since(modified)
+                .is_ok_and(|age| age < FRESH_FOR)
+        })
+}
+
+/// Result of a (possibly conditional) download.
+#[cfg_attr(not(feature = "remote"), allow(dead_code))]
+enum Download {
+    /// The server confirmed the cached copy (HTTP 304)
+    NotModified,
+    /// A new body, with its `ETag` if the server sent one
+    Body(Vec<u8>, Option<String>),
+}
+
+/// Make the cached copy of `url` at `path` current, or when `offline` only
+/// check that it is there.
+fn fetch_to(url: &str, path: &Path, offline: bool, timeout: Duration) -> Result<(), String> {
+    let cached = path.is_file();
+    if cached && (offline || is_fresh(path)) {
+        log::debug!("using cached {url}");
+        return Ok(());
+    }
+    if offline {
+        return Err(format!("Remote include {url} is not cached (--offline)"));
+    }
+    let etag = if cached {
+        fs::read_to_string(etag_path(path)).ok()
+    } else {
+        None
+    };
+    log::debug!("fetching {url}");
+    let cache_failed = |e: std::io::Error| format!("Failed to cache {url}: {e}");
+    match download(url, etag.as_deref(), timeout)? {
+        Download::NotModified => {
+            log::debug!("cached {url} is unchanged");
+            fs::File::options()
+                .write(true)
+                .open(path)
+                .and_then(|file| file.set_modified(SystemTime::now()))
+                .map_err(cache_failed)
+        }
+        Download::Body(body, etag) => {
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir).map_err(cache_failed)?;
+            }
+            write_atomically(path, &body).map_err(cache_failed)?;
+            if let Some(etag) = etag {
+                write_atomically(&etag_path(path), etag.as_bytes()).map_err(cache_failed)?;
+            } else {
+                let _ = fs::remove_file(etag_path(path));
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Replace `path` with `contents` through a temporary file and a rename, so a
+/// concurrent render never reads a partial copy.
+fn write_atomically(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let staged = path.with_file_name(format!(".{name}.tmp-{}", std::process::id()));
+    let written = fs::write(&staged, contents).and_then(|()| fs::rename(&staged, path));
+    if written.is_err() {
+        let _ = fs::remove_file(&staged);
+    }
+    written
+}
+
+#[cfg(feature = "remote")]
+fn download(url: &str, etag: Option<&str>, timeout: Duration) -> Result<Download, String> {
+    let agent: ureq::Agent = ureq::Agent::config_builder()
+        .timeout_global(Some(timeout))
+        .http_status_as_error(false)
+        .max_redirects(0)
+        .build()
+        .into();
+    let failed = |e: ureq::Error| match e {
+        ureq::Error::Timeout(_) => {
+            format!(
+                "Timed out fetching {url} (after {}s)",
+                timeout.as_secs_f32()
+            )
+        }
+        e => format!("Failed to fetch {url}: {e}"),
+    };
+    let mut request = agent.get(url);
+    if let Some(etag) = etag {
+        request = request.header("If-None-Match", etag);
+    }
+    let mut response = request.call().map_err(failed)?;
+    let status = response.status();
+    if status == 304 && etag.is_some() {
+        return Ok(Download::NotModified);
+    }
+    if status.is_redirection() {
+        let location = response
+            .headers()
+            .get("location")
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or("an unknown location");
+        return Err(format!(
+            "Failed to fetch {url}: HTTP {status} redirects to {location}; \
+             include the final URL instead"
+        ));
+    }
+    if status != 200 {
+        return Err(format!("Failed to fetch {url}: HTTP {status}"));
+    }
+    let etag = response
+        .headers()
+        .get("etag")
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+    let body = response.body_mut().read_to_vec().map_err(failed)?;
+    Ok(Download::Body(body, etag))
+}
+
+#[cfg(not(feature = "remote"))]
+fn download(url: &str, _etag: Option<&str>, _timeout: Duration) -> Result<Download, String> {
+    Err(format!(
+        "Cannot fetch {url}: prompter was built without the `remote` feature"
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn offline_uses_only_cached_copies() {
+        let path = crate::mk_tmp("prompter_remote").join("cached");
+        let url = "https://example.com/legal.md";
+        assert_eq!(
+            fetch_to(url, &path, true, TIMEOUT).unwrap_err(),
+            "Remote include https://example.com/legal.md is not cached (--offline)"
+        );
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, "Legal\n").unwrap();
+        assert_eq!(fetch_to(url, &path, true, TIMEOUT), Ok(()));
+        assert!(is_remote(url));
+        assert!(!is_remote("@https.md"));
+        assert_ne!(cache_path(url), cache_path("https://example.com/other.md"));
+    }
+
+    #[test]
+    fn allowlist_matches_the_host_only() {
+        assert_eq!(
+            host("https://Docs.Example.com:8443/a.md?x#y"),
+            "Docs.Example.com"
+        );
+        assert_eq!(host("https://example.com@evil.test/a.md"), "evil.test");
+        assert_eq!(host("http://[::1]:8080/a.md"), "::1");
+        let allowed = ["docs.example.com".to_string()];
+        let open = RemoteAccess::default();
+        assert_eq!(
+            check_allowed("https://DOCS.example.com/a.md", Some(&allowed), open),
+            Ok(())
+        );
+        assert_eq!(check_allowed("https://evil.test/a.md", None, open), Ok(()));
+        assert_eq!(
+            check_allowed(
+                "https://docs.example.com@evil.test/a.md",
+                Some(&allowed),
+                open
+            )
+            .unwrap_err(),
+            "Blocked remote include https://docs.example.com@evil.test/a.md: \
+             host 'evil.test' is not in [remote] allowed_hosts"
+        );
+        assert!(check_allowed("https://x.test/a.md", Some(&[]), open).is_err());
+        assert_eq!(
+            check_allowed("http://docs.example.com/a.md", Some(&allowed), open).unwrap_err(),
+            "Blocked remote include http://docs.example.com/a.md: \
+             only https:// includes are allowed (use --allow-http for http://)"
+        );
+        let http = RemoteAccess {
+            allow_http: true,
+            ..open
+        };
+        assert_eq!(
+            check_allowed("http://docs.example.com/a.md", Some(&allowed), http),
+            Ok(())
+        );
+        assert!(check_allowed("http://evil.test/a.md", Some(&allowed), http).is_err());
+        let denied = RemoteAccess { deny: true, ..open };
+        assert_eq!(
+            check_allowed("https://docs.example.com/a.md", Some(&allowed), denied).unwrap_err(),
+            "Blocked remote include https://docs.example.com/a.md: \
+             remote includes are denied (--deny-remote)"
+        );
+    }
+
+    #[cfg(feature = "remote")]
+    #[test]
+    fn fetch_revalidates_and_reports_status_and_timeout() {
+        use std::io::{Read, Write};
+        use std::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            let replies = [
+                "HTTP/1.1 200 OK\r\nETag: \"v1\"\r\nContent-Length: 6\r\n\r\nLegal\n",
+                "HTTP/1.1 304 Not Modified\r\nContent-Length: 0\r\n\r\n",
+                "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n",
+                "HTTP/1.1 302 Found\r\nLocation: https://evil.test/a.md\r\nContent-Length: 0\r\n\r\n",
+                "",
+            ];
+            for reply in replies {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut request = [0; 1024];
+                let read = stream.read(&mut request).unwrap_or(0);
+                let request = String::from_utf8_lossy(&request[..read]).to_lowercase();
+                let reply = if reply.starts_with("HTTP/1.1 304")
+                    && !request.contains("if-none-match: \"v1\"")
+                {
+                    "HTTP/1.1 500 Internal Server Error\r\nContent-Length: 0\r\n\r\n"
+                } else {
+                    reply
+                };
+                if reply.is_empty() {
+                    std::thread::sleep(Duration::from_secs(2));
+                } else {
+                    stream.write_all(reply.as_bytes()).unwrap();
+                }
+            }
+        });
+
+        let path = crate::mk_tmp("prompter_remote").join("cached");
+        let url = format!("http://{addr}/legal.md");
+        let make_stale = || {
+            fs::File::options()
+                .write(true)
+                .open(&path)
+                .unwrap()
+                .set_modified(SystemTime::now() - FRESH_FOR - Duration::from_secs(60))
+                .unwrap();
+        };
+        fetch_to(&url, &path, false, TIMEOUT).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "Legal\n");
+        assert_eq!(fs::read_to_string(etag_path(&path)).unwrap(), "\"v1\"");
+        // Fresh: served from the cache without a request
+        fetch_to(&url, &path, false, TIMEOUT).unwrap();
+        // Stale: revalidated with If-None-Match, and fresh again after the 304
+        make_stale();
+        fetch_to(&url, &path, false, TIMEOUT).unwrap();
+        assert!(is_fresh(&path));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "Legal\n");
+        make_stale();
+        assert_eq!(
+            fetch_to(&url, &path, false, TIMEOUT).unwrap_err(),
+            format!("Failed to fetch {url}: HTTP 404 Not Found")
+        );
+        assert_eq!(
+            fetch_to(&url, &path, false, TIMEOUT).unwrap_err(),
+            format!(
+                "Failed to fetch {url}: HTTP 302 Found redirects to https://evil.test/a.md; \
+                 include the final URL instead"
+            )
+        );
+        assert_eq!(fs::read_to_string(&path).unwrap(), "Legal\n");
+        let err = fetch_to(&url, &path, false, Duration::from_millis(200)).unwrap_err();
+        assert!(
+            err.starts_with(&format!("Timed out fetching {url}")),
+            "{err}"
+        );
+    }
+}
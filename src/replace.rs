@@ -0,0 +1,363 @@
+//! Library-wide find-and-replace for the `sed` subcommand.
+//!
+//! Rewrites every library fragment a profile references, once each, with a
+//! regular expression (or literal) substitution. A leading `---` frontmatter
+//! block is left alone unless asked otherwise. Nothing is written without
+//! `--apply`: by default the per-file counts are printed as a preview. Each
+//! fragment is rewritten through a temporary file renamed over it, so an
+//! interrupted run never leaves a half-written fragment. A fragment that
+//! cannot be read or written stops the run, or with `--keep-going` is
+//! reported after the rest have been rewritten.
+
+use regex::{NoExpand, Regex};
+use serde::Serialize;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+use crate::progress::progress_bar;
+use crate::{Config, ConfigSource, FailurePolicy, JsonLayout, referenced_fragments, to_json};
+
+/// What to replace, where, and whether to stop at the first failure.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplaceOptions {
+    /// Regular expression to search for (a plain string with `literal`)
+    pub pattern: String,
+    /// Replacement text; `$1` / `${name}` refer to capture groups unless `literal`
+    pub replacement: String,
+    /// Treat the pattern and replacement as plain text
+    pub literal: bool,
+    /// Also rewrite a leading `---` frontmatter block
+    pub include_frontmatter: bool,
+    /// Stop at the first fragment that fails, or rewrite the rest first
+    pub failures: FailurePolicy,
+    /// Show a progress bar on stderr (`--progress`)
+    pub progress: bool,
+}
+
+/// Replacements made (or, in a preview, that would be made) in one fragment.
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct FileReplacements {
+    /// Fragment path relative to the library
+    pub file: String,
+    /// Number of matches replaced
+    pub replacements: usize,
+}
+
+/// Summary of a find-and-replace run
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub struct ReplaceOutput {
+    /// Affected fragments, in profile order
+    pub files: Vec<FileReplacements>,
+    /// Total number of matches replaced
+    pub total: usize,
+    /// Whether the changes were written (`--apply`)
+    pub written: bool,
+    /// Fragments that could not be rewritten (`--keep-going`)
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<String>,
+}
+
+/// Length of the `---` frontmatter block at the start of `text`, including
+/// its closing delimiter line; 0 when there is none.
+fn frontmatter_len(text: &str) -> usize {
+    let mut lines = text.split_inclusive('\n');
+    let Some(open) = lines.next().filter(|line| line.trim_end() == "---") else {
+        return 0;
+    };
+    let mut len = open.len();
+    for line in lines {
+        len += line.len();
+        if line.trim_end() == "---" {
+            return len;
+        }
+    }
+    0
+}
+
+/// Contents of the `---` frontmatter block at the start of `text`, without
+/// its delimiter lines.
+pub(crate) fn frontmatter(text: &str) -> Option<&str> {
+    let len = frontmatter_len(text);
+    let block = &text[..len];
+    let start = block.find('\n')? + 1;
+    let inner = &block[start..];
+    let end = inner
+        .trim_end_matches(['\n', '\r'])
+        .rfind('\n')
+        .map_or(0, |i| i + 1);
+    Some(&inner[..end])
+}
+
+/// Apply the replacement to `text`, returning the new text and match count.
+fn replace_text(re: &Regex, text: &str, opts: &ReplaceOptions) -> (String, usize) {
+    let skip = if opts.include_frontmatter {
+        0
+    } else {
+        frontmatter_len(text)
+    };
+    let (head, body) = text.split_at(skip);
+    let count = re.find_iter(body).count();
+    if count == 0 {
+        return (text.to_string(), 0);
+    }
+    let replaced = if opts.literal {
+        re.replace_all(body, NoExpand(&opts.replacement))
+    } else {
+        re.replace_all(body, opts.replacement.as_str())
+    };
+    (format!("{head}{replaced}"), count)
+}
+
+/// Replace the contents of `path` with `text` through a temporary file in the
+/// same directory, keeping the file's permissions.
+fn write_replacing(path: &Path, text: &str) -> io::Result<()> {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let staged = path.with_file_name(format!(".{name}.sed-{}", std::process::id()));
+    let written = fs::metadata(path).and_then(|meta| {
+        fs::write(&staged, text)?;
+        fs::set_permissions(&staged, meta.permissions())?;
+        fs::rename(&staged, path)
+    });
+    if written.is_err() {
+        let _ = fs::remove_file(&staged);
+    }
+    written
+}
+
+/// Replace in one fragment, writing it when `apply` is set, and return the
+/// match count. A missing fragment has no matches.
+fn replace_in_file(
+    re: &Regex,
+    path: &Path,
+    opts: &ReplaceOptions,
+    apply: bool,
+) -> Result<usize, String> {
+    if !path.is_file() {
+        return Ok(0);
+    }
+    let text = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let (replaced, replacements) = replace_text(re, &text, opts);
+    if replacements > 0 && apply {
+        write_replacing(path, &replaced)
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    }
+    Ok(replacements)
+}
+
+/// Find and replace across every fragment referenced by a profile, writing
+/// the results when `apply` is set. Missing fragments are skipped.
+///
+/// With [`FailurePolicy::KeepGoing`], fragments that cannot be read or
+/// written are listed in `errors` instead of ending the run.
+///
+/// # Errors
+/// Returns an error if the pattern is not a valid regular expression or,
+/// with [`FailurePolicy::FailFast`], a fragment cannot be read or written.
+pub fn replace_in_library(
+    cfg: &Config,
+    lib: &Path,
+    opts: &ReplaceOptions,
+    apply: bool,
+) -> Result<ReplaceOutput, String> {
+    let pattern = if opts.literal {
+        regex::escape(&opts.pattern)
+    } else {
+        opts.pattern.clone()
+    };
+    let re =
+        Regex::new(&pattern).map_err(|e| format!("Invalid pattern '{}': {e}", opts.pattern))?;
+
+    let fragments = referenced_fragments(cfg);
+    let progress = progress_bar("replacing", fragments.len(), opts.progress);
+    let mut files = Vec::new();
+    let mut errors = Vec::new();
+    for (_, file) in fragments {
+        let replaced = replace_in_file(&re, &lib.join(file), opts, apply);
+        progress.inc(1);
+        match replaced {
+            Ok(0) => {}
+            Ok(replacements) => files.push(FileReplacements {
+                file: file.to_string(),
+                replacements,
+            }),
+            Err(e) if opts.failures == FailurePolicy::KeepGoing => errors.push(e),
+            Err(e) => {
+                progress.finish_and_clear();
+                return Err(e);
+            }
+        }
+    }
+    progress.finish_and_clear();
+    Ok(ReplaceOutput {
+        total: files.iter().map(|f| f.replacements).sum(),
+        files,
+        written: apply,
+        errors,
+    })
+}
+
+/// Write a find-and-replace summary as text or JSON.
+///
+/// # Errors
+/// Returns an error if JSON serialization or writing to the output fails.
+pub fn show_replace(
+    output: &ReplaceOutput,
+    json: bool,
+    json_layout: JsonLayout,
+    mut w: impl Write,
+) -> Result<(), String> {
+    if json {
+        let json_output = to_json(output, json_layout)?;
+        return writeln!(w, "{json_output}").map_err(|e| format!("Write error: {e}"));
+    }
+    for file in &output.files {
+        writeln!(w, "{}: {} replacement(s)", file.file, file.replacements)
+            .map_err(|e| format!("Write error: {e}"))?;
+    }
+    let (verb, note) = if output.written {
+        ("Replaced", "")
+    } else {
+        ("Would replace", " (preview; pass --apply to write)")
+    };
+    writeln!(
+        w,
+        "{verb} {} occurrence(s) in {} file(s){note}",
+        output.total,
+        output.files.len()
+    )
+    .map_err(|e| format!("Write error: {e}"))
+}
+
+/// Find and replace across the configured library and print the summary.
+///
+/// # Errors
+/// Returns an error if the configuration cannot be loaded, the pattern is
+/// invalid, a fragment cannot be read or written (after the summary, with
+/// `--keep-going`), or writing to stdout fails.
+pub fn run_replace_stdout(
+    config: &ConfigSource,
+    opts: &ReplaceOptions,
+    apply: bool,
+    json: bool,
+    json_layout: JsonLayout,
+) -> Result<(), String> {
+    let (cfg, lib) = config.load()?;
+    let output = replace_in_library(&cfg, &lib, opts, apply)?;
+    show_replace(&output, json, json_layout, io::stdout())?;
+    if output.errors.is_empty() {
+        Ok(())
+    } else {
+        Err(output.errors.join("\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn opts(pattern: &str, replacement: &str) -> ReplaceOptions {
+        ReplaceOptions {
+            pattern: pattern.into(),
+            replacement: replacement.into(),
+            literal: false,
+            include_frontmatter: false,
+            failures: FailurePolicy::FailFast,
+            progress: false,
+        }
+    }
+
+    #[test]
+    fn replace_skips_frontmatter_unless_asked() {
+        let re = Regex::new("colou?r").unwrap();
+        let text = "---\ntitle: colour\n---\nA colour, another colour\n";
+        assert_eq!(
+            replace_text(&re, text, &opts("colour", "color")),
+            (
+                "---\ntitle: colour\n---\nA color, another color\n".into(),
+                2
+            )
+        );
+        let all = ReplaceOptions {
+            include_frontmatter: true,
+            ..opts("colour", "color")
+        };
+        assert_eq!(replace_text(&re, text, &all).1, 3);
+        assert_eq!(frontmatter_len("---\nunterminated\n"), 0);
+
+        let re = Regex::new(&regex::escape("a.b")).unwrap();
+        let literal = ReplaceOptions {
+            literal: true,
+            ..opts("a.b", "$1")
+        };
+        assert_eq!(replace_text(&re, "a.b axb", &literal).0, "$1 axb");
+    }
+
+    #[test]
+    fn preview_reports_without_writing() {
+        let lib = crate::mk_tmp("prompter_replace");
+        fs::create_dir_all(&lib).unwrap();
+        fs::write(lib.join("a.md"), "use foo and foo\n").unwrap();
+        fs::write(lib.join("b.md"), "nothing here\n").unwrap();
+        let cfg = Config {
+            profiles: HashMap::from([
+                ("p".into(), vec!["a.md".into(), "b.md".into()]),
+                ("q".into(), vec!["a.md#1".into(), "gone.md".into()]),
+            ]),
+            ..Config::default()
+        };
+        let opts = opts(r"f(o+)", "b${1}r");
+
+        let preview = replace_in_library(&cfg, &lib, &opts, false).unwrap();
+        assert_eq!(preview.total, 2);
+        assert_eq!(preview.files.len(), 1);
+        assert_eq!(
+            fs::read_to_string(lib.join("a.md")).unwrap(),
+            "use foo and foo\n"
+        );
+        let mut out = Vec::new();
+        show_replace(&preview, false, JsonLayout::Pretty, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "a.md: 2 replacement(s)\nWould replace 2 occurrence(s) in 1 file(s) (preview; pass --apply to write)\n"
+        );
+
+        let written = replace_in_library(&cfg, &lib, &opts, true).unwrap();
+        assert!(written.written);
+        assert_eq!(
+            fs::read_to_string(lib.join("a.md")).unwrap(),
+            "use boor and boor\n"
+        );
+        let leftovers: Vec<_> = fs::read_dir(&lib)
+            .unwrap()
+            .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+            .filter(|name| name.starts_with('.'))
+            .collect();
+        assert!(leftovers.is_empty(), "{leftovers:?}");
+
+        let bad = ReplaceOptions {
+            pattern: "(".into(),
+            ..opts.clone()
+        };
+        assert!(replace_in_library(&cfg, &lib, &bad, false).is_err());
+
+        fs::write(lib.join("c.md"), b"foo \xff\n").unwrap();
+        fs::write(lib.join("a.md"), "foo\n").unwrap();
+        let cfg = Config {
+            profiles: HashMap::from([("p".into(), vec!["c.md".into(), "a.md".into()])]),
+            ..Config::default()
+        };
+        let err = replace_in_library(&cfg, &lib, &opts, true).unwrap_err();
+        assert!(err.contains("c.md"), "{err}");
+        assert_eq!(fs::read_to_string(lib.join("a.md")).unwrap(), "foo\n");
+        let keep_going = ReplaceOptions {
+            failures: FailurePolicy::KeepGoing,
+            ..opts
+        };
+        let output = replace_in_library(&cfg, &lib, &keep_going, true).unwrap();
+        assert_eq!(output.errors.len(), 1);
+        assert_eq!(fs::read_to_string(lib.join("a.md")).unwrap(), "boor\n");
+    }
+}
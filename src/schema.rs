@@ -0,0 +1,237 @@
+//! JSON Schema for configuration files (`prompter config-schema`).
+//!
+//! Editors such as VS Code (with a TOML or YAML extension) validate and
+//! complete `config.toml` against it. The schema follows the document the
+//! loader accepts: the top-level settings, the `[profiles]` and `[scenarios]`
+//! and `[remote]` tables, and every other table as a profile section. Because TOML reads
+//! `[python.api]` as a `python` table holding `api`, a profile section may
+//! also hold nested sections.
+
+use serde_json::{Value, json};
+
+use crate::{JsonLayout, to_json};
+
+/// JSON Schema dialect the schema is written in.
+pub const SCHEMA_DIALECT: &str = "https://json-schema.org/draft/2020-12/schema";
+
+/// The JSON Schema describing a configuration file.
+#[must_use]
+pub fn config_schema() -> Value {
+    let strings = json!({ "type": "array", "items": { "type": "string" } });
+    json!({
+        "$schema": SCHEMA_DIALECT,
+        "title": "prompter configuration",
+        "description": "Profiles, inline profiles, scenarios, and render settings for prompter.",
+        "type": "object",
+        "properties": {
+            "post_prompt": {
+                "type": "string",
+                "description": "Text appended after the rendered profiles unless --post-prompt is given"
+            },
+            "comment_prefix": {
+                "type": "string",
+                "description": "Prefix of fragment lines left out of the output (default \"//\"; empty disables)"
+            },
+            "relative_to": {
+                "type": "string",
+                "description": "Directory the library (DIR/library) and @path includes resolve against"
+            },
+            "include_base": {
+                "type": "string",
+                "description": "Directory profile fragments resolve against instead of the library"
+            },
+            "case_insensitive_profiles": {
+                "type": "boolean",
+                "description": "Match profile names without regard to case"
+            },
+            "profiles": {
+                "type": "object",
+                "description": "Inline profiles: name to body text",
+                "additionalProperties": { "type": "string" }
+            },
+            "scenarios": {
+                "type": "object",
+                "description": "Named sets of profiles and render settings for run --scenario",
+                "additionalProperties": { "$ref": "#/$defs/scenario" }
+            },
+            "remote": {
+                "type": "object",
+                "description": "Policy for https:// includes",
+                "properties": {
+                    "allowed_hosts": {
+                        "type": "array",
+                        "items": { "type": "string" },
+                        "description": "Hosts remote includes may be fetched from; any host when unset"
+                    }
+                }
+            }
+        },
+        "additionalProperties": { "$ref": "#/$defs/profile" },
+        "$defs": {
+            "profile": {
+                "type": "object",
+                "description": "A profile section; dotted names may nest as tables",
+                "properties": {
+                    "depends_on": {
+                        "description": "Fragments (.md, @path, with optional ? prefix and #range) and profiles to include, in order",
+                        "type": "array",
+                        "items": { "type": "string" }
+                    },
+                    "description": { "type": "string", "description": "One-line summary" },
+                    "tags": strings,
+                    "extends": {
+                        "type": "string",
+                        "description": "Parent profile whose dependencies come first"
+                    },
+                    "review_after": {
+                        "type": "string",
+                        "pattern": "^[0-9]{4}-[0-9]{2}-[0-9]{2}$",
+                        "description": "Date (YYYY-MM-DD) after which validate --review flags the profile"
+                    }
+                },
+                "additionalProperties": { "$ref": "#/$defs/profile" }
+            },
+            "scenario": {
+                "type": "object",
+                "properties": {
+                    "profiles": strings,
+                    "separator": { "type": "string" },
+                    "pre_prompt": { "type": "string" },
+                    "post_prompt": { "type": "string" }
+                },
+                "additionalProperties": false
+            }
+        }
+    })
+}
+
+/// Print the configuration JSON Schema to stdout.
+///
+/// # Errors
+/// Returns an error if JSON serialization fails.
+pub fn run_config_schema_stdout(json_layout: JsonLayout) -> Result<(), String> {
+    println!("{}", to_json(&config_schema(), json_layout)?);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Check `instance` against the subset of JSON Schema the config schema
+    /// uses, collecting the paths that do not match.
+    fn check(root: &Value, schema: &Value, instance: &Value, path: &str, errors: &mut Vec<String>) {
+        if let Some(reference) = schema["$ref"].as_str() {
+            let name = reference.trim_start_matches("#/$defs/");
+            return check(root, &root["$defs"][name], instance, path, errors);
+        }
+        if schema == &Value::Bool(false) {
+            errors.push(format!("{path}: not allowed"));
+            return;
+        }
+        let matches = match schema["type"].as_str() {
+            Some("object") => instance.is_object(),
+            Some("array") => instance.is_array(),
+            Some("string") => instance.is_string(),
+            Some("boolean") => instance.is_boolean(),
+            _ => true,
+        };
+        if !matches {
+            errors.push(format!("{path}: expected {}", schema["type"]));
+            return;
+        }
+        if let (Some(pattern), Some(text)) = (schema["pattern"].as_str(), instance.as_str()) {
+            let re = regex::Regex::new(pattern).unwrap();
+            if !re.is_match(text) {
+                errors.push(format!("{path}: does not match {pattern}"));
+            }
+        }
+        if let Some(items) = instance.as_array() {
+            for (i, item) in items.iter().enumerate() {
+                check(
+                    root,
+                    &schema["items"],
+                    item,
+                    &format!("{path}[{i}]"),
+                    errors,
+                );
+            }
+        }
+        if let Some(fields) = instance.as_object() {
+            for (key, value) in fields {
+                let field = schema["properties"]
+                    .get(key)
+                    .unwrap_or_else(|| &schema["additionalProperties"]);
+                check(root, field, value, &format!("{path}.{key}"), errors);
+            }
+        }
+    }
+
+    fn errors(instance: &Value) -> Vec<String> {
+        let schema = config_schema();
+        let mut errors = Vec::new();
+        check(&schema, &schema, instance, "$", &mut errors);
+        errors
+    }
+
+    #[test]
+    fn schema_is_well_formed() {
+        fn walk(value: &Value) {
+            if let Some(kind) = value.get("type") {
+                let known = ["object", "array", "string", "boolean"];
+                assert!(known.contains(&kind.as_str().unwrap()), "{kind}");
+            }
+            if let Some(object) = value.as_object() {
+                object.values().for_each(walk);
+            }
+        }
+
+        let schema = config_schema();
+        assert_eq!(schema["$schema"], SCHEMA_DIALECT);
+        let text = serde_json::to_string(&schema).unwrap();
+        for reference in text.split("\"$ref\":\"#/$defs/").skip(1) {
+            let name = &reference[..reference.find('"').unwrap()];
+            assert!(schema["$defs"][name].is_object(), "dangling $ref to {name}");
+        }
+        walk(&schema);
+    }
+
+    #[test]
+    fn schema_accepts_configs_the_loader_accepts() {
+        let config = json!({
+            "post_prompt": "Done.",
+            "case_insensitive_profiles": true,
+            "profiles": { "greet": "Hi" },
+            "python.api": {
+                "description": "Python",
+                "tags": ["python"],
+                "depends_on": ["a.md", "?@README.md#1-5", "greet"],
+                "review_after": "2025-01-31"
+            },
+            "rust": { "errors": { "extends": "python.api", "depends_on": [] } },
+            "scenarios": { "review": { "profiles": ["python.api"], "separator": "--" } },
+            "remote": { "allowed_hosts": ["example.com"] }
+        });
+        assert_eq!(errors(&config), Vec::<String>::new());
+        let parsed = crate::parse_config(
+            &serde_json::to_string(&config).unwrap(),
+            crate::ConfigFormat::Json,
+        );
+        assert!(parsed.is_ok(), "{parsed:?}");
+
+        let bad = json!({
+            "case_insensitive_profiles": "yes",
+            "p": { "depends_on": "a.md", "review_after": "soon" },
+            "scenarios": { "r": { "profile": [] } }
+        });
+        assert_eq!(
+            errors(&bad),
+            [
+                "$.case_insensitive_profiles: expected \"boolean\"",
+                "$.p.depends_on: expected \"array\"",
+                "$.p.review_after: does not match ^[0-9]{4}-[0-9]{2}-[0-9]{2}$",
+                "$.scenarios.r.profile: not allowed",
+            ]
+        );
+    }
+}
@@ -0,0 +1,252 @@
+//! HTTP access to profiles and renders (`prompter serve`).
+//!
+//! Two read-only endpoints, both answering from the configuration as it is on
+//! disk at the time of the request:
+//!
+//! - `GET /profiles`: the `list --json` document
+//! - `GET /render/PROFILE`: the rendered prompt as text; `separator=TEXT` and
+//!   repeated `var=KEY=VALUE` query parameters work like the `run` flags
+//!
+//! Renders use the plain system info line, without the emoji and color a
+//! terminal gets. Failures are `{"error": ...}` JSON objects with a 4xx or 5xx
+//! status. Each request is answered on its own thread, so a slow render (a
+//! remote include, say) does not hold up other clients. The server itself
+//! needs the `server` cargo feature; the request handling does not, so it is
+//! tested without a network.
+
+use chrono::Local;
+
+use crate::{
+    ConfigSource, ListFormat, ListOptions, RenderOptions, list_profiles, render_to_writer,
+    unescape, vars,
+};
+
+/// Default address `prompter serve` listens on.
+pub const DEFAULT_ADDR: &str = "127.0.0.1:8080";
+
+/// A response to one request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Response {
+    /// HTTP status code
+    pub status: u16,
+    /// `Content-Type` header value
+    pub content_type: &'static str,
+    /// Response body
+    pub body: String,
+}
+
+impl Response {
+    const fn text(body: String) -> Self {
+        Self {
+            status: 200,
+            content_type: "text/plain; charset=utf-8",
+            body,
+        }
+    }
+
+    const fn json(body: String) -> Self {
+        Self {
+            status: 200,
+            content_type: "application/json",
+            body,
+        }
+    }
+
+    fn error(status: u16, message: &str) -> Self {
+        Self {
+            status,
+            content_type: "application/json",
+            body: serde_json::json!({ "error": message }).to_string(),
+        }
+    }
+}
+
+/// Answer a request for `target` (path and query) made with `method`.
+#[must_use]
+pub fn handle(method: &str, target: &str, config: &ConfigSource) -> Response {
+    if method != "GET" {
+        return Response::error(405, &format!("Method {method} not allowed"));
+    }
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    if path == "/profiles" {
+        return list(config);
+    }
+    match path.strip_prefix("/render/") {
+        Some(profile) if !profile.is_empty() => render(&percent_decode(profile), query, config),
+        _ => Response::error(404, &format!("No such endpoint: {path}")),
+    }
+}
+
+fn list(config: &ConfigSource) -> Response {
+    let mut out = Vec::new();
+    let listed = config.load().and_then(|(cfg, lib)| {
+        list_profiles(
+            &cfg,
+            &lib,
+            &ListOptions::default(),
+            ListFormat::Json,
+            &mut out,
+        )
+    });
+    match listed {
+        Ok(()) => Response::json(String::from_utf8_lossy(&out).into_owned()),
+        Err(e) => Response::error(500, &e),
+    }
+}
+
+fn render(profile: &str, query: &str, config: &ConfigSource) -> Response {
+    let opts = match render_options(query) {
+        Ok(opts) => opts,
+        Err(e) => return Response::error(400, &e),
+    };
+    let (cfg, lib) = match config.load() {
+        Ok(loaded) => loaded,
+        Err(e) => return Response::error(500, &e),
+    };
+    if !cfg.profiles.contains_key(cfg.profile_name(profile)) {
+        return Response::error(404, &format!("Unknown profile: {profile}"));
+    }
+    let mut out = Vec::new();
+    match render_to_writer(&cfg, &lib, &mut out, &[profile.to_string()], &opts) {
+        Ok(()) => Response::text(String::from_utf8_lossy(&out).into_owned()),
+        Err(e) => Response::error(500, &e.to_string()),
+    }
+}
+
+/// Render options from the query string of a `/render` request.
+fn render_options(query: &str) -> Result<RenderOptions, String> {
+    let mut opts = RenderOptions {
+        styled: false,
+        ..RenderOptions::default()
+    };
+    opts.vars.builtin = vars::Variables::date_builtins(Local::now(), vars::DEFAULT_DATE_FORMAT);
+    for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        let value = percent_decode(value);
+        match percent_decode(key).as_str() {
+            "separator" => opts.separator = Some(unescape(&value)),
+            "var" => {
+                let (key, value) = vars::parse_var(&value)?;
+                opts.vars.global.insert(key, value);
+            }
+            other => return Err(format!("Unknown query parameter: {other}")),
+        }
+    }
+    Ok(opts)
+}
+
+/// Decode `%XX` escapes and `+` (a space) in a URL component. Malformed
+/// escapes are kept as written.
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = text
+            .get(i + 1..i + 3)
+            .filter(|_| bytes[i] == b'%')
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        if let Some(byte) = escaped {
+            out.push(byte);
+            i += 3;
+        } else {
+            out.push(if bytes[i] == b'+' { b' ' } else { bytes[i] });
+            i += 1;
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Serve `config` over HTTP on `addr` until the process is stopped.
+///
+/// # Errors
+/// Returns an error if `addr` cannot be listened on.
+#[cfg(feature = "server")]
+pub fn run_serve(addr: &str, config: &ConfigSource, quiet: bool) -> Result<(), String> {
+    let server =
+        tiny_http::Server::http(addr).map_err(|e| format!("Failed to listen on {addr}: {e}"))?;
+    if !quiet {
+        eprintln!("Serving prompter on http://{}", server.server_addr());
+    }
+    for request in server.incoming_requests() {
+        let config = config.clone();
+        std::thread::spawn(move || respond(request, &config));
+    }
+    Ok(())
+}
+
+/// Answer one request and send the response.
+#[cfg(feature = "server")]
+fn respond(request: tiny_http::Request, config: &ConfigSource) {
+    let response = handle(request.method().as_str(), request.url(), config);
+    log::info!(
+        "{} {} -> {}",
+        request.method(),
+        request.url(),
+        response.status
+    );
+    let mut reply =
+        tiny_http::Response::from_string(response.body).with_status_code(response.status);
+    if let Ok(header) = tiny_http::Header::from_bytes("Content-Type", response.content_type) {
+        reply = reply.with_header(header);
+    }
+    if let Err(e) = request.respond(reply) {
+        log::warn!("failed to send response: {e}");
+    }
+}
+
+/// Serve `config` over HTTP on `addr` until the process is stopped.
+///
+/// # Errors
+/// Always: this build does not include the server.
+#[cfg(not(feature = "server"))]
+pub fn run_serve(addr: &str, _config: &ConfigSource, _quiet: bool) -> Result<(), String> {
+    Err(format!(
+        "Cannot serve on {addr}: prompter was built without the `server` feature"
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn handle_lists_and_renders_profiles() {
+        let lib = crate::mk_tmp("prompter_serve");
+        fs::create_dir_all(&lib).unwrap();
+        fs::write(lib.join("a.md"), "Hello {{name}}\n").unwrap();
+        fs::write(lib.join("b.md"), "Bye\n").unwrap();
+        let config = ConfigSource::IncludeBase(
+            Box::new(ConfigSource::Inline(
+                "[greet]\ndepends_on = [\"a.md\", \"b.md\"]\n".into(),
+            )),
+            lib,
+        );
+
+        let list = handle("GET", "/profiles", &config);
+        assert_eq!(list.status, 200);
+        let json: serde_json::Value = serde_json::from_str(&list.body).unwrap();
+        assert_eq!(json["profiles"][0]["name"], "greet");
+
+        let render = handle(
+            "GET",
+            "/render/greet?var=name%3DAda+L&separator=%5Cn--%5Cn",
+            &config,
+        );
+        assert_eq!(render.status, 200, "{}", render.body);
+        assert!(render.body.contains("Hello Ada L\n"), "{}", render.body);
+        assert!(render.body.contains("\n--\n\nBye\n"), "{}", render.body);
+        assert!(render.body.contains("\nToday is "), "{}", render.body);
+
+        let missing = handle("GET", "/render/nope", &config);
+        assert_eq!(
+            (missing.status, missing.body.as_str()),
+            (404, r#"{"error":"Unknown profile: nope"}"#)
+        );
+        assert_eq!(handle("GET", "/render/greet?x=1", &config).status, 400);
+        assert_eq!(handle("GET", "/", &config).status, 404);
+        assert_eq!(handle("POST", "/profiles", &config).status, 405);
+        assert_eq!(percent_decode("%zz%41"), "%zzA");
+    }
+}
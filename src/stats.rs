@@ -0,0 +1,242 @@
+//! Library-wide statistics for the `stats` subcommand.
+//!
+//! Summarizes profile counts, rendered sizes, include depth, and reuse so a
+//! single command gives a snapshot of the whole prompt library.
+
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+use crate::{
+    Config, ConfigSource, JsonLayout, is_fragment, library_fragments, longest_chain,
+    profile_body_bytes, split_optional, to_json,
+};
+
+/// Profile referenced most often by other profiles
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub struct MostIncluded {
+    /// Profile name
+    pub name: String,
+    /// Number of direct references from other profiles
+    pub count: usize,
+}
+
+/// Aggregate statistics for a configuration and its library
+#[derive(Debug, Serialize)]
+pub struct LibraryStats {
+    /// Number of profiles defined in the configuration
+    pub profile_count: usize,
+    /// Number of markdown fragments found in the library directory
+    pub fragment_count: usize,
+    /// Total size in bytes of all library fragments
+    pub total_bytes: u64,
+    /// Mean rendered body size across profiles, in bytes
+    pub average_profile_bytes: f64,
+    /// Median rendered body size across profiles, in bytes
+    pub median_profile_bytes: f64,
+    /// Longest chain of profile includes, outermost first
+    pub deepest_chain: Vec<String>,
+    /// Profile with the most direct references, if any profile is referenced
+    pub most_included: Option<MostIncluded>,
+}
+
+/// Compute statistics for every profile in `cfg` against the library at `lib`.
+///
+/// # Errors
+/// Returns an error if any profile fails to resolve or a fragment cannot be read.
+pub fn library_stats(cfg: &Config, lib: &Path) -> Result<LibraryStats, String> {
+    let fragments = library_fragments(lib)?;
+    let mut total_bytes = 0;
+    for fragment in &fragments {
+        total_bytes += file_size(&lib.join(fragment))?;
+    }
+
+    let mut names: Vec<&String> = cfg.profiles.keys().collect();
+    names.sort();
+
+    let mut sizes = Vec::with_capacity(names.len());
+    for name in &names {
+        sizes.push(profile_body_bytes(name, cfg, lib)?);
+    }
+
+    let mut memo = HashMap::new();
+    let mut deepest_chain = Vec::new();
+    for name in &names {
+        let chain = longest_chain(name, cfg, &mut memo);
+        if chain.len() > deepest_chain.len() {
+            deepest_chain = chain;
+        }
+    }
+
+    let (average_profile_bytes, median_profile_bytes) = average_and_median(&mut sizes);
+
+    Ok(LibraryStats {
+        profile_count: cfg.profiles.len(),
+        fragment_count: fragments.len(),
+        total_bytes,
+        average_profile_bytes,
+        median_profile_bytes,
+        deepest_chain,
+        most_included: most_included(cfg),
+    })
+}
+
+fn file_size(path: &Path) -> Result<u64, String> {
+    fs::metadata(path)
+        .map(|m| m.len())
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))
+}
+
+fn most_included(cfg: &Config) -> Option<MostIncluded> {
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for deps in cfg.profiles.values() {
+        let deps = deps.iter().map(|d| split_optional(d).0);
+        for dep in deps.filter(|d| !is_fragment(d)) {
+            *counts.entry(dep).or_default() += 1;
+        }
+    }
+    counts
+        .into_iter()
+        .max_by(|a, b| a.1.cmp(&b.1).then_with(|| b.0.cmp(a.0)))
+        .map(|(name, count)| MostIncluded {
+            name: name.to_string(),
+            count,
+        })
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn average_and_median(sizes: &mut [u64]) -> (f64, f64) {
+    if sizes.is_empty() {
+        return (0.0, 0.0);
+    }
+    sizes.sort_unstable();
+    let total: u64 = sizes.iter().sum();
+    let average = total as f64 / sizes.len() as f64;
+    let mid = sizes.len() / 2;
+    let median = if sizes.len() % 2 == 0 {
+        (sizes[mid - 1] + sizes[mid]) as f64 / 2.0
+    } else {
+        sizes[mid] as f64
+    };
+    (average, median)
+}
+
+/// Write library statistics as text or JSON.
+///
+/// # Errors
+/// Returns an error if JSON serialization or writing to the output fails.
+pub fn show_stats(
+    stats: &LibraryStats,
+    json: bool,
+    json_layout: JsonLayout,
+    mut w: impl Write,
+) -> Result<(), String> {
+    if json {
+        let json_output = to_json(stats, json_layout)?;
+        writeln!(&mut w, "{json_output}").map_err(|e| format!("Write error: {e}"))?;
+        return Ok(());
+    }
+
+    let chain = if stats.deepest_chain.is_empty() {
+        "none".to_string()
+    } else {
+        format!(
+            "{} ({})",
+            stats.deepest_chain.join(" -> "),
+            stats.deepest_chain.len()
+        )
+    };
+    let most = stats.most_included.as_ref().map_or_else(
+        || "none".to_string(),
+        |m| format!("{} ({})", m.name, m.count),
+    );
+
+    writeln!(
+        &mut w,
+        "Profiles: {}\nFragments: {}\nTotal bytes: {}\nAverage profile size: {:.1} bytes\nMedian profile size: {:.1} bytes\nDeepest include chain: {chain}\nMost included profile: {most}",
+        stats.profile_count,
+        stats.fragment_count,
+        stats.total_bytes,
+        stats.average_profile_bytes,
+        stats.median_profile_bytes,
+    )
+    .map_err(|e| format!("Write error: {e}"))
+}
+
+/// Compute and print library statistics to stdout.
+///
+/// # Errors
+/// Returns an error if the configuration cannot be read or parsed, a profile
+/// fails to resolve, or writing to stdout fails.
+pub fn run_stats_stdout(
+    config: &ConfigSource,
+    json: bool,
+    json_layout: JsonLayout,
+) -> Result<(), String> {
+    let (cfg, lib) = config.load()?;
+    let stats = library_stats(&cfg, &lib)?;
+    show_stats(&stats, json, json_layout, io::stdout())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stats_summarize_sizes_depth_and_reuse() {
+        let lib = crate::mk_tmp("prompter_stats");
+        fs::create_dir_all(&lib).unwrap();
+        fs::write(lib.join("a.md"), b"12345").unwrap();
+        fs::write(lib.join("b.md"), b"123").unwrap();
+        fs::write(lib.join("unused.md"), b"1").unwrap();
+        let cfg = Config {
+            profiles: HashMap::from([
+                ("base".into(), vec!["a.md".into()]),
+                ("mid".into(), vec!["base".into(), "b.md".into()]),
+                ("top".into(), vec!["mid".into(), "base".into()]),
+            ]),
+            post_prompt: None,
+            ..Config::default()
+        };
+
+        let stats = library_stats(&cfg, &lib).unwrap();
+        assert_eq!(stats.profile_count, 3);
+        assert_eq!(stats.fragment_count, 3);
+        assert_eq!(stats.total_bytes, 9);
+        // base=5, mid=8, top=8
+        assert!((stats.average_profile_bytes - 7.0).abs() < f64::EPSILON);
+        assert!((stats.median_profile_bytes - 8.0).abs() < f64::EPSILON);
+        assert_eq!(stats.deepest_chain, vec!["top", "mid", "base"]);
+        assert_eq!(
+            stats.most_included,
+            Some(MostIncluded {
+                name: "base".into(),
+                count: 2
+            })
+        );
+    }
+
+    #[test]
+    fn stats_empty_config() {
+        let lib = crate::mk_tmp("prompter_stats_empty");
+        fs::create_dir_all(&lib).unwrap();
+        let cfg = Config {
+            profiles: HashMap::new(),
+            post_prompt: None,
+            ..Config::default()
+        };
+        let stats = library_stats(&cfg, &lib).unwrap();
+        assert_eq!(stats.profile_count, 0);
+        assert!(stats.deepest_chain.is_empty());
+        assert!(stats.most_included.is_none());
+        let mut out = Vec::new();
+        show_stats(&stats, false, JsonLayout::Pretty, &mut out).unwrap();
+        assert!(
+            String::from_utf8(out)
+                .unwrap()
+                .contains("Most included profile: none")
+        );
+    }
+}
@@ -0,0 +1,127 @@
+//! Built-in starter libraries for `prompter init --template`.
+
+/// A starter configuration plus the library fragments it references.
+pub struct InitTemplate {
+    /// Name selected with `--template`
+    pub name: &'static str,
+    /// Contents of the generated `config.toml`
+    pub config: &'static str,
+    /// Library fragments as `(relative path, contents)`
+    pub files: &'static [(&'static str, &'static str)],
+}
+
+/// Template used when `--template` is not given.
+pub const DEFAULT_TEMPLATE: &str = "coding";
+
+/// All built-in templates.
+pub const TEMPLATES: &[InitTemplate] = &[
+    InitTemplate {
+        name: "minimal",
+        config: r#"# Prompter configuration
+# Profiles map to sets of markdown files and/or other profiles.
+# Files are relative to $HOME/.local/prompter/library
+
+[base]
+depends_on = ["base.md"]
+"#,
+        files: &[(
+            "base.md",
+            "# base.md\nShared instructions for every prompt.\n",
+        )],
+    },
+    InitTemplate {
+        name: "coding",
+        config: r#"# Prompter configuration
+# Profiles map to sets of markdown files and/or other profiles.
+# Files are relative to $HOME/.local/prompter/library
+
+[python.api]
+depends_on = ["a/b/c.md", "f/g/h.md"]
+
+[general.testing]
+depends_on = ["python.api", "a/b/d.md"]
+"#,
+        files: &[
+            ("a/b/c.md", "# a/b/c.md\nExample snippet for python.api.\n"),
+            ("a/b.md", "# a/b.md\nFolder-level notes.\n"),
+            ("a/b/d.md", "# a/b/d.md\nGeneral testing snippet.\n"),
+            ("f/g/h.md", "# f/g/h.md\nShared helper snippet.\n"),
+        ],
+    },
+    InitTemplate {
+        name: "writing",
+        config: r#"# Prompter configuration
+# Profiles map to sets of markdown files and/or other profiles.
+# Files are relative to $HOME/.local/prompter/library
+
+[writing.style]
+depends_on = ["writing/voice.md", "writing/structure.md"]
+
+[writing.review]
+depends_on = ["writing.style", "writing/editing.md"]
+"#,
+        files: &[
+            (
+                "writing/voice.md",
+                "# writing/voice.md\nWrite plainly, in the active voice, for a general reader.\n",
+            ),
+            (
+                "writing/structure.md",
+                "# writing/structure.md\nLead with the main point; one idea per paragraph.\n",
+            ),
+            (
+                "writing/editing.md",
+                "# writing/editing.md\nPoint out unclear sentences and suggest shorter alternatives.\n",
+            ),
+        ],
+    },
+];
+
+/// Look up a built-in template by name.
+///
+/// # Errors
+/// Returns an error listing the available templates if `name` is unknown.
+pub fn find_template(name: &str) -> Result<&'static InitTemplate, String> {
+    TEMPLATES.iter().find(|t| t.name == name).ok_or_else(|| {
+        let names: Vec<&str> = TEMPLATES.iter().map(|t| t.name).collect();
+        format!(
+            "Unknown template '{name}'. Available templates: {}",
+            names.join(", ")
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse_config_toml, validate};
+
+    #[test]
+    fn templates_are_self_consistent() {
+        let root = crate::mk_tmp("prompter_templates");
+        for template in TEMPLATES {
+            let lib = root.join(template.name);
+            for (path, contents) in template.files {
+                let path = lib.join(path);
+                std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+                std::fs::write(path, contents).unwrap();
+            }
+            let cfg = parse_config_toml(template.config).unwrap();
+            assert!(
+                validate(&cfg, &lib, &crate::ValidateOptions::default()).is_ok(),
+                "template {} does not validate",
+                template.name
+            );
+        }
+        assert!(find_template(DEFAULT_TEMPLATE).is_ok());
+    }
+
+    #[test]
+    fn unknown_template_lists_available() {
+        let err = find_template("nope").err().unwrap();
+        assert_eq!(
+            err,
+            "Unknown template 'nope'. Available templates: minimal, coding, writing"
+        );
+    }
+}
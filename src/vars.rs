@@ -0,0 +1,388 @@
+//! `{{name}}` variable substitution for rendered fragments.
+//!
+//! Variables come from `run --env-file PATH`, `run --capture-env KEY`, and
+//! `run --var KEY=VAL` (global, in increasing precedence), and
+//! `run --profile-arg PROFILE:KEY=VAL` (only for content reached through
+//! `PROFILE`), on top of the built-in `today`, `now`, and `year`.
+//! Placeholders without a value are left untouched, and `\{\{` renders as a
+//! literal `{{`.
+
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, Local};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::{env, fs};
+
+/// Escaped placeholder opening, rendered as a literal `{{`.
+const ESCAPED_OPEN: &str = "\\{\\{";
+
+/// Default `strftime` format of the built-in `{{today}}` variable.
+pub const DEFAULT_DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// Variables applied while rendering.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Variables {
+    /// Predefined values, overridden by any explicit variable of the same name
+    pub builtin: BTreeMap<String, String>,
+    /// Values applied to every fragment
+    pub global: BTreeMap<String, String>,
+    /// Values applied to fragments reached through a profile, by profile name
+    pub profile: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+impl Variables {
+    /// Built-in date variables for `now`: `today` (formatted with
+    /// `date_format`), `now` (RFC 3339), and `year`.
+    #[must_use]
+    pub fn date_builtins(now: DateTime<Local>, date_format: &str) -> BTreeMap<String, String> {
+        BTreeMap::from([
+            ("today".to_string(), now.format(date_format).to_string()),
+            (
+                "now".to_string(),
+                now.to_rfc3339_opts(chrono::SecondsFormat::Secs, false),
+            ),
+            ("year".to_string(), now.format("%Y").to_string()),
+        ])
+    }
+
+    /// Substitution context for a fragment included through the profiles in
+    /// `via` (outermost first). Profile-scoped values override global ones,
+    /// and a nearer profile overrides a more distant one.
+    #[must_use]
+    pub fn context<'a>(&'a self, via: &[String]) -> HashMap<&'a str, &'a str> {
+        let mut ctx: HashMap<&str, &str> = self
+            .builtin
+            .iter()
+            .chain(&self.global)
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+            .collect();
+        for profile in via {
+            if let Some(scoped) = self.profile.get(profile) {
+                ctx.extend(scoped.iter().map(|(k, v)| (k.as_str(), v.as_str())));
+            }
+        }
+        ctx
+    }
+}
+
+/// Validate a `strftime` format for `--date-format`.
+///
+/// # Errors
+/// Returns an error if the format contains an unknown specifier.
+pub fn parse_date_format(s: &str) -> Result<String, String> {
+    if StrftimeItems::new(s).any(|item| matches!(item, Item::Error)) {
+        return Err(format!("Invalid date format '{s}'"));
+    }
+    Ok(s.to_string())
+}
+
+/// Parse a `KEY=VAL` variable assignment.
+///
+/// # Errors
+/// Returns an error if there is no `=` or the key is empty.
+pub fn parse_var(s: &str) -> Result<(String, String), String> {
+    let (key, value) = s
+        .split_once('=')
+        .ok_or_else(|| format!("Invalid variable '{s}': expected KEY=VALUE"))?;
+    let key = key.trim();
+    if key.is_empty() {
+        return Err(format!("Invalid variable '{s}': empty name"));
+    }
+    Ok((key.to_string(), value.to_string()))
+}
+
+/// Capture the environment variable `key` as a variable (`--capture-env`).
+///
+/// # Errors
+/// Returns an error if the name is invalid or the variable is not set.
+pub fn capture_env(key: &str) -> Result<(String, String), String> {
+    captured(key, env::var(key).ok(), false)
+}
+
+/// Capture the environment variable `key` as a variable, empty when it is not
+/// set (`--capture-env-optional`).
+///
+/// # Errors
+/// Returns an error if the name is invalid.
+pub fn capture_env_optional(key: &str) -> Result<(String, String), String> {
+    captured(key, env::var(key).ok(), true)
+}
+
+fn captured(key: &str, value: Option<String>, optional: bool) -> Result<(String, String), String> {
+    if key.is_empty() || key.contains(['=', '\0']) {
+        return Err(format!("Invalid environment variable name '{key}'"));
+    }
+    match value {
+        Some(value) => Ok((key.to_string(), value)),
+        None if optional => Ok((key.to_string(), String::new())),
+        None => Err(format!(
+            "Environment variable {key} is not set (use --capture-env-optional to allow that)"
+        )),
+    }
+}
+
+/// Variables loaded from a `.env`-style file (`run --env-file`), in file order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EnvFile(pub Vec<(String, String)>);
+
+/// Read and parse a `.env`-style file for `--env-file`.
+///
+/// # Errors
+/// Returns an error if the file cannot be read or a line is malformed.
+pub fn read_env_file(path: &str) -> Result<EnvFile, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("Failed to read {path}: {e}"))?;
+    parse_env_file(&text)
+        .map(EnvFile)
+        .map_err(|e| format!("{path}: {e}"))
+}
+
+/// Parse the `KEY=VALUE` lines of a `.env`-style file.
+///
+/// Blank lines and `#` comments are skipped and an `export ` prefix is
+/// accepted. Double-quoted values may contain `\n`, `\t`, `\"`, and `\\`
+/// escapes; single-quoted values are literal; unquoted values are trimmed and
+/// end at a ` #` comment.
+///
+/// # Errors
+/// Returns an error naming the first malformed line.
+pub fn parse_env_file(text: &str) -> Result<Vec<(String, String)>, String> {
+    let mut vars = Vec::new();
+    for (i, raw) in text.lines().enumerate() {
+        let line = raw.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let (key, value) = parse_var(line).map_err(|e| format!("line {}: {e}", i + 1))?;
+        let value = parse_env_value(value.trim())
+            .ok_or_else(|| format!("line {}: unterminated quoted value", i + 1))?;
+        vars.push((key, value));
+    }
+    Ok(vars)
+}
+
+/// Unquote an env-file value; `None` if a quote is left open.
+fn parse_env_value(value: &str) -> Option<String> {
+    if let Some(rest) = value.strip_prefix('\'') {
+        return rest
+            .split_once('\'')
+            .map(|(literal, _)| literal.to_string());
+    }
+    let Some(rest) = value.strip_prefix('"') else {
+        let end = value.find(" #").unwrap_or(value.len());
+        return Some(value[..end].trim_end().to_string());
+    };
+    let mut out = String::new();
+    let mut chars = rest.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => return Some(out),
+            '\\' => match chars.next()? {
+                'n' => out.push('\n'),
+                't' => out.push('\t'),
+                other => out.push(other),
+            },
+            c => out.push(c),
+        }
+    }
+    None
+}
+
+/// Parse a `PROFILE:KEY=VAL` profile-scoped variable assignment.
+///
+/// # Errors
+/// Returns an error if the profile or key is missing.
+pub fn parse_profile_arg(s: &str) -> Result<(String, String, String), String> {
+    let (profile, assignment) = s
+        .split_once(':')
+        .ok_or_else(|| format!("Invalid profile argument '{s}': expected PROFILE:KEY=VALUE"))?;
+    let profile = profile.trim();
+    if profile.is_empty() {
+        return Err(format!(
+            "Invalid profile argument '{s}': empty profile name"
+        ));
+    }
+    let (key, value) = parse_var(assignment)?;
+    Ok((profile.to_string(), key, value))
+}
+
+/// Replace `{{name}}` placeholders (surrounding whitespace allowed) with
+/// values from `ctx`, leaving unknown placeholders as written and turning
+/// escaped `\{\{` into a literal `{{`.
+#[must_use]
+#[allow(clippy::implicit_hasher)]
+pub fn substitute(content: &str, ctx: &HashMap<&str, &str>) -> String {
+    if ctx.is_empty() {
+        return content.replace(ESCAPED_OPEN, "{{");
+    }
+    let mut out = String::with_capacity(content.len());
+    let mut rest = content;
+    while let Some(open) = rest.find("{{") {
+        out.push_str(&rest[..open].replace(ESCAPED_OPEN, "{{"));
+        let after = &rest[open + 2..];
+        let Some(close) = after.find("}}") else {
+            rest = &rest[open..];
+            break;
+        };
+        match ctx.get(after[..close].trim()) {
+            Some(value) => out.push_str(value),
+            None => out.push_str(&rest[open..open + 2 + close + 2]),
+        }
+        rest = &after[close + 2..];
+    }
+    out.push_str(&rest.replace(ESCAPED_OPEN, "{{"));
+    out
+}
+
+/// `content` with each escaped `\{\{` turned into `{ {`, which neither
+/// [`substitute`] nor a placeholder scan reads as `{{`.
+#[must_use]
+pub fn hide_escapes(content: &str) -> String {
+    content.replace(ESCAPED_OPEN, "{ {")
+}
+
+/// Trimmed names of the `{{name}}` placeholders in `content`, in order.
+fn tokens(content: &str) -> impl Iterator<Item = &str> {
+    let mut rest = content;
+    std::iter::from_fn(move || {
+        let open = rest.find("{{")?;
+        let after = &rest[open + 2..];
+        let close = after.find("}}")?;
+        rest = &after[close + 2..];
+        Some(after[..close].trim())
+    })
+}
+
+/// Names of the `{{name}}` placeholders in `content`, sorted and deduplicated.
+#[must_use]
+pub fn placeholders(content: &str) -> BTreeSet<&str> {
+    tokens(content).filter(|name| !name.is_empty()).collect()
+}
+
+/// Placeholders in `content` that `ctx` has no value for (including empty
+/// `{{}}`), with their 1-based line numbers, in order of appearance.
+#[must_use]
+#[allow(clippy::implicit_hasher)]
+pub fn unresolved<'a>(content: &'a str, ctx: &HashMap<&str, &str>) -> Vec<(usize, &'a str)> {
+    content
+        .lines()
+        .enumerate()
+        .flat_map(|(i, line)| tokens(line).map(move |name| (i + 1, name)))
+        .filter(|(_, name)| !ctx.contains_key(name))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_assignments() {
+        assert_eq!(parse_var("a=b=c"), Ok(("a".into(), "b=c".into())));
+        assert!(parse_var("novalue").is_err());
+        assert!(parse_var("=x").is_err());
+        assert_eq!(
+            parse_profile_arg("backend:region=us-east"),
+            Ok(("backend".into(), "region".into(), "us-east".into()))
+        );
+        assert!(parse_profile_arg("region=us-east").is_err());
+    }
+
+    #[test]
+    fn captures_environment_values() {
+        assert_eq!(
+            captured("CI", Some("true".into()), false),
+            Ok(("CI".into(), "true".into()))
+        );
+        assert_eq!(captured("CI", None, true), Ok(("CI".into(), String::new())));
+        assert!(
+            captured("CI", None, false)
+                .unwrap_err()
+                .starts_with("Environment variable CI is not set")
+        );
+        assert!(captured("A=B", None, true).is_err());
+    }
+
+    #[test]
+    fn parses_env_files() {
+        let text = "# comment\n\nexport A=1\nB = plain value # note\nC=\"two\\nlines \\\"q\\\"\"\nD='$raw \\n'\nE=\n";
+        assert_eq!(
+            parse_env_file(text).unwrap(),
+            [
+                ("A".into(), "1".into()),
+                ("B".into(), "plain value".into()),
+                ("C".into(), "two\nlines \"q\"".into()),
+                ("D".into(), "$raw \\n".into()),
+                ("E".into(), String::new()),
+            ]
+        );
+        assert_eq!(
+            parse_env_file("A=1\nnovalue\n").unwrap_err(),
+            "line 2: Invalid variable 'novalue': expected KEY=VALUE"
+        );
+        assert!(parse_env_file("A=\"open\n").is_err());
+    }
+
+    #[test]
+    fn date_builtins_yield_to_explicit_vars() {
+        let now = chrono::TimeZone::with_ymd_and_hms(&Local, 2024, 3, 9, 14, 5, 0).unwrap();
+        let vars = Variables {
+            builtin: Variables::date_builtins(now, "%d/%m/%Y"),
+            global: BTreeMap::from([("year".into(), "1999".into())]),
+            ..Variables::default()
+        };
+        let ctx = vars.context(&[]);
+        assert_eq!(substitute("{{today}} {{year}}", &ctx), "09/03/2024 1999");
+        assert!(substitute("{{now}}", &ctx).starts_with("2024-03-09T14:05:00"));
+        assert!(parse_date_format("%A %e %B").is_ok());
+        assert!(parse_date_format("%Q").is_err());
+    }
+
+    #[test]
+    fn profile_scope_overrides_global() {
+        let vars = Variables {
+            global: BTreeMap::from([
+                ("region".into(), "eu".into()),
+                ("team".into(), "core".into()),
+            ]),
+            profile: BTreeMap::from([
+                (
+                    "backend".into(),
+                    BTreeMap::from([("region".into(), "us-east".into())]),
+                ),
+                (
+                    "api".into(),
+                    BTreeMap::from([("region".into(), "us-west".into())]),
+                ),
+            ]),
+            ..Variables::default()
+        };
+        let text = "{{ region }}/{{team}}/{{missing}}/{{";
+        assert_eq!(
+            substitute(text, &vars.context(&[])),
+            "eu/core/{{missing}}/{{"
+        );
+        let via = ["backend".to_string()];
+        assert_eq!(
+            substitute(text, &vars.context(&via)),
+            "us-east/core/{{missing}}/{{"
+        );
+        let via = ["backend".to_string(), "api".to_string()];
+        assert!(substitute(text, &vars.context(&via)).starts_with("us-west/"));
+        assert_eq!(
+            placeholders(text).into_iter().collect::<Vec<_>>(),
+            ["missing", "region", "team"]
+        );
+    }
+
+    #[test]
+    fn escapes_and_unresolved_tokens() {
+        let ctx = HashMap::from([("name", "N")]);
+        let text = "a \\{\\{name}} {{name}}\n{{ other }} {{}}\n";
+        assert_eq!(substitute(text, &ctx), "a {{name}} N\n{{ other }} {{}}\n");
+        assert_eq!(
+            substitute(text, &HashMap::new()),
+            "a {{name}} {{name}}\n{{ other }} {{}}\n"
+        );
+        assert_eq!(unresolved(text, &ctx), [(2, "other"), (2, "")]);
+    }
+}
@@ -0,0 +1,126 @@
+//! Diagnostics on stderr for `-v/--verbose` (stackable).
+//!
+//! Instrumentation goes through the `log` facade, so with verbosity off each
+//! point costs one level check. `-v` reports how long each phase took (config
+//! load, library scan, include resolution, body read, substitution,
+//! composition), `-vv` adds per-profile and per-file detail, and `-vvv`
+//! traces fragment cache hits. Parsing only records the level; the binary
+//! installs the logger with [`init`] before dispatching.
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use std::cell::Cell;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+/// Writes enabled records to stderr, stamped with the time since startup.
+struct StderrLogger {
+    start: OnceLock<Instant>,
+}
+
+static LOGGER: StderrLogger = StderrLogger {
+    start: OnceLock::new(),
+};
+
+impl Log for StderrLogger {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record<'_>) {
+        if self.enabled(record.metadata()) {
+            let elapsed = self.start.get_or_init(Instant::now).elapsed();
+            eprintln!(
+                "[{:>10} {:<5}] {}",
+                millis(elapsed),
+                record.level(),
+                record.args()
+            );
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// Log level for `verbosity` repetitions of `-v`.
+const fn level_filter(verbosity: u8) -> LevelFilter {
+    match verbosity {
+        0 => LevelFilter::Off,
+        1 => LevelFilter::Info,
+        2 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    }
+}
+
+/// Send diagnostics for `verbosity` repetitions of `-v` to stderr; 0 leaves
+/// logging off.
+pub fn init(verbosity: u8) {
+    let level = level_filter(verbosity);
+    if level == LevelFilter::Off {
+        return;
+    }
+    LOGGER.start.get_or_init(Instant::now);
+    // Only the first call can install the logger; later ones just set the level.
+    let _ = log::set_logger(&LOGGER);
+    log::set_max_level(level);
+}
+
+/// `elapsed` in milliseconds, e.g. `1.250ms`.
+fn millis(elapsed: Duration) -> String {
+    format!("{:.3}ms", elapsed.as_secs_f64() * 1000.0)
+}
+
+/// Log that `phase` took `elapsed` (at `-v`).
+pub fn phase_took(phase: &str, elapsed: Duration) {
+    log::info!("{phase}: {}", millis(elapsed));
+}
+
+/// Run `f`, logging how long `phase` took when `-v` is on.
+pub fn timed<T>(phase: &str, f: impl FnOnce() -> T) -> T {
+    if !log::log_enabled!(Level::Info) {
+        return f();
+    }
+    let start = Instant::now();
+    let result = f();
+    phase_took(phase, start.elapsed());
+    result
+}
+
+/// Time spent in one phase over many short steps, measured only when `-v` is on.
+#[derive(Debug, Default)]
+pub struct PhaseClock(Cell<Duration>);
+
+impl PhaseClock {
+    /// Run `f`, adding its duration to the total when `-v` is on.
+    pub fn time<T>(&self, f: impl FnOnce() -> T) -> T {
+        if !log::log_enabled!(Level::Info) {
+            return f();
+        }
+        let start = Instant::now();
+        let result = f();
+        self.0.set(self.0.get() + start.elapsed());
+        result
+    }
+
+    /// Total time measured so far.
+    #[must_use]
+    pub fn total(&self) -> Duration {
+        self.0.get()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verbosity_maps_to_levels_and_formats_millis() {
+        assert_eq!(level_filter(0), LevelFilter::Off);
+        assert_eq!(level_filter(1), LevelFilter::Info);
+        assert_eq!(level_filter(2), LevelFilter::Debug);
+        assert_eq!(level_filter(7), LevelFilter::Trace);
+        assert_eq!(millis(Duration::from_micros(1250)), "1.250ms");
+        let clock = PhaseClock::default();
+        assert_eq!(clock.time(|| 3), 3);
+        assert_eq!(clock.total(), Duration::ZERO);
+    }
+}
@@ -5,8 +5,8 @@
 
 use std::env;
 use std::fs;
-use std::io::Read;
-use std::path::PathBuf;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
 use std::process::Command;
 
 fn tmp_home(prefix: &str) -> PathBuf {
@@ -35,17 +35,31 @@ const fn bin_path() -> &'static str {
     env!("CARGO_BIN_EXE_prompter")
 }
 
+/// The prompter binary with `HOME` and `XDG_STATE_HOME` set to `home`, so its
+/// config and render history stay inside the test directory.
+fn prompter(home: &Path) -> Command {
+    let mut cmd = Command::new(bin_path());
+    cmd.env("HOME", home).env("XDG_STATE_HOME", home);
+    cmd
+}
+
+/// Default config file of [`prompter`] run with `HOME` set to `home`.
+fn config_file(home: &Path) -> PathBuf {
+    home.join(".config/prompter/config.toml")
+}
+
+/// Default library directory of [`prompter`] run with `HOME` set to `home`.
+fn library_dir(home: &Path) -> PathBuf {
+    home.join(".local/prompter/library")
+}
+
 #[test]
 fn test_init_list_validate_run() {
     let home = tmp_home("prompter_it_home");
     fs::create_dir_all(&home).unwrap();
 
     // init
-    let out = Command::new(bin_path())
-        .env("HOME", &home)
-        .arg("init")
-        .output()
-        .unwrap();
+    let out = prompter(&home).arg("init").output().unwrap();
     assert!(
         out.status.success(),
         "init failed: {}",
@@ -53,22 +67,14 @@ fn test_init_list_validate_run() {
     );
 
     // list
-    let out = Command::new(bin_path())
-        .env("HOME", &home)
-        .arg("list")
-        .output()
-        .unwrap();
+    let out = prompter(&home).arg("list").output().unwrap();
     assert!(out.status.success());
     let list = String::from_utf8_lossy(&out.stdout);
     assert!(list.contains("python.api"));
     assert!(list.contains("general.testing"));
 
     // validate
-    let out = Command::new(bin_path())
-        .env("HOME", &home)
-        .arg("validate")
-        .output()
-        .unwrap();
+    let out = prompter(&home).arg("validate").output().unwrap();
     assert!(
         out.status.success(),
         "validate failed: {}",
@@ -76,8 +82,7 @@ fn test_init_list_validate_run() {
     );
 
     // run profile
-    let out = Command::new(bin_path())
-        .env("HOME", &home)
+    let out = prompter(&home)
         .arg("run")
         .arg("python.api")
         .output()
@@ -125,23 +130,14 @@ depends_on = ["does.not.exist.md", "unknown_profile"]
     fs::write(cfg_path.join("config.toml"), cfg).unwrap();
 
     // validate should fail
-    let out = Command::new(bin_path())
-        .env("HOME", &home)
-        .arg("validate")
-        .output()
-        .unwrap();
+    let out = prompter(&home).arg("validate").output().unwrap();
     assert!(!out.status.success());
     let err = String::from_utf8_lossy(&out.stderr);
     assert!(err.contains("Missing file:"));
     assert!(err.contains("Unknown profile:"));
 
     // running profile should also fail
-    let out = Command::new(bin_path())
-        .env("HOME", &home)
-        .arg("run")
-        .arg("root")
-        .output()
-        .unwrap();
+    let out = prompter(&home).arg("run").arg("root").output().unwrap();
     assert!(!out.status.success());
 }
 
@@ -169,8 +165,7 @@ depends_on = ["child", "f/y.md", "a/x.md"]
     fs::write(cfg_path.join("config.toml"), cfg).unwrap();
 
     // run with separator that will be unescaped
-    let out = Command::new(bin_path())
-        .env("HOME", &home)
+    let out = prompter(&home)
         .args(["run", "--separator", "\\n--\\n", "root"]) // CLI will unescape to "\n--\n"
         .output()
         .unwrap();
@@ -196,6 +191,18 @@ depends_on = ["child", "f/y.md", "a/x.md"]
     assert!(output_str.ends_with(
         "Now, read the @AGENTS.md and @CLAUDE.md files in this directory, if they exist."
     ));
+
+    // The separator frames the body once, without repeating before the post-prompt
+    let out = prompter(&home)
+        .args(["run", "--no-system-info", "-s", "<SEP>", "-p", "PRE\\n"])
+        .args(["-P", "POST", "root"])
+        .output()
+        .unwrap();
+    assert!(out.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&out.stdout),
+        "PRE\n<SEP>\nAX\n<SEP>\nFY\n<SEP>\n\nPOST"
+    );
 }
 
 #[test]
@@ -214,11 +221,7 @@ depends_on = ["A"]
 "#;
     fs::write(cfg_path.join("config.toml"), cfg).unwrap();
 
-    let out = Command::new(bin_path())
-        .env("HOME", &home)
-        .arg("validate")
-        .output()
-        .unwrap();
+    let out = prompter(&home).arg("validate").output().unwrap();
     assert!(!out.status.success());
     let err = String::from_utf8_lossy(&out.stderr);
     assert!(err.contains("Cycle detected"), "stderr: {err}");
@@ -245,6 +248,18 @@ fn test_completions_bash() {
     assert!(stdout.contains("_prompter"));
 }
 
+#[test]
+fn test_completions_dynamic_command_override() {
+    let out = Command::new(bin_path())
+        .args(["completions", "bash", "--dynamic-command", "pr"])
+        .output()
+        .unwrap();
+    assert!(out.status.success());
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(stdout.contains("pr list --config"));
+    assert!(!stdout.contains("prompter list --config"));
+}
+
 #[test]
 fn test_completions_zsh() {
     let out = Command::new(bin_path())
@@ -278,11 +293,9 @@ fn test_doctor_command() {
 #[test]
 fn test_help_flag() {
     let out = Command::new(bin_path()).arg("--help").output().unwrap();
-    // Help output may go to stdout or stderr depending on exit code
-    let stdout = String::from_utf8_lossy(&out.stdout);
-    let stderr = String::from_utf8_lossy(&out.stderr);
-    let combined = format!("{stdout}{stderr}");
-    assert!(combined.contains("prompter") || combined.contains("Usage:"));
+    assert!(out.status.success());
+    assert!(String::from_utf8_lossy(&out.stdout).contains("Usage:"));
+    assert!(out.stderr.is_empty());
 }
 
 #[test]
@@ -291,15 +304,10 @@ fn test_run_with_separator() {
     fs::create_dir_all(&home).unwrap();
 
     // Init first
-    Command::new(bin_path())
-        .env("HOME", &home)
-        .arg("init")
-        .output()
-        .unwrap();
+    prompter(&home).arg("init").output().unwrap();
 
     // Run with custom separator
-    let out = Command::new(bin_path())
-        .env("HOME", &home)
+    let out = prompter(&home)
         .args(["run", "--separator", "\\n---\\n", "python.api"])
         .output()
         .unwrap();
@@ -313,14 +321,9 @@ fn test_run_with_custom_pre_prompt() {
     let home = tmp_home("prompter_it_pre");
     fs::create_dir_all(&home).unwrap();
 
-    Command::new(bin_path())
-        .env("HOME", &home)
-        .arg("init")
-        .output()
-        .unwrap();
+    prompter(&home).arg("init").output().unwrap();
 
-    let out = Command::new(bin_path())
-        .env("HOME", &home)
+    let out = prompter(&home)
         .args(["run", "--pre-prompt", "Custom prefix", "python.api"])
         .output()
         .unwrap();
@@ -334,14 +337,9 @@ fn test_run_with_custom_post_prompt() {
     let home = tmp_home("prompter_it_post");
     fs::create_dir_all(&home).unwrap();
 
-    Command::new(bin_path())
-        .env("HOME", &home)
-        .arg("init")
-        .output()
-        .unwrap();
+    prompter(&home).arg("init").output().unwrap();
 
-    let out = Command::new(bin_path())
-        .env("HOME", &home)
+    let out = prompter(&home)
         .args(["run", "--post-prompt", "Custom suffix", "python.api"])
         .output()
         .unwrap();
@@ -376,8 +374,7 @@ depends_on = ["shared/common.md", "b/only.md"]
     fs::write(cfg_path.join("config.toml"), cfg).unwrap();
 
     // Run with multiple profiles: `prompter run profile.a profile.b`
-    let out = Command::new(bin_path())
-        .env("HOME", &home)
+    let out = prompter(&home)
         .args(["run", "profile.a", "profile.b"])
         .output()
         .unwrap();
@@ -409,3 +406,1162 @@ depends_on = ["shared/common.md", "b/only.md"]
         "Files should appear in order: COMMON < A_ONLY < B_ONLY"
     );
 }
+
+#[test]
+fn test_color_option_controls_tree_output() {
+    let home = tmp_home("prompter_it_color");
+    fs::create_dir_all(&home).unwrap();
+    prompter(&home).arg("init").output().unwrap();
+
+    let out = prompter(&home)
+        .args(["--color", "always", "tree"])
+        .output()
+        .unwrap();
+    assert!(out.status.success());
+    assert!(String::from_utf8_lossy(&out.stdout).contains("\u{1b}["));
+
+    let out = prompter(&home)
+        .args(["--color", "never", "tree"])
+        .output()
+        .unwrap();
+    assert!(out.status.success());
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(!stdout.contains("\u{1b}["));
+    assert!(stdout.contains("general.testing"));
+}
+
+#[test]
+fn test_color_follows_stderr_for_validation_errors() {
+    let home = tmp_home("prompter_it_color_stderr");
+    fs::create_dir_all(&home).unwrap();
+    let validate = |color: &str| {
+        prompter(&home)
+            .args([
+                "--color",
+                color,
+                "--config-inline",
+                "[p]\ndepends_on = [\"x\"]\n",
+            ])
+            .arg("validate")
+            .output()
+            .unwrap()
+    };
+
+    let out = validate("always");
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("\u{1b}["));
+
+    // stderr is a pipe here, so auto leaves the errors plain
+    let out = validate("auto");
+    assert!(!out.status.success());
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(stderr.starts_with("Validation errors:\n"));
+    assert!(!stderr.contains("\u{1b}["));
+}
+
+#[test]
+fn test_stats_json() {
+    let home = tmp_home("prompter_it_stats");
+    fs::create_dir_all(&home).unwrap();
+    prompter(&home).arg("init").output().unwrap();
+
+    let out = prompter(&home).args(["stats", "--json"]).output().unwrap();
+    assert!(
+        out.status.success(),
+        "stats failed: {}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    let stats: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+    assert_eq!(stats["profile_count"], 2);
+    assert_eq!(stats["deepest_chain"][0], "general.testing");
+    assert_eq!(stats["most_included"]["name"], "python.api");
+}
+
+#[test]
+fn test_quiet_suppresses_informational_output() {
+    let home = tmp_home("prompter_it_quiet");
+    fs::create_dir_all(&home).unwrap();
+    let out = prompter(&home).args(["--quiet", "init"]).output().unwrap();
+    assert!(out.status.success());
+    assert!(out.stdout.is_empty());
+
+    let out = prompter(&home).args(["validate", "-q"]).output().unwrap();
+    assert!(out.status.success());
+    assert!(out.stdout.is_empty() && out.stderr.is_empty());
+
+    // Errors still reach stderr
+    let cfg = home.join(".config/prompter/config.toml");
+    fs::write(&cfg, "[broken]\ndepends_on = [\"missing.md\"]\n").unwrap();
+    let out = prompter(&home)
+        .args(["--quiet", "validate"])
+        .output()
+        .unwrap();
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("Missing file"));
+
+    // Render warnings and the shuffle seed are informational too
+    let render = |quiet: bool| {
+        let mut cmd = prompter(&home);
+        if quiet {
+            cmd.arg("--quiet");
+        }
+        cmd.current_dir(&home)
+            .args(["--config-inline", "[p]\ndepends_on = [\"a.md\"]\n"])
+            .args(["run", "--on-missing", "skip", "--exclude", "x"])
+            .args(["--shuffle-includes", "p", "nobody"])
+            .output()
+            .unwrap()
+    };
+    fs::write(home.join("a.md"), "A\n").unwrap();
+    let out = render(false);
+    assert!(out.status.success());
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(
+        stderr.contains("skipping unknown profile 'nobody'"),
+        "{stderr}"
+    );
+    assert!(stderr.contains("excluded profile 'x'"), "{stderr}");
+    assert!(stderr.contains("Shuffle seed: "), "{stderr}");
+    let out = render(true);
+    assert!(out.status.success());
+    assert!(
+        out.stderr.is_empty(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+}
+
+#[test]
+fn test_config_check_preflight() {
+    let home = tmp_home("prompter_it_config_check");
+    fs::create_dir_all(&home).unwrap();
+    prompter(&home).arg("init").output().unwrap();
+    let cfg = home.join(".config/prompter/config.toml");
+    fs::write(&cfg, "[broken]\ndepends_on = [\"missing.md\"]\n").unwrap();
+
+    // tree alone does not look at the library
+    let out = prompter(&home).arg("tree").output().unwrap();
+    assert!(out.status.success());
+
+    let out = prompter(&home)
+        .args(["--config-check", "tree"])
+        .output()
+        .unwrap();
+    assert!(!out.status.success());
+    assert!(out.stdout.is_empty());
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(stderr.contains("Config check failed"));
+    assert!(stderr.contains("Missing file"));
+}
+
+#[test]
+fn test_init_template() {
+    let home = tmp_home("prompter_it_template");
+    fs::create_dir_all(&home).unwrap();
+    let out = prompter(&home)
+        .args(["init", "--template", "writing"])
+        .output()
+        .unwrap();
+    assert!(out.status.success());
+    let out = prompter(&home).arg("list").output().unwrap();
+    assert_eq!(
+        String::from_utf8_lossy(&out.stdout),
+        "writing.review\nwriting.style\n"
+    );
+
+    let out = prompter(&home)
+        .args(["init", "--template", "nope"])
+        .output()
+        .unwrap();
+    assert!(!out.status.success());
+    assert!(
+        String::from_utf8_lossy(&out.stderr)
+            .contains("Available templates: minimal, coding, writing")
+    );
+}
+
+#[test]
+fn test_config_inline_resolves_fragments_from_cwd() {
+    let dir = tmp_home("prompter_it_inline");
+    fs::create_dir_all(dir.join("notes")).unwrap();
+    fs::write(dir.join("notes/inline.md"), "INLINE FRAGMENT\n").unwrap();
+
+    let out = prompter(&dir)
+        .current_dir(&dir)
+        .args([
+            "--config-inline",
+            "[demo]\ndepends_on = [\"notes/inline.md\"]\n",
+            "run",
+            "demo",
+        ])
+        .output()
+        .unwrap();
+    assert!(
+        out.status.success(),
+        "run failed: {}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    assert!(String::from_utf8_lossy(&out.stdout).contains("INLINE FRAGMENT"));
+}
+
+#[test]
+fn test_yaml_and_json_configs() {
+    let dir = tmp_home("prompter_it_formats");
+    fs::create_dir_all(dir.join("library")).unwrap();
+    fs::write(dir.join("library/a.md"), "FRAGMENT A\n").unwrap();
+    fs::write(
+        dir.join("prompts.yaml"),
+        "profiles:\n  hi: Hello.\ndemo:\n  depends_on: [hi, a.md]\n",
+    )
+    .unwrap();
+    fs::write(
+        dir.join("prompts.conf"),
+        r#"{"demo": {"depends_on": ["a.md"]}}"#,
+    )
+    .unwrap();
+    let prompter = |args: &[&str]| prompter(&dir).args(args).output().unwrap();
+
+    let yaml = dir.join("prompts.yaml");
+    let out = prompter(&["--config", yaml.to_str().unwrap(), "run", "demo"]);
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(
+        stdout.contains("Hello.") && stdout.contains("FRAGMENT A"),
+        "{stdout}"
+    );
+
+    let conf = dir.join("prompts.conf");
+    let out = prompter(&["--config", conf.to_str().unwrap(), "run", "demo"]);
+    assert!(!out.status.success());
+    let out = prompter(&[
+        "--input-format",
+        "json",
+        "--config",
+        conf.to_str().unwrap(),
+        "run",
+        "demo",
+    ]);
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    assert!(String::from_utf8_lossy(&out.stdout).contains("FRAGMENT A"));
+}
+
+#[test]
+fn test_history_and_repeat() {
+    let dir = tmp_home("prompter_it_history");
+    fs::create_dir_all(dir.join("library/notes")).unwrap();
+    fs::write(dir.join("library/notes/a.md"), "FRAGMENT A\n").unwrap();
+    fs::write(dir.join("library/notes/b.md"), "FRAGMENT B\n").unwrap();
+    let config = "[a]\ndepends_on = [\"notes/a.md\"]\n[b]\ndepends_on = [\"notes/b.md\"]\n";
+    fs::write(dir.join("prompter.toml"), config).unwrap();
+    let state = dir.join("state");
+    let prompter = |args: &[&str]| {
+        prompter(&dir)
+            .current_dir(&dir)
+            .env("XDG_STATE_HOME", &state)
+            .args(args)
+            .output()
+            .unwrap()
+    };
+
+    assert!(
+        prompter(&["--config", "prompter.toml", "run", "a"])
+            .status
+            .success()
+    );
+    assert!(
+        prompter(&["--config", "prompter.toml", "run", "b"])
+            .status
+            .success()
+    );
+    assert!(state.join("prompter/history.jsonl").exists());
+
+    let out = prompter(&["history"]);
+    assert!(out.status.success());
+    let listing = String::from_utf8_lossy(&out.stdout);
+    let lines: Vec<&str> = listing.lines().collect();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].starts_with("  1  ") && lines[0].contains(" b "));
+
+    let out = prompter(&["run", "--repeat", "2"]);
+    assert!(
+        out.status.success(),
+        "repeat failed: {}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    assert!(String::from_utf8_lossy(&out.stdout).contains("FRAGMENT A"));
+
+    let out = prompter(&["run", "--repeat", "9"]);
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("No history entry 9"));
+
+    // Inline config text is not recorded, so such an entry cannot be replayed
+    let secret = "[a]\ndepends_on = [\"library/notes/a.md\"]\n# token: hunter2\n";
+    assert!(
+        prompter(&["--config-inline", secret, "run", "a"])
+            .status
+            .success()
+    );
+    let history = fs::read_to_string(state.join("prompter/history.jsonl")).unwrap();
+    assert!(!history.contains("hunter2"));
+    let out = prompter(&["run", "--repeat", "1"]);
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("used --config-inline"));
+
+    // PROMPTER_NO_HISTORY turns recording off
+    let out = prompter(&["history"]);
+    let recorded = String::from_utf8_lossy(&out.stdout).lines().count();
+    let off = Command::new(bin_path())
+        .current_dir(&dir)
+        .env("HOME", &dir)
+        .env("XDG_STATE_HOME", &state)
+        .env("PROMPTER_NO_HISTORY", "1")
+        .args(["--config", "prompter.toml", "run", "b"])
+        .output()
+        .unwrap();
+    assert!(off.status.success());
+    let out = prompter(&["history"]);
+    assert_eq!(
+        String::from_utf8_lossy(&out.stdout).lines().count(),
+        recorded
+    );
+}
+
+#[test]
+fn test_doctor_deep_reports_validation_findings() {
+    let home = tmp_home("prompter_it_doctor_deep");
+    let config_dir = home.join(".config/prompter");
+    let library = home.join(".local/prompter/library");
+    fs::create_dir_all(&config_dir).unwrap();
+    fs::create_dir_all(&library).unwrap();
+    fs::write(library.join("a.md"), "A\n").unwrap();
+    fs::write(
+        config_dir.join("config.toml"),
+        "[p]\ndepends_on = [\"a.md\", \"a.md\", \"missing.md\"]\n",
+    )
+    .unwrap();
+
+    let doctor = |args: &[&str]| {
+        prompter(&home)
+            .env("EDITOR", "vi")
+            .args(args)
+            .output()
+            .unwrap()
+    };
+
+    let out = doctor(&["--json", "doctor"]);
+    assert!(out.status.success());
+    assert!(!String::from_utf8_lossy(&out.stdout).contains("missing.md"));
+
+    let out = doctor(&["--json", "doctor", "--deep"]);
+    assert!(!out.status.success());
+    let report: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+    let errors = report["errors"].as_array().unwrap();
+    assert!(errors[0].as_str().unwrap().starts_with("Missing file:"));
+    let warnings = report["warnings"].as_array().unwrap();
+    assert_eq!(warnings[0], "Duplicate dependency: a.md (in [p])");
+}
+
+#[test]
+fn test_doctor_fix_creates_missing_setup() {
+    let home = tmp_home("prompter_it_doctor_fix");
+    fs::create_dir_all(&home).unwrap();
+    let doctor = |args: &[&str], answer: &str| {
+        let mut child = prompter(&home)
+            .env("EDITOR", "vi")
+            .args(args)
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::piped())
+            .spawn()
+            .unwrap();
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(answer.as_bytes())
+            .unwrap();
+        child.wait_with_output().unwrap()
+    };
+
+    let out = doctor(&["--json", "doctor", "--fix"], "n\n");
+    assert!(!out.status.success());
+    assert!(!config_file(&home).exists());
+
+    let out = doctor(&["--json", "doctor", "--fix"], "y\n");
+    assert!(out.status.success());
+    assert!(config_file(&home).exists());
+    assert!(library_dir(&home).is_dir());
+    let report: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+    assert_eq!(report["errors"], serde_json::json!([]));
+    assert_eq!(
+        report["library_directory"].as_str().map(PathBuf::from),
+        Some(library_dir(&home))
+    );
+}
+
+#[test]
+fn test_run_to_stderr() {
+    let dir = tmp_home("prompter_it_to_stderr");
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("a.md"), "PROMPT BODY\n").unwrap();
+    let run = |args: &[&str]| {
+        prompter(&dir)
+            .current_dir(&dir)
+            .args(["--config-inline", "[p]\ndepends_on = [\"a.md\"]\n"])
+            .args(args)
+            .output()
+            .unwrap()
+    };
+
+    let out = run(&["run", "--to-stderr", "p"]);
+    assert!(out.status.success());
+    assert!(out.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("PROMPT BODY"));
+
+    let out = run(&["--json", "run", "--to-stderr", "p"]);
+    assert!(out.status.success());
+    assert!(String::from_utf8_lossy(&out.stdout).contains("PROMPT BODY"));
+    assert!(!String::from_utf8_lossy(&out.stderr).contains("PROMPT BODY"));
+}
+
+#[test]
+fn test_meta_prints_frontmatter() {
+    let dir = tmp_home("prompter_it_meta");
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("a.md"), "---\nowner: docs\n---\nPROMPT BODY\n").unwrap();
+    let run = |args: &[&str]| {
+        prompter(&dir)
+            .current_dir(&dir)
+            .args([
+                "--config-inline",
+                "[p]\ndescription = \"Docs\"\ndepends_on = [\"a.md\"]\n",
+            ])
+            .args(args)
+            .output()
+            .unwrap()
+    };
+
+    let out = run(&["meta", "p"]);
+    assert!(out.status.success());
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(stdout.contains("description = \"Docs\""), "{stdout}");
+    assert!(stdout.contains("owner = \"docs\""), "{stdout}");
+    assert!(!stdout.contains("PROMPT BODY"));
+
+    let out = run(&["--json", "meta", "--all"]);
+    let value: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+    assert_eq!(
+        value["profiles"][0]["fragments"][0]["frontmatter"]["owner"],
+        "docs"
+    );
+
+    assert!(!run(&["meta"]).status.success());
+}
+
+#[test]
+fn test_run_output_target() {
+    let dir = tmp_home("prompter_it_output_target");
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("a.md"), "PROMPT BODY\n").unwrap();
+    let run = |args: &[&str]| {
+        prompter(&dir)
+            .current_dir(&dir)
+            .args(["--config-inline", "[p]\ndepends_on = [\"a.md\"]\n"])
+            .args(args)
+            .output()
+            .unwrap()
+    };
+
+    let out = run(&["run", "--output", "out.txt", "p"]);
+    assert!(out.status.success());
+    assert!(out.stdout.is_empty());
+    assert!(
+        fs::read_to_string(dir.join("out.txt"))
+            .unwrap()
+            .contains("PROMPT BODY")
+    );
+
+    let out = run(&["--json", "run", "--output", "fd:2", "p"]);
+    assert!(out.status.success());
+    assert!(out.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("PROMPT BODY"));
+
+    let out = run(&["run", "--output", "/dev/stdout", "p"]);
+    assert!(String::from_utf8_lossy(&out.stdout).contains("PROMPT BODY"));
+
+    let out = run(&["run", "--output", "fd:x", "p"]);
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("expected fd:N"));
+    if cfg!(unix) {
+        let out = run(&["run", "--output", "fd:97", "p"]);
+        assert!(!out.status.success());
+        assert!(String::from_utf8_lossy(&out.stderr).contains("Cannot write to fd:97"));
+
+        // The inherited descriptor is written as is, so 3>> appends.
+        fs::write(dir.join("log.txt"), "EARLIER\n").unwrap();
+        let script = format!(
+            "'{}' --config-inline \"$CONFIG\" run --output fd:3 p 3>>log.txt",
+            bin_path()
+        );
+        let out = Command::new("sh")
+            .current_dir(&dir)
+            .env("HOME", &dir)
+            .env("XDG_STATE_HOME", &dir)
+            .env("CONFIG", "[p]\ndepends_on = [\"a.md\"]\n")
+            .args(["-c", &script])
+            .output()
+            .unwrap();
+        assert!(
+            out.status.success(),
+            "{}",
+            String::from_utf8_lossy(&out.stderr)
+        );
+        let log = fs::read_to_string(dir.join("log.txt")).unwrap();
+        assert!(
+            log.starts_with("EARLIER\n") && log.contains("PROMPT BODY"),
+            "{log}"
+        );
+    }
+    let out = run(&["run", "--output", "fd:0", "p"]);
+    assert!(String::from_utf8_lossy(&out.stderr).contains("fd:0 is standard input"));
+}
+
+#[test]
+fn test_run_print_order() {
+    let dir = tmp_home("prompter_it_print_order");
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("a.md"), "FRAGMENT A\n").unwrap();
+    fs::write(dir.join("b.md"), "FRAGMENT B\n").unwrap();
+    let out = prompter(&dir)
+        .current_dir(&dir)
+        .args([
+            "--config-inline",
+            "[base]\ndepends_on = [\"a.md\"]\n[top]\ndepends_on = [\"b.md\", \"base\"]\n[other]\ndepends_on = [\"a.md\"]\n",
+            "run",
+            "--print-order",
+            "top",
+            "other",
+        ])
+        .output()
+        .unwrap();
+    assert!(out.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&out.stderr),
+        "Render order:\n  top\n    base\n"
+    );
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(stdout.find("FRAGMENT B").unwrap() < stdout.find("FRAGMENT A").unwrap());
+}
+
+#[test]
+fn test_run_check() {
+    let dir = tmp_home("prompter_it_run_check");
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("a.md"), "Hello {{name}}\n").unwrap();
+    let run = |args: &[&str]| {
+        prompter(&dir)
+            .current_dir(&dir)
+            .args([
+                "--config-inline",
+                "[p]\ndepends_on = [\"a.md\"]\n[bad]\ndepends_on = [\"gone.md\"]\n",
+            ])
+            .args(args)
+            .output()
+            .unwrap()
+    };
+
+    let out = run(&["run", "--check", "--var", "name=x", "p"]);
+    assert!(out.status.success());
+    assert_eq!(String::from_utf8_lossy(&out.stdout), "OK\n");
+
+    let out = run(&["--quiet", "run", "--check", "--var", "name=x", "p"]);
+    assert!(out.status.success());
+    assert!(out.stdout.is_empty());
+
+    let out = run(&["run", "--check", "p"]);
+    assert!(!out.status.success());
+    assert!(out.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("Unresolved variable {{name}}"));
+
+    let out = run(&["run", "--check", "bad"]);
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("gone.md"));
+}
+
+#[test]
+fn test_run_json_error_names_failed_profile() {
+    let dir = tmp_home("prompter_it_failed_profile");
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("a.md"), "A\n").unwrap();
+    let out = prompter(&dir)
+        .current_dir(&dir)
+        .args([
+            "--config-inline",
+            "[a]\ndepends_on = [\"a.md\"]\n[b]\ndepends_on = [\"inner\"]\n[inner]\ndepends_on = [\"gone.md\"]\n",
+            "--json",
+            "run",
+            "a",
+            "b",
+        ])
+        .output()
+        .unwrap();
+    assert!(!out.status.success());
+    let err: serde_json::Value = serde_json::from_slice(&out.stderr).unwrap();
+    assert_eq!(err["failed_profile"], "b");
+    assert_eq!(err["include_chain"], serde_json::json!(["b", "inner"]));
+    assert!(err["error"].as_str().unwrap().starts_with("Missing file:"));
+}
+
+#[test]
+fn test_compact_and_pretty_json() {
+    let dir = tmp_home("prompter_it_compact_json");
+    fs::create_dir_all(&dir).unwrap();
+    let run = |args: &[&str]| {
+        prompter(&dir)
+            .current_dir(&dir)
+            .args([
+                "--config-inline",
+                "[p]\ndepends_on = [\"a.md\"]\n",
+                "--json",
+            ])
+            .args(args)
+            .output()
+            .unwrap()
+    };
+
+    let pretty = run(&["tree"]);
+    assert!(pretty.status.success());
+    assert!(String::from_utf8_lossy(&pretty.stdout).lines().count() > 1);
+    assert_eq!(run(&["--pretty", "tree"]).stdout, pretty.stdout);
+
+    let compact = run(&["--compact", "tree"]);
+    assert!(compact.status.success());
+    let compact = String::from_utf8_lossy(&compact.stdout);
+    assert_eq!(compact.lines().count(), 1);
+    assert!(compact.starts_with(r#"{"trees":[{"#));
+
+    assert!(!run(&["--pretty", "--compact", "tree"]).status.success());
+}
+
+#[test]
+fn test_run_env_file() {
+    let dir = tmp_home("prompter_it_env_file");
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("a.md"), "{{greeting}}, {{name}}!\n").unwrap();
+    fs::write(
+        dir.join(".env"),
+        "# team defaults\ngreeting=\"Hi\"\nname=file\n",
+    )
+    .unwrap();
+    let run = |args: &[&str]| {
+        prompter(&dir)
+            .current_dir(&dir)
+            .args(["--config-inline", "[p]\ndepends_on = [\"a.md\"]\n", "run"])
+            .args(args)
+            .output()
+            .unwrap()
+    };
+
+    let out = run(&["--env-file", ".env", "--var", "name=cli", "p"]);
+    assert!(out.status.success());
+    assert!(String::from_utf8_lossy(&out.stdout).contains("Hi, cli!"));
+
+    let out = run(&["--env-file", "missing.env", "p"]);
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("Failed to read missing.env"));
+
+    let out = prompter(&dir)
+        .current_dir(&dir)
+        .env("name", "env")
+        .env_remove("greeting")
+        .args(["--config-inline", "[p]\ndepends_on = [\"a.md\"]\n", "run"])
+        .args(["--env-file", ".env", "--capture-env", "name"])
+        .args(["--capture-env-optional", "greeting", "p"])
+        .output()
+        .unwrap();
+    assert!(out.status.success());
+    assert!(String::from_utf8_lossy(&out.stdout).contains("\n, env!"));
+    let out = run(&["--capture-env", "PROMPTER_IT_UNSET", "p"]);
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("PROMPTER_IT_UNSET is not set"));
+}
+
+#[test]
+fn test_lint_fail_on_warning() {
+    let dir = tmp_home("prompter_it_lint");
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("a.md"), "fine \n").unwrap();
+    let lint = |args: &[&str]| {
+        prompter(&dir)
+            .current_dir(&dir)
+            .args(["--config-inline", "[p]\ndepends_on = [\"a.md\"]\n", "lint"])
+            .args(args)
+            .output()
+            .unwrap()
+    };
+
+    let out = lint(&[]);
+    assert!(out.status.success());
+    assert!(String::from_utf8_lossy(&out.stdout).contains("a.md:1: trailing whitespace"));
+
+    let out = lint(&["--fail-on-warning"]);
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("Lint found 1 warning(s)"));
+}
+
+#[test]
+fn test_run_all_render_cache() {
+    let dir = tmp_home("prompter_it_cache");
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("a.md"), "A\n").unwrap();
+    fs::write(dir.join("b.md"), "B\n").unwrap();
+    let run_all = |args: &[&str]| {
+        let out = prompter(&dir)
+            .current_dir(&dir)
+            .env_remove("XDG_CACHE_HOME")
+            .args([
+                "--config-inline",
+                "[a]\ndepends_on = [\"a.md\"]\n[b]\ndepends_on = [\"a\", \"b.md\"]\n",
+                "run",
+                "--all",
+                "--output-dir",
+                "dist",
+            ])
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(
+            out.status.success(),
+            "run --all failed: {}",
+            String::from_utf8_lossy(&out.stderr)
+        );
+        String::from_utf8_lossy(&out.stdout).into_owned()
+    };
+
+    assert!(!run_all(&[]).contains("from cache"));
+    assert!(dir.join(".cache/prompter/render").is_dir());
+    assert!(run_all(&[]).contains("(2 from cache)"));
+    assert!(!run_all(&["--no-cache"]).contains("from cache"));
+
+    fs::write(dir.join("a.md"), "A2\n").unwrap();
+    assert!(!run_all(&[]).contains("from cache"));
+    assert!(
+        fs::read_to_string(dir.join("dist/b.txt"))
+            .unwrap()
+            .contains("A2")
+    );
+
+    fs::write(dir.join("b.md"), "B2\n").unwrap();
+    assert!(run_all(&[]).contains("(1 from cache)"));
+    assert!(!run_all(&["--clear-cache"]).contains("from cache"));
+}
+
+#[test]
+fn test_json_errors_are_structured() {
+    let dir = tmp_home("prompter_it_json_errors");
+    fs::create_dir_all(&dir).unwrap();
+    let run = |args: &[&str]| {
+        let out = prompter(&dir)
+            .current_dir(&dir)
+            .arg("--json-errors")
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(!out.status.success());
+        let stderr = String::from_utf8_lossy(&out.stderr);
+        serde_json::from_str::<serde_json::Value>(stderr.trim()).unwrap()
+    };
+
+    let err = run(&[
+        "--config-inline",
+        "[top]\ndepends_on = [\"mid\"]\n[mid]\ndepends_on = [\"gone.md\"]\n",
+        "run",
+        "top",
+    ]);
+    assert_eq!(err["code"], "render");
+    assert!(err["message"].as_str().unwrap().contains("Missing file"));
+    assert_eq!(
+        err["context"]["include_chain"],
+        serde_json::json!(["top", "mid"])
+    );
+
+    let err = run(&["--config-inline", "[p]\ndepends_on = [\"x\"]\n", "validate"]);
+    assert_eq!(err["code"], "validation");
+    assert_eq!(
+        err["context"]["errors"][0],
+        "Unknown profile: x (referenced by [p])"
+    );
+
+    let err = run(&["run", "--no-such-flag"]);
+    assert_eq!(err["code"], "usage");
+}
+
+#[test]
+fn test_run_scenario() {
+    let dir = tmp_home("prompter_it_scenario");
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("a.md"), "ALPHA\n").unwrap();
+    fs::write(dir.join("b.md"), "BETA\n").unwrap();
+    let config = "[a]\ndepends_on = [\"a.md\"]\n[b]\ndepends_on = [\"b.md\"]\n\
+                  [scenarios.review]\nprofiles = [\"a\", \"b\"]\n\
+                  separator = \"<SEP>\"\npre_prompt = \"SCENARIO PRE\"\n";
+    let run = |args: &[&str]| {
+        prompter(&dir)
+            .current_dir(&dir)
+            .args(["--config-inline", config, "run", "--scenario", "review"])
+            .args(args)
+            .output()
+            .unwrap()
+    };
+
+    let out = run(&[]);
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(stdout.starts_with("SCENARIO PRE"));
+    assert!(stdout.contains("ALPHA\n<SEP>\nBETA\n<SEP>"), "{stdout}");
+
+    let out = run(&["--separator", "<CLI>", "--pre-prompt", "CLI PRE"]);
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(stdout.starts_with("CLI PRE"));
+    assert!(stdout.contains("ALPHA\n<CLI>\nBETA\n<CLI>"), "{stdout}");
+
+    let out = prompter(&dir)
+        .current_dir(&dir)
+        .args(["--config-inline", config, "run", "--scenario", "nope"])
+        .output()
+        .unwrap();
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("Unknown scenario: nope"));
+}
+
+#[test]
+fn test_layered_config_dump() {
+    let dir = tmp_home("prompter_it_layered");
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(
+        dir.join("team.toml"),
+        "post_prompt = \"TEAM\"\n[shared]\ndepends_on = [\"a.md\"]\n[style]\ndepends_on = [\"team.md\"]\n",
+    )
+    .unwrap();
+    fs::write(
+        dir.join("mine.toml"),
+        "[style]\ndepends_on = [\"mine.md\"]\n",
+    )
+    .unwrap();
+
+    let out = prompter(&dir)
+        .current_dir(&dir)
+        .args(["--config", "team.toml", "--config", "mine.toml"])
+        .args(["--json", "--dump-config"])
+        .output()
+        .unwrap();
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    let dump: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+    assert_eq!(dump["source"], "team.toml + mine.toml");
+    assert_eq!(dump["post_prompt"], "TEAM");
+    assert_eq!(
+        dump["profiles"]["style"]["depends_on"],
+        serde_json::json!(["mine.md"])
+    );
+    assert_eq!(
+        dump["profiles"]["shared"]["depends_on"],
+        serde_json::json!(["a.md"])
+    );
+}
+
+#[test]
+fn test_bare_run_without_terminal_is_usage_error() {
+    let out = Command::new(bin_path())
+        .args(["--config-inline", "[p]\ndepends_on = []\n", "run"])
+        .output()
+        .unwrap();
+    assert_eq!(out.status.code(), Some(2));
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(stderr.contains("<PROFILES>"), "{stderr}");
+}
+
+#[test]
+fn test_verbose_logs_phase_timings_to_stderr() {
+    let dir = tmp_home("prompter_it_verbose");
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("a.md"), "ALPHA\n").unwrap();
+    let config = "[a]\ndepends_on = [\"a.md\"]\n";
+    let run = |flags: &[&str]| {
+        prompter(&dir)
+            .current_dir(&dir)
+            .args(["--config-inline", config])
+            .args(flags)
+            .args(["run", "a"])
+            .output()
+            .unwrap()
+    };
+
+    let quiet = run(&[]);
+    assert!(quiet.stderr.is_empty());
+    let out = run(&["-v"]);
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&out.stdout).replace(char::is_numeric, ""),
+        String::from_utf8_lossy(&quiet.stdout).replace(char::is_numeric, "")
+    );
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    for phase in [
+        "config load:",
+        "include resolution:",
+        "body read:",
+        "substitution:",
+        "composition:",
+    ] {
+        assert!(stderr.contains(phase), "{phase} missing from {stderr}");
+    }
+    assert!(!stderr.contains("a.md ("));
+
+    let stderr = String::from_utf8_lossy(&run(&["-vv"]).stderr).into_owned();
+    assert!(stderr.contains("a.md (6 bytes)"), "{stderr}");
+    assert!(!run(&["-v", "--quiet"]).status.success());
+}
+
+#[test]
+fn test_export_import_round_trip() {
+    let dir = tmp_home("prompter_it_bundle");
+    let lib = dir.join("library");
+    fs::create_dir_all(lib.join("nested")).unwrap();
+    fs::write(lib.join("nested/a.md"), "ALPHA\n").unwrap();
+    fs::write(
+        dir.join("config.toml"),
+        "[a]\ndescription = \"First\"\ndepends_on = [\"nested/a.md\"]\n",
+    )
+    .unwrap();
+    let prompter = |args: &[&str]| {
+        prompter(&dir)
+            .current_dir(&dir)
+            .args(args)
+            .output()
+            .unwrap()
+    };
+
+    let out = prompter(&["--config", "config.toml", "export", "bundle.json"]);
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    let bundle: serde_json::Value =
+        serde_json::from_slice(&fs::read(dir.join("bundle.json")).unwrap()).unwrap();
+    assert_eq!(bundle["library"]["nested/a.md"], "ALPHA\n");
+
+    let out = prompter(&["import", "bundle.json", "--into", "copy"]);
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    let render = |config: &str| prompter(&["--config", config, "run", "a"]).stdout;
+    assert!(String::from_utf8_lossy(&render("copy/config.toml")).contains("ALPHA"));
+    assert_eq!(
+        String::from_utf8_lossy(&render("copy/config.toml")).replace(char::is_numeric, ""),
+        String::from_utf8_lossy(&render("config.toml")).replace(char::is_numeric, "")
+    );
+
+    let out = prompter(&["import", "bundle.json", "--into", "copy"]);
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("already exists"));
+}
+
+#[test]
+fn test_run_offline_remote_include() {
+    let dir = tmp_home("prompter_it_remote");
+    fs::create_dir_all(&dir).unwrap();
+    let url = "https://example.com/snippets/legal.md";
+    let config = format!("[p]\ndepends_on = [\"{url}\"]\n[q]\ndepends_on = [\"?{url}\"]\n");
+    let run = |profile: &str| {
+        prompter(&dir)
+            .env("XDG_CACHE_HOME", dir.join("cache"))
+            .args(["--offline", "--config-inline", &config, "run", profile])
+            .output()
+            .unwrap()
+    };
+
+    let out = run("p");
+    assert!(!out.status.success());
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(stderr.contains(&format!("Remote include {url} is not cached (--offline)")));
+    assert!(run("q").status.success());
+
+    let cached = dir.join("cache/prompter/remote");
+    fs::create_dir_all(&cached).unwrap();
+    let name = prompter::HashAlgorithm::Sha256.hex_digest(url.as_bytes());
+    fs::write(cached.join(name), "Cached legal text\n").unwrap();
+    let out = run("p");
+    assert!(out.status.success());
+    assert!(String::from_utf8_lossy(&out.stdout).contains("Cached legal text"));
+
+    let out = prompter(&dir)
+        .env("XDG_CACHE_HOME", dir.join("cache"))
+        .args(["--deny-remote", "--config-inline", &config, "run", "q"])
+        .output()
+        .unwrap();
+    assert!(!out.status.success());
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(stderr.contains(&format!(
+        "Blocked remote include {url}: remote includes are denied (--deny-remote)"
+    )));
+    let allowlisted = format!("{config}[remote]\nallowed_hosts = [\"cdn.example.com\"]\n");
+    let out = prompter(&dir)
+        .env("XDG_CACHE_HOME", dir.join("cache"))
+        .args(["--offline", "--config-inline", &allowlisted, "run", "p"])
+        .output()
+        .unwrap();
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("host 'example.com' is not in"));
+}
+
+#[test]
+fn test_run_metrics_json() {
+    let dir = tmp_home("prompter_it_metrics");
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("a.md"), "PROMPT BODY\n").unwrap();
+    let run = |args: &[&str]| {
+        prompter(&dir)
+            .current_dir(&dir)
+            .args(["--config-inline", "[p]\ndepends_on = [\"a.md\"]\n"])
+            .args(["run", "--metrics-json", "metrics/render.jsonl"])
+            .args(args)
+            .output()
+            .unwrap()
+    };
+
+    let out = run(&["p"]);
+    assert!(out.status.success());
+    assert!(String::from_utf8_lossy(&out.stdout).contains("PROMPT BODY"));
+    let out = run(&["--output", "out.txt", "p"]);
+    assert!(out.status.success());
+    let written = fs::read(dir.join("out.txt")).unwrap();
+
+    let text = fs::read_to_string(dir.join("metrics/render.jsonl")).unwrap();
+    let lines: Vec<serde_json::Value> = text
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[1]["profiles"], serde_json::json!(["p"]));
+    assert_eq!(lines[1]["output_bytes"], written.len());
+    assert_eq!(lines[1]["estimated_tokens"], written.len().div_ceil(4));
+    assert!(lines[1]["elapsed_ms"].is_u64());
+    assert!(!run(&["--check", "p"]).status.success());
+}
+
+#[test]
+fn test_run_diff_against() {
+    let dir = tmp_home("prompter_it_diff_against");
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("a.md"), "PROMPT BODY\n").unwrap();
+    let run = |args: &[&str]| {
+        prompter(&dir)
+            .current_dir(&dir)
+            .args(["--config-inline", "[p]\ndepends_on = [\"a.md\"]\n"])
+            .args(["run", "--pre-prompt", "", "--post-prompt", "END"])
+            .args(args)
+            .output()
+            .unwrap()
+    };
+
+    // A fixed golden file: no date or system line, and a final newline the
+    // render itself does not end with, as editors save it.
+    fs::write(dir.join("golden.md"), "\nPROMPT BODY\n\n\nEND\n").unwrap();
+    let out = run(&["--no-system-info", "p"]);
+    assert_eq!(
+        String::from_utf8_lossy(&out.stdout),
+        "\nPROMPT BODY\n\n\nEND"
+    );
+    let out = run(&["--diff-against", "golden.md", "p"]);
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    assert!(out.stdout.is_empty() && out.stderr.is_empty());
+
+    fs::write(dir.join("a.md"), "CHANGED BODY\n").unwrap();
+    let out = run(&["--diff-against", "golden.md", "p"]);
+    assert!(!out.status.success());
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(
+        stdout.starts_with("--- golden.md\n+++ rendered\n"),
+        "{stdout}"
+    );
+    assert!(stdout.contains("-PROMPT BODY\n+CHANGED BODY\n"), "{stdout}");
+    assert!(
+        String::from_utf8_lossy(&out.stderr)
+            .contains("Rendered output of p differs from golden.md")
+    );
+
+    let out = run(&["--diff-against", "missing.md", "p"]);
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("Failed to read missing.md"));
+}
+
+#[test]
+fn test_verbose_reports_skipped_optional_remote_include() {
+    let home = tmp_home("prompter_it_optional_remote");
+    fs::create_dir_all(&home).unwrap();
+    let run = |verbose: &[&str]| {
+        prompter(&home)
+            .args(["--offline", "--config-inline"])
+            .arg("[p]\ndepends_on = [\"?https://uncached.invalid/a.md\"]\n")
+            .args(verbose)
+            .args(["run", "p"])
+            .output()
+            .unwrap()
+    };
+    let note = "skipping optional include: Remote include https://uncached.invalid/a.md \
+                is not cached (--offline)";
+
+    let out = run(&["-v"]);
+    assert!(out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains(note));
+
+    let out = run(&[]);
+    assert!(out.status.success());
+    assert!(!String::from_utf8_lossy(&out.stderr).contains(note));
+}
+
+#[test]
+fn test_list_by_mtime_warns_about_a_broken_profile() {
+    let home = tmp_home("prompter_it_list_broken");
+    fs::create_dir_all(&home).unwrap();
+    let out = prompter(&home)
+        .args([
+            "--config-inline",
+            "[broken]\ndepends_on = [\"missing.md\"]\n",
+        ])
+        .args(["list", "--sort", "mtime"])
+        .output()
+        .unwrap();
+    assert!(out.status.success());
+    assert_eq!(String::from_utf8_lossy(&out.stdout), "broken\n");
+    assert!(
+        String::from_utf8_lossy(&out.stderr)
+            .contains("Warning: profile 'broken' has no modification time")
+    );
+}